@@ -20,5 +20,144 @@
 // OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
 // SOFTWARE.
 
+
+// The colormaps of the secondary, 256 RGB colors from the lowest value of
+// the window to the highest as the LUT texture (sampler2).
+
 pub mod cmocean;
 pub mod jet;
+pub mod magma;
+pub mod viridis;
+
+pub const SIZE: usize = 256;
+
+// The built-in colormaps by name, "thermal" for cmocean.
+pub const NAMES: [&str; 6] = ["jet", "thermal", "viridis", "magma", "gray", "bands"];
+
+// The number of the bands of "bands".
+const BANDS: usize = 10;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Colormap {
+    name: String,
+    lut: Vec<u8>,
+}
+
+impl Default for Colormap {
+    fn default() -> Self {
+        Colormap {
+            name: String::from("jet"),
+            lut: jet::LUT.to_vec(),
+        }
+    }
+}
+
+impl Colormap {
+    pub fn from_name(name: &str) -> Option<Colormap> {
+        let lut = match name {
+            "jet" => jet::LUT.to_vec(),
+            "thermal" | "cmocean" => cmocean::LUT.to_vec(),
+            "viridis" => viridis::LUT.to_vec(),
+            "magma" => magma::LUT.to_vec(),
+            "gray" | "grayscale" => (0..SIZE).flat_map(|i| [i as u8; 3]).collect(),
+            "bands" => discrete(&jet::LUT, BANDS),
+            _ => return None,
+        };
+        Some(Colormap {
+            name: String::from(name),
+            lut,
+        })
+    }
+
+    // From `colors`, r, g, b of 2 colors or more from the lowest value. They
+    // are interpolated, or painted in equal bands if `discrete`.
+    pub fn from_colors(name: &str, colors: &[u8], discrete: bool) -> Result<Colormap, String> {
+        if !colors.len().is_multiple_of(3) || colors.len() < 6 {
+            return Err(format!("expected r, g, b of 2 colors or more, got {} values.", colors.len()));
+        }
+        let n = colors.len() / 3;
+        let lut = (0..SIZE)
+            .flat_map(|i| {
+                let t = i as f32 / (SIZE - 1) as f32;
+                if discrete {
+                    let k = ((t * n as f32) as usize).min(n - 1);
+                    [0, 1, 2].map(|ch| colors[k * 3 + ch])
+                } else {
+                    let x = t * (n - 1) as f32;
+                    let k = (x.floor() as usize).min(n - 2);
+                    let f = x - k as f32;
+                    [0, 1, 2].map(|ch| {
+                        let (a, b) = (colors[k * 3 + ch] as f32, colors[(k + 1) * 3 + ch] as f32);
+                        (a + (b - a) * f).round() as u8
+                    })
+                }
+            })
+            .collect();
+        Ok(Colormap {
+            name: String::from(name),
+            lut,
+        })
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    // SIZE RGB colors
+    pub fn lut(&self) -> &[u8] {
+        &self.lut
+    }
+
+    // The nearest color at `t` in [0, 1] of the window.
+    pub fn color(&self, t: f32) -> [u8; 3] {
+        let i = (t.clamp(0.0, 1.0) * (SIZE - 1) as f32).round() as usize;
+        [self.lut[i * 3], self.lut[i * 3 + 1], self.lut[i * 3 + 2]]
+    }
+}
+
+// `lut` in `n` bands of the colors at their centers.
+fn discrete(lut: &[u8], n: usize) -> Vec<u8> {
+    let size = lut.len() / 3;
+    (0..size)
+        .flat_map(|i| {
+            let band = i * n / size;
+            let k = ((band as f32 + 0.5) / n as f32 * size as f32) as usize;
+            [lut[k * 3], lut[k * 3 + 1], lut[k * 3 + 2]]
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_colormaps() {
+        for name in NAMES {
+            let colormap = Colormap::from_name(name).unwrap();
+            assert_eq!(colormap.lut().len(), SIZE * 3, "{}", name);
+            assert_eq!(colormap.name(), name);
+        }
+        assert!(Colormap::from_name("rainbow").is_none());
+        assert_eq!(Colormap::default(), Colormap::from_name("jet").unwrap());
+        assert_eq!(Colormap::from_name("gray").unwrap().color(1.0), [255, 255, 255]);
+
+        let bands = Colormap::from_name("bands").unwrap();
+        let distinct = bands.lut().chunks_exact(3).fold(Vec::new(), |mut colors: Vec<&[u8]>, c| {
+            if colors.last() != Some(&c) {
+                colors.push(c);
+            }
+            colors
+        });
+        assert_eq!(distinct.len(), BANDS);
+
+        let custom = Colormap::from_colors("custom", &[0, 0, 0, 255, 0, 200], false).unwrap();
+        assert_eq!(custom.color(0.0), [0, 0, 0]);
+        assert_eq!(custom.color(1.0), [255, 0, 200]);
+        assert_eq!(custom.color(0.5), [128, 0, 100]);
+        let custom = Colormap::from_colors("custom", &[0, 0, 0, 255, 0, 200], true).unwrap();
+        assert_eq!(custom.color(0.49), [0, 0, 0]);
+        assert_eq!(custom.color(0.51), [255, 0, 200]);
+        assert!(Colormap::from_colors("custom", &[0, 0, 0, 255], false).is_err());
+    }
+}
@@ -0,0 +1,59 @@
+// MIT License
+
+// Copyright (c) 2023 Techtron-Lab
+
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+// The magma colormap of matplotlib, from its polynomial fit.
+pub const LUT: [u8; 256 * 3] = [
+    // ncolors = 256
+    // # r   g   b
+    0, 0, 0, 0, 0, 1, 0, 1, 4, 0, 2, 6, 1, 2, 9, 1, 3, 11, 2, 3, 14, 3, 4, 16,
+    3, 4, 19, 4, 5, 21, 5, 5, 23, 6, 6, 26, 7, 6, 28, 7, 7, 31, 8, 7, 33, 9, 7, 36,
+    10, 8, 38, 11, 8, 40, 12, 9, 43, 14, 9, 45, 15, 9, 47, 16, 10, 50, 17, 10, 52, 18, 10, 54,
+    19, 11, 57, 21, 11, 59, 22, 11, 61, 23, 11, 63, 25, 12, 65, 26, 12, 67, 27, 12, 70, 29, 12, 72,
+    30, 13, 74, 31, 13, 76, 33, 13, 78, 34, 14, 80, 36, 14, 82, 37, 14, 83, 39, 14, 85, 40, 15, 87,
+    42, 15, 89, 43, 15, 91, 45, 15, 93, 46, 16, 94, 48, 16, 96, 49, 16, 98, 51, 16, 99, 52, 17, 101,
+    54, 17, 102, 55, 17, 104, 57, 17, 105, 59, 18, 107, 60, 18, 108, 62, 18, 109, 63, 19, 111, 65, 19, 112,
+    66, 19, 113, 68, 19, 115, 70, 20, 116, 71, 20, 117, 73, 20, 118, 74, 21, 119, 76, 21, 120, 78, 21, 121,
+    79, 22, 122, 81, 22, 123, 83, 22, 124, 84, 23, 125, 86, 23, 126, 87, 23, 126, 89, 24, 127, 91, 24, 128,
+    92, 24, 128, 94, 25, 129, 96, 25, 130, 97, 25, 130, 99, 26, 131, 101, 26, 131, 102, 26, 132, 104, 27, 132,
+    105, 27, 132, 107, 28, 133, 109, 28, 133, 110, 28, 133, 112, 29, 134, 114, 29, 134, 115, 30, 134, 117, 30, 134,
+    119, 31, 134, 120, 31, 134, 122, 31, 134, 124, 32, 134, 125, 32, 134, 127, 33, 134, 129, 33, 134, 130, 34, 134,
+    132, 34, 134, 134, 35, 134, 135, 35, 134, 137, 36, 134, 139, 36, 133, 140, 37, 133, 142, 37, 133, 144, 38, 132,
+    145, 38, 132, 147, 39, 132, 149, 39, 131, 150, 40, 131, 152, 40, 131, 154, 41, 130, 155, 42, 130, 157, 42, 129,
+    158, 43, 129, 160, 43, 128, 162, 44, 128, 163, 45, 127, 165, 45, 127, 167, 46, 126, 168, 46, 125, 170, 47, 125,
+    172, 48, 124, 173, 48, 124, 175, 49, 123, 176, 50, 122, 178, 51, 122, 180, 51, 121, 181, 52, 120, 183, 53, 120,
+    184, 54, 119, 186, 54, 118, 187, 55, 118, 189, 56, 117, 190, 57, 116, 192, 57, 115, 194, 58, 115, 195, 59, 114,
+    197, 60, 113, 198, 61, 113, 200, 62, 112, 201, 63, 111, 203, 63, 111, 204, 64, 110, 205, 65, 109, 207, 66, 109,
+    208, 67, 108, 210, 68, 107, 211, 69, 107, 212, 70, 106, 214, 71, 105, 215, 72, 105, 217, 73, 104, 218, 74, 104,
+    219, 75, 103, 220, 77, 102, 222, 78, 102, 223, 79, 101, 224, 80, 101, 225, 81, 100, 227, 82, 100, 228, 83, 99,
+    229, 85, 99, 230, 86, 98, 231, 87, 98, 232, 88, 98, 233, 90, 97, 234, 91, 97, 235, 92, 97, 237, 94, 96,
+    238, 95, 96, 238, 97, 96, 239, 98, 96, 240, 99, 96, 241, 101, 95, 242, 102, 95, 243, 104, 95, 244, 105, 95,
+    245, 107, 95, 245, 108, 95, 246, 110, 95, 247, 111, 95, 248, 113, 95, 248, 115, 95, 249, 116, 96, 249, 118, 96,
+    250, 120, 96, 251, 121, 96, 251, 123, 97, 252, 125, 97, 252, 127, 97, 253, 128, 98, 253, 130, 98, 253, 132, 99,
+    254, 134, 99, 254, 136, 100, 255, 137, 100, 255, 139, 101, 255, 141, 101, 255, 143, 102, 255, 145, 103, 255, 147, 104,
+    255, 149, 104, 255, 151, 105, 255, 153, 106, 255, 155, 107, 255, 157, 108, 255, 159, 109, 255, 161, 110, 255, 163, 111,
+    255, 165, 112, 255, 167, 113, 255, 169, 114, 255, 171, 116, 255, 173, 117, 255, 175, 118, 255, 177, 119, 255, 180, 121,
+    255, 182, 122, 255, 184, 123, 255, 186, 125, 255, 188, 126, 255, 190, 128, 254, 192, 129, 254, 194, 131, 254, 196, 132,
+    254, 198, 134, 253, 200, 135, 253, 202, 137, 253, 205, 139, 253, 207, 140, 252, 209, 142, 252, 211, 144, 252, 213, 145,
+    252, 215, 147, 251, 216, 149, 251, 218, 150, 251, 220, 152, 251, 222, 154, 251, 224, 156, 251, 226, 157, 250, 228, 159,
+    250, 229, 161, 250, 231, 163, 250, 233, 164, 250, 234, 166, 250, 236, 168, 250, 237, 170, 251, 239, 171, 251, 240, 173,
+    251, 241, 175, 251, 243, 177, 252, 244, 178, 252, 245, 180, 253, 246, 182, 253, 247, 183, 254, 248, 185, 254, 249, 186
+];
@@ -0,0 +1,139 @@
+// MIT License
+
+// Copyright (c) 2023 Techtron-Lab
+
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+
+pub mod tag;
+pub mod dataset;
+pub mod ct;
+pub mod rtdose;
+pub mod rtstruct;
+pub mod writer;
+
+pub use dataset::{parse, DataSet, Element, Value};
+pub use tag::{Tag, VR};
+
+use tag::IMAGE_ORIENTATION_PATIENT;
+
+// The direction cosines of the rows and columns, defaults to axial.
+pub(crate) fn image_orientation(ds: &DataSet) -> Result<Vec<f64>, String> {
+    match ds.f64s(IMAGE_ORIENTATION_PATIENT) {
+        Some(v) if v.len() == 6 => Ok(v),
+        Some(_) => Err(String::from("invalid ImageOrientationPatient.")),
+        None => Ok(vec![1.0, 0.0, 0.0, 0.0, 1.0, 0.0]),
+    }
+}
+
+pub(crate) fn to_orientation(v: &[f64]) -> [f32; 6] {
+    let mut orientation = [0.0; 6];
+    for (o, v) in orientation.iter_mut().zip(v) {
+        *o = *v as f32;
+    }
+    orientation
+}
+
+pub(crate) fn cross(a: &[f64], b: &[f64]) -> [f64; 3] {
+    [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ]
+}
+
+pub(crate) fn dot(a: &[f64], b: &[f64]) -> f64 {
+    a.iter().zip(b).map(|(x, y)| x * y).sum()
+}
+
+#[cfg(test)]
+pub(crate) mod test_utils {
+    use super::tag::*;
+
+    pub fn push_element(buf: &mut Vec<u8>, explicit_vr: bool, tag: Tag, vr: &[u8; 2], value: &[u8]) {
+        let mut value = value.to_vec();
+        if value.len() % 2 == 1 {
+            value.push(VR::from_bytes(*vr).unwrap().padding());
+        }
+        buf.extend(tag.0.to_le_bytes());
+        buf.extend(tag.1.to_le_bytes());
+        if explicit_vr {
+            buf.extend(vr);
+            if VR::from_bytes(*vr).unwrap().has_long_length() {
+                buf.extend([0, 0]);
+                buf.extend((value.len() as u32).to_le_bytes());
+            } else {
+                buf.extend((value.len() as u16).to_le_bytes());
+            }
+        } else {
+            buf.extend((value.len() as u32).to_le_bytes());
+        }
+        buf.extend(value);
+    }
+
+    pub fn push_sequence(buf: &mut Vec<u8>, explicit_vr: bool, tag: Tag, items: &[Vec<u8>], undefined: bool) {
+        let mut value = Vec::new();
+        for item in items {
+            value.extend(ITEM.0.to_le_bytes());
+            value.extend(ITEM.1.to_le_bytes());
+            if undefined {
+                value.extend(0xFFFF_FFFFu32.to_le_bytes());
+                value.extend(item);
+                value.extend(ITEM_DELIMITATION_ITEM.0.to_le_bytes());
+                value.extend(ITEM_DELIMITATION_ITEM.1.to_le_bytes());
+                value.extend(0u32.to_le_bytes());
+            } else {
+                value.extend((item.len() as u32).to_le_bytes());
+                value.extend(item);
+            }
+        }
+        buf.extend(tag.0.to_le_bytes());
+        buf.extend(tag.1.to_le_bytes());
+        if explicit_vr {
+            buf.extend(b"SQ\0\0");
+        }
+        if undefined {
+            buf.extend(0xFFFF_FFFFu32.to_le_bytes());
+            buf.extend(value);
+            buf.extend(SEQUENCE_DELIMITATION_ITEM.0.to_le_bytes());
+            buf.extend(SEQUENCE_DELIMITATION_ITEM.1.to_le_bytes());
+            buf.extend(0u32.to_le_bytes());
+        } else {
+            buf.extend((value.len() as u32).to_le_bytes());
+            buf.extend(value);
+        }
+    }
+
+    pub fn part10_with_transfer_syntax(transfer_syntax: &str, body: &[u8]) -> Vec<u8> {
+        let mut buf = vec![0u8; 128];
+        buf.extend(b"DICM");
+        push_element(&mut buf, true, TRANSFER_SYNTAX_UID, b"UI", transfer_syntax.as_bytes());
+        buf.extend(body);
+        buf
+    }
+
+    pub fn part10(explicit_vr: bool, body: &[u8]) -> Vec<u8> {
+        let transfer_syntax = if explicit_vr {
+            TRANSFER_SYNTAX_EXPLICIT_LE
+        } else {
+            TRANSFER_SYNTAX_IMPLICIT_LE
+        };
+        part10_with_transfer_syntax(transfer_syntax, body)
+    }
+}
@@ -0,0 +1,492 @@
+// MIT License
+
+// Copyright (c) 2023 Techtron-Lab
+
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+
+use std::collections::BTreeMap;
+
+use log::warn;
+
+use super::dataset::{parse, DataSet};
+use super::tag::*;
+use super::{cross, dot, image_orientation, to_orientation};
+use crate::modal::{VolumeDataType, VolumeInfo};
+
+// An image series reconstructed from the single frame slices of a CT (or any
+// other cross sectional modality) study.
+#[derive(Clone)]
+pub struct CTSeries {
+    pub study_instance_uid: String,
+    pub series_instance_uid: String,
+    pub frame_of_reference_uid: String,
+    // SOP instance UIDs of the slices in the order of `data`.
+    pub sop_instance_uids: Vec<String>,
+    // and their SOP class UIDs, CT or MR images
+    pub sop_class_uids: Vec<String>,
+    // The rescale of `info` maps `data` to Hounsfield units.
+    pub info: VolumeInfo,
+    // Stored values, slice by slice along the slice normal.
+    pub data: Vec<i16>,
+    // Patient and study attributes of the first slice, to be copied into the
+    // objects derived from this series.
+    pub patient_study: DataSet,
+}
+
+// Everything but the pixels of a CTSeries, used by the objects referencing it.
+#[derive(Debug, Clone)]
+pub struct SeriesReference {
+    pub study_instance_uid: String,
+    pub series_instance_uid: String,
+    pub frame_of_reference_uid: String,
+    pub sop_instance_uids: Vec<String>,
+    pub sop_class_uids: Vec<String>,
+    pub slice_locations: Vec<f32>,
+    pub patient_study: DataSet,
+}
+
+const PATIENT_STUDY_TAGS: [Tag; 10] = [
+    PATIENT_NAME,
+    PATIENT_ID,
+    PATIENT_BIRTH_DATE,
+    PATIENT_SEX,
+    STUDY_INSTANCE_UID,
+    STUDY_DATE,
+    STUDY_TIME,
+    STUDY_ID,
+    ACCESSION_NUMBER,
+    REFERRING_PHYSICIAN_NAME,
+];
+
+struct Slice {
+    ds: DataSet,
+    // position along the slice normal
+    location: f64,
+}
+
+// Whether `ds` is a single frame CT or MR image. Without SOPClassUID it is
+// told by Modality, and without either it is taken as one.
+fn is_slice_image(ds: &DataSet) -> bool {
+    let image = match (ds.string(SOP_CLASS_UID), ds.string(MODALITY)) {
+        (Some(class), _) => class == SOP_CLASS_CT_IMAGE || class == SOP_CLASS_MR_IMAGE,
+        (None, Some(modality)) => modality == "CT" || modality == "MR",
+        (None, None) => true,
+    };
+    image && ds.contains(PIXEL_DATA) && ds.i32(NUMBER_OF_FRAMES).unwrap_or(1) <= 1
+}
+
+// SOPClassUID of an image accepted by is_slice_image, told by Modality if
+// it is missing.
+fn sop_class(ds: &DataSet) -> String {
+    match (ds.string(SOP_CLASS_UID), ds.string(MODALITY)) {
+        (Some(class), _) => class,
+        (None, Some(modality)) if modality == "MR" => String::from(SOP_CLASS_MR_IMAGE),
+        _ => String::from(SOP_CLASS_CT_IMAGE),
+    }
+}
+
+// Load all the image series in `files`. Files which are not single frame CT
+// or MR images are skipped, as are the series which cannot be reconstructed;
+// it fails only if none can. The returned series are ordered by
+// SeriesInstanceUID.
+pub fn load_ct_series(files: &[&[u8]]) -> Result<Vec<CTSeries>, String> {
+    let mut groups: BTreeMap<String, Vec<DataSet>> = BTreeMap::new();
+    for (i, file) in files.iter().enumerate() {
+        let ds = match parse(file) {
+            Ok(ds) => ds,
+            Err(err) => {
+                warn!("skip file {}: {}", i, err);
+                continue;
+            }
+        };
+        if !is_slice_image(&ds) {
+            continue;
+        }
+        let uid = ds.string(SERIES_INSTANCE_UID).unwrap_or_default();
+        groups.entry(uid).or_default().push(ds);
+    }
+
+    let mut series = Vec::new();
+    let mut errors = Vec::new();
+    for (uid, slices) in groups {
+        match CTSeries::from_slices(uid.clone(), slices) {
+            Ok(s) => series.push(s),
+            Err(err) => {
+                warn!("skip series {}: {}", uid, err);
+                errors.push(format!("series {}: {}", uid, err));
+            }
+        }
+    }
+    if series.is_empty() && !errors.is_empty() {
+        return Err(errors.join("; "));
+    }
+    Ok(series)
+}
+
+impl CTSeries {
+    pub fn reference(&self) -> SeriesReference {
+        SeriesReference {
+            study_instance_uid: self.study_instance_uid.clone(),
+            series_instance_uid: self.series_instance_uid.clone(),
+            frame_of_reference_uid: self.frame_of_reference_uid.clone(),
+            sop_instance_uids: self.sop_instance_uids.clone(),
+            sop_class_uids: self.sop_class_uids.clone(),
+            slice_locations: self.info.get_slice_locations().cloned().unwrap_or_default(),
+            patient_study: self.patient_study.clone(),
+        }
+    }
+
+    fn from_slices(series_instance_uid: String, slices: Vec<DataSet>) -> Result<CTSeries, String> {
+        let first = slices.first().ok_or("empty series.")?;
+        let rows = first.i32(ROWS).ok_or("missing Rows.")?;
+        let columns = first.i32(COLUMNS).ok_or("missing Columns.")?;
+        let orientation = image_orientation(first)?;
+        let normal = cross(&orientation[0..3], &orientation[3..6]);
+        let pixel_spacing = first.f64s(PIXEL_SPACING).unwrap_or_else(|| vec![1.0, 1.0]);
+        if pixel_spacing.len() != 2 {
+            return Err(String::from("invalid PixelSpacing."));
+        }
+        let close = |a: &[f64], b: &[f64]| a.len() == b.len() && a.iter().zip(b).all(|(a, b)| (a - b).abs() < 1e-4);
+
+        let mut sorted = Vec::with_capacity(slices.len());
+        for ds in slices {
+            if ds.i32(ROWS) != Some(rows) || ds.i32(COLUMNS) != Some(columns) {
+                return Err(format!(
+                    "slices of series {} have different dimensions.",
+                    series_instance_uid
+                ));
+            }
+            if !close(&image_orientation(&ds)?, &orientation) {
+                return Err(format!(
+                    "slices of series {} have different orientations.",
+                    series_instance_uid
+                ));
+            }
+            if !close(&ds.f64s(PIXEL_SPACING).unwrap_or_else(|| vec![1.0, 1.0]), &pixel_spacing) {
+                return Err(format!(
+                    "slices of series {} have different pixel spacings.",
+                    series_instance_uid
+                ));
+            }
+            let location = match ds.f64s(IMAGE_POSITION_PATIENT) {
+                Some(p) if p.len() == 3 => dot(&p, &normal),
+                _ => ds
+                    .f64(SLICE_LOCATION)
+                    .ok_or("missing ImagePositionPatient and SliceLocation.")?,
+            };
+            sorted.push(Slice { ds, location });
+        }
+        if let Some(s) = sorted.iter().find(|s| !s.location.is_finite()) {
+            return Err(format!("invalid slice location {}.", s.location));
+        }
+        sorted.sort_by(|a, b| a.location.total_cmp(&b.location));
+        // a spacing of 0 between the slices cannot be reconstructed
+        if let Some(pair) = sorted.windows(2).find(|w| w[1].location - w[0].location < 1e-3) {
+            return Err(format!("duplicate slice location {}.", pair[0].location));
+        }
+
+        let first = &sorted[0].ds;
+        let depth = sorted.len();
+        let spacing_z = if depth > 1 {
+            (sorted[depth - 1].location - sorted[0].location) / (depth - 1) as f64
+        } else {
+            first.f64(SLICE_THICKNESS).unwrap_or(1.0)
+        };
+        // the volume is sampled as evenly spaced slices
+        let tolerance = (spacing_z * 0.01).max(1e-3);
+        if let Some(pair) = sorted
+            .windows(2)
+            .find(|w| (w[1].location - w[0].location - spacing_z).abs() > tolerance)
+        {
+            return Err(format!(
+                "uneven slice spacing of {} mm at {}, expected {} mm.",
+                pair[1].location - pair[0].location,
+                pair[0].location,
+                spacing_z
+            ));
+        }
+
+        let mut info = VolumeInfo::new(VolumeDataType::Int16, columns, rows, depth as i32);
+        // PixelSpacing is (row spacing, column spacing), i.e. (y, x)
+        info.set_spacing((
+            pixel_spacing[1] as f32,
+            pixel_spacing[0] as f32,
+            spacing_z as f32,
+        ));
+        info.set_slice_locations(sorted.iter().map(|s| s.location as f32).collect());
+
+        info.set_image_position(match first.f64s(IMAGE_POSITION_PATIENT) {
+            Some(p) if p.len() == 3 => [p[0] as f32, p[1] as f32, p[2] as f32],
+            _ => [0.0, 0.0, sorted[0].location as f32],
+        });
+        info.set_image_orientation(to_orientation(&orientation));
+
+        // The series is stored with the rescale of the first slice, the
+        // slices with a different rescale are mapped to it.
+        let slope = first.f64(RESCALE_SLOPE).unwrap_or(1.0);
+        let mut intercept = first.f64(RESCALE_INTERCEPT).unwrap_or(0.0);
+        let frame_len = (rows * columns) as usize;
+        let mut stored: Vec<i32> = Vec::with_capacity(frame_len * depth);
+        let mut sop_instance_uids = Vec::with_capacity(depth);
+        let mut sop_class_uids = Vec::with_capacity(depth);
+        for slice in &sorted {
+            let start = stored.len();
+            decode_frame(&slice.ds, frame_len, &mut stored)?;
+            let s = slice.ds.f64(RESCALE_SLOPE).unwrap_or(1.0);
+            let b = slice.ds.f64(RESCALE_INTERCEPT).unwrap_or(0.0);
+            if s != slope || b != intercept {
+                warn!("slices of series {} have different rescales.", series_instance_uid);
+                for v in &mut stored[start..] {
+                    *v = ((*v as f64 * s + b - intercept) / slope).round() as i32;
+                }
+            }
+            sop_instance_uids.push(slice.ds.string(SOP_INSTANCE_UID).unwrap_or_default());
+            sop_class_uids.push(sop_class(&slice.ds));
+        }
+
+        // Values out of the i16 range, e.g. unsigned ones above i16::MAX,
+        // are shifted into it and the intercept compensates for it. A range
+        // wider than i16 is stored in coarser steps of a larger slope.
+        let min = stored.iter().copied().min().unwrap_or(0);
+        let max = stored.iter().copied().max().unwrap_or(0);
+        // `origin` is stored as i16::MIN
+        let (origin, step) = if min >= i16::MIN as i32 && max <= i16::MAX as i32 {
+            (i16::MIN as i32, 1)
+        } else {
+            (min, (max - min) / 65536 + 1)
+        };
+        if step > 1 {
+            warn!(
+                "values of series {} span {} to {}, stored in steps of {}.",
+                series_instance_uid, min, max, step
+            );
+        }
+        let data: Vec<i16> = stored
+            .iter()
+            .map(|v| ((v - origin) / step + i16::MIN as i32) as i16)
+            .collect();
+        intercept += (origin - i16::MIN as i32 * step) as f64 * slope;
+        info.set_rescale((slope * step as f64) as f32, intercept as f32);
+
+        let mut patient_study = DataSet::new();
+        for tag in PATIENT_STUDY_TAGS {
+            if let Some(element) = first.get(tag) {
+                patient_study.insert(element.clone());
+            }
+        }
+
+        Ok(CTSeries {
+            study_instance_uid: first.string(STUDY_INSTANCE_UID).unwrap_or_default(),
+            series_instance_uid,
+            frame_of_reference_uid: first.string(FRAME_OF_REFERENCE_UID).unwrap_or_default(),
+            sop_instance_uids,
+            sop_class_uids,
+            info,
+            data,
+            patient_study,
+        })
+    }
+}
+
+// Append the stored values of the frame of `ds` to `data`.
+fn decode_frame(ds: &DataSet, frame_len: usize, data: &mut Vec<i32>) -> Result<(), String> {
+    let bits_allocated = ds.i32(BITS_ALLOCATED).unwrap_or(16);
+    let signed = ds.i32(PIXEL_REPRESENTATION).unwrap_or(0) == 1;
+    if ds.i32(SAMPLES_PER_PIXEL).unwrap_or(1) != 1 {
+        return Err(String::from("only single sample per pixel images are supported."));
+    }
+    let pixels = ds.bytes(PIXEL_DATA).ok_or("missing PixelData.")?;
+
+    let bytes_per_pixel = match bits_allocated {
+        8 => 1,
+        16 => 2,
+        _ => return Err(format!("unsupported BitsAllocated: {}", bits_allocated)),
+    };
+    if pixels.len() < frame_len * bytes_per_pixel {
+        return Err(String::from("PixelData is shorter than Rows * Columns."));
+    }
+
+    match (bytes_per_pixel, signed) {
+        (1, false) => data.extend(pixels[..frame_len].iter().map(|v| *v as i32)),
+        (1, true) => data.extend(pixels[..frame_len].iter().map(|v| *v as i8 as i32)),
+        (_, false) => data.extend(
+            pixels
+                .chunks_exact(2)
+                .take(frame_len)
+                .map(|c| u16::from_le_bytes([c[0], c[1]]) as i32),
+        ),
+        (_, true) => data.extend(
+            pixels
+                .chunks_exact(2)
+                .take(frame_len)
+                .map(|c| i16::from_le_bytes([c[0], c[1]]) as i32),
+        ),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::dicom::test_utils::*;
+
+    fn ct_slice(series: &str, sop: &str, z: f64, pixels: &[u16]) -> Vec<u8> {
+        ct_slice_with_intercept(series, sop, z, pixels, "-1024")
+    }
+
+    fn ct_slice_with_intercept(series: &str, sop: &str, z: f64, pixels: &[u16], intercept: &str) -> Vec<u8> {
+        ct_slice_with(series, sop, z, pixels, intercept, b"1\\0\\0\\0\\1\\0", b"0.5\\0.25")
+    }
+
+    fn ct_slice_with(
+        series: &str,
+        sop: &str,
+        z: f64,
+        pixels: &[u16],
+        intercept: &str,
+        orientation: &[u8],
+        spacing: &[u8],
+    ) -> Vec<u8> {
+        let mut body = Vec::new();
+        push_element(&mut body, true, SOP_INSTANCE_UID, b"UI", sop.as_bytes());
+        push_element(&mut body, true, SERIES_INSTANCE_UID, b"UI", series.as_bytes());
+        let ipp = format!("-10\\-20\\{}", z);
+        push_element(&mut body, true, IMAGE_POSITION_PATIENT, b"DS", ipp.as_bytes());
+        push_element(&mut body, true, IMAGE_ORIENTATION_PATIENT, b"DS", orientation);
+        push_element(&mut body, true, ROWS, b"US", &2u16.to_le_bytes());
+        push_element(&mut body, true, COLUMNS, b"US", &3u16.to_le_bytes());
+        push_element(&mut body, true, PIXEL_SPACING, b"DS", spacing);
+        push_element(&mut body, true, BITS_ALLOCATED, b"US", &16u16.to_le_bytes());
+        push_element(&mut body, true, PIXEL_REPRESENTATION, b"US", &0u16.to_le_bytes());
+        push_element(&mut body, true, RESCALE_INTERCEPT, b"DS", intercept.as_bytes());
+        push_element(&mut body, true, RESCALE_SLOPE, b"DS", b"1");
+        let data: Vec<u8> = pixels.iter().flat_map(|v| v.to_le_bytes()).collect();
+        push_element(&mut body, true, PIXEL_DATA, b"OW", &data);
+        part10(true, &body)
+    }
+
+    #[test]
+    fn test_load_ct_series() {
+        let f0 = ct_slice("1.2.3", "1.2.3.1", 5.0, &[1024, 1025, 1026, 1027, 1028, 1029]);
+        let f1 = ct_slice("1.2.3", "1.2.3.2", -5.0, &[0, 1, 2, 3, 4, 5]);
+        let f2 = ct_slice("1.2.3", "1.2.3.3", 0.0, &[2024; 6]);
+        let f3 = ct_slice("1.2.4", "1.2.4.1", 0.0, &[0; 6]);
+        let junk = vec![1u8, 2, 3];
+        let files: Vec<&[u8]> = vec![&f0, &junk, &f1, &f3, &f2];
+
+        let series = load_ct_series(&files).unwrap();
+        assert_eq!(series.len(), 2);
+
+        let s = &series[0];
+        assert_eq!(s.series_instance_uid, "1.2.3");
+        assert_eq!(s.info.get_dimension(), (3, 2, 3));
+        assert_eq!(s.info.get_spacing(), Some((0.25, 0.5, 5.0)));
+        assert_eq!(s.info.get_slice_locations(), Some(&vec![-5.0, 0.0, 5.0]));
+        assert_eq!(s.info.get_image_position().unwrap()[2], -5.0);
+        assert_eq!(s.sop_instance_uids, vec!["1.2.3.2", "1.2.3.3", "1.2.3.1"]);
+        assert_eq!(s.sop_class_uids, vec![SOP_CLASS_CT_IMAGE; 3]);
+        assert_eq!(s.info.get_rescale(), (1.0, -1024.0));
+        assert_eq!(s.data.len(), 18);
+        assert_eq!(&s.data[0..6], &[0, 1, 2, 3, 4, 5]);
+        assert_eq!(&s.data[6..12], &[2024; 6]);
+        assert_eq!(&s.data[12..18], &[1024, 1025, 1026, 1027, 1028, 1029]);
+        assert_eq!(s.info.to_real_value(s.data[0] as f32), -1024.0);
+
+        assert_eq!(series[1].info.get_dimension(), (3, 2, 1));
+    }
+
+    #[test]
+    fn test_load_unsigned_series() {
+        let f0 = ct_slice_with_intercept("1.2.3", "1.2.3.1", 0.0, &[0, 1, 2, 3, 4, 65535], "-1024");
+        let f1 = ct_slice_with_intercept("1.2.3", "1.2.3.2", 1.0, &[0; 6], "-1000");
+        let files: Vec<&[u8]> = vec![&f0, &f1];
+
+        let series = load_ct_series(&files).unwrap();
+        let s = &series[0];
+        assert_eq!(s.info.get_rescale(), (1.0, 31744.0));
+        let real: Vec<f32> = s.data.iter().map(|v| s.info.to_real_value(*v as f32)).collect();
+        assert_eq!(&real[0..6], &[-1024.0, -1023.0, -1022.0, -1021.0, -1020.0, 64511.0]);
+        assert_eq!(&real[6..12], &[-1000.0; 6]);
+    }
+
+    #[test]
+    fn test_load_wide_range_series() {
+        // -40000 to 65535 do not fit in 16 bits, they are stored in steps of 2
+        let f0 = ct_slice_with_intercept("1.2.3", "1.2.3.1", 0.0, &[0, 1, 2, 3, 4, 65535], "0");
+        let f1 = ct_slice_with_intercept("1.2.3", "1.2.3.2", 1.0, &[0; 6], "-40000");
+        let files: Vec<&[u8]> = vec![&f0, &f1];
+
+        let series = load_ct_series(&files).unwrap();
+        let s = &series[0];
+        assert_eq!(s.info.get_rescale().0, 2.0);
+        let real: Vec<f32> = s.data.iter().map(|v| s.info.to_real_value(*v as f32)).collect();
+        for (real, expected) in real.iter().zip([0.0, 1.0, 2.0, 3.0, 4.0, 65535.0, -40000.0]) {
+            assert!((real - expected).abs() <= 1.0, "{} != {}", real, expected);
+        }
+    }
+
+    #[test]
+    fn test_reject_inconsistent_series() {
+        let f0 = ct_slice("1.2.3", "1.2.3.1", 0.0, &[0; 6]);
+        let f1 = ct_slice("1.2.3", "1.2.3.2", 1.0, &[0; 6]);
+        let tilted = ct_slice_with("1.2.3", "1.2.3.3", 2.0, &[0; 6], "0", b"1\\0\\0\\0\\0.8\\0.6", b"0.5\\0.25");
+        let err = load_ct_series(&[&f0, &f1, &tilted]).err().unwrap();
+        assert!(err.contains("different orientations"), "{}", err);
+
+        let coarse = ct_slice_with("1.2.3", "1.2.3.3", 2.0, &[0; 6], "0", b"1\\0\\0\\0\\1\\0", b"1\\1");
+        let err = load_ct_series(&[&f0, &f1, &coarse]).err().unwrap();
+        assert!(err.contains("different pixel spacings"), "{}", err);
+
+        // a missing slice leaves a gap of 2 mm among the gaps of 1 mm
+        let f3 = ct_slice("1.2.3", "1.2.3.4", 3.0, &[0; 6]);
+        let err = load_ct_series(&[&f0, &f1, &f3]).err().unwrap();
+        assert!(err.contains("uneven slice spacing"), "{}", err);
+    }
+
+    #[test]
+    fn test_skip_other_objects() {
+        let f0 = ct_slice("1.2.3", "1.2.3.1", 0.0, &[0; 6]);
+        let f1 = ct_slice("1.2.3", "1.2.3.2", 1.0, &[0; 6]);
+        // a dose grid and a multi-frame image with pixels are not slices
+        let mut body = Vec::new();
+        push_element(&mut body, true, SOP_CLASS_UID, b"UI", SOP_CLASS_RT_DOSE.as_bytes());
+        push_element(&mut body, true, SERIES_INSTANCE_UID, b"UI", b"1.2.5");
+        push_element(&mut body, true, PIXEL_DATA, b"OW", &[0; 12]);
+        let dose = part10(true, &body);
+        let mut body = Vec::new();
+        push_element(&mut body, true, SOP_CLASS_UID, b"UI", SOP_CLASS_CT_IMAGE.as_bytes());
+        push_element(&mut body, true, SERIES_INSTANCE_UID, b"UI", b"1.2.6");
+        push_element(&mut body, true, NUMBER_OF_FRAMES, b"IS", b"2 ");
+        push_element(&mut body, true, PIXEL_DATA, b"OW", &[0; 24]);
+        let frames = part10(true, &body);
+        // a series with two slices at the same location is skipped
+        let d0 = ct_slice("1.2.7", "1.2.7.1", 0.0, &[0; 6]);
+        let d1 = ct_slice("1.2.7", "1.2.7.2", 0.0, &[0; 6]);
+        let files: Vec<&[u8]> = vec![&f0, &dose, &f1, &frames, &d0, &d1];
+
+        let series = load_ct_series(&files).unwrap();
+        assert_eq!(series.len(), 1);
+        assert_eq!(series[0].series_instance_uid, "1.2.3");
+        assert_eq!(series[0].info.get_spacing(), Some((0.25, 0.5, 1.0)));
+
+        let err = load_ct_series(&[&d0, &d1]).err().unwrap();
+        assert!(err.contains("duplicate slice location"), "{}", err);
+    }
+}
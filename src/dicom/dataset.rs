@@ -0,0 +1,476 @@
+// MIT License
+
+// Copyright (c) 2023 Techtron-Lab
+
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+
+use std::collections::BTreeMap;
+
+use super::tag::*;
+
+const UNDEFINED_LENGTH: u32 = 0xFFFF_FFFF;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Bytes(Vec<u8>),
+    Sequence(Vec<DataSet>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Element {
+    pub tag: Tag,
+    pub vr: VR,
+    pub value: Value,
+}
+
+// A DICOM data set, which is also used for the items of a sequence.
+// Only little endian transfer syntaxes are supported, so numeric values are
+// always stored in little endian.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DataSet {
+    elements: BTreeMap<Tag, Element>,
+}
+
+impl DataSet {
+    pub fn new() -> DataSet {
+        DataSet {
+            elements: BTreeMap::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.elements.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.elements.is_empty()
+    }
+
+    pub fn insert(&mut self, element: Element) {
+        self.elements.insert(element.tag, element);
+    }
+
+    pub fn get(&self, tag: Tag) -> Option<&Element> {
+        self.elements.get(&tag)
+    }
+
+    pub fn contains(&self, tag: Tag) -> bool {
+        self.elements.contains_key(&tag)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Element> {
+        self.elements.values()
+    }
+
+    // The setters below take the VR from the dictionary of this module.
+    pub fn set_bytes(&mut self, tag: Tag, bytes: Vec<u8>) {
+        self.insert(Element {
+            tag,
+            vr: dictionary_vr(tag),
+            value: Value::Bytes(bytes),
+        });
+    }
+
+    pub fn set_string(&mut self, tag: Tag, value: &str) {
+        self.set_bytes(tag, value.as_bytes().to_vec());
+    }
+
+    // A multi-valued string, the values are joined by `\`.
+    pub fn set_strings(&mut self, tag: Tag, values: &[String]) {
+        self.set_string(tag, &values.join("\\"));
+    }
+
+    // Decimal strings rounded to 1/1000, which keeps positions in millimeter
+    // within the 16 characters of a DS value.
+    pub fn set_decimals(&mut self, tag: Tag, values: &[f64]) {
+        let values: Vec<String> = values.iter().map(|v| format_decimal(*v)).collect();
+        self.set_strings(tag, &values);
+    }
+
+    pub fn set_integers(&mut self, tag: Tag, values: &[i32]) {
+        let values: Vec<String> = values.iter().map(|v| v.to_string()).collect();
+        self.set_strings(tag, &values);
+    }
+
+    pub fn set_sequence(&mut self, tag: Tag, items: Vec<DataSet>) {
+        self.insert(Element {
+            tag,
+            vr: VR::SQ,
+            value: Value::Sequence(items),
+        });
+    }
+
+    pub fn bytes(&self, tag: Tag) -> Option<&[u8]> {
+        match &self.get(tag)?.value {
+            Value::Bytes(v) => Some(v.as_slice()),
+            Value::Sequence(_) => None,
+        }
+    }
+
+    pub fn sequence(&self, tag: Tag) -> Option<&[DataSet]> {
+        match &self.get(tag)?.value {
+            Value::Sequence(v) => Some(v.as_slice()),
+            Value::Bytes(_) => None,
+        }
+    }
+
+    // The value as a string with the padding removed.
+    pub fn string(&self, tag: Tag) -> Option<String> {
+        let bytes = self.bytes(tag)?;
+        let s = String::from_utf8_lossy(bytes);
+        Some(s.trim_end_matches([' ', '\0']).to_string())
+    }
+
+    // The values of a multi-valued string such as `1.0\2.0\3.0`.
+    pub fn strings(&self, tag: Tag) -> Option<Vec<String>> {
+        let s = self.string(tag)?;
+        Some(s.split('\\').map(|v| v.trim().to_string()).collect())
+    }
+
+    // Numeric values, either decoded from the text VRs (DS, IS) or the binary ones.
+    pub fn f64s(&self, tag: Tag) -> Option<Vec<f64>> {
+        let element = self.get(tag)?;
+        let bytes = self.bytes(tag)?;
+        let values = match element.vr {
+            VR::FD | VR::OD => bytes
+                .chunks_exact(8)
+                .map(|c| f64::from_le_bytes(c.try_into().unwrap()))
+                .collect(),
+            VR::FL | VR::OF => bytes
+                .chunks_exact(4)
+                .map(|c| f32::from_le_bytes(c.try_into().unwrap()) as f64)
+                .collect(),
+            VR::US | VR::OW => bytes
+                .chunks_exact(2)
+                .map(|c| u16::from_le_bytes(c.try_into().unwrap()) as f64)
+                .collect(),
+            VR::SS => bytes
+                .chunks_exact(2)
+                .map(|c| i16::from_le_bytes(c.try_into().unwrap()) as f64)
+                .collect(),
+            VR::UL | VR::OL => bytes
+                .chunks_exact(4)
+                .map(|c| u32::from_le_bytes(c.try_into().unwrap()) as f64)
+                .collect(),
+            VR::SL => bytes
+                .chunks_exact(4)
+                .map(|c| i32::from_le_bytes(c.try_into().unwrap()) as f64)
+                .collect(),
+            _ => {
+                let mut values = Vec::new();
+                for s in self.strings(tag)? {
+                    if s.is_empty() {
+                        continue;
+                    }
+                    values.push(s.parse::<f64>().ok()?);
+                }
+                values
+            }
+        };
+        Some(values)
+    }
+
+    pub fn f64(&self, tag: Tag) -> Option<f64> {
+        self.f64s(tag)?.first().copied()
+    }
+
+    pub fn f32s(&self, tag: Tag) -> Option<Vec<f32>> {
+        Some(self.f64s(tag)?.iter().map(|v| *v as f32).collect())
+    }
+
+    pub fn f32(&self, tag: Tag) -> Option<f32> {
+        self.f64(tag).map(|v| v as f32)
+    }
+
+    pub fn i32(&self, tag: Tag) -> Option<i32> {
+        self.f64(tag).map(|v| v.round() as i32)
+    }
+}
+
+fn format_decimal(v: f64) -> String {
+    let s = format!("{:.3}", v);
+    let s = s.trim_end_matches('0').trim_end_matches('.');
+    if s == "-0" {
+        String::from("0")
+    } else {
+        s.to_string()
+    }
+}
+
+struct Reader<'a> {
+    buf: &'a [u8],
+    pos: usize,
+    explicit_vr: bool,
+}
+
+impl<'a> Reader<'a> {
+    fn new(buf: &'a [u8], pos: usize, explicit_vr: bool) -> Reader<'a> {
+        Reader {
+            buf,
+            pos,
+            explicit_vr,
+        }
+    }
+
+    fn is_eof(&self) -> bool {
+        self.pos >= self.buf.len()
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], String> {
+        if self.pos + len > self.buf.len() {
+            return Err(format!(
+                "unexpected end of data at offset {} (need {} bytes).",
+                self.pos, len
+            ));
+        }
+        let s = &self.buf[self.pos..self.pos + len];
+        self.pos += len;
+        Ok(s)
+    }
+
+    fn u16(&mut self) -> Result<u16, String> {
+        let b = self.take(2)?;
+        Ok(u16::from_le_bytes([b[0], b[1]]))
+    }
+
+    fn u32(&mut self) -> Result<u32, String> {
+        let b = self.take(4)?;
+        Ok(u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    fn tag(&mut self) -> Result<Tag, String> {
+        let group = self.u16()?;
+        let element = self.u16()?;
+        Ok(Tag(group, element))
+    }
+
+    fn peek_tag(&self) -> Option<Tag> {
+        if self.pos + 4 > self.buf.len() {
+            return None;
+        }
+        let b = &self.buf[self.pos..self.pos + 4];
+        Some(Tag(
+            u16::from_le_bytes([b[0], b[1]]),
+            u16::from_le_bytes([b[2], b[3]]),
+        ))
+    }
+
+    // Read the data set until `end` or, when `end` is None, until an item
+    // delimitation item.
+    fn data_set(&mut self, end: Option<usize>) -> Result<DataSet, String> {
+        let mut ds = DataSet::new();
+        loop {
+            match end {
+                Some(end) if self.pos >= end => break,
+                None if self.is_eof() => return Err(String::from("missing item delimitation.")),
+                _ => (),
+            }
+            if self.peek_tag() == Some(ITEM_DELIMITATION_ITEM) {
+                self.tag()?;
+                self.u32()?;
+                break;
+            }
+            let element = self.element()?;
+            ds.insert(element);
+        }
+        Ok(ds)
+    }
+
+    fn element(&mut self) -> Result<Element, String> {
+        let tag = self.tag()?;
+        let (vr, len) = if self.explicit_vr {
+            let b = self.take(2)?;
+            let vr = VR::from_bytes([b[0], b[1]])
+                .ok_or(format!("invalid VR {:?} of {}.", b, tag))?;
+            let len = if vr.has_long_length() {
+                self.take(2)?;
+                self.u32()?
+            } else {
+                self.u16()? as u32
+            };
+            (vr, len)
+        } else {
+            let len = self.u32()?;
+            let vr = dictionary_vr(tag);
+            // A tag missing in the dictionary is a sequence if it has an
+            // undefined length or its value starts with an item.
+            if vr == VR::UN
+                && tag != PIXEL_DATA
+                && (len == UNDEFINED_LENGTH || self.peek_tag() == Some(ITEM))
+            {
+                (VR::SQ, len)
+            } else {
+                (vr, len)
+            }
+        };
+
+        if vr == VR::SQ || (vr == VR::UN && len == UNDEFINED_LENGTH) {
+            // Sequences of unknown VR are always encoded in implicit VR.
+            let explicit_vr = self.explicit_vr;
+            if vr == VR::UN {
+                self.explicit_vr = false;
+            }
+            let items = self.sequence_items(len);
+            self.explicit_vr = explicit_vr;
+            return Ok(Element {
+                tag,
+                vr: VR::SQ,
+                value: Value::Sequence(items?),
+            });
+        }
+
+        if len == UNDEFINED_LENGTH {
+            return Err(format!(
+                "encapsulated (compressed) data of {} is not supported.",
+                tag
+            ));
+        }
+        let bytes = self.take(len as usize)?.to_vec();
+        Ok(Element {
+            tag,
+            vr,
+            value: Value::Bytes(bytes),
+        })
+    }
+
+    fn sequence_items(&mut self, len: u32) -> Result<Vec<DataSet>, String> {
+        let end = if len == UNDEFINED_LENGTH {
+            None
+        } else {
+            Some(self.pos + len as usize)
+        };
+        let mut items = Vec::new();
+        loop {
+            match end {
+                Some(end) if self.pos >= end => break,
+                None if self.is_eof() => {
+                    return Err(String::from("missing sequence delimitation."))
+                }
+                _ => (),
+            }
+            let tag = self.tag()?;
+            let item_len = self.u32()?;
+            match tag {
+                ITEM => {
+                    let item = if item_len == UNDEFINED_LENGTH {
+                        self.data_set(None)?
+                    } else {
+                        let item_end = self.pos + item_len as usize;
+                        self.data_set(Some(item_end))?
+                    };
+                    items.push(item);
+                }
+                SEQUENCE_DELIMITATION_ITEM => break,
+                _ => return Err(format!("unexpected {} in a sequence.", tag)),
+            }
+        }
+        Ok(items)
+    }
+}
+
+// Parse a DICOM Part 10 file. Files without the 128 byte preamble are treated
+// as a bare data set in implicit VR little endian.
+pub fn parse(buf: &[u8]) -> Result<DataSet, String> {
+    let has_preamble = buf.len() >= 132 && &buf[128..132] == b"DICM";
+    if !has_preamble {
+        let mut reader = Reader::new(buf, 0, false);
+        return reader.data_set(Some(buf.len()));
+    }
+
+    // The file meta information is always in explicit VR little endian.
+    let mut reader = Reader::new(buf, 132, true);
+    let mut ds = DataSet::new();
+    while reader.peek_tag().map(|t| t.group() == 0x0002) == Some(true) {
+        let element = reader.element()?;
+        ds.insert(element);
+    }
+
+    let transfer_syntax = ds
+        .string(TRANSFER_SYNTAX_UID)
+        .unwrap_or_else(|| String::from(TRANSFER_SYNTAX_IMPLICIT_LE));
+    reader.explicit_vr = match transfer_syntax.as_str() {
+        TRANSFER_SYNTAX_IMPLICIT_LE => false,
+        TRANSFER_SYNTAX_EXPLICIT_LE => true,
+        _ => return Err(format!("unsupported transfer syntax: {}", transfer_syntax)),
+    };
+    let end = buf.len();
+    let body = reader.data_set(Some(end))?;
+    for element in body.elements.into_values() {
+        ds.insert(element);
+    }
+    Ok(ds)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::dicom::test_utils::*;
+
+    #[test]
+    fn test_parse_explicit_vr() {
+        let mut body = Vec::new();
+        push_element(&mut body, true, PATIENT_NAME, b"PN", b"Doe^John");
+        push_element(&mut body, true, ROWS, b"US", &512u16.to_le_bytes());
+        push_element(&mut body, true, PIXEL_SPACING, b"DS", b"0.5\\0.75");
+        let buf = part10(true, &body);
+
+        let ds = parse(&buf).unwrap();
+        assert_eq!(ds.string(PATIENT_NAME).unwrap(), "Doe^John");
+        assert_eq!(ds.i32(ROWS), Some(512));
+        assert_eq!(ds.f64s(PIXEL_SPACING), Some(vec![0.5, 0.75]));
+        assert_eq!(
+            ds.string(TRANSFER_SYNTAX_UID).unwrap(),
+            TRANSFER_SYNTAX_EXPLICIT_LE
+        );
+    }
+
+    #[test]
+    fn test_parse_sequence() {
+        let roi_contour_sequence = Tag(0x3006, 0x0039);
+        let roi_number = Tag(0x3006, 0x0084);
+
+        // explicit VR, undefined length sequence with undefined length items
+        let mut item = Vec::new();
+        push_element(&mut item, true, roi_number, b"IS", b"7 ");
+        let mut body = Vec::new();
+        push_sequence(&mut body, true, roi_contour_sequence, &[item.clone(), item], true);
+        let ds = parse(&part10(true, &body)).unwrap();
+        let seq = ds.sequence(roi_contour_sequence).unwrap();
+        assert_eq!(seq.len(), 2);
+        assert_eq!(seq[1].i32(roi_number), Some(7));
+
+        // implicit VR, defined length
+        let mut item = Vec::new();
+        push_element(&mut item, false, roi_number, b"IS", b"3 ");
+        let mut body = Vec::new();
+        push_sequence(&mut body, false, roi_contour_sequence, &[item], false);
+        let ds = parse(&part10(false, &body)).unwrap();
+        let seq = ds.sequence(roi_contour_sequence).unwrap();
+        assert_eq!(seq.len(), 1);
+        assert_eq!(seq[0].i32(roi_number), Some(3));
+    }
+
+    #[test]
+    fn test_parse_unsupported() {
+        let buf = part10_with_transfer_syntax("1.2.840.10008.1.2.4.50", &[]);
+        assert!(parse(&buf).is_err());
+        assert!(parse(&[0u8; 10]).is_err());
+    }
+}
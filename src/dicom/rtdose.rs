@@ -0,0 +1,233 @@
+// MIT License
+
+// Copyright (c) 2023 Techtron-Lab
+
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+
+use super::dataset::{parse, DataSet};
+use super::tag::*;
+use super::{cross, dot, image_orientation, to_orientation};
+use crate::modal::{VolumeDataType, VolumeInfo};
+
+// A dose (or any other scalar field) grid stored as a DICOM RTDOSE object.
+#[derive(Clone)]
+pub struct RTDose {
+    pub series_instance_uid: String,
+    pub frame_of_reference_uid: String,
+    pub dose_units: String,
+    pub dose_type: String,
+    pub info: VolumeInfo,
+    // Dose values with DoseGridScaling applied, frame by frame along the
+    // slice normal.
+    pub data: Vec<f32>,
+}
+
+pub fn load_rtdose(buf: &[u8]) -> Result<RTDose, String> {
+    let ds = parse(buf)?;
+    RTDose::from_data_set(&ds)
+}
+
+impl RTDose {
+    pub fn from_data_set(ds: &DataSet) -> Result<RTDose, String> {
+        let modality = ds.string(MODALITY).unwrap_or_default();
+        let sop_class = ds.string(SOP_CLASS_UID).unwrap_or_default();
+        if modality != "RTDOSE" && sop_class != SOP_CLASS_RT_DOSE {
+            return Err(format!("not an RTDOSE object (modality: {}).", modality));
+        }
+
+        let rows = ds.i32(ROWS).ok_or("missing Rows.")?;
+        let columns = ds.i32(COLUMNS).ok_or("missing Columns.")?;
+        let frames = ds.i32(NUMBER_OF_FRAMES).unwrap_or(1);
+        if frames < 1 {
+            return Err(format!("invalid NumberOfFrames: {}", frames));
+        }
+        let pixel_spacing = ds.f64s(PIXEL_SPACING).ok_or("missing PixelSpacing.")?;
+        if pixel_spacing.len() != 2 {
+            return Err(String::from("invalid PixelSpacing."));
+        }
+        let position = match ds.f64s(IMAGE_POSITION_PATIENT) {
+            Some(p) if p.len() == 3 => p,
+            _ => return Err(String::from("missing ImagePositionPatient.")),
+        };
+        let orientation = image_orientation(ds)?;
+        let normal = cross(&orientation[0..3], &orientation[3..6]);
+        let scaling = ds.f64(DOSE_GRID_SCALING).unwrap_or(1.0);
+
+        let offsets = match ds.f64s(GRID_FRAME_OFFSET_VECTOR) {
+            Some(v) => v,
+            None if frames == 1 => vec![0.0],
+            None => return Err(String::from("missing GridFrameOffsetVector.")),
+        };
+        if offsets.iter().any(|v| !v.is_finite()) {
+            return Err(String::from("invalid GridFrameOffsetVector."));
+        }
+        if offsets.len() != frames as usize {
+            return Err(format!(
+                "GridFrameOffsetVector has {} values for {} frames.",
+                offsets.len(),
+                frames
+            ));
+        }
+        // The offsets are relative to ImagePositionPatient if the first one is
+        // zero, otherwise they are the absolute positions along the normal.
+        let origin = dot(&position, &normal);
+        let locations: Vec<f64> = if offsets[0] == 0.0 {
+            offsets.iter().map(|v| origin + v).collect()
+        } else {
+            offsets.clone()
+        };
+
+        let frame_len = (rows * columns) as usize;
+        let values = decode_pixels(ds, frame_len * frames as usize)?;
+
+        // Frames are stored in the order of the offsets which can be
+        // descending, while the textures need ascending locations.
+        let mut order: Vec<usize> = (0..frames as usize).collect();
+        order.sort_by(|a, b| locations[*a].total_cmp(&locations[*b]));
+        let mut data = Vec::with_capacity(values.len());
+        for i in &order {
+            let frame = &values[i * frame_len..(i + 1) * frame_len];
+            data.extend(frame.iter().map(|v| (v * scaling) as f32));
+        }
+        let sorted: Vec<f32> = order.iter().map(|i| locations[*i] as f32).collect();
+        // a spacing of 0 between the frames cannot be reconstructed
+        if let Some(pair) = sorted.windows(2).find(|w| w[1] - w[0] < 1e-3) {
+            return Err(format!("duplicate frame offset at {}.", pair[0]));
+        }
+
+        let depth = sorted.len();
+        let spacing_z = if depth > 1 {
+            (sorted[depth - 1] - sorted[0]) / (depth - 1) as f32
+        } else {
+            ds.f32(SLICE_THICKNESS).unwrap_or(1.0)
+        };
+        let mut info = VolumeInfo::new(VolumeDataType::Float32, columns, rows, frames);
+        info.set_spacing((pixel_spacing[1] as f32, pixel_spacing[0] as f32, spacing_z));
+        info.set_slice_locations(sorted);
+
+        // position of the first frame after sorting
+        let first = order[0];
+        let shift = (locations[first] - origin) as f32;
+        info.set_image_position([
+            position[0] as f32 + shift * normal[0] as f32,
+            position[1] as f32 + shift * normal[1] as f32,
+            position[2] as f32 + shift * normal[2] as f32,
+        ]);
+        info.set_image_orientation(to_orientation(&orientation));
+
+        Ok(RTDose {
+            series_instance_uid: ds.string(SERIES_INSTANCE_UID).unwrap_or_default(),
+            frame_of_reference_uid: ds.string(FRAME_OF_REFERENCE_UID).unwrap_or_default(),
+            dose_units: ds.string(DOSE_UNITS).unwrap_or_default(),
+            dose_type: ds.string(DOSE_TYPE).unwrap_or_default(),
+            info,
+            data,
+        })
+    }
+}
+
+// Decode `len` stored values of the pixel data.
+fn decode_pixels(ds: &DataSet, len: usize) -> Result<Vec<f64>, String> {
+    let bits_allocated = ds.i32(BITS_ALLOCATED).unwrap_or(16);
+    let signed = ds.i32(PIXEL_REPRESENTATION).unwrap_or(0) == 1;
+    let pixels = ds.bytes(PIXEL_DATA).ok_or("missing PixelData.")?;
+    let bytes_per_pixel = match bits_allocated {
+        16 => 2,
+        32 => 4,
+        _ => return Err(format!("unsupported BitsAllocated: {}", bits_allocated)),
+    };
+    if pixels.len() < len * bytes_per_pixel {
+        return Err(String::from("PixelData is shorter than the dose grid."));
+    }
+    let chunks = pixels.chunks_exact(bytes_per_pixel).take(len);
+    let values = match (bytes_per_pixel, signed) {
+        (2, false) => chunks.map(|c| u16::from_le_bytes([c[0], c[1]]) as f64).collect(),
+        (2, true) => chunks.map(|c| i16::from_le_bytes([c[0], c[1]]) as f64).collect(),
+        (_, false) => chunks
+            .map(|c| u32::from_le_bytes([c[0], c[1], c[2], c[3]]) as f64)
+            .collect(),
+        (_, true) => chunks
+            .map(|c| i32::from_le_bytes([c[0], c[1], c[2], c[3]]) as f64)
+            .collect(),
+    };
+    Ok(values)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::dicom::test_utils::*;
+
+    fn rtdose(offsets: &[u8], position: &[u8]) -> Vec<u8> {
+        rtdose_frames(offsets, position, b"3")
+    }
+
+    fn rtdose_frames(offsets: &[u8], position: &[u8], frames: &[u8]) -> Vec<u8> {
+        let mut body = Vec::new();
+        push_element(&mut body, true, MODALITY, b"CS", b"RTDOSE");
+        push_element(&mut body, true, IMAGE_POSITION_PATIENT, b"DS", position);
+        push_element(&mut body, true, NUMBER_OF_FRAMES, b"IS", frames);
+        push_element(&mut body, true, ROWS, b"US", &1u16.to_le_bytes());
+        push_element(&mut body, true, COLUMNS, b"US", &2u16.to_le_bytes());
+        push_element(&mut body, true, PIXEL_SPACING, b"DS", b"2.5\\2.0");
+        push_element(&mut body, true, BITS_ALLOCATED, b"US", &32u16.to_le_bytes());
+        push_element(&mut body, true, PIXEL_REPRESENTATION, b"US", &0u16.to_le_bytes());
+        push_element(&mut body, true, DOSE_UNITS, b"CS", b"GY");
+        push_element(&mut body, true, GRID_FRAME_OFFSET_VECTOR, b"DS", offsets);
+        push_element(&mut body, true, DOSE_GRID_SCALING, b"DS", b"0.5");
+        let data: Vec<u8> = [0u32, 2, 4, 6, 8, 10].iter().flat_map(|v| v.to_le_bytes()).collect();
+        push_element(&mut body, true, PIXEL_DATA, b"OB", &data);
+        part10(true, &body)
+    }
+
+    #[test]
+    fn test_load_rtdose() {
+        let dose = load_rtdose(&rtdose(b"0\\3\\6", b"-5\\-2.5\\-10")).unwrap();
+        assert_eq!(dose.dose_units, "GY");
+        assert_eq!(dose.info.get_dimension(), (2, 1, 3));
+        assert_eq!(dose.info.get_spacing(), Some((2.0, 2.5, 3.0)));
+        assert_eq!(dose.info.get_slice_locations(), Some(&vec![-10.0, -7.0, -4.0]));
+        assert_eq!(dose.info.get_image_position(), Some([-5.0, -2.5, -10.0]));
+        assert_eq!(dose.data, vec![0.0, 1.0, 2.0, 3.0, 4.0, 5.0]);
+    }
+
+    #[test]
+    fn test_load_rtdose_descending_offsets() {
+        let dose = load_rtdose(&rtdose(b"0\\-3\\-6", b"0\\0\\10")).unwrap();
+        assert_eq!(dose.info.get_slice_locations(), Some(&vec![4.0, 7.0, 10.0]));
+        assert_eq!(dose.info.get_image_position(), Some([0.0, 0.0, 4.0]));
+        assert_eq!(dose.data, vec![4.0, 5.0, 2.0, 3.0, 0.0, 1.0]);
+
+        // absolute offsets
+        let dose = load_rtdose(&rtdose(b"10\\13\\16", b"0\\0\\10")).unwrap();
+        assert_eq!(dose.info.get_slice_locations(), Some(&vec![10.0, 13.0, 16.0]));
+    }
+
+    #[test]
+    fn test_load_rtdose_invalid_offsets() {
+        assert!(load_rtdose(&rtdose(b"", b"0\\0\\10")).is_err());
+        assert!(load_rtdose(&rtdose(b"0\\3", b"0\\0\\10")).is_err());
+        assert!(load_rtdose(&rtdose(b"0\\NaN\\6", b"0\\0\\10")).is_err());
+        let err = load_rtdose(&rtdose(b"0\\6\\6", b"0\\0\\10")).err().unwrap();
+        assert!(err.contains("duplicate frame offset"), "{}", err);
+        assert!(load_rtdose(&rtdose(b"0\\0\\6", b"0\\0\\10")).is_err());
+        assert!(load_rtdose(&rtdose_frames(b"", b"0\\0\\10", b"0")).is_err());
+    }
+}
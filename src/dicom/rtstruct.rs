@@ -0,0 +1,350 @@
+// MIT License
+
+// Copyright (c) 2023 Techtron-Lab
+
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+
+use log::warn;
+use wasm_bindgen::prelude::*;
+
+use super::ct::SeriesReference;
+use super::dataset::{parse, DataSet};
+use super::tag::*;
+use super::writer::{generate_uid, write};
+use crate::techtron::graphic::contour::{Contour2Df32Builder, Structure, StructureSet};
+
+// Load the ROIs of a DICOM RTSTRUCT file. Only the closed planar contours are
+// kept, each one becomes a contour at the z of its first point.
+#[wasm_bindgen]
+pub fn load_rtstruct(buf: &[u8]) -> Result<StructureSet, String> {
+    let ds = parse(buf)?;
+    structure_set_from_data_set(&ds)
+}
+
+pub fn structure_set_from_data_set(ds: &DataSet) -> Result<StructureSet, String> {
+    let modality = ds.string(MODALITY).unwrap_or_default();
+    let sop_class = ds.string(SOP_CLASS_UID).unwrap_or_default();
+    if modality != "RTSTRUCT" && sop_class != SOP_CLASS_RT_STRUCTURE_SET {
+        return Err(format!("not an RTSTRUCT object (modality: {}).", modality));
+    }
+
+    let mut set = StructureSet::new();
+    let rois = ds
+        .sequence(STRUCTURE_SET_ROI_SEQUENCE)
+        .ok_or("missing StructureSetROISequence.")?;
+    for roi in rois {
+        let number = roi.i32(ROI_NUMBER).ok_or("missing ROINumber.")?;
+        let mut structure = Structure::new();
+        structure.set_number(number);
+        structure.set_name(roi.string(ROI_NAME).unwrap_or_default());
+        if set.get_frame_of_reference_uid().is_empty() {
+            if let Some(uid) = roi.string(REFERENCED_FRAME_OF_REFERENCE_UID) {
+                set.set_frame_of_reference_uid(uid);
+            }
+        }
+        if let Some(item) = find_roi_contour(ds, number) {
+            read_roi_contour(item, &mut structure)?;
+        }
+        set.push(structure);
+    }
+
+    if set.get_frame_of_reference_uid().is_empty() {
+        let uid = ds
+            .sequence(REFERENCED_FRAME_OF_REFERENCE_SEQUENCE)
+            .and_then(|items| items.first())
+            .and_then(|item| item.string(FRAME_OF_REFERENCE_UID));
+        if let Some(uid) = uid {
+            set.set_frame_of_reference_uid(uid);
+        }
+    }
+    Ok(set)
+}
+
+fn find_roi_contour(ds: &DataSet, number: i32) -> Option<&DataSet> {
+    ds.sequence(ROI_CONTOUR_SEQUENCE)?
+        .iter()
+        .find(|item| item.i32(REFERENCED_ROI_NUMBER) == Some(number))
+}
+
+fn read_roi_contour(item: &DataSet, structure: &mut Structure) -> Result<(), String> {
+    if let Some(color) = item.f64s(ROI_DISPLAY_COLOR) {
+        if color.len() == 3 {
+            let c: Vec<u8> = color.iter().map(|v| v.clamp(0.0, 255.0) as u8).collect();
+            structure.set_color(c[0], c[1], c[2]);
+        }
+    }
+
+    let contours = match item.sequence(CONTOUR_SEQUENCE) {
+        Some(v) => v,
+        None => return Ok(()),
+    };
+    for contour in contours {
+        let geometric_type = contour.string(CONTOUR_GEOMETRIC_TYPE).unwrap_or_default();
+        if geometric_type != "CLOSED_PLANAR" {
+            warn!("skip a {} contour of ROI {}", geometric_type, structure.get_number());
+            continue;
+        }
+        let data = contour.f32s(CONTOUR_DATA).ok_or("missing ContourData.")?;
+        if data.len() % 3 != 0 {
+            return Err(String::from("the length of ContourData is not a multiple of 3."));
+        }
+        if let Some(n) = contour.i32(NUMBER_OF_CONTOUR_POINTS) {
+            if n as usize * 3 != data.len() {
+                warn!("NumberOfContourPoints {} does not match ContourData", n);
+            }
+        }
+        if data.is_empty() {
+            continue;
+        }
+
+        let mut builder = Contour2Df32Builder::new();
+        for p in data.chunks_exact(3) {
+            builder.push(p[0], p[1]);
+        }
+        structure.push(data[2], builder.close());
+    }
+    Ok(())
+}
+
+// Write `set` as an RTSTRUCT file referencing the image series `series`. The
+// contours are expected in the patient coordinates of the series. ROIs without
+// a number, or with one used already, are numbered after the largest one.
+pub fn write_rtstruct(set: &StructureSet, series: &SeriesReference, label: &str) -> Result<Vec<u8>, String> {
+    let frame_of_reference_uid = &series.frame_of_reference_uid;
+    if !set.get_frame_of_reference_uid().is_empty() && set.get_frame_of_reference_uid() != *frame_of_reference_uid {
+        return Err(format!(
+            "the structure set is in the frame of reference {}, not in the one of the series.",
+            set.get_frame_of_reference_uid()
+        ));
+    }
+
+    let mut ds = series.patient_study.clone();
+    ds.set_string(SOP_CLASS_UID, SOP_CLASS_RT_STRUCTURE_SET);
+    ds.set_string(SOP_INSTANCE_UID, &generate_uid());
+    ds.set_string(MODALITY, "RTSTRUCT");
+    ds.set_string(SERIES_INSTANCE_UID, &generate_uid());
+    ds.set_string(SERIES_NUMBER, "");
+    ds.set_string(STRUCTURE_SET_LABEL, label);
+    ds.set_string(STRUCTURE_SET_DATE, "");
+    ds.set_string(STRUCTURE_SET_TIME, "");
+
+    // referenced frame of reference > study > series > images
+    let images: Vec<DataSet> = (0..series.sop_instance_uids.len()).map(|i| image_reference(series, i)).collect();
+    let mut referenced_series = DataSet::new();
+    referenced_series.set_string(SERIES_INSTANCE_UID, &series.series_instance_uid);
+    referenced_series.set_sequence(CONTOUR_IMAGE_SEQUENCE, images);
+    let mut referenced_study = DataSet::new();
+    referenced_study.set_string(REFERENCED_SOP_CLASS_UID, SOP_CLASS_STUDY_COMPONENT_MANAGEMENT);
+    referenced_study.set_string(REFERENCED_SOP_INSTANCE_UID, &series.study_instance_uid);
+    referenced_study.set_sequence(RT_REFERENCED_SERIES_SEQUENCE, vec![referenced_series]);
+    let mut referenced_frame = DataSet::new();
+    referenced_frame.set_string(FRAME_OF_REFERENCE_UID, frame_of_reference_uid);
+    referenced_frame.set_sequence(RT_REFERENCED_STUDY_SEQUENCE, vec![referenced_study]);
+    ds.set_sequence(REFERENCED_FRAME_OF_REFERENCE_SEQUENCE, vec![referenced_frame]);
+
+    let mut rois = Vec::new();
+    let mut roi_contours = Vec::new();
+    let mut observations = Vec::new();
+    let mut numbers: Vec<i32> = Vec::new();
+    let mut next_number = set.structures().iter().map(|s| s.get_number()).max().unwrap_or(0).max(0) + 1;
+    for structure in set.structures() {
+        let mut number = structure.get_number();
+        if number <= 0 || numbers.contains(&number) {
+            number = next_number;
+            next_number += 1;
+        }
+        numbers.push(number);
+
+        let mut roi = DataSet::new();
+        roi.set_integers(ROI_NUMBER, &[number]);
+        roi.set_string(REFERENCED_FRAME_OF_REFERENCE_UID, frame_of_reference_uid);
+        roi.set_string(ROI_NAME, &structure.get_name());
+        roi.set_string(ROI_GENERATION_ALGORITHM, "");
+        rois.push(roi);
+
+        let mut contours = Vec::new();
+        for (z, contour) in structure.iter() {
+            let mut item = DataSet::new();
+            if let Some(i) = series.slice_locations.iter().position(|v| (v - z).abs() < 0.01) {
+                item.set_sequence(CONTOUR_IMAGE_SEQUENCE, vec![image_reference(series, i)]);
+            }
+            let points = contour.points();
+            let mut data = Vec::with_capacity(points.len() * 3);
+            for p in points {
+                data.extend([p[0] as f64, p[1] as f64, z as f64]);
+            }
+            item.set_string(CONTOUR_GEOMETRIC_TYPE, "CLOSED_PLANAR");
+            item.set_integers(NUMBER_OF_CONTOUR_POINTS, &[points.len() as i32]);
+            item.set_decimals(CONTOUR_DATA, &data);
+            contours.push(item);
+        }
+        let color: Vec<i32> = structure.get_color().iter().map(|v| *v as i32).collect();
+        let mut roi_contour = DataSet::new();
+        roi_contour.set_integers(ROI_DISPLAY_COLOR, &color);
+        roi_contour.set_integers(REFERENCED_ROI_NUMBER, &[number]);
+        roi_contour.set_sequence(CONTOUR_SEQUENCE, contours);
+        roi_contours.push(roi_contour);
+
+        let mut observation = DataSet::new();
+        observation.set_integers(OBSERVATION_NUMBER, &[number]);
+        observation.set_integers(REFERENCED_ROI_NUMBER, &[number]);
+        observation.set_string(RT_ROI_INTERPRETED_TYPE, "");
+        observation.set_string(ROI_INTERPRETER, "");
+        observations.push(observation);
+    }
+    ds.set_sequence(STRUCTURE_SET_ROI_SEQUENCE, rois);
+    ds.set_sequence(ROI_CONTOUR_SEQUENCE, roi_contours);
+    ds.set_sequence(RT_ROI_OBSERVATIONS_SEQUENCE, observations);
+
+    write(&ds)
+}
+
+// of the i-th image of `series`
+fn image_reference(series: &SeriesReference, i: usize) -> DataSet {
+    let sop_class = series.sop_class_uids.get(i).map_or(SOP_CLASS_CT_IMAGE, |v| v.as_str());
+    let mut item = DataSet::new();
+    item.set_string(REFERENCED_SOP_CLASS_UID, sop_class);
+    item.set_string(REFERENCED_SOP_INSTANCE_UID, &series.sop_instance_uids[i]);
+    item
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::dicom::test_utils::*;
+
+    fn contour(geometric_type: &[u8], data: &[u8]) -> Vec<u8> {
+        let mut item = Vec::new();
+        push_element(&mut item, false, CONTOUR_GEOMETRIC_TYPE, b"CS", geometric_type);
+        push_element(&mut item, false, CONTOUR_DATA, b"DS", data);
+        item
+    }
+
+    #[test]
+    fn test_load_rtstruct() {
+        let mut roi1 = Vec::new();
+        push_element(&mut roi1, false, ROI_NUMBER, b"IS", b"1");
+        push_element(&mut roi1, false, REFERENCED_FRAME_OF_REFERENCE_UID, b"UI", b"1.2.3.4");
+        push_element(&mut roi1, false, ROI_NAME, b"LO", b"Liver");
+        let mut roi2 = Vec::new();
+        push_element(&mut roi2, false, ROI_NUMBER, b"IS", b"7");
+        push_element(&mut roi2, false, ROI_NAME, b"LO", b"Tumor");
+
+        let contours = vec![
+            contour(b"CLOSED_PLANAR", b"0\\0\\-5\\10\\0\\-5\\10\\10\\-5"),
+            contour(b"POINT", b"1\\1\\0"),
+            contour(b"CLOSED_PLANAR", b"0\\0\\-2.5\\10\\0\\-2.5\\10\\10\\-2.5\\0\\10\\-2.5"),
+        ];
+        let mut roi_contour = Vec::new();
+        push_element(&mut roi_contour, false, ROI_DISPLAY_COLOR, b"IS", b"0\\255\\128");
+        push_element(&mut roi_contour, false, REFERENCED_ROI_NUMBER, b"IS", b"7");
+        push_sequence(&mut roi_contour, false, CONTOUR_SEQUENCE, &contours, true);
+
+        let mut body = Vec::new();
+        push_element(&mut body, false, MODALITY, b"CS", b"RTSTRUCT");
+        push_sequence(&mut body, false, STRUCTURE_SET_ROI_SEQUENCE, &[roi1, roi2], false);
+        push_sequence(&mut body, false, ROI_CONTOUR_SEQUENCE, &[roi_contour], true);
+
+        let set = load_rtstruct(&part10(false, &body)).unwrap();
+        assert_eq!(set.len(), 2);
+        assert_eq!(set.get_frame_of_reference_uid(), "1.2.3.4");
+
+        let liver = set.find_by_name("Liver").unwrap();
+        assert_eq!(liver.get_number(), 1);
+        assert_eq!(liver.len(), 0);
+
+        let tumor = set.find_by_number(7).unwrap();
+        assert_eq!(tumor.get_name(), "Tumor");
+        assert_eq!(tumor.get_color(), vec![0, 255, 128]);
+        assert_eq!(tumor.len(), 2);
+        assert!(tumor.get_contours_at(-5.0).is_some());
+        assert!(tumor.get_contours_at(-2.5).is_some());
+        assert!(tumor.get_contours_at(0.0).is_none());
+    }
+
+    #[test]
+    fn test_load_rtstruct_wrong_modality() {
+        let mut body = Vec::new();
+        push_element(&mut body, true, MODALITY, b"CS", b"CT");
+        assert!(load_rtstruct(&part10(true, &body)).is_err());
+    }
+
+    #[test]
+    fn test_write_rtstruct() {
+        let mut patient_study = DataSet::new();
+        patient_study.set_string(PATIENT_ID, "P001");
+        patient_study.set_string(STUDY_INSTANCE_UID, "1.2.3");
+        let series = SeriesReference {
+            study_instance_uid: String::from("1.2.3"),
+            series_instance_uid: String::from("1.2.3.4"),
+            frame_of_reference_uid: String::from("1.2.3.5"),
+            sop_instance_uids: vec![String::from("1.2.3.4.1"), String::from("1.2.3.4.2")],
+            sop_class_uids: vec![String::from(SOP_CLASS_MR_IMAGE); 2],
+            slice_locations: vec![-2.5, 0.0],
+            patient_study,
+        };
+
+        let mut set = StructureSet::new();
+        let mut gtv = Structure::new();
+        gtv.set_number(2);
+        gtv.set_name(String::from("GTV"));
+        gtv.set_color(10, 20, 30);
+        let mut cb = Contour2Df32Builder::new();
+        cb.push(1.5, -2.0);
+        cb.push(10.0, -2.0);
+        cb.push(10.0, 8.25);
+        gtv.push(0.0, cb.close());
+        set.push(gtv);
+        let mut ablation = Structure::new();
+        ablation.set_number(2);
+        ablation.set_name(String::from("Ablation"));
+        set.push(ablation);
+
+        let buf = write_rtstruct(&set, &series, "kepler").unwrap();
+        let ds = parse(&buf).unwrap();
+        assert_eq!(ds.string(PATIENT_ID).unwrap(), "P001");
+        assert_eq!(ds.string(STUDY_INSTANCE_UID).unwrap(), "1.2.3");
+        let frame = &ds.sequence(REFERENCED_FRAME_OF_REFERENCE_SEQUENCE).unwrap()[0];
+        let study = &frame.sequence(RT_REFERENCED_STUDY_SEQUENCE).unwrap()[0];
+        let referenced_series = &study.sequence(RT_REFERENCED_SERIES_SEQUENCE).unwrap()[0];
+        assert_eq!(referenced_series.string(SERIES_INSTANCE_UID).unwrap(), "1.2.3.4");
+        assert_eq!(referenced_series.sequence(CONTOUR_IMAGE_SEQUENCE).unwrap().len(), 2);
+        let contour = &ds.sequence(ROI_CONTOUR_SEQUENCE).unwrap()[0].sequence(CONTOUR_SEQUENCE).unwrap()[0];
+        let image = &contour.sequence(CONTOUR_IMAGE_SEQUENCE).unwrap()[0];
+        assert_eq!(image.string(REFERENCED_SOP_INSTANCE_UID).unwrap(), "1.2.3.4.2");
+        assert_eq!(image.string(REFERENCED_SOP_CLASS_UID).unwrap(), SOP_CLASS_MR_IMAGE);
+
+        // round trip
+        let read = load_rtstruct(&buf).unwrap();
+        assert_eq!(read.len(), 2);
+        assert_eq!(read.get_frame_of_reference_uid(), "1.2.3.5");
+        let gtv = read.find_by_number(2).unwrap();
+        assert_eq!(gtv.get_name(), "GTV");
+        assert_eq!(gtv.get_color(), vec![10, 20, 30]);
+        let contours = gtv.get_contours_at(0.0).unwrap();
+        let points: Vec<[f32; 2]> = contours[0].points().iter().map(|p| [p[0], p[1]]).collect();
+        assert_eq!(points, vec![[1.5, -2.0], [10.0, -2.0], [10.0, 8.25]]);
+        assert_eq!(read.find_by_name("Ablation").unwrap().get_number(), 3);
+
+        // structures of another frame of reference
+        set.set_frame_of_reference_uid(String::from("9.9"));
+        assert!(write_rtstruct(&set, &series, "kepler").is_err());
+    }
+}
@@ -0,0 +1,259 @@
+// MIT License
+
+// Copyright (c) 2023 Techtron-Lab
+
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Tag(pub u16, pub u16);
+
+impl Tag {
+    pub fn group(&self) -> u16 {
+        self.0
+    }
+
+    pub fn element(&self) -> u16 {
+        self.1
+    }
+}
+
+impl fmt::Display for Tag {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "({:04X},{:04X})", self.0, self.1)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VR {
+    AE, AS, AT, CS, DA, DS, DT, FD, FL, IS, LO, LT, OB, OD, OF, OL, OV, OW, PN, SH, SL, SQ,
+    SS, ST, SV, TM, UC, UI, UL, UN, UR, US, UT, UV,
+}
+
+const VR_TABLE: [(VR, [u8; 2]); 34] = [
+    (VR::AE, *b"AE"), (VR::AS, *b"AS"), (VR::AT, *b"AT"), (VR::CS, *b"CS"), (VR::DA, *b"DA"),
+    (VR::DS, *b"DS"), (VR::DT, *b"DT"), (VR::FD, *b"FD"), (VR::FL, *b"FL"), (VR::IS, *b"IS"),
+    (VR::LO, *b"LO"), (VR::LT, *b"LT"), (VR::OB, *b"OB"), (VR::OD, *b"OD"), (VR::OF, *b"OF"),
+    (VR::OL, *b"OL"), (VR::OV, *b"OV"), (VR::OW, *b"OW"), (VR::PN, *b"PN"), (VR::SH, *b"SH"),
+    (VR::SL, *b"SL"), (VR::SQ, *b"SQ"), (VR::SS, *b"SS"), (VR::ST, *b"ST"), (VR::SV, *b"SV"),
+    (VR::TM, *b"TM"), (VR::UC, *b"UC"), (VR::UI, *b"UI"), (VR::UL, *b"UL"), (VR::UN, *b"UN"),
+    (VR::UR, *b"UR"), (VR::US, *b"US"), (VR::UT, *b"UT"), (VR::UV, *b"UV"),
+];
+
+impl VR {
+    pub fn from_bytes(b: [u8; 2]) -> Option<VR> {
+        VR_TABLE.iter().find(|(_, v)| *v == b).map(|(vr, _)| *vr)
+    }
+
+    pub fn to_bytes(self) -> [u8; 2] {
+        VR_TABLE
+            .iter()
+            .find(|(vr, _)| *vr == self)
+            .map(|(_, v)| *v)
+            .unwrap()
+    }
+
+    // VRs which use the 4 byte length field in explicit VR transfer syntaxes.
+    pub fn has_long_length(self) -> bool {
+        use VR::*;
+        matches!(self, OB | OD | OF | OL | OV | OW | SQ | UC | UN | UR | UT | SV | UV)
+    }
+
+    pub fn is_text(self) -> bool {
+        use VR::*;
+        matches!(
+            self,
+            AE | AS | CS | DA | DS | DT | IS | LO | LT | PN | SH | ST | TM | UC | UI | UR | UT
+        )
+    }
+
+    // Padding byte used to make the value length even.
+    pub fn padding(self) -> u8 {
+        if self.is_text() && self != VR::UI {
+            b' '
+        } else {
+            0
+        }
+    }
+}
+
+pub const TRANSFER_SYNTAX_IMPLICIT_LE: &str = "1.2.840.10008.1.2";
+pub const TRANSFER_SYNTAX_EXPLICIT_LE: &str = "1.2.840.10008.1.2.1";
+
+pub const SOP_CLASS_CT_IMAGE: &str = "1.2.840.10008.5.1.4.1.1.2";
+pub const SOP_CLASS_MR_IMAGE: &str = "1.2.840.10008.5.1.4.1.1.4";
+pub const SOP_CLASS_RT_DOSE: &str = "1.2.840.10008.5.1.4.1.1.481.2";
+pub const SOP_CLASS_RT_STRUCTURE_SET: &str = "1.2.840.10008.5.1.4.1.1.481.3";
+pub const SOP_CLASS_STUDY_COMPONENT_MANAGEMENT: &str = "1.2.840.10008.3.1.2.3.1";
+
+// File meta information
+pub const FILE_META_GROUP_LENGTH: Tag = Tag(0x0002, 0x0000);
+pub const FILE_META_INFORMATION_VERSION: Tag = Tag(0x0002, 0x0001);
+pub const MEDIA_STORAGE_SOP_CLASS_UID: Tag = Tag(0x0002, 0x0002);
+pub const MEDIA_STORAGE_SOP_INSTANCE_UID: Tag = Tag(0x0002, 0x0003);
+pub const TRANSFER_SYNTAX_UID: Tag = Tag(0x0002, 0x0010);
+pub const IMPLEMENTATION_CLASS_UID: Tag = Tag(0x0002, 0x0012);
+pub const IMPLEMENTATION_VERSION_NAME: Tag = Tag(0x0002, 0x0013);
+
+// Patient / study / series
+pub const SOP_CLASS_UID: Tag = Tag(0x0008, 0x0016);
+pub const SOP_INSTANCE_UID: Tag = Tag(0x0008, 0x0018);
+pub const STUDY_DATE: Tag = Tag(0x0008, 0x0020);
+pub const STUDY_TIME: Tag = Tag(0x0008, 0x0030);
+pub const ACCESSION_NUMBER: Tag = Tag(0x0008, 0x0050);
+pub const MODALITY: Tag = Tag(0x0008, 0x0060);
+pub const REFERRING_PHYSICIAN_NAME: Tag = Tag(0x0008, 0x0090);
+pub const REFERENCED_SOP_CLASS_UID: Tag = Tag(0x0008, 0x1150);
+pub const REFERENCED_SOP_INSTANCE_UID: Tag = Tag(0x0008, 0x1155);
+pub const PATIENT_NAME: Tag = Tag(0x0010, 0x0010);
+pub const PATIENT_ID: Tag = Tag(0x0010, 0x0020);
+pub const PATIENT_BIRTH_DATE: Tag = Tag(0x0010, 0x0030);
+pub const PATIENT_SEX: Tag = Tag(0x0010, 0x0040);
+pub const SLICE_THICKNESS: Tag = Tag(0x0018, 0x0050);
+pub const STUDY_INSTANCE_UID: Tag = Tag(0x0020, 0x000D);
+pub const SERIES_INSTANCE_UID: Tag = Tag(0x0020, 0x000E);
+pub const STUDY_ID: Tag = Tag(0x0020, 0x0010);
+pub const SERIES_NUMBER: Tag = Tag(0x0020, 0x0011);
+pub const INSTANCE_NUMBER: Tag = Tag(0x0020, 0x0013);
+pub const IMAGE_POSITION_PATIENT: Tag = Tag(0x0020, 0x0032);
+pub const IMAGE_ORIENTATION_PATIENT: Tag = Tag(0x0020, 0x0037);
+pub const FRAME_OF_REFERENCE_UID: Tag = Tag(0x0020, 0x0052);
+pub const SLICE_LOCATION: Tag = Tag(0x0020, 0x1041);
+
+// Image pixel
+pub const SAMPLES_PER_PIXEL: Tag = Tag(0x0028, 0x0002);
+pub const PHOTOMETRIC_INTERPRETATION: Tag = Tag(0x0028, 0x0004);
+pub const NUMBER_OF_FRAMES: Tag = Tag(0x0028, 0x0008);
+pub const ROWS: Tag = Tag(0x0028, 0x0010);
+pub const COLUMNS: Tag = Tag(0x0028, 0x0011);
+pub const PIXEL_SPACING: Tag = Tag(0x0028, 0x0030);
+pub const BITS_ALLOCATED: Tag = Tag(0x0028, 0x0100);
+pub const BITS_STORED: Tag = Tag(0x0028, 0x0101);
+pub const HIGH_BIT: Tag = Tag(0x0028, 0x0102);
+pub const PIXEL_REPRESENTATION: Tag = Tag(0x0028, 0x0103);
+pub const RESCALE_INTERCEPT: Tag = Tag(0x0028, 0x1052);
+pub const RESCALE_SLOPE: Tag = Tag(0x0028, 0x1053);
+pub const PIXEL_DATA: Tag = Tag(0x7FE0, 0x0010);
+
+// RT dose
+pub const DOSE_UNITS: Tag = Tag(0x3004, 0x0002);
+pub const DOSE_TYPE: Tag = Tag(0x3004, 0x0004);
+pub const DOSE_SUMMATION_TYPE: Tag = Tag(0x3004, 0x000A);
+pub const GRID_FRAME_OFFSET_VECTOR: Tag = Tag(0x3004, 0x000C);
+pub const DOSE_GRID_SCALING: Tag = Tag(0x3004, 0x000E);
+
+// RT structure set
+pub const STRUCTURE_SET_LABEL: Tag = Tag(0x3006, 0x0002);
+pub const STRUCTURE_SET_DATE: Tag = Tag(0x3006, 0x0008);
+pub const STRUCTURE_SET_TIME: Tag = Tag(0x3006, 0x0009);
+pub const REFERENCED_FRAME_OF_REFERENCE_SEQUENCE: Tag = Tag(0x3006, 0x0010);
+pub const RT_REFERENCED_STUDY_SEQUENCE: Tag = Tag(0x3006, 0x0012);
+pub const RT_REFERENCED_SERIES_SEQUENCE: Tag = Tag(0x3006, 0x0014);
+pub const CONTOUR_IMAGE_SEQUENCE: Tag = Tag(0x3006, 0x0016);
+pub const STRUCTURE_SET_ROI_SEQUENCE: Tag = Tag(0x3006, 0x0020);
+pub const ROI_NUMBER: Tag = Tag(0x3006, 0x0022);
+pub const REFERENCED_FRAME_OF_REFERENCE_UID: Tag = Tag(0x3006, 0x0024);
+pub const ROI_NAME: Tag = Tag(0x3006, 0x0026);
+pub const ROI_DISPLAY_COLOR: Tag = Tag(0x3006, 0x002A);
+pub const ROI_GENERATION_ALGORITHM: Tag = Tag(0x3006, 0x0036);
+pub const ROI_CONTOUR_SEQUENCE: Tag = Tag(0x3006, 0x0039);
+pub const CONTOUR_SEQUENCE: Tag = Tag(0x3006, 0x0040);
+pub const CONTOUR_GEOMETRIC_TYPE: Tag = Tag(0x3006, 0x0042);
+pub const NUMBER_OF_CONTOUR_POINTS: Tag = Tag(0x3006, 0x0046);
+pub const CONTOUR_DATA: Tag = Tag(0x3006, 0x0050);
+pub const RT_ROI_OBSERVATIONS_SEQUENCE: Tag = Tag(0x3006, 0x0080);
+pub const OBSERVATION_NUMBER: Tag = Tag(0x3006, 0x0082);
+pub const REFERENCED_ROI_NUMBER: Tag = Tag(0x3006, 0x0084);
+pub const RT_ROI_INTERPRETED_TYPE: Tag = Tag(0x3006, 0x00A4);
+pub const ROI_INTERPRETER: Tag = Tag(0x3006, 0x00A6);
+
+// Sequence delimitation
+pub const ITEM: Tag = Tag(0xFFFE, 0xE000);
+pub const ITEM_DELIMITATION_ITEM: Tag = Tag(0xFFFE, 0xE00D);
+pub const SEQUENCE_DELIMITATION_ITEM: Tag = Tag(0xFFFE, 0xE0DD);
+
+// VR of the tags known to this module, used for implicit VR transfer syntax.
+pub fn dictionary_vr(tag: Tag) -> VR {
+    use VR::*;
+    if tag.element() == 0x0000 {
+        // group length
+        return UL;
+    }
+    match tag {
+        FILE_META_INFORMATION_VERSION => OB,
+        MEDIA_STORAGE_SOP_CLASS_UID
+        | MEDIA_STORAGE_SOP_INSTANCE_UID
+        | TRANSFER_SYNTAX_UID
+        | IMPLEMENTATION_CLASS_UID
+        | SOP_CLASS_UID
+        | SOP_INSTANCE_UID
+        | STUDY_INSTANCE_UID
+        | SERIES_INSTANCE_UID
+        | FRAME_OF_REFERENCE_UID
+        | REFERENCED_SOP_CLASS_UID
+        | REFERENCED_SOP_INSTANCE_UID
+        | REFERENCED_FRAME_OF_REFERENCE_UID => UI,
+        STUDY_DATE | PATIENT_BIRTH_DATE | STRUCTURE_SET_DATE => DA,
+        STUDY_TIME | STRUCTURE_SET_TIME => TM,
+        MODALITY
+        | PHOTOMETRIC_INTERPRETATION
+        | PATIENT_SEX
+        | DOSE_UNITS
+        | DOSE_TYPE
+        | DOSE_SUMMATION_TYPE
+        | CONTOUR_GEOMETRIC_TYPE
+        | ROI_GENERATION_ALGORITHM
+        | RT_ROI_INTERPRETED_TYPE => CS,
+        PATIENT_NAME | REFERRING_PHYSICIAN_NAME | ROI_INTERPRETER => PN,
+        PATIENT_ID | ROI_NAME => LO,
+        STUDY_ID | ACCESSION_NUMBER | IMPLEMENTATION_VERSION_NAME | STRUCTURE_SET_LABEL => SH,
+        SLICE_THICKNESS
+        | IMAGE_POSITION_PATIENT
+        | IMAGE_ORIENTATION_PATIENT
+        | SLICE_LOCATION
+        | PIXEL_SPACING
+        | RESCALE_INTERCEPT
+        | RESCALE_SLOPE
+        | GRID_FRAME_OFFSET_VECTOR
+        | DOSE_GRID_SCALING
+        | CONTOUR_DATA => DS,
+        SERIES_NUMBER
+        | INSTANCE_NUMBER
+        | NUMBER_OF_FRAMES
+        | ROI_NUMBER
+        | ROI_DISPLAY_COLOR
+        | NUMBER_OF_CONTOUR_POINTS
+        | OBSERVATION_NUMBER
+        | REFERENCED_ROI_NUMBER => IS,
+        REFERENCED_FRAME_OF_REFERENCE_SEQUENCE
+        | RT_REFERENCED_STUDY_SEQUENCE
+        | RT_REFERENCED_SERIES_SEQUENCE
+        | CONTOUR_IMAGE_SEQUENCE
+        | RT_ROI_OBSERVATIONS_SEQUENCE
+        | STRUCTURE_SET_ROI_SEQUENCE
+        | ROI_CONTOUR_SEQUENCE
+        | CONTOUR_SEQUENCE => SQ,
+        SAMPLES_PER_PIXEL | ROWS | COLUMNS | BITS_ALLOCATED | BITS_STORED | HIGH_BIT
+        | PIXEL_REPRESENTATION => US,
+        PIXEL_DATA => OW,
+        _ => UN,
+    }
+}
@@ -0,0 +1,214 @@
+// MIT License
+
+// Copyright (c) 2023 Techtron-Lab
+
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+
+use super::dataset::{DataSet, Element, Value};
+use super::tag::*;
+
+// Root of the UIDs generated by this module, see generate_uid().
+const UID_ROOT: &str = "2.25";
+const IMPLEMENTATION_CLASS: &str = "2.25.138294065153087839424360357937391128418";
+const IMPLEMENTATION_VERSION: &str = "KEPLER_RA";
+
+// Encode `ds` as a DICOM part 10 file with the implicit VR little endian
+// transfer syntax, whose 32 bit lengths hold values of any size, e.g. long
+// ContourData. The file meta information is generated from the SOP class
+// and instance UIDs of the data set, the group 0002 elements of `ds` are ignored.
+pub fn write(ds: &DataSet) -> Result<Vec<u8>, String> {
+    let sop_class = ds.string(SOP_CLASS_UID).ok_or("missing SOPClassUID.")?;
+    let sop_instance = ds.string(SOP_INSTANCE_UID).ok_or("missing SOPInstanceUID.")?;
+
+    let mut meta = DataSet::new();
+    meta.set_bytes(FILE_META_INFORMATION_VERSION, vec![0, 1]);
+    meta.set_string(MEDIA_STORAGE_SOP_CLASS_UID, &sop_class);
+    meta.set_string(MEDIA_STORAGE_SOP_INSTANCE_UID, &sop_instance);
+    meta.set_string(TRANSFER_SYNTAX_UID, TRANSFER_SYNTAX_IMPLICIT_LE);
+    meta.set_string(IMPLEMENTATION_CLASS_UID, IMPLEMENTATION_CLASS);
+    meta.set_string(IMPLEMENTATION_VERSION_NAME, IMPLEMENTATION_VERSION);
+    let mut meta_bytes = Vec::new();
+    // the file meta information is always in explicit VR
+    for element in meta.iter() {
+        write_element(&mut meta_bytes, element, true)?;
+    }
+
+    let mut buf = vec![0u8; 128];
+    buf.extend(b"DICM");
+    write_element(
+        &mut buf,
+        &Element {
+            tag: FILE_META_GROUP_LENGTH,
+            vr: VR::UL,
+            value: Value::Bytes((meta_bytes.len() as u32).to_le_bytes().to_vec()),
+        },
+        true,
+    )?;
+    buf.extend(meta_bytes);
+    for element in ds.iter().filter(|e| e.tag.group() != 0x0002) {
+        write_element(&mut buf, element, false)?;
+    }
+    Ok(buf)
+}
+
+fn write_data_set(buf: &mut Vec<u8>, ds: &DataSet, explicit_vr: bool) -> Result<(), String> {
+    for element in ds.iter() {
+        write_element(buf, element, explicit_vr)?;
+    }
+    Ok(())
+}
+
+// In implicit VR the VR is left out and the length is always 32 bit.
+fn write_element(buf: &mut Vec<u8>, element: &Element, explicit_vr: bool) -> Result<(), String> {
+    let value = match &element.value {
+        Value::Bytes(bytes) => {
+            let mut value = bytes.clone();
+            if value.len() % 2 == 1 {
+                value.push(element.vr.padding());
+            }
+            value
+        }
+        Value::Sequence(items) => {
+            let mut value = Vec::new();
+            for item in items {
+                let mut item_bytes = Vec::new();
+                write_data_set(&mut item_bytes, item, explicit_vr)?;
+                write_tag(&mut value, ITEM);
+                value.extend((item_bytes.len() as u32).to_le_bytes());
+                value.extend(item_bytes);
+            }
+            value
+        }
+    };
+
+    write_tag(buf, element.tag);
+    if !explicit_vr {
+        buf.extend((value.len() as u32).to_le_bytes());
+    } else if element.vr.has_long_length() {
+        buf.extend(element.vr.to_bytes());
+        buf.extend([0, 0]);
+        buf.extend((value.len() as u32).to_le_bytes());
+    } else {
+        buf.extend(element.vr.to_bytes());
+        let len = u16::try_from(value.len()).map_err(|_| {
+            format!(
+                "the value of {} is {} bytes, longer than its VR allows.",
+                element.tag,
+                value.len()
+            )
+        })?;
+        buf.extend(len.to_le_bytes());
+    }
+    buf.extend(value);
+    Ok(())
+}
+
+fn write_tag(buf: &mut Vec<u8>, tag: Tag) {
+    buf.extend(tag.group().to_le_bytes());
+    buf.extend(tag.element().to_le_bytes());
+}
+
+// A new UID made from a random 128 bit number under the 2.25 root.
+pub fn generate_uid() -> String {
+    format!("{}.{}", UID_ROOT, random_u128())
+}
+
+#[cfg(target_family = "wasm")]
+fn random_u128() -> u128 {
+    let mut v: u128 = 0;
+    for _ in 0..4 {
+        v = (v << 32) | (js_sys::Math::random() * 4294967296.0) as u128;
+    }
+    v
+}
+
+#[cfg(not(target_family = "wasm"))]
+fn random_u128() -> u128 {
+    use std::collections::hash_map::RandomState;
+    use std::hash::{BuildHasher, Hasher};
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos())
+        .unwrap_or(0);
+    let mut v: u128 = 0;
+    for _ in 0..2 {
+        let mut hasher = RandomState::new().build_hasher();
+        hasher.write_u128(nanos);
+        v = (v << 64) | hasher.finish() as u128;
+    }
+    v
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::dicom::dataset::parse;
+
+    #[test]
+    fn test_write() {
+        let mut item = DataSet::new();
+        item.set_integers(ROI_NUMBER, &[3]);
+        item.set_string(ROI_NAME, "GTV");
+        let mut ds = DataSet::new();
+        ds.set_string(SOP_CLASS_UID, SOP_CLASS_RT_STRUCTURE_SET);
+        ds.set_string(SOP_INSTANCE_UID, &generate_uid());
+        ds.set_string(MODALITY, "RTSTRUCT");
+        ds.set_decimals(CONTOUR_DATA, &[1.5, -0.0001, 2.0 / 3.0, 120.0]);
+        ds.set_sequence(STRUCTURE_SET_ROI_SEQUENCE, vec![item.clone(), DataSet::new()]);
+
+        let buf = write(&ds).unwrap();
+        let read = parse(&buf).unwrap();
+        assert_eq!(read.string(TRANSFER_SYNTAX_UID).unwrap(), TRANSFER_SYNTAX_IMPLICIT_LE);
+        assert_eq!(read.string(MEDIA_STORAGE_SOP_INSTANCE_UID), ds.string(SOP_INSTANCE_UID));
+        assert_eq!(read.string(CONTOUR_DATA).unwrap(), "1.5\\0\\0.667\\120");
+        let items = read.sequence(STRUCTURE_SET_ROI_SEQUENCE).unwrap();
+        assert_eq!(items.len(), 2);
+        assert_eq!(items[0].i32(ROI_NUMBER), Some(3));
+        assert_eq!(items[0].string(ROI_NAME).unwrap(), "GTV");
+        assert!(items[1].is_empty());
+    }
+
+    // A contour of 6000 points, longer than the 16 bit length of DS in
+    // explicit VR.
+    #[test]
+    fn test_write_long_value() {
+        let data: Vec<f64> = (0..18000).map(|i| i as f64 + 0.25).collect();
+        let mut item = DataSet::new();
+        item.set_decimals(CONTOUR_DATA, &data);
+        let mut ds = DataSet::new();
+        ds.set_string(SOP_CLASS_UID, SOP_CLASS_RT_STRUCTURE_SET);
+        ds.set_string(SOP_INSTANCE_UID, &generate_uid());
+        ds.set_sequence(CONTOUR_SEQUENCE, vec![item]);
+
+        let read = parse(&write(&ds).unwrap()).unwrap();
+        let items = read.sequence(CONTOUR_SEQUENCE).unwrap();
+        assert_eq!(items[0].f64s(CONTOUR_DATA).unwrap(), data);
+    }
+
+    #[test]
+    fn test_generate_uid() {
+        let uid = generate_uid();
+        assert!(uid.starts_with("2.25."));
+        assert!(uid.len() <= 64);
+        assert_ne!(uid, generate_uid());
+    }
+}
@@ -29,7 +29,7 @@ use std::ops::DerefMut;
 use std::rc::Rc;
 
 use do_notation::m;
-use js_sys::{ArrayBuffer, Float32Array, Int16Array, Uint16Array, Uint8Array};
+use js_sys::{Array, ArrayBuffer, Float32Array, Int16Array, Uint16Array, Uint8Array};
 use wasm_bindgen::prelude::*;
 use wasm_bindgen::JsCast;
 // use web_sys::{File, FileReader};
@@ -41,17 +41,28 @@ use web_sys::{
     WebGlTexture, //WebGlUniformLocation,
 };
 
-// use crate::colormap::cmocean::LUT;
-use crate::colormap::jet::LUT;
+use crate::colormap::{self, Colormap};
+use crate::dicom::ct::{load_ct_series, SeriesReference};
+use crate::dicom::rtdose::load_rtdose;
+use crate::dicom::rtstruct::write_rtstruct;
+use crate::techtron::graphic::contour::{Structure, StructureSet};
+use crate::techtron::graphic::marching_cubes::{marching_cubes_impl, Surface};
 use crate::modal::{VolumeDataType, VolumeInfo};
+use crate::render::cpu::{CpuLayer, CpuVolume};
+use crate::render::annotations::{Annotation, Annotations};
+use crate::render::isolines::{IsoLevel, IsoLineOverlay};
+use crate::render::overlay::{StructureOverlay, StructureStyle};
+use crate::render::{viewport_extent, Crosshair, FieldBand, FieldDisplay, Fusion, RgbaImage};
+use crate::scheduler::{Dirty, FrameScheduler};
+use crate::techtron::core::base::Base;
 use crate::shader_sources::*;
 use crate::techtron::prelude::*;
+use crate::transfer_function::TransferFunction;
 use crate::utils::*;
 use crate::view::*;
 
 use log::{info, warn, Level};
-
-const PIXEL_VAL_TO_POSITIVE: u16 = 1500;
+use nalgebra::Vector3;
 
 macro_rules! set_view_param {
     ($i: expr, $w: ident, $v: expr) => {
@@ -96,9 +107,27 @@ impl ZLocations {
         type GL2 = WebGl2RenderingContext;
 
         let mut loc = v.to_vec();
-        loc.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        loc.sort_by(|a, b| a.total_cmp(b));
         ZLocations { loc: loc.clone() }
     }
+
+    // The index of the slice nearest to `offset` mm along the normal from the
+    // middle of the first and the last slices, which is the center of the
+    // view frame. None beyond half the mean spacing outside of the slices.
+    fn nearest(&self, offset: f32) -> Option<usize> {
+        let len = self.loc.len();
+        let (min, max) = (*self.loc.first()?, self.loc[len - 1]);
+        let location = (min + max) / 2.0 + offset;
+        let half = if len > 1 { (max - min) / (len - 1) as f32 / 2.0 } else { 0.5 };
+        if location < min - half || location > max + half {
+            return None;
+        }
+        self.loc
+            .iter()
+            .enumerate()
+            .min_by(|a, b| (a.1 - location).abs().total_cmp(&(b.1 - location).abs()))
+            .map(|(i, _)| i)
+    }
 }
 
 impl GenTexture<Texture3DRGBA16> for ZLocations {
@@ -120,6 +149,8 @@ struct GlVolume {
     volume_info: VolumeInfo,
     // context: WebGl2RenderingContext,
     data: Vec<i16>,
+    // the DICOM series the volume is loaded from
+    series: Option<SeriesReference>,
 }
 
 impl GlVolume {
@@ -137,45 +168,38 @@ impl GlVolume {
             .expect("cannot get the performance object.");
         let t0 = p.now();
         let src = Int16Array::new_with_byte_offset(buffer.as_ref(), 0);
-        let mut data: Vec<i16> = src.to_vec();
-        for ele in &mut data {
-            *ele += PIXEL_VAL_TO_POSITIVE as i16;
-        }
+        let volume = GlVolume::from_vec(src.to_vec(), info);
         let t1 = p.now();
         log(format!("process dicom in WASM took {} msecs", t1 - t0).as_str());
 
+        volume
+    }
+
+    fn from_vec(data: Vec<i16>, info: VolumeInfo) -> Self {
         GlVolume {
-            volume_info: info.clone(),
-            // context: context,
+            volume_info: info,
             data,
+            series: None,
         }
     }
-}
 
-impl GenTexture<Texture3DRGBA16> for GlVolume {
-    // type Target = Texture3DRGBA16;
-    fn gen_texture3d(&self) -> Texture3DRGBA16 {
-        let mut data: Vec<u16> = Vec::new();
-        for d in &self.data {
-            data.push(*d as u16);
-        }
+    fn get_real_value(&self, index: usize) -> f32 {
+        self.volume_info.to_real_value(self.data[index] as f32)
+    }
+}
 
-        log(&format!("data length: {}", self.data.len()));
-        log(&format!("data2 length: {}", data.len()));
-        let info = &self.volume_info;
-        Texture3DRGBA16::new(
-            info.get_width(),
-            info.get_height(),
-            info.get_depth(),
-            Rc::new(data),
-        )
+// The stored values as they are. Integer textures cannot be filtered, the
+// shaders interpolate them in primary_texel and apply the rescale.
+impl GenTexture<Texture3DR16I> for GlVolume {
+    fn gen_texture3d(&self) -> Texture3DR16I {
+        let (w, h, d) = self.volume_info.get_dimension();
+        Texture3DR16I::new(w, h, d, Rc::new(self.data.clone()))
     }
 }
 
 #[derive(Debug, Clone)]
 struct EFVolume {
-    dim: (i32, i32, i32),
-    spacing: (f32, f32, f32),
+    info: VolumeInfo,
     data: Vec<f32>,
 }
 
@@ -186,9 +210,10 @@ impl EFVolume {
         spacing: (f32, f32, f32),
     ) -> EFVolume {
         let f32array = Float32Array::new_with_byte_offset(buf.as_ref(), 0);
+        let mut info = VolumeInfo::new(VolumeDataType::Float32, dim.0, dim.1, dim.2);
+        info.set_spacing(spacing);
         EFVolume {
-            dim,
-            spacing,
+            info,
             data: f32array.to_vec(),
         }
     }
@@ -206,20 +231,11 @@ impl EFVolume {
     }
 }
 
-impl GenTexture<Texture3DRGBA16> for EFVolume {
-    fn gen_texture3d(&self) -> Texture3DRGBA16 {
-        let minmax = self.minmax();
-        let min = minmax.0;
-        let max = minmax.1;
-
-        let k = 65535.0;
-
-        let norm: Vec<f32> = self.data.iter().map(|x| (x - min) / (max - min)).collect();
-        let norm_packed: Vec<u16> = norm.iter().map(|x| (x * k) as u16).collect();
-
-        info!("norm_packed: {:?}", norm_packed);
-        info!("dim: {} {} {}", self.dim.0, self.dim.1, self.dim.2);
-        Texture3DRGBA16::new(self.dim.0, self.dim.1, self.dim.2, Rc::new(norm_packed))
+impl GenTexture<Texture3DR32F> for EFVolume {
+    fn gen_texture3d(&self) -> Texture3DR32F {
+        let (w, h, d) = self.info.get_dimension();
+        info!("dim: {} {} {}", w, h, d);
+        Texture3DR32F::new(w, h, d, Rc::new(self.data.clone()), TextureFilter::Linear)
     }
 }
 
@@ -237,9 +253,30 @@ pub struct GlCanvas {
     trans_view: Option<TransverseView>,
     sagi_view: Option<SagittalView>,
     coronal_view: Option<CoronalView>,
+    three_d_view: Option<ThreeDView>,
+    oblique_view: Option<ObliqueView>,
+    needle_inline_view: Option<ObliqueView>,
+    needle_perpendicular_view: Option<ObliqueView>,
+    transfer_function: TransferFunction,
     geometry: Option<Geometry>,
     rendables: Vec<Box<dyn Rendable>>,
-    layout_manager: LayoutOneLargeThreeSmall,
+    layout_manager: LayoutManager,
+    scheduler: FrameScheduler,
+    // drawn on the transverse, sagittal and coronal views
+    structures: Rc<RefCell<StructureOverlay>>,
+    isolines: Rc<RefCell<IsoLineOverlay>>,
+    annotations: Rc<RefCell<Annotations>>,
+    // of set_annotation_text_scale, times the device pixel ratio
+    annotation_text_scale: u32,
+    // of the secondary on all the views
+    field_display: FieldDisplay,
+    // in the view frame of the primary in mm, see move_cursor
+    cursor: Vector3<f32>,
+    crosshair_visible: bool,
+    crosshair_color: [f32; 3],
+    cursor_callback: Option<js_sys::Function>,
+    // whether the cursor has moved since the callback was last queued
+    cursor_moved: bool,
 }
 
 #[wasm_bindgen]
@@ -253,10 +290,7 @@ impl GlCanvas {
         // let program = Rc::new(RefCell::new(
         //     load_shaders(&context, VERTEX_SHADER_SOURCE, FRAGMENT_SHADER_SOURCE).unwrap(),
         // ));
-        let lut_data = Rc::new(crate::colormap::jet::LUT.to_vec());
-        let lut = Rc::new(RefCell::new(
-            Texture3DRGB8::new(256, 1, 1, lut_data).load_texture(&GLContext::new(context.clone())),
-        ));
+        let lut = Rc::new(RefCell::new(Self::load_lut(&context, &Colormap::default())));
         let mut glcanvas = GlCanvas {
             context: context.clone(),
             // program: program.clone(),
@@ -271,9 +305,25 @@ impl GlCanvas {
             trans_view: None,
             sagi_view: None,
             coronal_view: None,
+            three_d_view: None,
+            oblique_view: None,
+            needle_inline_view: None,
+            needle_perpendicular_view: None,
+            transfer_function: TransferFunction::preset("soft tissue").unwrap(),
             rendables: Vec::new(),
             geometry: None,
-            layout_manager: LayoutOneLargeThreeSmall::new(width, height, CanvasView::Transverse),
+            layout_manager: LayoutManager::new(width, height, CanvasView::Transverse),
+            scheduler: FrameScheduler::new(),
+            structures: Rc::new(RefCell::new(StructureOverlay::new())),
+            isolines: Rc::new(RefCell::new(IsoLineOverlay::new())),
+            annotations: Rc::new(RefCell::new(Annotations::new())),
+            annotation_text_scale: 1,
+            field_display: FieldDisplay::default(),
+            cursor: Vector3::zeros(),
+            crosshair_visible: false,
+            crosshair_color: [1.0, 0.85, 0.1],
+            cursor_callback: None,
+            cursor_moved: false,
         };
         return glcanvas;
     }
@@ -298,6 +348,7 @@ impl GlCanvas {
 
     pub fn set_canvas_dim(&mut self, w: i32, h: i32) -> Result<(), JsValue> {
         self.canvas_dim = (w, h);
+        self.scheduler.mark_all(Dirty::LAYOUT);
         Ok(())
     }
 
@@ -350,6 +401,10 @@ impl GlCanvas {
         // self.trans_view.as_mut().map(|x| x.primary_window = window);
         self.sagi_view.as_mut().map(|x| x.ct.window = window);
         self.coronal_view.as_mut().map(|x| x.ct.window = window);
+        for v in self.oblique_views_mut() {
+            v.ct.window = window;
+        }
+        self.scheduler.mark_all(Dirty::CONTENT);
         Ok(())
     }
 
@@ -359,11 +414,14 @@ impl GlCanvas {
     }
 
     pub fn set_primary_level(&mut self, level: f32) -> Result<(), JsValue> {
-        let lev = level + PIXEL_VAL_TO_POSITIVE as f32;
-        self.trans_view.as_mut().map(|x| x.ct.level = lev);
+        self.trans_view.as_mut().map(|x| x.ct.level = level);
         // self.trans_view.as_mut().map(|x| x.primary_level = lev);
-        self.sagi_view.as_mut().map(|x| x.ct.level = lev);
-        self.coronal_view.as_mut().map(|x| x.ct.level = lev);
+        self.sagi_view.as_mut().map(|x| x.ct.level = level);
+        self.coronal_view.as_mut().map(|x| x.ct.level = level);
+        for v in self.oblique_views_mut() {
+            v.ct.level = level;
+        }
+        self.scheduler.mark_all(Dirty::CONTENT);
         Ok(())
     }
 
@@ -383,6 +441,13 @@ impl GlCanvas {
         self.coronal_view
             .as_mut()
             .map(|x| x.dose.as_mut().map(|d| d.window = window));
+        self.three_d_view
+            .as_mut()
+            .map(|x| x.dose.as_mut().map(|d| d.window = window));
+        for d in self.oblique_views_mut().filter_map(|v| v.dose.as_mut()) {
+            d.window = window;
+        }
+        self.scheduler.mark_all(Dirty::CONTENT);
         Ok(())
     }
 
@@ -396,7 +461,6 @@ impl GlCanvas {
     }
 
     pub fn set_secondary_level(&mut self, level: f32) -> Result<(), JsValue> {
-        //let lev = level + PIXEL_VAL_TO_POSITIVE as f32;
         let lev = level;
         self.trans_view
             .as_mut()
@@ -407,6 +471,13 @@ impl GlCanvas {
         self.coronal_view
             .as_mut()
             .map(|x| x.dose.as_mut().map(|d| d.level = lev));
+        self.three_d_view
+            .as_mut()
+            .map(|x| x.dose.as_mut().map(|d| d.level = lev));
+        for d in self.oblique_views_mut().filter_map(|v| v.dose.as_mut()) {
+            d.level = lev;
+        }
+        self.scheduler.mark_all(Dirty::CONTENT);
         Ok(())
     }
 
@@ -421,6 +492,7 @@ impl GlCanvas {
     }
     pub fn set_scale_transverse(&mut self, scale: f32) -> Result<(), JsValue> {
         self.trans_view.as_mut().map(|x| x.scale = scale);
+        self.scheduler.mark(CanvasView::Transverse, Dirty::CONTENT);
         Ok(())
     }
 
@@ -433,6 +505,7 @@ impl GlCanvas {
     pub fn set_scale_sagittal(&mut self, scale: f32) -> Result<(), JsValue> {
         // self.sagi_view.as_mut().map(|x| x.scale = scale);
         set_view_param!(self.sagi_view, scale, scale);
+        self.scheduler.mark(CanvasView::Sagittal, Dirty::CONTENT);
         Ok(())
     }
 
@@ -445,6 +518,7 @@ impl GlCanvas {
 
     pub fn set_scale_coronal(&mut self, scale: f32) -> Result<(), JsValue> {
         self.coronal_view.as_mut().map(|x| x.scale = scale);
+        self.scheduler.mark(CanvasView::Coronal, Dirty::CONTENT);
         Ok(())
     }
 
@@ -471,6 +545,7 @@ impl GlCanvas {
 
     pub fn set_pan_transverse_x(&mut self, x: f32) -> Result<(), JsValue> {
         self.trans_view.as_mut().map(|v| v.pan_x = x);
+        self.scheduler.mark(CanvasView::Transverse, Dirty::CONTENT);
         Ok(())
     }
 
@@ -483,6 +558,7 @@ impl GlCanvas {
 
     pub fn set_pan_transverse_y(&mut self, y: f32) -> Result<(), JsValue> {
         self.trans_view.as_mut().map(|v| v.pan_y = y);
+        self.scheduler.mark(CanvasView::Transverse, Dirty::CONTENT);
         Ok(())
     }
 
@@ -495,6 +571,7 @@ impl GlCanvas {
 
     pub fn set_pan_sagittal_x(&mut self, x: f32) -> Result<(), JsValue> {
         self.sagi_view.as_mut().map(|v| v.pan_x = x);
+        self.scheduler.mark(CanvasView::Sagittal, Dirty::CONTENT);
         Ok(())
     }
 
@@ -507,6 +584,7 @@ impl GlCanvas {
 
     pub fn set_pan_sagittal_y(&mut self, y: f32) -> Result<(), JsValue> {
         self.sagi_view.as_mut().map(|v| v.pan_y = y);
+        self.scheduler.mark(CanvasView::Sagittal, Dirty::CONTENT);
         Ok(())
     }
 
@@ -519,6 +597,7 @@ impl GlCanvas {
 
     pub fn set_pan_coronal_x(&mut self, x: f32) -> Result<(), JsValue> {
         self.coronal_view.as_mut().map(|v| v.pan_x = x);
+        self.scheduler.mark(CanvasView::Coronal, Dirty::CONTENT);
         Ok(())
     }
 
@@ -530,6 +609,7 @@ impl GlCanvas {
     }
     pub fn set_pan_coronal_y(&mut self, z: f32) -> Result<(), JsValue> {
         self.coronal_view.as_mut().map(|v| v.pan_y = z);
+        self.scheduler.mark(CanvasView::Coronal, Dirty::CONTENT);
         Ok(())
     }
 
@@ -541,7 +621,7 @@ impl GlCanvas {
     }
 
     pub fn set_slice_transverse(&mut self, slice: f32) -> Result<(), JsValue> {
-        self.trans_view.as_mut().map(|v| v.z = slice);
+        self.move_cursor_to_slice(Orientation::Transverse, slice);
         Ok(())
     }
 
@@ -553,7 +633,7 @@ impl GlCanvas {
     }
 
     pub fn set_slice_sagittal(&mut self, slice: f32) -> Result<(), JsValue> {
-        self.sagi_view.as_mut().map(|v| v.x = slice);
+        self.move_cursor_to_slice(Orientation::Sagittal, slice);
         Ok(())
     }
     pub fn get_slice_sagittal(&self) -> Result<f32, JsValue> {
@@ -564,7 +644,7 @@ impl GlCanvas {
     }
 
     pub fn set_slice_coronal(&mut self, slice: f32) -> Result<(), JsValue> {
-        self.coronal_view.as_mut().map(|v| v.y = slice);
+        self.move_cursor_to_slice(Orientation::Coronal, slice);
         Ok(())
     }
 
@@ -575,6 +655,178 @@ impl GlCanvas {
             .ok_or("data hasn't been initialized".into())
     }
 
+    // The cursor in the patient coordinates in mm. The transverse, sagittal
+    // and coronal views move to the slices through it.
+    pub fn set_cursor(&mut self, x: f32, y: f32, z: f32) -> Result<(), JsValue> {
+        let to_view = self.patient_to_view()?;
+        self.move_cursor(Vector3::from(to_view.transform_point(&[x, y, z])));
+        Ok(())
+    }
+
+    pub fn get_cursor(&self) -> Result<Box<[f32]>, JsValue> {
+        let primary = self.primary_volume.as_ref().ok_or("primary is not loaded.")?;
+        let c = self.cursor;
+        Ok(Box::new(primary.volume_info.view_to_patient().transform_point(&[c.x, c.y, c.z])))
+    }
+
+    // Move the cursor to the point under (x, y) of the canvas, in CSS pixels
+    // from the top left as in a mouse event, and return the name of the view
+    // there as in maximize. The 3D view and the background return undefined.
+    pub fn set_cursor_from_canvas(&mut self, x: f32, y: f32) -> Result<Option<String>, JsValue> {
+        Ok(self.pick(x, y).map(|(view, point)| {
+            self.move_cursor(point);
+            String::from(view.name())
+        }))
+    }
+
+    // Move the lens of the spyglass of the view under (x, y) of the canvas,
+    // as in set_cursor_from_canvas, to the point there. The cursor and the
+    // slices stay where they are.
+    pub fn set_lens_from_canvas(&mut self, x: f32, y: f32) -> Result<Option<String>, JsValue> {
+        let (view, point) = match self.pick(x, y) {
+            Some(picked) => picked,
+            None => return Ok(None),
+        };
+        let done = match view {
+            CanvasView::Transverse => self.trans_view.as_mut().map(|v| v.fusion = v.fusion.follow(point)),
+            CanvasView::Sagittal => self.sagi_view.as_mut().map(|v| v.fusion = v.fusion.follow(point)),
+            CanvasView::Coronal => self.coronal_view.as_mut().map(|v| v.fusion = v.fusion.follow(point)),
+            CanvasView::Oblique => self.oblique_view.as_mut().map(|v| v.fusion = v.fusion.follow(point)),
+            CanvasView::NeedleInline => self.needle_inline_view.as_mut().map(|v| v.fusion = v.fusion.follow(point)),
+            CanvasView::NeedlePerpendicular => {
+                self.needle_perpendicular_view.as_mut().map(|v| v.fusion = v.fusion.follow(point))
+            }
+            CanvasView::ThreeD => None,
+        };
+        done.ok_or("data hasn't been initialized")?;
+        self.scheduler.mark(view, Dirty::CONTENT);
+        Ok(Some(String::from(view.name())))
+    }
+
+    // The view under (x, y) in CSS pixels and the point there in the view
+    // frame, None on the 3D view and the background.
+    fn pick(&self, x: f32, y: f32) -> Option<(CanvasView, Vector3<f32>)> {
+        let (_, canvas_height) = self.layout_manager.dimension();
+        // the viewports are in pixels of the drawing buffer from the bottom left
+        let ratio = self.layout_manager.pixel_ratio();
+        let (x, y) = (x * ratio, canvas_height as f32 - y * ratio);
+        for view in CanvasView::ALL {
+            let (x0, y0, width, height) = self.layout_manager.layout(&view);
+            let (x1, y1) = (x - x0 as f32, y - y0 as f32);
+            if width <= 0 || height <= 0 || x1 < 0.0 || y1 < 0.0 || x1 >= width as f32 || y1 >= height as f32 {
+                continue;
+            }
+            let q = (x1 / width as f32 * 2.0 - 1.0, y1 / height as f32 * 2.0 - 1.0);
+            let point = match view {
+                CanvasView::Transverse => self.trans_view.as_ref().map(|v| v.slice_params().point(q)),
+                CanvasView::Sagittal => self.sagi_view.as_ref().map(|v| v.slice_params().point(q)),
+                CanvasView::Coronal => self.coronal_view.as_ref().map(|v| v.slice_params().point(q)),
+                CanvasView::ThreeD => None,
+                // on the plane as drawn
+                CanvasView::Oblique | CanvasView::NeedleInline | CanvasView::NeedlePerpendicular => {
+                    let oblique = match view {
+                        CanvasView::Oblique => self.oblique_view.as_ref(),
+                        CanvasView::NeedleInline => self.needle_inline_view.as_ref(),
+                        _ => self.needle_perpendicular_view.as_ref(),
+                    };
+                    oblique.map(|v| Vector3::from(v.plane_transform().transform_point(&[q.0, q.1, 0.0])))
+                }
+            };
+            return point.map(|p| (view, p));
+        }
+        None
+    }
+
+    // Off by default. The cursor and the slices stay where they are.
+    pub fn set_crosshair(&mut self, visible: bool, r: f32, g: f32, b: f32) {
+        self.crosshair_visible = visible;
+        self.crosshair_color = [r, g, b];
+        self.update_crosshair();
+    }
+
+    // `callback` is called with the cursor in the patient coordinates in mm,
+    // (x, y, z), whenever it moves, including by the slices of the views. It
+    // is called once per frame with the last cursor, in an animation frame
+    // queued by render_frame, so it may call back into the canvas. It must
+    // never be called synchronously from a method of the canvas, where the
+    // canvas is borrowed and calling into it throws.
+    pub fn set_cursor_callback(&mut self, callback: Option<js_sys::Function>) {
+        self.cursor_callback = callback;
+    }
+
+    fn crosshair(&self) -> Option<Crosshair> {
+        if self.crosshair_visible {
+            Some(Crosshair {
+                cursor: self.cursor,
+                color: self.crosshair_color,
+            })
+        } else {
+            None
+        }
+    }
+
+    // The transverse, sagittal and coronal views show the slices through
+    // `cursor`, and all the views but 3D draw the crosshair through it.
+    fn move_cursor(&mut self, cursor: Vector3<f32>) {
+        self.cursor = cursor;
+        // the slices are in 250 mm
+        let slice = |orientation: Orientation| orientation.axes()[2].dot(&cursor) / 250.0;
+        if let Some(v) = self.trans_view.as_mut() {
+            v.z = slice(Orientation::Transverse);
+            v.fusion = v.fusion.follow(cursor);
+        }
+        if let Some(v) = self.sagi_view.as_mut() {
+            v.x = slice(Orientation::Sagittal);
+            v.fusion = v.fusion.follow(cursor);
+        }
+        if let Some(v) = self.coronal_view.as_mut() {
+            v.y = slice(Orientation::Coronal);
+            v.fusion = v.fusion.follow(cursor);
+        }
+        for v in self.oblique_views_mut() {
+            v.fusion = v.fusion.follow(cursor);
+        }
+        self.update_crosshair();
+        self.cursor_moved = true;
+    }
+
+    // The crosshair of all the views but 3D from the cursor and the settings.
+    fn update_crosshair(&mut self) {
+        let crosshair = self.crosshair();
+        if let Some(v) = self.trans_view.as_mut() {
+            v.crosshair = crosshair;
+        }
+        if let Some(v) = self.sagi_view.as_mut() {
+            v.crosshair = crosshair;
+        }
+        if let Some(v) = self.coronal_view.as_mut() {
+            v.crosshair = crosshair;
+        }
+        for v in self.oblique_views_mut() {
+            v.crosshair = crosshair;
+        }
+        for view in CanvasView::ALL {
+            if view != CanvasView::ThreeD {
+                self.scheduler.mark(view, Dirty::CONTENT);
+            }
+        }
+    }
+
+    fn move_cursor_to_slice(&mut self, orientation: Orientation, slice: f32) {
+        let n = orientation.axes()[2];
+        self.move_cursor(self.cursor + n * (slice * 250.0 - n.dot(&self.cursor)));
+    }
+
+    // Queue the cursor callback, see set_cursor_callback.
+    fn notify_cursor(&self) {
+        let (callback, cursor) = match (self.cursor_callback.as_ref(), self.get_cursor()) {
+            (Some(callback), Ok(cursor)) => (callback, cursor),
+            _ => return,
+        };
+        let args = js_sys::Array::of3(&cursor[0].into(), &cursor[1].into(), &cursor[2].into());
+        self.scheduler.defer(callback.clone(), args);
+    }
+
     pub fn set_blend(&mut self, k: f32) -> Result<(), JsValue> {
         if k < 0.0 || k > 1.0 {
             return Err(JsValue::from("blend shall be in between 0.0 and 1.0."));
@@ -582,19 +834,179 @@ impl GlCanvas {
         self.trans_view.as_mut().map(|v| v.blend = k);
         self.sagi_view.as_mut().map(|v| v.blend = k);
         self.coronal_view.as_mut().map(|v| v.blend = k);
+        for v in self.oblique_views_mut() {
+            v.blend = k;
+        }
+        self.scheduler.mark_all(Dirty::CONTENT);
         Ok(())
     }
 
-    // pub fn set_secondary_lut(&mut self, lut: bool) -> Result<(), JsValue> {
-    //     self.set_uniform1i("lut", if lut { 1 } else { 0 })
-    // }
+    // How the secondary is fused with the primary on `view`, any but "3D":
+    // "blend" by set_blend, "checkerboard" in tiles of `size` mm, "difference"
+    // of the gray levels, or "spyglass" in a lens of radius `size` mm that
+    // follows the cursor, or set_lens_from_canvas.
+    pub fn set_fusion(&mut self, view: &str, mode: &str, size: f32) -> Result<(), JsValue> {
+        let target = CanvasView::from_name(view).ok_or(format!("unknown view: {}", view))?;
+        let fusion = Fusion::from_name(mode, size, self.cursor).ok_or(format!("unknown fusion mode: {}", mode))?;
+        let sized = matches!(fusion, Fusion::Checkerboard { .. } | Fusion::Spyglass { .. });
+        if sized && (!size.is_finite() || size <= 0.0) {
+            return Err(JsValue::from("the tiles and the lens shall be larger than 0 mm."));
+        }
+        let done = match target {
+            CanvasView::Transverse => self.trans_view.as_mut().map(|v| v.fusion = fusion),
+            CanvasView::Sagittal => self.sagi_view.as_mut().map(|v| v.fusion = fusion),
+            CanvasView::Coronal => self.coronal_view.as_mut().map(|v| v.fusion = fusion),
+            CanvasView::Oblique => self.oblique_view.as_mut().map(|v| v.fusion = fusion),
+            CanvasView::NeedleInline => self.needle_inline_view.as_mut().map(|v| v.fusion = fusion),
+            CanvasView::NeedlePerpendicular => self.needle_perpendicular_view.as_mut().map(|v| v.fusion = fusion),
+            CanvasView::ThreeD => return Err(JsValue::from("the 3D view has no fusion.")),
+        };
+        done.ok_or("data hasn't been initialized")?;
+        self.scheduler.mark(target, Dirty::CONTENT);
+        Ok(())
+    }
+
+    // Project a slab of `thickness` mm across the view "T", "S" or "C", with
+    // `mode` one of "plane", "max", "min" and "mean". The secondary is
+    // projected the same way if `field` is set.
+    pub fn set_slab(&mut self, view: &str, mode: &str, thickness: f32, field: bool) -> Result<(), JsValue> {
+        let mode = SlabMode::from_name(mode).ok_or(format!("unknown slab mode: {}", mode))?;
+        if !thickness.is_finite() || thickness < 0.0 {
+            return Err(JsValue::from("the slab thickness shall be a non-negative number."));
+        }
+        let slab = Slab { mode, thickness, field };
+        let (done, target) = match view {
+            "T" => (self.trans_view.as_mut().map(|v| v.slab = slab), CanvasView::Transverse),
+            "S" => (self.sagi_view.as_mut().map(|v| v.slab = slab), CanvasView::Sagittal),
+            "C" => (self.coronal_view.as_mut().map(|v| v.slab = slab), CanvasView::Coronal),
+            _ => return Err(JsValue::from(format!("unknown view: {}", view))),
+        };
+        done.ok_or("data hasn't been initialized")?;
+        self.scheduler.mark(target, Dirty::CONTENT);
+        Ok(())
+    }
+
+    // One of colormap_names, for the secondary on all the views.
+    pub fn set_secondary_colormap(&mut self, name: &str) -> Result<(), JsValue> {
+        let colormap = Colormap::from_name(name).ok_or(format!("unknown colormap {}", name))?;
+        self.set_colormap(colormap);
+        Ok(())
+    }
+
+    // `colors` given as r, g, b of 2 colors or more from the lowest value of
+    // the window, interpolated or in equal bands if `discrete`.
+    pub fn set_secondary_lut(&mut self, colors: Uint8Array, discrete: bool) -> Result<(), JsValue> {
+        let colormap = Colormap::from_colors("custom", &colors.to_vec(), discrete)?;
+        self.set_colormap(colormap);
+        Ok(())
+    }
+
+    pub fn colormap_names(&self) -> Vec<JsValue> {
+        colormap::NAMES.iter().map(|name| JsValue::from_str(name)).collect()
+    }
+
+    // The units of the secondary on the colorbar, e.g. V/cm.
+    pub fn set_secondary_units(&mut self, units: &str) {
+        self.annotations.borrow_mut().set_units(units);
+        self.mark_overlays_changed();
+    }
+
+    // The secondary is transparent below `lower` in its values.
+    pub fn set_secondary_threshold(&mut self, lower: f32) {
+        self.set_field_display(FieldDisplay::Colormap {
+            ramp: Some((lower, lower)),
+        });
+    }
+
+    // The opacity of the secondary goes from 0 at `lower` to 1 at `upper`.
+    pub fn set_secondary_opacity_ramp(&mut self, lower: f32, upper: f32) -> Result<(), JsValue> {
+        if lower.is_nan() || upper.is_nan() || lower > upper {
+            return Err(format!("the ramp from {} to {} is reversed.", lower, upper).into());
+        }
+        self.set_field_display(FieldDisplay::Colormap {
+            ramp: Some((lower, upper)),
+        });
+        Ok(())
+    }
+
+    // Paint only the bands of the secondary, e.g. the reversible, the
+    // irreversible and the thermal ranges, `ranges` given as lower, upper and
+    // `colors` as r, g, b for each of them. A value in more than one band is
+    // in the first one.
+    pub fn set_secondary_bands(&mut self, ranges: Float32Array, colors: Uint8Array) -> Result<(), JsValue> {
+        let ranges = ranges.to_vec();
+        let colors = colors.to_vec();
+        if !ranges.len().is_multiple_of(2) || colors.len() != ranges.len() / 2 * 3 {
+            return Err(format!(
+                "expected lower and upper and r, g, b for each band, got {} and {} values.",
+                ranges.len(),
+                colors.len()
+            )
+            .into());
+        }
+        let bands: Vec<FieldBand> = ranges
+            .chunks_exact(2)
+            .zip(colors.chunks_exact(3))
+            .map(|(r, c)| FieldBand {
+                lower: r[0],
+                upper: r[1],
+                color: [c[0], c[1], c[2]],
+            })
+            .collect();
+        self.set_field_display(FieldDisplay::bands(&bands)?);
+        Ok(())
+    }
+
+    // The colormap over the whole secondary again.
+    pub fn clear_secondary_display(&mut self) {
+        self.set_field_display(FieldDisplay::default());
+    }
+
+    fn set_field_display(&mut self, display: FieldDisplay) {
+        self.field_display = display;
+        self.trans_view
+            .as_mut()
+            .map(|x| x.dose.as_mut().map(|d| d.display = display));
+        self.sagi_view
+            .as_mut()
+            .map(|x| x.dose.as_mut().map(|d| d.display = display));
+        self.coronal_view
+            .as_mut()
+            .map(|x| x.dose.as_mut().map(|d| d.display = display));
+        self.three_d_view
+            .as_mut()
+            .map(|x| x.dose.as_mut().map(|d| d.display = display));
+        for dose in self.oblique_views_mut().filter_map(|v| v.dose.as_mut()) {
+            dose.display = display;
+        }
+        self.scheduler.mark_all(Dirty::CONTENT);
+    }
+
+    fn load_lut(context: &WebGl2RenderingContext, colormap: &Colormap) -> LoadedTexture {
+        Texture3DRGB8::new(colormap::SIZE as i32, 1, 1, Rc::new(colormap.lut().to_vec()))
+            .load_texture(&GLContext::new(context.clone()))
+    }
+
+    // The LUT texture is shared by the secondary of all the views.
+    fn set_colormap(&mut self, colormap: Colormap) {
+        *self.lut.borrow_mut() = Self::load_lut(&self.context, &colormap);
+        self.annotations.borrow_mut().set_colormap(colormap);
+        self.scheduler.mark_all(Dirty::CONTENT);
+    }
+
+    // The quad coordinates of a view span its viewport_extent.
+    fn view_extent(&self, view: &CanvasView) -> (f32, f32) {
+        let (_, _, width, height) = self.layout_manager.layout(view);
+        viewport_extent(width, height)
+    }
 
     pub fn get_transverse_coord(&self, x: f32, y: f32) -> Result<Box<[f32]>, JsValue> {
         let scale = &self.get_scale_transverse()?;
         let pan_x = &self.get_pan_transverse_x()?;
         let pan_y = &self.get_pan_transverse_y()?;
-        let x1 = (x - pan_x) / scale * 250.0;
-        let y1 = (y - pan_y) / scale * 250.0;
+        let (extent_x, extent_y) = self.view_extent(&CanvasView::Transverse);
+        let x1 = (x * extent_x - pan_x) / scale * 250.0;
+        let y1 = (y * extent_y - pan_y) / scale * 250.0;
         let v = vec![x1, y1];
         Ok(v.into_boxed_slice())
     }
@@ -603,8 +1015,9 @@ impl GlCanvas {
         let scale = &self.get_scale_sagittal()?;
         let pan_x = &self.get_pan_sagittal_x()?;
         let pan_y = &self.get_pan_sagittal_y()?;
-        let x1 = (x - pan_x) / scale * 250.0;
-        let y1 = (y - pan_y) / scale * 250.0;
+        let (extent_x, extent_y) = self.view_extent(&CanvasView::Sagittal);
+        let x1 = (x * extent_x - pan_x) / scale * 250.0;
+        let y1 = (y * extent_y - pan_y) / scale * 250.0;
         let v = vec![x1, y1];
         Ok(v.into_boxed_slice())
     }
@@ -613,71 +1026,40 @@ impl GlCanvas {
         let scale = &self.get_scale_coronal()?;
         let pan_x = &self.get_pan_coronal_x()?;
         let pan_y = &self.get_pan_coronal_y()?;
-        let x1 = (x - pan_x) / scale * 250.0;
-        let y1 = (y - pan_y) / scale * 250.0;
+        let (extent_x, extent_y) = self.view_extent(&CanvasView::Coronal);
+        let x1 = (x * extent_x - pan_x) / scale * 250.0;
+        let y1 = (y * extent_y - pan_y) / scale * 250.0;
         let v = vec![x1, y1];
         Ok(v.into_boxed_slice())
     }
 
-    pub fn get_transverse_value_in_primary(&self, x: f32, y: f32) -> Result<i16, JsValue> {
-        let scale = &self.get_scale_transverse()?;
-        let pan_x = &self.get_pan_transverse_x()?;
-        let pan_y = &self.get_pan_transverse_y()?;
+    // The value of the primary at (x, y) of the transverse view, which are in
+    // mm from the center of the view with y pointing up, in the real world
+    // values of the primary, e.g. Hounsfield units. The row and the column
+    // are found through the patient transform, and the slice is the nearest
+    // of the slice locations, which need not be evenly spaced.
+    pub fn get_transverse_value_in_primary(&self, x: f32, y: f32) -> Result<f32, JsValue> {
         let primary = self.primary_volume.as_ref().ok_or("error: primary")?;
-        let (width, height, depth) = primary.volume_info.get_dimension();
-        let (sx, sy, sz) = primary.volume_info.get_spacing().ok_or("error")?;
+        let info = &primary.volume_info;
+        let (width, height, depth) = info.get_dimension();
+        // the slice is in 250 mm
+        let z = self.get_slice_transverse()? * 250.0;
+        let to_voxel = info
+            .patient_to_voxel()
+            .ok_or("cannot invert the voxel to patient transform")?
+            .compose(&info.view_to_patient());
+        let v = to_voxel.transform_point(&[x, -y, z]);
         let z_locations = self
             .primary_slice_locations
             .as_ref()
             .ok_or("cannot retrieve slice locations")?;
-        let zloc = &z_locations.loc;
-        let len = zloc.len();
-        let min = zloc[0];
-        let max = zloc[len - 1];
-        let data = &primary.data;
-        let x1 = x; //(x - pan_x) / scale;
-        let y1 = y; //(y - pan_y) / scale;
-        let z1 = &self.get_slice_transverse()?;
-
-        // do the calculation
-        // let nx: usize = (x1 / sx * 250.0 + (width as f32) / 2.0).round() as usize;
-        // let ny: usize = (-y1 / sy * 250.0 + (height as f32) / 2.0).round() as usize;
-        let nx: usize = (x1 / sx + (width as f32) / 2.0).round() as usize;
-        let ny: usize = (-y1 / sy + (height as f32) / 2.0).round() as usize;
-
-        // let norm_loc: Vec<f32> = loc.iter().map(|x| (x - min) / (max - min)).collect();
-        let k = (max - min) / 500.0;
-        let nz: usize = zloc
-            .iter()
-            .map(|x| k * ((x - min) / (max - min) * 2.0 - 1.0))
-            .map(|x| (x - z1).abs())
-            .enumerate()
-            .min_by(|x, y| x.1.partial_cmp(&y.1).unwrap_or(Ordering::Equal))
-            .ok_or("cannot find z slice")?
-            .0;
-        log_object(unsafe { &js_sys::Float32Array::view(&zloc) });
-        log(format!(
-            "x:{}, x1:{}, nx: {}, sx: {}, width: {}",
-            x, x1, nx, sx, width
-        )
-        .as_str());
-        log(format!(
-            "y:{}, y1:{}, ny: {}, sy: {}, width: {}",
-            y, y1, ny, sy, width
-        )
-        .as_str());
-        log(format!(
-            "z:{}, z1:{}, nz: {}, sz: {}, width: {}",
-            z1 * 250.0,
-            z1,
-            nz,
-            sz,
-            width
-        )
-        .as_str());
-        let index = nz * (width * height) as usize + ny * width as usize + nx;
-        let value = data[index] - PIXEL_VAL_TO_POSITIVE as i16;
-        Ok(value)
+        let nz = z_locations.nearest(z).map(|i| i as i32).unwrap_or(-1);
+        let (nx, ny) = (v[0].round() as i32, v[1].round() as i32);
+        if nx < 0 || nx >= width || ny < 0 || ny >= height || nz < 0 || nz >= depth {
+            return Err(JsValue::from("the position is out of the primary."));
+        }
+        let index = (nz * width * height + ny * width + nx) as usize;
+        Ok(primary.get_real_value(index))
     }
 
     pub fn get_normalized_slice_loc(&self) -> Result<Box<[f32]>, JsValue> {
@@ -792,6 +1174,7 @@ impl GlCanvas {
         self.coronal_view
             .as_mut()
             .map(|v| v.needles.borrow_mut().uah = (u0, a, h));
+        self.scheduler.mark_all(Dirty::CONTENT);
         Ok(())
     }
 
@@ -806,6 +1189,7 @@ impl GlCanvas {
         self.coronal_view
             .as_mut()
             .map(|v| v.needles.borrow_mut().needle_pos = (x, y, z));
+        self.scheduler.mark_all(Dirty::CONTENT);
         Ok(())
     }
 
@@ -820,6 +1204,7 @@ impl GlCanvas {
         self.coronal_view
             .as_mut()
             .map(|v| v.needles.borrow_mut().needle_rot = theta);
+        self.scheduler.mark_all(Dirty::CONTENT);
         Ok(())
     }
     pub fn set_needle_length(&mut self, len: f32) -> Result<(), JsValue> {
@@ -833,66 +1218,363 @@ impl GlCanvas {
         self.coronal_view
             .as_mut()
             .map(|v| v.needles.borrow_mut().needle_length = len);
+        self.scheduler.mark_all(Dirty::CONTENT);
         Ok(())
     }
 
+    // The size of the canvas in CSS pixels. The drawing buffer is resized to
+    // it times the device pixel ratio and the views are laid out on it.
     pub fn update_canvas_dim(&mut self, width: i32, height: i32) -> Result<(), JsValue> {
-        self.trans_view.as_mut().map(|v| {
-            self.layout_manager.set_width(width);
-            self.layout_manager.set_height(height);
-            // v.size = self.layout_manager.layout(&CanvasView::Transverse);
-        });
-        self.sagi_view.as_mut().map(|v| {
-            self.layout_manager.set_width(width);
-            self.layout_manager.set_height(height);
-            // v.size = self.layout_manager.layout(&CanvasView::Sagittal);
-        });
-        self.coronal_view.as_mut().map(|v| {
-            self.layout_manager.set_width(width);
-            self.layout_manager.set_height(height);
-            // v.size = self.layout_manager.layout(&CanvasView::Coronal);
+        self.layout_manager.set_css_size(width.max(0), height.max(0));
+        self.resize_drawing_buffer()?;
+        self.scheduler.mark_all(Dirty::LAYOUT);
+        Ok(())
+    }
+
+    // window.devicePixelRatio, for the canvas to be drawn at the resolution
+    // of the screen. The annotations are scaled with it.
+    pub fn set_device_pixel_ratio(&mut self, ratio: f32) -> Result<(), JsValue> {
+        if !ratio.is_finite() || ratio <= 0.0 {
+            return Err(format!("invalid device pixel ratio {}", ratio).into());
+        }
+        self.layout_manager.set_pixel_ratio(ratio);
+        self.update_text_scale();
+        self.resize_drawing_buffer()?;
+        self.scheduler.mark_all(Dirty::LAYOUT);
+        Ok(())
+    }
+
+    fn resize_drawing_buffer(&self) -> Result<(), JsValue> {
+        let canvas = self
+            .context
+            .canvas()
+            .ok_or("the context has no canvas")?
+            .dyn_into::<web_sys::HtmlCanvasElement>()?;
+        let (width, height) = self.layout_manager.dimension();
+        // resizing clears the canvas even to the same size
+        if canvas.width() != width as u32 || canvas.height() != height as u32 {
+            canvas.set_width(width as u32);
+            canvas.set_height(height as u32);
+        }
+        Ok(())
+    }
+
+    // "1+3" for a large view with three small ones beside it, or "1x1",
+    // "1x2", "2x2" and "1x3" in rows x columns from the maximized view.
+    pub fn set_layout(&mut self, name: &str) -> Result<(), JsValue> {
+        let kind = LayoutKind::from_name(name).ok_or(format!("unknown layout {}", name))?;
+        self.layout_manager.set_kind(kind);
+        self.scheduler.mark_all(Dirty::LAYOUT);
+        Ok(())
+    }
+
+    // `views` named as maximize and separated by commas, in the cells row by
+    // row from the top left.
+    pub fn set_layout_grid(&mut self, columns: i32, rows: i32, views: &str) -> Result<(), JsValue> {
+        if columns < 1 || rows < 1 {
+            return Err(format!("invalid grid of {} x {}", rows, columns).into());
+        }
+        let mut cells: Vec<CanvasView> = Vec::new();
+        for name in views.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+            let view = CanvasView::from_name(name).ok_or(format!("unknown view: {}", name))?;
+            if cells.contains(&view) {
+                return Err(format!("view {} is given twice", name).into());
+            }
+            cells.push(view);
+        }
+        if cells.is_empty() || cells.len() > (columns * rows) as usize {
+            return Err(format!("expected 1 to {} views, got {}", columns * rows, cells.len()).into());
+        }
+        self.layout_manager.set_kind(LayoutKind::Custom {
+            columns,
+            rows,
+            views: cells,
         });
+        self.scheduler.mark_all(Dirty::LAYOUT);
         Ok(())
     }
 
     pub fn maximize(&mut self, t: &str) -> Result<(), JsValue> {
-        match t {
-            "T" => {
-                self.layout_manager.set_maximized(CanvasView::Transverse);
-                // self.update_layout();
-                return Ok(());
-            }
-            "S" => {
-                self.layout_manager.set_maximized(CanvasView::Sagittal);
-                // self.update_layout();
-                return Ok(());
-            }
-            "C" => {
-                self.layout_manager.set_maximized(CanvasView::Coronal);
-                // self.update_layout();
-                return Ok(());
-            }
-            "3D" => {
-                self.layout_manager.set_maximized(CanvasView::ThreeD);
-                // self.update_layout();
-                return Ok(());
+        let view = CanvasView::from_name(t).ok_or_else(JsValue::undefined)?;
+        self.layout_manager.set_maximized(view);
+        self.scheduler.mark_all(Dirty::LAYOUT);
+        Ok(())
+    }
+
+    fn update_layout(&mut self) {
+        for view in CanvasView::ALL {
+            let size = self.layout_manager.layout(&view);
+            if let Some(s) = self.view_size_mut(view) {
+                *s = size;
             }
-            _ => return Err(JsValue::undefined()),
         }
     }
 
-    fn update_layout(&mut self) {
-        self.trans_view
-            .as_mut()
-            .map(|v| v.size = self.layout_manager.layout(&CanvasView::Transverse));
-        self.sagi_view
+    // The viewport of a view, None if it is not set up.
+    fn view_size_mut(&mut self, view: CanvasView) -> Option<&mut (i32, i32, i32, i32)> {
+        match view {
+            CanvasView::Transverse => self.trans_view.as_mut().map(|v| &mut v.size),
+            CanvasView::Sagittal => self.sagi_view.as_mut().map(|v| &mut v.size),
+            CanvasView::Coronal => self.coronal_view.as_mut().map(|v| &mut v.size),
+            CanvasView::ThreeD => self.three_d_view.as_mut().map(|v| &mut v.size),
+            CanvasView::Oblique => self.oblique_view.as_mut().map(|v| &mut v.size),
+            CanvasView::NeedleInline => self.needle_inline_view.as_mut().map(|v| &mut v.size),
+            CanvasView::NeedlePerpendicular => self.needle_perpendicular_view.as_mut().map(|v| &mut v.size),
+        }
+    }
+
+    fn oblique_views_mut(&mut self) -> impl Iterator<Item = &mut ObliqueView> {
+        self.oblique_view
+            .iter_mut()
+            .chain(self.needle_inline_view.iter_mut())
+            .chain(self.needle_perpendicular_view.iter_mut())
+    }
+
+    // The oblique plane is in the view frame of the primary, i.e. in mm from
+    // its center along its axes; `up` is the direction to the top of the view.
+    pub fn set_oblique_origin(&mut self, x: f32, y: f32, z: f32) -> Result<(), JsValue> {
+        let view = self.oblique_view.as_mut().ok_or("data hasn't been initialized")?;
+        view.origin = Vector3::new(x, y, z);
+        self.scheduler.mark(CanvasView::Oblique, Dirty::CONTENT);
+        Ok(())
+    }
+
+    pub fn set_oblique_orientation(
+        &mut self,
+        nx: f32,
+        ny: f32,
+        nz: f32,
+        ux: f32,
+        uy: f32,
+        uz: f32,
+    ) -> Result<(), JsValue> {
+        let view = self.oblique_view.as_mut().ok_or("data hasn't been initialized")?;
+        view.set_plane(view.origin, Vector3::new(nx, ny, nz), Vector3::new(ux, uy, uz))?;
+        self.scheduler.mark(CanvasView::Oblique, Dirty::CONTENT);
+        Ok(())
+    }
+
+    // [origin, normal, up], 9 values.
+    pub fn get_oblique_plane(&self) -> Result<Box<[f32]>, JsValue> {
+        let view = self.oblique_view.as_ref().ok_or("data hasn't been initialized")?;
+        let v: Vec<f32> = [view.origin, view.normal, view.up]
+            .iter()
+            .flat_map(|v| v.iter().copied())
+            .collect();
+        Ok(v.into_boxed_slice())
+    }
+
+    // Spin the oblique view about its normal, in radian.
+    pub fn rotate_oblique(&mut self, angle: f32) -> Result<(), JsValue> {
+        let view = self.oblique_view.as_mut().ok_or("data hasn't been initialized")?;
+        view.rotate(angle);
+        self.scheduler.mark(CanvasView::Oblique, Dirty::CONTENT);
+        Ok(())
+    }
+
+    // Tilt the oblique plane about the horizontal and the vertical axes of the
+    // view, in radian.
+    pub fn tilt_oblique(&mut self, about_horizontal: f32, about_vertical: f32) -> Result<(), JsValue> {
+        let view = self.oblique_view.as_mut().ok_or("data hasn't been initialized")?;
+        view.tilt(about_horizontal, about_vertical);
+        self.scheduler.mark(CanvasView::Oblique, Dirty::CONTENT);
+        Ok(())
+    }
+
+    // Move the oblique plane along its normal, in mm.
+    pub fn move_oblique(&mut self, distance: f32) -> Result<(), JsValue> {
+        let view = self.oblique_view.as_mut().ok_or("data hasn't been initialized")?;
+        view.origin += view.normal * distance;
+        self.scheduler.mark(CanvasView::Oblique, Dirty::CONTENT);
+        Ok(())
+    }
+
+    pub fn set_scale_oblique(&mut self, scale: f32) -> Result<(), JsValue> {
+        let view = self.oblique_view.as_mut().ok_or("data hasn't been initialized")?;
+        view.scale = scale;
+        self.scheduler.mark(CanvasView::Oblique, Dirty::CONTENT);
+        Ok(())
+    }
+
+    pub fn set_pan_oblique(&mut self, x: f32, y: f32) -> Result<(), JsValue> {
+        let view = self.oblique_view.as_mut().ok_or("data hasn't been initialized")?;
+        view.pan_x = x;
+        view.pan_y = y;
+        self.scheduler.mark(CanvasView::Oblique, Dirty::CONTENT);
+        Ok(())
+    }
+
+    pub fn set_scale_needle_views(&mut self, scale: f32) -> Result<(), JsValue> {
+        let views = self.needle_inline_view.iter_mut().chain(self.needle_perpendicular_view.iter_mut());
+        for view in views {
+            view.scale = scale;
+        }
+        self.scheduler.mark(CanvasView::NeedleInline, Dirty::CONTENT);
+        self.scheduler.mark(CanvasView::NeedlePerpendicular, Dirty::CONTENT);
+        Ok(())
+    }
+
+    // The point at (x, y) of the oblique view, in [-1, 1] with y pointing up,
+    // in the view frame of the primary.
+    pub fn get_oblique_coord(&self, x: f32, y: f32) -> Result<Box<[f32]>, JsValue> {
+        let view = self.oblique_view.as_ref().ok_or("data hasn't been initialized")?;
+        let p = view.plane_transform().transform_point(&[x, y, 0.0]);
+        Ok(Box::new(p))
+    }
+
+    // `points` is [value, r, g, b, a, ...] with the values in the real world
+    // values of the primary and the colors and opacities in [0, 1].
+    pub fn set_transfer_function(&mut self, points: Float32Array) -> Result<(), JsValue> {
+        let tf = TransferFunction::from_flat(&points.to_vec())?;
+        self.apply_transfer_function(tf);
+        Ok(())
+    }
+
+    // One of "bone", "soft tissue" and "lung".
+    pub fn set_transfer_function_preset(&mut self, name: &str) -> Result<(), JsValue> {
+        let tf = TransferFunction::preset(name).ok_or(format!("unknown transfer function: {}", name))?;
+        self.apply_transfer_function(tf);
+        Ok(())
+    }
+
+    fn apply_transfer_function(&mut self, tf: TransferFunction) {
+        if let Some(view) = self.three_d_view.as_mut() {
+            view.transfer = Rc::new(RefCell::new(Self::load_transfer_function(&self.context, &tf)));
+            view.transfer_range = tf.range();
+        }
+        self.transfer_function = tf;
+        self.scheduler.mark(CanvasView::ThreeD, Dirty::CONTENT);
+    }
+
+    fn load_transfer_function(context: &WebGl2RenderingContext, tf: &TransferFunction) -> LoadedTexture {
+        Texture3DRGBA8::new(256, 1, 1, Rc::new(tf.lut(256))).load_texture(&GLContext::new(context.clone()))
+    }
+
+    // The azimuth and elevation of the 3D camera in radian.
+    pub fn set_rotation_3d(&mut self, azimuth: f32, elevation: f32) -> Result<(), JsValue> {
+        let view = self.three_d_view.as_mut().ok_or("data hasn't been initialized")?;
+        view.azimuth = azimuth;
+        view.elevation = elevation.clamp(-std::f32::consts::FRAC_PI_2, std::f32::consts::FRAC_PI_2);
+        self.scheduler.mark(CanvasView::ThreeD, Dirty::CONTENT);
+        Ok(())
+    }
+
+    pub fn get_rotation_3d(&self) -> Result<Box<[f32]>, JsValue> {
+        let view = self.three_d_view.as_ref().ok_or("data hasn't been initialized")?;
+        Ok(vec![view.azimuth, view.elevation].into_boxed_slice())
+    }
+
+    pub fn set_scale_3d(&mut self, scale: f32) -> Result<(), JsValue> {
+        self.three_d_view
             .as_mut()
-            .map(|v| v.size = self.layout_manager.layout(&CanvasView::Sagittal));
-        self.coronal_view
+            .map(|v| v.scale = scale)
+            .ok_or("data hasn't been initialized")?;
+        self.scheduler.mark(CanvasView::ThreeD, Dirty::CONTENT);
+        Ok(())
+    }
+
+    // Add a surface in the patient coordinates to the 3D view and return its
+    // id. The surfaces are cleared when a new primary is loaded.
+    pub fn add_surface_3d(&mut self, surface: &Surface, r: f32, g: f32, b: f32, opacity: f32) -> Result<u32, JsValue> {
+        let model = self.patient_to_view()?;
+        self.add_mesh_3d(surface, model.to_column_major(), [r, g, b, opacity], false)
+    }
+
+    pub fn add_structure_3d(&mut self, structure: &Structure, opacity: f32) -> Result<u32, JsValue> {
+        let c = structure.get_color();
+        let color = [c[0] as f32 / 255.0, c[1] as f32 / 255.0, c[2] as f32 / 255.0, opacity];
+        let model = self.patient_to_view()?;
+        self.add_mesh_3d(&structure.to_mesh(), model.to_column_major(), color, false)
+    }
+
+    // The isosurface of the secondary at `isovalue`, placed and moved with
+    // the needles like the secondary in the views.
+    pub fn add_secondary_isosurface_3d(
+        &mut self,
+        isovalue: f32,
+        r: f32,
+        g: f32,
+        b: f32,
+        opacity: f32,
+    ) -> Result<u32, JsValue> {
+        let volume = self.secondary_volume.as_ref().ok_or("secondary is not loaded.")?;
+        let (w, h, d) = volume.info.get_dimension();
+        let surface = marching_cubes_impl(&volume.data, isovalue, w, h, d);
+        let follow_needles = self
+            .trans_view
+            .as_ref()
+            .and_then(|v| v.dose.as_ref())
+            .map(|d| d.follow_needles)
+            .unwrap_or(false);
+        let model = self
+            .patient_to_view()?
+            .compose(&self.placed_secondary_info()?.voxel_to_patient());
+        self.add_mesh_3d(&surface, model.to_column_major(), [r, g, b, opacity], follow_needles)
+    }
+
+    pub fn set_surface_color_3d(&mut self, id: u32, r: f32, g: f32, b: f32, opacity: f32) -> Result<(), JsValue> {
+        let view = self.three_d_view.as_mut().ok_or("data hasn't been initialized")?;
+        let mesh = view.find_mesh_mut(id).ok_or(format!("cannot find surface {}", id))?;
+        mesh.color = [r, g, b, opacity];
+        self.scheduler.mark(CanvasView::ThreeD, Dirty::CONTENT);
+        Ok(())
+    }
+
+    pub fn set_surface_visible_3d(&mut self, id: u32, visible: bool) -> Result<(), JsValue> {
+        let view = self.three_d_view.as_mut().ok_or("data hasn't been initialized")?;
+        let mesh = view.find_mesh_mut(id).ok_or(format!("cannot find surface {}", id))?;
+        mesh.visible = visible;
+        self.scheduler.mark(CanvasView::ThreeD, Dirty::CONTENT);
+        Ok(())
+    }
+
+    pub fn remove_surface_3d(&mut self, id: u32) -> Result<(), JsValue> {
+        let view = self.three_d_view.as_mut().ok_or("data hasn't been initialized")?;
+        if view.remove_mesh(id) {
+            self.scheduler.mark(CanvasView::ThreeD, Dirty::CONTENT);
+            Ok(())
+        } else {
+            Err(format!("cannot find surface {}", id).into())
+        }
+    }
+
+    fn add_mesh_3d(&mut self, surface: &Surface, model: [f32; 16], color: [f32; 4], follow_needles: bool) -> Result<u32, JsValue> {
+        if surface.is_empty() {
+            return Err("the surface is empty.".into());
+        }
+        let geometry = MeshGeometry::new(
+            &GLContext::new(self.context.clone()),
+            surface.vertex_buffer(),
+            surface.normal_buffer(),
+            surface.index_buffer(),
+        )?;
+        let view = self.three_d_view.as_mut().ok_or("data hasn't been initialized")?;
+        let id = view.add_mesh(geometry, model, color, follow_needles);
+        self.scheduler.mark(CanvasView::ThreeD, Dirty::CONTENT);
+        Ok(id)
+    }
+
+    // From the patient coordinates to the view frame of the primary.
+    fn patient_to_view(&self) -> Result<Base, String> {
+        let primary = self.primary_volume.as_ref().ok_or("primary is not loaded.")?;
+        primary
+            .volume_info
+            .view_to_patient()
+            .inverse()
+            .ok_or(String::from("the orientation of the primary is singular."))
+    }
+
+    // Opacity per mm of the field above the lower end of its window, 0 hides it.
+    pub fn set_field_opacity_3d(&mut self, opacity: f32) -> Result<(), JsValue> {
+        self.three_d_view
             .as_mut()
-            .map(|v| v.size = self.layout_manager.layout(&CanvasView::Coronal));
+            .map(|v| v.field_opacity = opacity.clamp(0.0, 1.0))
+            .ok_or("data hasn't been initialized")?;
+        self.scheduler.mark(CanvasView::ThreeD, Dirty::CONTENT);
+        Ok(())
     }
 
+    #[allow(clippy::too_many_arguments)]
     pub fn load_primary(
         &mut self,
         buffer: ArrayBuffer,
@@ -954,10 +1636,61 @@ impl GlCanvas {
         let spacing = (spacing_x, spacing_y, spacing_z);
         let mut vinfo = VolumeInfo::new(VolumeDataType::Int16, w, h, d as i32);
         vinfo.set_spacing(spacing);
-        self.primary_volume = Some(GlVolume::from_array_buffer(
+        let volume = GlVolume::from_array_buffer(
             // self.context.clone(),
             buffer, vinfo, 0,
-        ));
+        );
+        self.load_primary_volume(volume, z)
+    }
+
+    // Load a primary image series from DICOM files. `files` is an array of
+    // ArrayBuffers. If the files contain several series, the one specified by
+    // `series_uid` is loaded, otherwise the one with the most slices.
+    pub fn load_primary_dicom(&mut self, files: Array, series_uid: Option<String>) -> Result<(), JsValue> {
+        info!("info: load_primary_dicom");
+        let buffers: Vec<Vec<u8>> = files.iter().map(|f| Uint8Array::new(&f).to_vec()).collect();
+        let slices: Vec<&[u8]> = buffers.iter().map(|b| b.as_slice()).collect();
+        let series = load_ct_series(&slices)?;
+        let series = match series_uid {
+            Some(uid) => series
+                .into_iter()
+                .find(|s| s.series_instance_uid == uid)
+                .ok_or(format!("cannot find series {}", uid))?,
+            None => series
+                .into_iter()
+                .max_by_key(|s| s.info.get_depth())
+                .ok_or("no image series found in the files.")?,
+        };
+        info!(
+            "series {}: {:?}",
+            &series.series_instance_uid,
+            series.info.get_dimension()
+        );
+        let z = series
+            .info
+            .get_slice_locations()
+            .ok_or("cannot retrieve slice locations")?
+            .clone();
+        let reference = series.reference();
+        let mut volume = GlVolume::from_vec(series.data, series.info);
+        volume.series = Some(reference);
+        self.load_primary_volume(volume, z)
+    }
+
+    fn load_primary_volume(&mut self, volume: GlVolume, z: Vec<f32>) -> Result<(), JsValue> {
+        let (w, h, d) = volume.volume_info.get_dimension();
+        let spacing = volume.volume_info.get_spacing().ok_or("spacing is missing")?;
+        let (spacing_x, spacing_y, spacing_z) = spacing;
+        // the texture covers the whole voxels of the first and last slices
+        let size_z: f32 = d as f32 * spacing_z;
+        self.annotations
+            .borrow_mut()
+            .set_patient_transform(volume.volume_info.view_to_patient());
+        self.primary_volume = Some(volume);
+        // the structures and the secondary are placed with the primary
+        self.structures.borrow_mut().clear();
+        self.isolines.borrow_mut().clear_field();
+        self.cursor = Vector3::zeros();
 
         let zloc = ZLocations::new(self.context.clone(), 4, z.as_slice());
         log("after ZLocations::new");
@@ -968,15 +1701,14 @@ impl GlCanvas {
         self.primary_slice_locations = Some(zloc);
 
         if let Some(volume) = self.primary_volume.as_ref() {
+            let texture: Texture3DR16I = volume.gen_texture3d();
             let texture = Rc::new(RefCell::new(
-                volume
-                    .gen_texture3d()
-                    .load_texture(&GLContext::new(self.context.clone())),
+                texture.load_texture(&GLContext::new(self.context.clone())),
             ));
             let trans_prog = load_shaders(
                 &self.context,
                 VERTEX_SHADER_SOURCE,
-                FRAGMENT_SHADER_SOURCE_TRANS,
+                &slice_fragment_shader(Orientation::Transverse).0,
             )?;
             let needles = Rc::new(RefCell::new(TwoNeedleGeometry {
                 uah: (15000., 0.5, 10.),
@@ -988,7 +1720,8 @@ impl GlCanvas {
                 texture: texture.clone(),
                 z_loc: self.primary_loc_tex.as_ref().unwrap().clone(),
                 window: 600.,
-                level: 1600.,
+                level: 100.,
+                rescale: volume.volume_info.get_rescale(),
                 spacing,
                 size: (w as f32 * spacing_x, h as f32 * spacing_y, size_z),
             };
@@ -1004,16 +1737,20 @@ impl GlCanvas {
                 pan_y: 0.,
                 size: self.layout_manager.layout(&CanvasView::Transverse),
                 blend: 0.5,
+                fusion: Fusion::default(),
                 needles: needles.clone(),
                 num_of_indices: 6,
                 minmax: (0., 0.),
+                slab: Slab::default(),
                 ct: ct.clone(),
                 dose: None,
+                overlay: self.new_overlay_pane(),
+                crosshair: self.crosshair(),
             };
             let sagi_prog = load_shaders(
                 &self.context,
                 VERTEX_SHADER_SOURCE,
-                FRAGMENT_SHADER_SOURCE_SAGITTAL,
+                &slice_fragment_shader(Orientation::Sagittal).0,
             )?;
 
             let sagi_view = SagittalView {
@@ -1025,16 +1762,20 @@ impl GlCanvas {
                 pan_y: 0.,
                 size: self.layout_manager.layout(&CanvasView::Sagittal),
                 blend: 0.5,
+                fusion: Fusion::default(),
                 needles: needles.clone(),
                 num_of_indices: 6,
+                slab: Slab::default(),
                 ct: ct.clone(),
                 dose: None,
+                overlay: self.new_overlay_pane(),
+                crosshair: self.crosshair(),
             };
 
             let coronal_prog = load_shaders(
                 &self.context,
                 VERTEX_SHADER_SOURCE,
-                FRAGMENT_SHADER_SOURCE_CORONAL,
+                &slice_fragment_shader(Orientation::Coronal).0,
             )?;
             let coronal_view = CoronalView {
                 context: GLContext::new(self.context.clone()),
@@ -1045,23 +1786,119 @@ impl GlCanvas {
                 pan_y: 0.,
                 size: self.layout_manager.layout(&CanvasView::Coronal),
                 blend: 0.5,
+                fusion: Fusion::default(),
                 needles: needles.clone(),
                 num_of_indices: 6,
+                slab: Slab::default(),
                 ct: ct.clone(),
                 dose: None,
+                overlay: self.new_overlay_pane(),
+                crosshair: self.crosshair(),
+            };
+
+            let three_d_prog = load_shaders(
+                &self.context,
+                VERTEX_SHADER_SOURCE,
+                &three_d_fragment_shader().0,
+            )?;
+            let transfer = Self::load_transfer_function(&self.context, &self.transfer_function);
+            let mesh_prog = load_shaders(
+                &self.context,
+                VERTEX_SHADER_SOURCE_MESH,
+                FRAGMENT_SHADER_SOURCE_MESH,
+            )?;
+            let three_d_view = ThreeDView {
+                context: GLContext::new(self.context.clone()),
+                program: three_d_prog,
+                scale: 1.,
+                azimuth: 0.,
+                elevation: 0.,
+                size: self.layout_manager.layout(&CanvasView::ThreeD),
+                step: spacing_x.min(spacing_y).min(spacing_z).max(0.5),
+                needles: needles.clone(),
+                num_of_indices: 6,
+                transfer: Rc::new(RefCell::new(transfer)),
+                transfer_range: self.transfer_function.range(),
+                field_opacity: 0.2,
+                ct: ct.clone(),
+                dose: None,
+                mesh_program: mesh_prog,
+                meshes: Vec::new(),
+                next_mesh_id: 0,
             };
 
             self.trans_view = Some(trans_view);
             self.sagi_view = Some(sagi_view);
             self.coronal_view = Some(coronal_view);
+            self.three_d_view = Some(three_d_view);
+
+            self.oblique_view = Some(self.new_oblique_view(&needles, &ct, CanvasView::Oblique, None)?);
+            self.needle_inline_view = Some(self.new_oblique_view(
+                &needles,
+                &ct,
+                CanvasView::NeedleInline,
+                Some(NeedleAlignment::Inline),
+            )?);
+            self.needle_perpendicular_view = Some(self.new_oblique_view(
+                &needles,
+                &ct,
+                CanvasView::NeedlePerpendicular,
+                Some(NeedleAlignment::Perpendicular),
+            )?);
         }
         self.set_primary_spacing(spacing)?;
         self.set_primary_size(w as f32 * spacing_x, h as f32 * spacing_y, size_z)?;
 
+        self.scheduler.mark_all(Dirty::CONTENT);
         log("finished load_primary_with_slice_locations");
         Ok(())
     }
 
+    fn new_overlay_pane(&self) -> OverlayPane {
+        OverlayPane::new(
+            &GLContext::new(self.context.clone()),
+            self.structures.clone(),
+            self.isolines.clone(),
+            self.annotations.clone(),
+        )
+    }
+
+    // Starts as the transverse view unless it follows the needles.
+    fn new_oblique_view(
+        &self,
+        needles: &Rc<RefCell<TwoNeedleGeometry>>,
+        ct: &CTPane,
+        kind: CanvasView,
+        alignment: Option<NeedleAlignment>,
+    ) -> Result<ObliqueView, JsValue> {
+        let program = load_shaders(
+            &self.context,
+            VERTEX_SHADER_SOURCE,
+            &oblique_fragment_shader().0,
+        )?;
+        Ok(ObliqueView {
+            context: GLContext::new(self.context.clone()),
+            program,
+            // 100 mm across for the needles
+            scale: if alignment.is_some() { 5. } else { 1. },
+            pan_x: 0.,
+            pan_y: 0.,
+            size: self.layout_manager.layout(&kind),
+            blend: 0.5,
+            fusion: Fusion::default(),
+            needles: needles.clone(),
+            num_of_indices: 6,
+            origin: Vector3::zeros(),
+            normal: Vector3::z(),
+            up: -Vector3::y(),
+            alignment,
+            ct: ct.clone(),
+            dose: None,
+            crosshair: self.crosshair(),
+        })
+    }
+
+    #[allow(clippy::too_many_arguments)]
     pub fn load_secondary(
         &mut self,
         buffer: ArrayBuffer,
@@ -1073,15 +1910,246 @@ impl GlCanvas {
         spacing_z: f32,
     ) -> Result<(), JsValue> {
         info!("info: load_secondary");
+        let volume = EFVolume::from_array_buffer(buffer, (w, h, d), (spacing_x, spacing_y, spacing_z));
+
+        // Make the Z locations which is evenly distributed in this case
+        let mut loc: Vec<f32> = vec![0.0];
+        for i in 1..d {
+            loc.push(spacing_z * i as f32);
+        }
+        self.load_secondary_volume(volume, loc, true, (300., 1200.))
+    }
+
+    // Load a dose grid from a DICOM RTDOSE file as the secondary volume. The
+    // grid is placed relative to the primary using the patient coordinates of
+    // both, so the primary should be loaded with load_primary_dicom first.
+    // Otherwise the primary is centered at the origin.
+    pub fn load_secondary_dicom(&mut self, buffer: ArrayBuffer) -> Result<(), JsValue> {
+        info!("info: load_secondary_dicom");
+        let dose = load_rtdose(&Uint8Array::new(&buffer).to_vec())?;
+        let z = dose
+            .info
+            .get_slice_locations()
+            .ok_or("cannot retrieve slice locations")?
+            .clone();
+        info!(
+            "dose {} ({}): {:?}",
+            &dose.series_instance_uid,
+            &dose.dose_units,
+            dose.info.get_dimension()
+        );
+
+        let volume = EFVolume {
+            info: dose.info,
+            data: dose.data,
+        };
+        let (min, max) = volume.minmax();
+        self.load_secondary_volume(volume, z, false, (max - min, (max + min) / 2.0))
+    }
+
+    // Draw `structures` on the transverse, sagittal and coronal views in
+    // their own colors, in place of the ones before. The contours are in the
+    // patient coordinates in millimeter, placed with the primary.
+    pub fn set_structure_set(&mut self, structures: &StructureSet) -> Result<(), JsValue> {
+        let to_view = self.patient_to_view()?;
+        let primary = self.primary_volume.as_ref().ok_or("primary is not loaded.")?;
+        let (_, _, spacing_z) = primary.volume_info.get_spacing().ok_or("spacing is missing")?;
+        self.structures
+            .borrow_mut()
+            .set_structures(structures, &to_view, spacing_z);
+        self.mark_overlays_changed();
+        Ok(())
+    }
+
+    pub fn clear_structure_set(&mut self) {
+        self.structures.borrow_mut().clear();
+        self.mark_overlays_changed();
+    }
+
+    // The line width in pixels and the fill opacity in [0, 1] of the
+    // structure of ROI number `number`. The outline is drawn inside of it.
+    pub fn set_structure_style(
+        &mut self,
+        number: i32,
+        r: u8,
+        g: u8,
+        b: u8,
+        line_width: f32,
+        fill_opacity: f32,
+    ) -> Result<(), JsValue> {
+        self.update_structure_style(number, |style| {
+            style.color = [r, g, b];
+            style.line_width = line_width.max(0.0);
+            style.fill_opacity = fill_opacity.clamp(0.0, 1.0);
+        })
+    }
+
+    pub fn set_structure_visible(&mut self, number: i32, visible: bool) -> Result<(), JsValue> {
+        self.update_structure_style(number, |style| style.visible = visible)
+    }
+
+    fn update_structure_style<F>(&mut self, number: i32, f: F) -> Result<(), JsValue>
+    where
+        F: FnOnce(&mut StructureStyle),
+    {
+        let mut structures = self.structures.borrow_mut();
+        let mut style = structures
+            .style(number)
+            .ok_or(format!("cannot find structure {}", number))?;
+        f(&mut style);
+        structures.set_style(number, style)?;
+        drop(structures);
+        self.mark_overlays_changed();
+        Ok(())
+    }
+
+    // Iso-lines of the secondary on the transverse, sagittal and coronal
+    // views at `values`, e.g. 500, 700 and 900 V/cm, in the colors of
+    // `colors` given as r, g, b for each of them.
+    pub fn set_iso_levels(&mut self, values: Float32Array, colors: Uint8Array) -> Result<(), JsValue> {
+        let values = values.to_vec();
+        let colors = colors.to_vec();
+        if colors.len() != values.len() * 3 {
+            return Err(format!("expected {} color components, got {}.", values.len() * 3, colors.len()).into());
+        }
+        let levels = values
+            .iter()
+            .zip(colors.chunks_exact(3))
+            .map(|(value, c)| IsoLevel {
+                value: *value,
+                color: [c[0], c[1], c[2]],
+            })
+            .collect();
+        self.isolines.borrow_mut().set_levels(levels);
+        self.mark_overlays_changed();
+        Ok(())
+    }
+
+    pub fn clear_iso_levels(&mut self) {
+        self.isolines.borrow_mut().set_levels(Vec::new());
+        self.mark_overlays_changed();
+    }
+
+    // in pixels
+    pub fn set_iso_line_width(&mut self, width: f32) {
+        self.isolines.borrow_mut().set_line_width(width);
+        self.mark_overlays_changed();
+    }
+
+    // `name` is one of "orientation" for the orientation labels, "scale_bar",
+    // "window_level", "slice_position" and "colorbar" for the colorbar of the
+    // secondary, drawn on the transverse, sagittal and coronal views.
+    pub fn set_annotation_visible(&mut self, name: &str, visible: bool) -> Result<(), JsValue> {
+        let annotation = Annotation::from_name(name).ok_or(format!("unknown annotation {}", name))?;
+        self.annotations.borrow_mut().set_visible(annotation, visible);
+        self.mark_overlays_changed();
+        Ok(())
+    }
+
+    pub fn set_annotation_color(&mut self, r: u8, g: u8, b: u8) {
+        self.annotations.borrow_mut().set_color([r, g, b]);
+        self.mark_overlays_changed();
+    }
+
+    // in CSS pixels a pixel of the font, 1 by default
+    pub fn set_annotation_text_scale(&mut self, scale: u32) {
+        self.annotation_text_scale = scale.max(1);
+        self.update_text_scale();
+        self.mark_overlays_changed();
+    }
+
+    // The font is drawn in pixels of the drawing buffer.
+    fn update_text_scale(&self) {
+        let scale = self.annotation_text_scale as f32 * self.layout_manager.pixel_ratio();
+        self.annotations.borrow_mut().set_text_scale(scale.round().max(1.0) as usize);
+    }
+
+    fn mark_overlays_changed(&mut self) {
+        for view in [CanvasView::Transverse, CanvasView::Sagittal, CanvasView::Coronal] {
+            self.scheduler.mark(view, Dirty::CONTENT);
+        }
+    }
+
+    // Export `structures` as an RTSTRUCT file referencing the primary series,
+    // which has to be loaded by load_primary_dicom. The contours are in the
+    // patient coordinates in millimeter. If `field_isovalue` is given, the
+    // region of the secondary above it is added as an ROI named `field_roi_name`.
+    pub fn export_rtstruct(
+        &self,
+        structures: &StructureSet,
+        field_isovalue: Option<f32>,
+        field_roi_name: Option<String>,
+    ) -> Result<Uint8Array, JsValue> {
+        let series = self
+            .primary_volume
+            .as_ref()
+            .and_then(|v| v.series.as_ref())
+            .ok_or("the primary is not loaded from DICOM files.")?;
+        let mut set = structures.clone();
+        if let Some(isovalue) = field_isovalue {
+            let mut structure = self.secondary_structure(isovalue)?;
+            structure.set_name(field_roi_name.unwrap_or_else(|| String::from("Ablation zone")));
+            structure.set_color(255, 128, 0);
+            set.push(structure);
+        }
+        let buf = write_rtstruct(&set, series, "KEPLER_RA")?;
+        Ok(Uint8Array::from(buf.as_slice()))
+    }
+
+    // The region of the secondary above `isovalue` in the patient coordinates
+    // of the primary, at the position it is displayed.
+    fn secondary_structure(&self, isovalue: f32) -> Result<Structure, String> {
+        let volume = self.secondary_volume.as_ref().ok_or("secondary is not loaded.")?;
+        let primary = self.primary_volume.as_ref().ok_or("primary is not loaded.")?;
+        let view = self.trans_view.as_ref().ok_or("views are not set up.")?;
+        let dose = view.dose.as_ref().ok_or("secondary is not loaded.")?;
+        let info = self.placed_secondary_info()?;
+
+        let mut to_patient = info.voxel_to_patient();
+        if dose.follow_needles {
+            // the same shift as the shaders, npos is in 250 mm with y pointing up
+            let npos = view.needles.borrow().needle_pos;
+            let view_to_patient = primary.volume_info.view_to_patient();
+            let p = view_to_patient.transform_point(&[npos.0 * 250.0, -npos.1 * 250.0, npos.2 * 250.0]);
+            let o = view_to_patient.transform_point(&[0.0, 0.0, 0.0]);
+            to_patient = Base::translation(p[0] - o[0], p[1] - o[1], p[2] - o[2]).compose(&to_patient);
+        }
+        Ok(Structure::from_field(
+            &volume.data,
+            info.get_dimension(),
+            &to_patient,
+            isovalue,
+        ))
+    }
+
+    // The secondary as it is placed in the patient coordinates. A secondary
+    // without an image position, such as the ablation field, is centered on
+    // the primary along its axes.
+    fn placed_secondary_info(&self) -> Result<VolumeInfo, String> {
+        let volume = self.secondary_volume.as_ref().ok_or("secondary is not loaded.")?;
+        let primary = self.primary_volume.as_ref().ok_or("primary is not loaded.")?;
+        let mut info = volume.info.clone();
+        if info.get_image_position().is_none() {
+            if let Some(orientation) = primary.volume_info.get_image_orientation() {
+                info.set_image_orientation(orientation);
+            }
+            info.set_center(primary.volume_info.get_center());
+        }
+        Ok(info)
+    }
+
+    fn load_secondary_volume(
+        &mut self,
+        volume: EFVolume,
+        z: Vec<f32>,
+        follow_needles: bool,
+        window_level: (f32, f32),
+    ) -> Result<(), JsValue> {
         self.trans_view.as_ref().unwrap().program.use_program();
-        let spacing = (spacing_x, spacing_y, spacing_z);
-        let mut info = VolumeInfo::new(VolumeDataType::Int16, w, h, d);
-        info.set_spacing(spacing);
-        self.secondary_volume = Some(EFVolume::from_array_buffer(
-            buffer,
-            (w, h, d),
-            (spacing_x, spacing_y, spacing_z),
-        ));
+        let (w, h, d) = volume.info.get_dimension();
+        let spacing = volume.info.get_spacing().ok_or("spacing is missing")?;
+        let (spacing_x, spacing_y, spacing_z) = spacing;
+        self.secondary_volume = Some(volume);
         // self.set_secondary_dim(w as f32, h as f32, d as f32)?;
         self.set_secondary_spacing(spacing)?;
         self.set_secondary_size(
@@ -1090,12 +2158,7 @@ impl GlCanvas {
             d as f32 * spacing_z,
         )?;
 
-        // Make the Z locations which is evenly distributed in this case
-        let mut loc: Vec<f32> = vec![0.0];
-        for i in 1..d {
-            loc.push(spacing_z * i as f32);
-        }
-        let zloc = ZLocations::new(self.context.clone(), 5, loc.as_slice());
+        let zloc = ZLocations::new(self.context.clone(), 5, z.as_slice());
         let zloc_tex = zloc
             .gen_texture3d()
             .load_texture(&GLContext::new(self.context.clone()));
@@ -1106,28 +2169,48 @@ impl GlCanvas {
         //     .as_mut()
         //     .map(|v| v.z_loc2 = Some(self.secondary_loc_tex.as_ref().unwrap().clone()));
 
+        // from the view frame of the primary through the patient coordinates
+        // to the texture coordinates of the secondary
+        let primary = self.primary_volume.as_ref().ok_or("primary is not loaded.")?;
+        let placed = self.placed_secondary_info()?;
+        let transform = placed
+            .patient_to_texture()
+            .ok_or("cannot invert the voxel to patient transform of the secondary")?
+            .compose(&primary.volume_info.view_to_patient());
+
         if let Some(volume) = self.secondary_volume.as_ref() {
+            // the iso-lines are traced on a copy in the memory
+            let field = CpuVolume::new(w as usize, h as usize, d as usize, volume.data.clone())?;
+            let layer = CpuLayer::secondary(field, Vector3::new(spacing_x, spacing_y, spacing_z), transform.clone());
+            self.isolines.borrow_mut().set_field(layer, follow_needles);
+
             let texture = Rc::new(RefCell::new(
                 volume
                     .gen_texture3d()
                     .load_texture(&GLContext::new(self.context.clone())),
             ));
+            let registration = UniformBuffer::new(&GLContext::new(self.context.clone()), 16)?;
+            registration.update(0, &transform.to_column_major())?;
             let dose = DosePane {
                 texture: texture.clone(),
                 z_loc: self.secondary_loc_tex.as_ref().unwrap().clone(),
                 lut: self.lut.clone(),
-                window: 300.,
-                level: 1200.,
-                spacing: (spacing_x, spacing_y, spacing_z),
+                window: window_level.0,
+                level: window_level.1,
+                spacing,
                 size: (
                     w as f32 * spacing_x,
                     h as f32 * spacing_y,
                     d as f32 * spacing_z,
                 ),
                 minmax: volume.minmax(),
+                registration: Rc::new(registration),
+                follow_needles,
+                display: self.field_display,
             };
             info!("secondary spacing: {:?}", &dose.spacing);
             info!("secondary size: {:?}", &dose.size);
+            info!("secondary center: {:?}", &placed.get_center());
             self.trans_view.as_mut().map(|v| {
                 v.dose = Some(dose.clone());
             });
@@ -1138,10 +2221,17 @@ impl GlCanvas {
             self.coronal_view.as_mut().map(|v| {
                 v.dose = Some(dose.clone());
             });
+            if let Some(v) = self.three_d_view.as_mut() {
+                v.dose = Some(dose.clone());
+            }
+            for v in self.oblique_views_mut() {
+                v.dose = Some(dose.clone());
+            }
         }
 
         // self.show_secondary(true)?;
 
+        self.scheduler.mark_all(Dirty::CONTENT);
         Ok(())
     }
 
@@ -1204,25 +2294,129 @@ impl GlCanvas {
         Ok(())
     }
 
+    // Redraw all the views now.
     pub fn render(&mut self) -> Result<(), JsValue> {
-        let gl = self.context.clone();
-        type GL2 = WebGl2RenderingContext;
-        gl.clear_color(0.5, 0.5, 0.5, 1.0);
-        gl.clear(GL2::COLOR_BUFFER_BIT | GL2::DEPTH_BUFFER_BIT);
-
         info!("render...");
-        self.update_layout();
-        self.geometry.as_ref().map(|geo| geo.enable_buffer());
-        self.trans_view.as_mut().map(|v| v.render());
-        self.sagi_view.as_mut().map(|v| v.render());
-        self.coronal_view.as_mut().map(|v| v.render());
+        self.scheduler.mark_all(Dirty::LAYOUT);
         /* info!("renables: {}", self.rendables.len()); */
         /* for view in &mut self.rendables { */
         /*     info!("redering"); */
         /*     view.render(); */
         /* } */
+        self.render_frame()
+    }
+
+    // `callback` is called in the animation frame after the views have
+    // changed, normally as `() => canvas.render_frame()`. Several changes
+    // before the frame are drawn together.
+    pub fn set_frame_callback(&mut self, callback: Option<js_sys::Function>) {
+        self.scheduler.set_callback(callback);
+    }
+
+    pub fn needs_render(&self) -> bool {
+        self.scheduler.is_dirty()
+    }
+
+    // Redraw the views changed since the last frame, each within its
+    // viewport, or the whole canvas if the layout has changed. Nothing is
+    // drawn if nothing has changed, so it may be called in every frame.
+    pub fn render_frame(&mut self) -> Result<(), JsValue> {
+        if std::mem::take(&mut self.cursor_moved) {
+            self.notify_cursor();
+        }
+        if !self.scheduler.is_dirty() {
+            return Ok(());
+        }
+        let dirty = self.scheduler.take();
+        let gl = self.context.clone();
+        type GL2 = WebGl2RenderingContext;
+        gl.clear_color(0.5, 0.5, 0.5, 1.0);
+
+        self.update_layout();
+        self.geometry.as_ref().map(|geo| geo.enable_buffer());
+        if FrameScheduler::needs_full_redraw(&dirty) {
+            gl.clear(GL2::COLOR_BUFFER_BIT | GL2::DEPTH_BUFFER_BIT);
+            for view in CanvasView::ALL {
+                self.render_view(view);
+            }
+            return Ok(());
+        }
+        gl.enable(GL2::SCISSOR_TEST);
+        for (view, _) in dirty {
+            let (x0, y0, width, height) = self.layout_manager.layout(&view);
+            gl.scissor(x0, y0, width, height);
+            gl.clear(GL2::COLOR_BUFFER_BIT | GL2::DEPTH_BUFFER_BIT);
+            self.render_view(view);
+        }
+        gl.disable(GL2::SCISSOR_TEST);
         Ok(())
     }
+
+    // Views without a viewport are skipped.
+    fn render_view(&mut self, view: CanvasView) {
+        match self.view_size_mut(view) {
+            Some((_, _, width, height)) if *width > 0 && *height > 0 => {}
+            _ => return,
+        }
+        let rendable: Option<&mut dyn Rendable> = match view {
+            CanvasView::Transverse => self.trans_view.as_mut().map(|v| v as &mut dyn Rendable),
+            CanvasView::Sagittal => self.sagi_view.as_mut().map(|v| v as &mut dyn Rendable),
+            CanvasView::Coronal => self.coronal_view.as_mut().map(|v| v as &mut dyn Rendable),
+            CanvasView::ThreeD => self.three_d_view.as_mut().map(|v| v as &mut dyn Rendable),
+            CanvasView::Oblique => self.oblique_view.as_mut().map(|v| v as &mut dyn Rendable),
+            CanvasView::NeedleInline => self.needle_inline_view.as_mut().map(|v| v as &mut dyn Rendable),
+            CanvasView::NeedlePerpendicular => self
+                .needle_perpendicular_view
+                .as_mut()
+                .map(|v| v as &mut dyn Rendable),
+        };
+        if let Some(v) = rendable {
+            v.render();
+        }
+    }
+
+    // Render `view`, a name as maximize or "all" for the whole layout, at
+    // `width` x `height` pixels offscreen, and return it as a PNG file. The
+    // canvas is left as it is.
+    pub fn export_png(&mut self, view: &str, width: i32, height: i32) -> Result<Uint8Array, JsValue> {
+        let views = match view {
+            "all" => CanvasView::ALL.to_vec(),
+            name => vec![CanvasView::from_name(name).ok_or(format!("unknown view: {}", name))?],
+        };
+        let target = RenderTarget::new(&GLContext::new(self.context.clone()), width, height)?;
+        let gl = self.context.clone();
+        type GL2 = WebGl2RenderingContext;
+
+        // the whole layout or the view alone over the target
+        let dimension = self.layout_manager.dimension();
+        self.layout_manager.set_width(width);
+        self.layout_manager.set_height(height);
+        self.update_layout();
+        if let [view] = views[..] {
+            if let Some(size) = self.view_size_mut(view) {
+                *size = (0, 0, width, height);
+            }
+        }
+
+        target.bind();
+        gl.clear_color(0.5, 0.5, 0.5, 1.0);
+        gl.clear(GL2::COLOR_BUFFER_BIT | GL2::DEPTH_BUFFER_BIT);
+        if let Some(geometry) = self.geometry.as_ref() {
+            geometry.enable_buffer();
+        }
+        for view in views {
+            self.render_view(view);
+        }
+        target.unbind();
+
+        self.layout_manager.set_width(dimension.0);
+        self.layout_manager.set_height(dimension.1);
+        self.update_layout();
+
+        let pixels = target.read_pixels()?;
+        let image = RgbaImage::from_bottom_up(width as usize, height as usize, pixels)?;
+        Ok(Uint8Array::from(image.to_png().as_slice()))
+    }
 }
 
 impl Drop for GlCanvas {
@@ -1243,8 +2437,11 @@ pub fn get_context_by_canvas_id(canvas_id: &str) -> Result<WebGl2RenderingContex
         .ok_or("cannot obtain the canvas.")?;
 
     let canvas: web_sys::HtmlCanvasElement = canvas.dyn_into::<web_sys::HtmlCanvasElement>()?;
+    // the views not redrawn in a frame are kept, see GlCanvas::render_frame
+    let options = js_sys::Object::new();
+    js_sys::Reflect::set(&options, &JsValue::from("preserveDrawingBuffer"), &JsValue::TRUE)?;
     let context = canvas
-        .get_context("webgl2")?
+        .get_context_with_context_options("webgl2", &options)?
         .ok_or("cannot obtain webgl2 context")?
         .dyn_into::<WebGl2RenderingContext>()?;
 
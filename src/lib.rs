@@ -23,13 +23,17 @@
 
 #![allow(unused)]
 mod colormap;
+mod transfer_function;
 mod modal;
+mod dicom;
 mod utils;
 // mod textureid;
 mod debug;
 mod techtron;
 mod view;
 mod shader_sources;
+mod scheduler;
+mod render;
 mod glcanvas;
 
 // Refactoring
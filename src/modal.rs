@@ -28,6 +28,7 @@ use js_sys::{ArrayBuffer, Int16Array, Uint16Array};
 use wasm_bindgen::prelude::*;
 use wasm_bindgen::JsCast;
 use web_sys::{File, FileReader};
+use nalgebra::SMatrix;
 use web_sys::{
     WebGl2RenderingContext,
     WebGlProgram,
@@ -35,6 +36,8 @@ use web_sys::{
     WebGlTexture, //WebGlUniformLocation,
 };
 
+use crate::techtron::core::base::Base;
+
 // struct volume_t {
 //     dim: (usize, usize, usize),
 //     channel: usize,
@@ -73,19 +76,27 @@ use web_sys::{
 //     }
 // }
 
-#[derive(Copy, Clone)]      
+#[derive(Debug, Copy, Clone)]      
 pub enum VolumeDataType {
     Undefined,
     Uint16,
     Int16,
+    Float32,
 }
 
-#[derive(Clone)]
+#[derive(Debug, Clone)]
 pub struct VolumeInfo {
     dimension: (i32, i32, i32),
     spacing: Option<(f32, f32, f32)>, // in millimeter
     data_type: VolumeDataType,
     slice_locations: Option<Vec<f32>>,
+    // ImagePositionPatient of the first voxel, in millimeter
+    position: Option<[f32; 3]>,
+    // ImageOrientationPatient, the direction cosines of the rows and columns
+    orientation: Option<[f32; 6]>,
+    // RescaleSlope and RescaleIntercept, the modality LUT from the stored
+    // values to the real world values, e.g. Hounsfield units
+    rescale: (f32, f32),
 }
 
 impl VolumeInfo {
@@ -95,6 +106,9 @@ impl VolumeInfo {
             spacing: None,
             data_type: data_type,
             slice_locations: None,
+            position: None,
+            orientation: None,
+            rescale: (1.0, 0.0),
         }
     }
 
@@ -165,8 +179,187 @@ impl VolumeInfo {
     pub fn get_slice_locations(&self) -> Option<&Vec<f32>> {
         self.slice_locations.as_ref()
     }
+
+    pub fn set_slice_locations(&mut self, slice_locations: Vec<f32>) {
+        self.slice_locations = Some(slice_locations);
+    }
+
+    pub fn get_image_position(&self) -> Option<[f32; 3]> {
+        self.position
+    }
+
+    pub fn set_image_position(&mut self, position: [f32; 3]) {
+        self.position = Some(position);
+    }
+
+    pub fn get_image_orientation(&self) -> Option<[f32; 6]> {
+        self.orientation
+    }
+
+    pub fn set_image_orientation(&mut self, orientation: [f32; 6]) {
+        self.orientation = Some(orientation);
+    }
+
+    pub fn get_rescale(&self) -> (f32, f32) {
+        self.rescale
+    }
+
+    pub fn set_rescale(&mut self, slope: f32, intercept: f32) {
+        self.rescale = (slope, intercept);
+    }
+
+    // The real world value of a stored value.
+    pub fn to_real_value(&self, stored: f32) -> f32 {
+        stored * self.rescale.0 + self.rescale.1
+    }
+
+    pub fn to_stored_value(&self, real: f32) -> f32 {
+        (real - self.rescale.1) / self.rescale.0
+    }
+
+    // The directions of the x, y and z axes of the volume in the patient
+    // coordinates, axial if the orientation is not known.
+    pub fn get_directions(&self) -> [[f32; 3]; 3] {
+        let o = self.orientation.unwrap_or([1.0, 0.0, 0.0, 0.0, 1.0, 0.0]);
+        let normal = [
+            o[1] * o[5] - o[2] * o[4],
+            o[2] * o[3] - o[0] * o[5],
+            o[0] * o[4] - o[1] * o[3],
+        ];
+        [[o[0], o[1], o[2]], [o[3], o[4], o[5]], normal]
+    }
+
+    // Patient position of the center of the volume. Volumes without an image
+    // position are centered at the origin.
+    pub fn get_center(&self) -> [f32; 3] {
+        match self.position {
+            Some(_) => {
+                let (w, h, d) = self.dimension;
+                self.voxel_to_patient().transform_point(&[
+                    (w - 1) as f32 / 2.0,
+                    (h - 1) as f32 / 2.0,
+                    (d - 1) as f32 / 2.0,
+                ])
+            }
+            None => [0.0, 0.0, 0.0],
+        }
+    }
+
+    // Move the volume so that its center is at `center`.
+    pub fn set_center(&mut self, center: [f32; 3]) {
+        let (w, h, d) = self.dimension;
+        let (sx, sy, sz) = self.spacing.unwrap_or((1.0, 1.0, 1.0));
+        let half = [
+            (w - 1) as f32 / 2.0 * sx,
+            (h - 1) as f32 / 2.0 * sy,
+            (d - 1) as f32 / 2.0 * sz,
+        ];
+        let dirs = self.get_directions();
+        let mut position = center;
+        for (i, p) in position.iter_mut().enumerate() {
+            *p -= dirs[0][i] * half[0] + dirs[1][i] * half[1] + dirs[2][i] * half[2];
+        }
+        self.position = Some(position);
+    }
+
+    // The 4x4 transform from the voxel indices to the patient coordinates in
+    // millimeter. The slices are assumed to be evenly spaced.
+    pub fn voxel_to_patient(&self) -> Base {
+        let (sx, sy, sz) = self.spacing.unwrap_or((1.0, 1.0, 1.0));
+        let origin = match self.position {
+            Some(p) => p,
+            None => {
+                let mut info = self.clone();
+                info.set_center([0.0, 0.0, 0.0]);
+                info.position.unwrap()
+            }
+        };
+        let [r, c, n] = self.get_directions();
+        Base::from_matrix(SMatrix::<f32, 4, 4>::from_row_slice(&[
+            r[0] * sx, c[0] * sy, n[0] * sz, origin[0],
+            r[1] * sx, c[1] * sy, n[1] * sz, origin[1],
+            r[2] * sx, c[2] * sy, n[2] * sz, origin[2],
+            0.0, 0.0, 0.0, 1.0,
+        ]))
+    }
+
+    pub fn patient_to_voxel(&self) -> Option<Base> {
+        self.voxel_to_patient().inverse()
+    }
+
+    // From the patient coordinates to the texture coordinates of the volume,
+    // which are 0 and 1 at the outer edges of the first and last voxels.
+    pub fn patient_to_texture(&self) -> Option<Base> {
+        let (w, h, d) = self.dimension;
+        let voxel_to_texture = SMatrix::<f32, 4, 4>::from_row_slice(&[
+            1.0 / w as f32, 0.0, 0.0, 0.5 / w as f32,
+            0.0, 1.0 / h as f32, 0.0, 0.5 / h as f32,
+            0.0, 0.0, 1.0 / d as f32, 0.5 / d as f32,
+            0.0, 0.0, 0.0, 1.0,
+        ]);
+        Some(Base::from_matrix(voxel_to_texture).compose(&self.patient_to_voxel()?))
+    }
+
+    // From the millimeters relative to the center of the volume along its
+    // axes, which is the frame the views are drawn in, to the patient
+    // coordinates.
+    pub fn view_to_patient(&self) -> Base {
+        let [r, c, n] = self.get_directions();
+        let o = self.get_center();
+        Base::from_matrix(SMatrix::<f32, 4, 4>::from_row_slice(&[
+            r[0], c[0], n[0], o[0],
+            r[1], c[1], n[1], o[1],
+            r[2], c[2], n[2], o[2],
+            0.0, 0.0, 0.0, 1.0,
+        ]))
+    }
 }
 
 struct Volume<T> {
     data: Vec<T>,
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn assert_point_eq(a: [f32; 3], b: [f32; 3]) {
+        for i in 0..3 {
+            assert!((a[i] - b[i]).abs() < 1e-4, "{:?} != {:?}", a, b);
+        }
+    }
+
+    #[test]
+    fn test_voxel_to_patient() {
+        let mut info = VolumeInfo::new(VolumeDataType::Int16, 4, 3, 5);
+        info.set_spacing((0.5, 2.0, 3.0));
+        // a centered volume without position
+        assert_point_eq(info.voxel_to_patient().transform_point(&[0.0, 0.0, 0.0]), [-0.75, -2.0, -6.0]);
+        assert_point_eq(info.get_center(), [0.0, 0.0, 0.0]);
+
+        // rows along -y and columns along x, the slices along +z
+        info.set_image_position([10.0, 20.0, -30.0]);
+        info.set_image_orientation([0.0, -1.0, 0.0, 1.0, 0.0, 0.0]);
+        let m = info.voxel_to_patient();
+        assert_point_eq(m.transform_point(&[2.0, 1.0, 1.0]), [12.0, 19.0, -27.0]);
+        let inv = info.patient_to_voxel().unwrap();
+        assert_point_eq(inv.transform_point(&[12.0, 19.0, -27.0]), [2.0, 1.0, 1.0]);
+        assert_point_eq(info.get_center(), [12.0, 19.25, -24.0]);
+        assert_point_eq(info.view_to_patient().transform_point(&[0.0, 0.0, 0.0]), info.get_center());
+
+        let tex = info.patient_to_texture().unwrap();
+        assert_point_eq(tex.transform_point(&[10.0, 20.0, -30.0]), [0.125, 1.0 / 6.0, 0.1]);
+
+        info.set_center([0.0, 0.0, 0.0]);
+        assert_point_eq(info.get_center(), [0.0, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn test_rescale() {
+        let mut info = VolumeInfo::new(VolumeDataType::Int16, 1, 1, 1);
+        assert_eq!(info.to_real_value(-1000.0), -1000.0);
+        info.set_rescale(0.5, -1024.0);
+        assert_eq!(info.to_real_value(2048.0), 0.0);
+        assert_eq!(info.to_stored_value(-24.0), 2000.0);
+    }
+}
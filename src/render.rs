@@ -0,0 +1,485 @@
+// MIT License
+
+// Copyright (c) 2023 Techtron-Lab
+
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+
+// The pipeline of the transverse, sagittal and coronal views independent of
+// the graphics API: from the viewport to the view frame of the primary, the
+// window and level of both volumes, the colormap of the secondary and the
+// fusion of the two. The WebGL views draw it with the shaders from
+// slice_fragment_shader, and CpuRenderer draws the same images without a GPU
+// as the reference.
+
+pub mod annotations;
+pub mod cpu;
+pub mod isolines;
+pub mod overlay;
+pub mod png;
+
+use nalgebra::Vector3;
+
+use crate::shader_sources::Orientation;
+use crate::view::{Slab, SlabMode};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct WindowLevel {
+    pub window: f32,
+    pub level: f32,
+}
+
+impl WindowLevel {
+    // The gray level in [0, 1] of `value`, as primary_gray of the shaders.
+    pub fn gray(&self, value: f32) -> f32 {
+        ((value - (self.level - self.window / 2.0)) / self.window).clamp(0.0, 1.0)
+    }
+}
+
+// The most bands of FieldDisplay, as MAX_BANDS of the shaders.
+pub const MAX_BANDS: usize = 8;
+
+// A range of the values of the secondary painted in `color`, from `lower`
+// up to but not including `upper`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FieldBand {
+    pub lower: f32,
+    pub upper: f32,
+    pub color: [u8; 3],
+}
+
+// How the secondary is painted over the primary, in its real world values.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FieldDisplay {
+    // The colormap over the window. With `ramp`, transparent below its lower
+    // end and opaque from its upper end, linear between, a threshold if they
+    // are equal, and transparent outside of the secondary.
+    Colormap { ramp: Option<(f32, f32)> },
+    // Only the first `count` bands, the first one a value is in, each opaque
+    // in its color, transparent elsewhere.
+    Bands { bands: [FieldBand; MAX_BANDS], count: usize },
+}
+
+impl Default for FieldDisplay {
+    fn default() -> Self {
+        FieldDisplay::Colormap { ramp: None }
+    }
+}
+
+impl FieldDisplay {
+    pub fn bands(bands: &[FieldBand]) -> Result<FieldDisplay, String> {
+        if bands.is_empty() || bands.len() > MAX_BANDS {
+            return Err(format!("expected 1 to {} bands, got {}.", MAX_BANDS, bands.len()));
+        }
+        if let Some(band) = bands.iter().find(|b| b.lower.is_nan() || b.upper.is_nan() || b.lower >= b.upper) {
+            return Err(format!("the band from {} to {} is empty.", band.lower, band.upper));
+        }
+        let mut array = [FieldBand {
+            lower: 0.0,
+            upper: 0.0,
+            color: [0; 3],
+        }; MAX_BANDS];
+        array[..bands.len()].copy_from_slice(bands);
+        Ok(FieldDisplay::Bands {
+            bands: array,
+            count: bands.len(),
+        })
+    }
+
+    // The color and the opacity of `value`, None outside of the secondary,
+    // with `colormap` from the gray level of `window`, as secondary_rgba of
+    // the shaders.
+    pub fn rgba<F>(&self, value: Option<f32>, window: &WindowLevel, colormap: F) -> ([f32; 3], f32)
+    where
+        F: Fn(f32) -> [f32; 3],
+    {
+        match (self, value) {
+            (FieldDisplay::Bands { bands, count }, Some(value)) => bands[..*count]
+                .iter()
+                .find(|b| value >= b.lower && value < b.upper)
+                .map(|b| (b.color.map(|c| c as f32 / 255.0), 1.0))
+                .unwrap_or(([0.0; 3], 0.0)),
+            (FieldDisplay::Bands { .. }, None) => ([0.0; 3], 0.0),
+            (FieldDisplay::Colormap { ramp }, value) => {
+                let gray = value.map(|v| window.gray(v)).unwrap_or(0.0);
+                let alpha = match (ramp, value) {
+                    (None, _) => 1.0,
+                    (Some(_), None) => 0.0,
+                    (Some((lower, upper)), Some(v)) => {
+                        if v >= *upper {
+                            1.0
+                        } else if v < *lower {
+                            0.0
+                        } else {
+                            (v - lower) / (upper - lower)
+                        }
+                    }
+                };
+                (colormap(gray), alpha)
+            }
+        }
+    }
+}
+
+// Lines through the cursor along the axes of a view, a pixel wide.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Crosshair {
+    // in the view frame of the primary in mm
+    pub cursor: Vector3<f32>,
+    pub color: [f32; 3],
+}
+
+impl Crosshair {
+    // Whether `p` is on the lines along `u` and `v`, the axes of a view
+    // whose pixels are `pixel` mm along them, as crosshair_at of the shaders.
+    pub fn covers(&self, p: &Vector3<f32>, u: &Vector3<f32>, v: &Vector3<f32>, pixel: (f32, f32)) -> bool {
+        let d = p - self.cursor;
+        d.dot(u).abs() < pixel.0 / 2.0 || d.dot(v).abs() < pixel.1 / 2.0
+    }
+}
+
+// How the secondary is fused with the primary in a view.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Fusion {
+    // the secondary over the primary with the blend coefficient
+    #[default]
+    Blend,
+    // alternating tiles of the primary and the secondary, `tile` mm on a side
+    // along the axes of the view
+    Checkerboard { tile: f32 },
+    // the absolute difference between the gray levels of the two
+    Difference,
+    // the secondary alone in a circle of `radius` mm around `center`, which
+    // follows the cursor, and the primary alone outside of it
+    Spyglass { center: Vector3<f32>, radius: f32 },
+}
+
+impl Fusion {
+    // "blend", "checkerboard", "difference" or "spyglass", with `size` the
+    // tiles or the radius of the lens in mm.
+    pub fn from_name(name: &str, size: f32, center: Vector3<f32>) -> Option<Fusion> {
+        match name {
+            "blend" => Some(Fusion::Blend),
+            "checkerboard" => Some(Fusion::Checkerboard { tile: size }),
+            "difference" => Some(Fusion::Difference),
+            "spyglass" | "lens" => Some(Fusion::Spyglass { center, radius: size }),
+            _ => None,
+        }
+    }
+
+    // The lens moved to `cursor`, the others as they are.
+    pub fn follow(self, cursor: Vector3<f32>) -> Fusion {
+        match self {
+            Fusion::Spyglass { radius, .. } => Fusion::Spyglass { center: cursor, radius },
+            fusion => fusion,
+        }
+    }
+
+    // The weight of the secondary at `p` of a view along `u` and `v` with the
+    // blend coefficient `k`, as fusion_weight of the shaders.
+    pub fn weight(&self, p: &Vector3<f32>, u: &Vector3<f32>, v: &Vector3<f32>, k: f32) -> f32 {
+        match self {
+            Fusion::Blend | Fusion::Difference => k,
+            Fusion::Checkerboard { tile } => {
+                let i = (p.dot(u) / tile).floor() + (p.dot(v) / tile).floor();
+                if i.rem_euclid(2.0) >= 1.0 {
+                    1.0
+                } else {
+                    0.0
+                }
+            }
+            Fusion::Spyglass { center, radius } => {
+                let d = p - center;
+                if d.dot(u).powi(2) + d.dot(v).powi(2) < radius * radius {
+                    1.0
+                } else {
+                    0.0
+                }
+            }
+        }
+    }
+
+    // The final color from the gray level of the primary, the color and the
+    // opacity of the secondary with its gray level, and `weight`, as fuse of
+    // the shaders.
+    pub fn fuse(&self, gray0: f32, rgba1: ([f32; 3], f32), gray1: f32, weight: f32) -> [f32; 3] {
+        match self {
+            Fusion::Difference => [(gray0 - gray1).abs(); 3],
+            _ => blend(gray0, rgba1.0, weight * rgba1.1),
+        }
+    }
+}
+
+// The state of a view needed to draw a slice. The volumes and their
+// placement belong to the renderers.
+#[derive(Debug, Clone, Copy)]
+pub struct SliceParams {
+    pub orientation: Orientation,
+    pub scale: f32,
+    pub pan: (f32, f32),
+    // of the viewport, see viewport_extent
+    pub extent: (f32, f32),
+    // in 250 mm along the axis across the view
+    pub slice: f32,
+    // blend coefficient, 0 for the primary only and 1 for the secondary only
+    pub blend: f32,
+    pub slab: Slab,
+    pub primary: WindowLevel,
+    // None without a secondary
+    pub secondary: Option<WindowLevel>,
+    pub field_display: FieldDisplay,
+    pub fusion: Fusion,
+    pub crosshair: Option<Crosshair>,
+}
+
+// The half width and height of a viewport in the half of its shorter side,
+// which covers 500 mm at scale 1 in all the views.
+pub fn viewport_extent(width: i32, height: i32) -> (f32, f32) {
+    if width <= 0 || height <= 0 {
+        return (1.0, 1.0);
+    }
+    let side = width.min(height) as f32;
+    (width as f32 / side, height as f32 / side)
+}
+
+impl SliceParams {
+    // The point at `q` of the viewport, in [-1, 1] with y pointing up, in the
+    // view frame of the primary.
+    pub fn point(&self, q: (f32, f32)) -> Vector3<f32> {
+        let [u, v, n] = self.orientation.axes();
+        let s = (
+            (q.0 * self.extent.0 - self.pan.0) / self.scale,
+            (q.1 * self.extent.1 - self.pan.1) / self.scale,
+        );
+        (u * s.0 + v * s.1 + n * self.slice) * 250.0
+    }
+
+    // The inverse of point, for `p` on the slice or projected onto it.
+    pub fn viewport_coord(&self, p: &Vector3<f32>) -> (f32, f32) {
+        let [u, v, _] = self.orientation.axes();
+        (
+            (u.dot(p) / 250.0 * self.scale + self.pan.0) / self.extent.0,
+            (v.dot(p) / 250.0 * self.scale + self.pan.1) / self.extent.1,
+        )
+    }
+
+    // The number of samples across the slab and the step between them in mm,
+    // a sample every `spacing` mm of the primary along the axis across.
+    pub fn slab_samples(&self, spacing: &Vector3<f32>) -> (usize, Vector3<f32>) {
+        const MAX_SLAB_SAMPLES: usize = 64;
+        let n_axis = self.orientation.axes()[2];
+        if self.slab.mode == SlabMode::Plane || self.slab.thickness <= 0.0 {
+            return (1, Vector3::zeros());
+        }
+        let sp = spacing.dot(&n_axis.abs());
+        let n = ((self.slab.thickness / sp).ceil() as usize + 1).clamp(2, MAX_SLAB_SAMPLES);
+        (n, n_axis * (self.slab.thickness / (n - 1) as f32))
+    }
+
+    // A plane of the tests without a secondary, `scale` times 500 mm across.
+    #[cfg(test)]
+    pub fn plane(orientation: Orientation, scale: f32, slice: f32, primary: (f32, f32)) -> SliceParams {
+        SliceParams {
+            orientation,
+            scale,
+            pan: (0.0, 0.0),
+            extent: (1.0, 1.0),
+            slice,
+            blend: 0.0,
+            slab: Slab::default(),
+            primary: WindowLevel {
+                window: primary.0,
+                level: primary.1,
+            },
+            secondary: None,
+            field_display: FieldDisplay::default(),
+            fusion: Fusion::default(),
+            crosshair: None,
+        }
+    }
+}
+
+// The final color of the blend, as slice_fragment_shader.
+pub fn blend(gray0: f32, color1: [f32; 3], k: f32) -> [f32; 3] {
+    [
+        gray0 * (1.0 - k) + color1[0] * k,
+        gray0 * (1.0 - k) + color1[1] * k,
+        gray0 * (1.0 - k) + color1[2] * k,
+    ]
+}
+
+// 8-bit RGBA pixels from the top left row by row.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RgbaImage {
+    pub width: usize,
+    pub height: usize,
+    pub data: Vec<u8>,
+}
+
+impl RgbaImage {
+    pub fn new(width: usize, height: usize) -> RgbaImage {
+        RgbaImage {
+            width,
+            height,
+            data: vec![0; width * height * 4],
+        }
+    }
+
+    pub fn fill(&mut self, rgba: [u8; 4]) {
+        for pixel in self.data.chunks_exact_mut(4) {
+            pixel.copy_from_slice(&rgba);
+        }
+    }
+
+    pub fn pixel(&self, x: usize, y: usize) -> [u8; 4] {
+        let i = (y * self.width + x) * 4;
+        [self.data[i], self.data[i + 1], self.data[i + 2], self.data[i + 3]]
+    }
+
+    pub fn set_pixel(&mut self, x: usize, y: usize, rgba: [u8; 4]) {
+        let i = (y * self.width + x) * 4;
+        self.data[i..i + 4].copy_from_slice(&rgba);
+    }
+
+    // `color` with `alpha` over the pixel, whose alpha is straight.
+    pub fn blend_pixel(&mut self, x: usize, y: usize, color: [u8; 3], alpha: f32) {
+        let i = (y * self.width + x) * 4;
+        let pixel = &mut self.data[i..i + 4];
+        let below = pixel[3] as f32 / 255.0 * (1.0 - alpha);
+        let a = alpha + below;
+        if a <= 0.0 {
+            return;
+        }
+        for ch in 0..3 {
+            let c = (color[ch] as f32 * alpha + pixel[ch] as f32 * below) / a;
+            pixel[ch] = c.round() as u8;
+        }
+        pixel[3] = (a * 255.0).round() as u8;
+    }
+
+    // `area` from (x0, y0) to (x1, y1) exclusive from the top left, clipped
+    // to the image, None if nothing is left.
+    pub fn clip(&self, area: (i32, i32, i32, i32)) -> Option<(usize, usize, usize, usize)> {
+        let (x0, y0) = (area.0.max(0) as usize, area.1.max(0) as usize);
+        let (x1, y1) = (area.2.clamp(0, self.width as i32) as usize, area.3.clamp(0, self.height as i32) as usize);
+        if x0 < x1 && y0 < y1 {
+            Some((x0, y0, x1, y1))
+        } else {
+            None
+        }
+    }
+
+    // The pixels of an area of the image given by clip.
+    pub fn crop(&self, area: (usize, usize, usize, usize)) -> RgbaImage {
+        let (x0, y0, x1, y1) = area;
+        let mut data = Vec::with_capacity((x1 - x0) * (y1 - y0) * 4);
+        for y in y0..y1 {
+            data.extend_from_slice(&self.data[(y * self.width + x0) * 4..(y * self.width + x1) * 4]);
+        }
+        RgbaImage {
+            width: x1 - x0,
+            height: y1 - y0,
+            data,
+        }
+    }
+
+    // The pixels of an area given by clip from `other` of the same size.
+    pub fn copy_area(&mut self, other: &RgbaImage, area: (usize, usize, usize, usize)) {
+        let (x0, y0, x1, y1) = area;
+        for y in y0..y1 {
+            let row = (y * self.width + x0) * 4..(y * self.width + x1) * 4;
+            self.data[row.clone()].copy_from_slice(&other.data[row]);
+        }
+    }
+
+    pub fn to_png(&self) -> Vec<u8> {
+        png::encode_png(self)
+    }
+
+    // Rows from the bottom, as read back from WebGL.
+    pub fn from_bottom_up(width: usize, height: usize, data: Vec<u8>) -> Result<RgbaImage, String> {
+        if data.len() != width * height * 4 {
+            return Err(format!("expected {} bytes of pixels, got {}.", width * height * 4, data.len()));
+        }
+        let data = data.chunks_exact(width * 4).rev().flatten().copied().collect();
+        Ok(RgbaImage { width, height, data })
+    }
+
+    // A binary PPM (P6) without the alpha channel.
+    pub fn to_ppm(&self) -> Vec<u8> {
+        let mut buf = format!("P6\n{} {}\n255\n", self.width, self.height).into_bytes();
+        for pixel in self.data.chunks_exact(4) {
+            buf.extend_from_slice(&pixel[..3]);
+        }
+        buf
+    }
+
+    pub fn from_ppm(buf: &[u8]) -> Result<RgbaImage, String> {
+        // the magic number, the width, the height and the maximum value are
+        // separated by single whitespaces, the last one before the pixels
+        let mut fields = Vec::new();
+        let mut start = 0;
+        let mut pos = 0;
+        while fields.len() < 4 {
+            let c = *buf.get(pos).ok_or("truncated PPM header")?;
+            if c.is_ascii_whitespace() {
+                if pos > start {
+                    fields.push(String::from_utf8_lossy(&buf[start..pos]).to_string());
+                }
+                start = pos + 1;
+            }
+            pos += 1;
+        }
+        if fields[0] != "P6" || fields[3] != "255" {
+            return Err(String::from("only 8-bit binary PPM is supported."));
+        }
+        let width: usize = fields[1].parse().map_err(|_| "invalid PPM width")?;
+        let height: usize = fields[2].parse().map_err(|_| "invalid PPM height")?;
+        let pixels = &buf[pos..];
+        if pixels.len() != width * height * 3 {
+            return Err(format!("expected {} bytes of pixels, got {}.", width * height * 3, pixels.len()));
+        }
+        let mut image = RgbaImage::new(width, height);
+        for (rgba, rgb) in image.data.chunks_exact_mut(4).zip(pixels.chunks_exact(3)) {
+            rgba[..3].copy_from_slice(rgb);
+            rgba[3] = 255;
+        }
+        Ok(image)
+    }
+
+    // The largest difference of a channel, None if the sizes differ.
+    pub fn max_difference(&self, other: &RgbaImage) -> Option<u8> {
+        if self.width != other.width || self.height != other.height {
+            return None;
+        }
+        self.data
+            .iter()
+            .zip(other.data.iter())
+            .map(|(a, b)| a.abs_diff(*b))
+            .max()
+            .or(Some(0))
+    }
+}
+
+// Draws slices into its target, e.g. a canvas or an image.
+pub trait SliceRenderer {
+    // Draw `slice` over the viewport (x0, y0, width, height) of the target,
+    // whose origin is at the bottom left.
+    fn render_slice(&mut self, slice: &SliceParams, viewport: (i32, i32, i32, i32)) -> Result<(), String>;
+}
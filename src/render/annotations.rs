@@ -0,0 +1,762 @@
+// MIT License
+
+// Copyright (c) 2023 Techtron-Lab
+
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+
+// The text and the marks on the slice views: the orientation labels at the
+// edges, a scale bar, the readouts of the window and level and the slice
+// position, and the colorbar of the secondary. They are drawn from a glyph
+// atlas of a 5x7 pixel font into the overlay image of the view, each in the
+// color with a dark outline to be readable over any slice. The readouts
+// change with the slice, the zoom and the window, the rest only with the
+// settings, so the overlay redraws just the readouts as marks.
+
+use nalgebra::Vector3;
+
+use crate::colormap::Colormap;
+use crate::render::{FieldBand, FieldDisplay, RgbaImage, SliceParams, WindowLevel};
+use crate::techtron::core::base::Base;
+
+const GLYPH_WIDTH: usize = 5;
+const GLYPH_HEIGHT: usize = 7;
+const OUTLINE_COLOR: [u8; 3] = [0, 0, 0];
+
+// The rows of the glyphs from the top, the leftmost pixel in bit 4.
+const FONT: &[(char, [u8; GLYPH_HEIGHT])] = &[
+    (' ', [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00]),
+    ('%', [0x18, 0x19, 0x02, 0x04, 0x08, 0x13, 0x03]),
+    ('(', [0x02, 0x04, 0x08, 0x08, 0x08, 0x04, 0x02]),
+    (')', [0x08, 0x04, 0x02, 0x02, 0x02, 0x04, 0x08]),
+    ('+', [0x00, 0x04, 0x04, 0x1F, 0x04, 0x04, 0x00]),
+    ('-', [0x00, 0x00, 0x00, 0x1F, 0x00, 0x00, 0x00]),
+    ('.', [0x00, 0x00, 0x00, 0x00, 0x00, 0x0C, 0x0C]),
+    ('/', [0x00, 0x01, 0x02, 0x04, 0x08, 0x10, 0x00]),
+    (':', [0x00, 0x0C, 0x0C, 0x00, 0x0C, 0x0C, 0x00]),
+    ('0', [0x0E, 0x11, 0x13, 0x15, 0x19, 0x11, 0x0E]),
+    ('1', [0x04, 0x0C, 0x04, 0x04, 0x04, 0x04, 0x0E]),
+    ('2', [0x0E, 0x11, 0x01, 0x02, 0x04, 0x08, 0x1F]),
+    ('3', [0x1F, 0x02, 0x04, 0x02, 0x01, 0x11, 0x0E]),
+    ('4', [0x02, 0x06, 0x0A, 0x12, 0x1F, 0x02, 0x02]),
+    ('5', [0x1F, 0x10, 0x1E, 0x01, 0x01, 0x11, 0x0E]),
+    ('6', [0x06, 0x08, 0x10, 0x1E, 0x11, 0x11, 0x0E]),
+    ('7', [0x1F, 0x01, 0x02, 0x04, 0x08, 0x08, 0x08]),
+    ('8', [0x0E, 0x11, 0x11, 0x0E, 0x11, 0x11, 0x0E]),
+    ('9', [0x0E, 0x11, 0x11, 0x0F, 0x01, 0x02, 0x0C]),
+    ('A', [0x0E, 0x11, 0x11, 0x11, 0x1F, 0x11, 0x11]),
+    ('B', [0x1E, 0x11, 0x11, 0x1E, 0x11, 0x11, 0x1E]),
+    ('C', [0x0E, 0x11, 0x10, 0x10, 0x10, 0x11, 0x0E]),
+    ('D', [0x1C, 0x12, 0x11, 0x11, 0x11, 0x12, 0x1C]),
+    ('E', [0x1F, 0x10, 0x10, 0x1E, 0x10, 0x10, 0x1F]),
+    ('F', [0x1F, 0x10, 0x10, 0x1E, 0x10, 0x10, 0x10]),
+    ('G', [0x0E, 0x11, 0x10, 0x17, 0x11, 0x11, 0x0F]),
+    ('H', [0x11, 0x11, 0x11, 0x1F, 0x11, 0x11, 0x11]),
+    ('I', [0x0E, 0x04, 0x04, 0x04, 0x04, 0x04, 0x0E]),
+    ('J', [0x07, 0x02, 0x02, 0x02, 0x02, 0x12, 0x0C]),
+    ('K', [0x11, 0x12, 0x14, 0x18, 0x14, 0x12, 0x11]),
+    ('L', [0x10, 0x10, 0x10, 0x10, 0x10, 0x10, 0x1F]),
+    ('M', [0x11, 0x1B, 0x15, 0x15, 0x11, 0x11, 0x11]),
+    ('N', [0x11, 0x11, 0x19, 0x15, 0x13, 0x11, 0x11]),
+    ('O', [0x0E, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0E]),
+    ('P', [0x1E, 0x11, 0x11, 0x1E, 0x10, 0x10, 0x10]),
+    ('Q', [0x0E, 0x11, 0x11, 0x11, 0x15, 0x12, 0x0D]),
+    ('R', [0x1E, 0x11, 0x11, 0x1E, 0x14, 0x12, 0x11]),
+    ('S', [0x0F, 0x10, 0x10, 0x0E, 0x01, 0x01, 0x1E]),
+    ('T', [0x1F, 0x04, 0x04, 0x04, 0x04, 0x04, 0x04]),
+    ('U', [0x11, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0E]),
+    ('V', [0x11, 0x11, 0x11, 0x11, 0x11, 0x0A, 0x04]),
+    ('W', [0x11, 0x11, 0x11, 0x15, 0x15, 0x15, 0x0A]),
+    ('X', [0x11, 0x11, 0x0A, 0x04, 0x0A, 0x11, 0x11]),
+    ('Y', [0x11, 0x11, 0x11, 0x0A, 0x04, 0x04, 0x04]),
+    ('Z', [0x1F, 0x01, 0x02, 0x04, 0x08, 0x10, 0x1F]),
+    ('a', [0x00, 0x00, 0x0E, 0x01, 0x0F, 0x11, 0x0F]),
+    ('b', [0x10, 0x10, 0x16, 0x19, 0x11, 0x11, 0x1E]),
+    ('c', [0x00, 0x00, 0x0E, 0x10, 0x10, 0x11, 0x0E]),
+    ('d', [0x01, 0x01, 0x0D, 0x13, 0x11, 0x11, 0x0F]),
+    ('e', [0x00, 0x00, 0x0E, 0x11, 0x1F, 0x10, 0x0E]),
+    ('f', [0x06, 0x09, 0x08, 0x1C, 0x08, 0x08, 0x08]),
+    ('g', [0x00, 0x0F, 0x11, 0x11, 0x0F, 0x01, 0x0E]),
+    ('h', [0x10, 0x10, 0x16, 0x19, 0x11, 0x11, 0x11]),
+    ('i', [0x04, 0x00, 0x0C, 0x04, 0x04, 0x04, 0x0E]),
+    ('j', [0x02, 0x00, 0x06, 0x02, 0x02, 0x12, 0x0C]),
+    ('k', [0x10, 0x10, 0x12, 0x14, 0x18, 0x14, 0x12]),
+    ('l', [0x0C, 0x04, 0x04, 0x04, 0x04, 0x04, 0x0E]),
+    ('m', [0x00, 0x00, 0x1A, 0x15, 0x15, 0x11, 0x11]),
+    ('n', [0x00, 0x00, 0x16, 0x19, 0x11, 0x11, 0x11]),
+    ('o', [0x00, 0x00, 0x0E, 0x11, 0x11, 0x11, 0x0E]),
+    ('p', [0x00, 0x00, 0x1E, 0x11, 0x1E, 0x10, 0x10]),
+    ('q', [0x00, 0x00, 0x0D, 0x13, 0x0F, 0x01, 0x01]),
+    ('r', [0x00, 0x00, 0x16, 0x19, 0x10, 0x10, 0x10]),
+    ('s', [0x00, 0x00, 0x0E, 0x10, 0x0E, 0x01, 0x1E]),
+    ('t', [0x08, 0x08, 0x1C, 0x08, 0x08, 0x09, 0x06]),
+    ('u', [0x00, 0x00, 0x11, 0x11, 0x11, 0x13, 0x0D]),
+    ('v', [0x00, 0x00, 0x11, 0x11, 0x11, 0x0A, 0x04]),
+    ('w', [0x00, 0x00, 0x11, 0x11, 0x15, 0x15, 0x0A]),
+    ('x', [0x00, 0x00, 0x11, 0x0A, 0x04, 0x0A, 0x11]),
+    ('y', [0x00, 0x00, 0x11, 0x11, 0x0F, 0x01, 0x0E]),
+    ('z', [0x00, 0x00, 0x1F, 0x02, 0x04, 0x08, 0x1F]),
+];
+
+// The glyphs of FONT side by side, `scale` pixels a font pixel, each in a
+// cell with a margin of a font pixel for the outline.
+#[derive(Debug, Clone)]
+pub struct GlyphAtlas {
+    scale: usize,
+    // the width of a cell and of the atlas, in pixels
+    cell_width: usize,
+    cell_height: usize,
+    width: usize,
+    // 1 for the glyphs and 2 for their outlines
+    mask: Vec<u8>,
+}
+
+impl GlyphAtlas {
+    pub fn new(scale: usize) -> GlyphAtlas {
+        let scale = scale.max(1);
+        let (cell_width, cell_height) = ((GLYPH_WIDTH + 2) * scale, (GLYPH_HEIGHT + 2) * scale);
+        let width = cell_width * FONT.len();
+        let mut mask = vec![0u8; width * cell_height];
+        for (n, (_, rows)) in FONT.iter().enumerate() {
+            let on = |x: i32, y: i32| {
+                x >= 0
+                    && y >= 0
+                    && (x as usize) < GLYPH_WIDTH
+                    && (y as usize) < GLYPH_HEIGHT
+                    && rows[y as usize] & (0x10 >> x) != 0
+            };
+            for y in 0..cell_height {
+                for x in 0..cell_width {
+                    // in the font pixels from the top left of the glyph
+                    let (fx, fy) = ((x / scale) as i32 - 1, (y / scale) as i32 - 1);
+                    let value = if on(fx, fy) {
+                        1
+                    } else if (-1..=1).any(|dy| (-1..=1).any(|dx| on(fx + dx, fy + dy))) {
+                        2
+                    } else {
+                        0
+                    };
+                    mask[y * width + n * cell_width + x] = value;
+                }
+            }
+        }
+        GlyphAtlas {
+            scale,
+            cell_width,
+            cell_height,
+            width,
+            mask,
+        }
+    }
+
+    pub fn scale(&self) -> usize {
+        self.scale
+    }
+
+    // The size of `text` in pixels without the outline.
+    pub fn text_size(&self, text: &str) -> (usize, usize) {
+        let n = text.chars().count();
+        let advance = (GLYPH_WIDTH + 1) * self.scale;
+        ((n * advance).saturating_sub(self.scale), GLYPH_HEIGHT * self.scale)
+    }
+
+    // The cell of `c`, the unknown characters as spaces.
+    fn cell(&self, c: char) -> usize {
+        FONT.iter().position(|(g, _)| *g == c).unwrap_or(0)
+    }
+
+    // Draw `text` with the top left of its first glyph at (x, y) of `image`,
+    // which may be outside of it.
+    pub fn draw_text(&self, image: &mut RgbaImage, x: i32, y: i32, text: &str, color: [u8; 3]) {
+        let advance = ((GLYPH_WIDTH + 1) * self.scale) as i32;
+        let margin = self.scale as i32;
+        // all the outlines first not to cover the glyphs next to them
+        for (value, color) in [(2, OUTLINE_COLOR), (1, color)] {
+            for (i, c) in text.chars().enumerate() {
+                let x0 = x + i as i32 * advance - margin;
+                let y0 = y - margin;
+                let cell = self.cell(c) * self.cell_width;
+                for cy in 0..self.cell_height {
+                    for cx in 0..self.cell_width {
+                        if self.mask[cy * self.width + cell + cx] != value {
+                            continue;
+                        }
+                        let (px, py) = (x0 + cx as i32, y0 + cy as i32);
+                        if px >= 0 && py >= 0 && (px as usize) < image.width && (py as usize) < image.height {
+                            image.set_pixel(px as usize, py as usize, [color[0], color[1], color[2], 255]);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+// A readout as drawn, to find the pixels that change with it.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Mark {
+    // the top left of the first glyph
+    Text { x: i32, y: i32, text: String },
+    // from (x0, y0) to (x1, y1) exclusive, outlined
+    Bar { x0: i32, y0: i32, x1: i32, y1: i32 },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Annotation {
+    // A/P, L/R and S/I at the edges of the view
+    OrientationLabels,
+    ScaleBar,
+    WindowLevel,
+    SlicePosition,
+    // of the secondary in its units, if there is one
+    Colorbar,
+}
+
+impl Annotation {
+    pub fn from_name(name: &str) -> Option<Annotation> {
+        match name {
+            "orientation" => Some(Annotation::OrientationLabels),
+            "scale_bar" => Some(Annotation::ScaleBar),
+            "window_level" => Some(Annotation::WindowLevel),
+            "slice_position" => Some(Annotation::SlicePosition),
+            "colorbar" => Some(Annotation::Colorbar),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Annotations {
+    orientation_labels: bool,
+    scale_bar: bool,
+    window_level: bool,
+    slice_position: bool,
+    colorbar: bool,
+    color: [u8; 3],
+    // of the secondary and its units, e.g. V/cm
+    colormap: Colormap,
+    units: String,
+    // from the view frame to the patient coordinates, for the labels and
+    // the slice position
+    to_patient: Base,
+    atlas: GlyphAtlas,
+    // changes with the settings above
+    revision: u32,
+}
+
+impl Default for Annotations {
+    fn default() -> Self {
+        Annotations {
+            orientation_labels: true,
+            scale_bar: true,
+            window_level: true,
+            slice_position: true,
+            colorbar: true,
+            color: [255, 255, 255],
+            colormap: Colormap::default(),
+            units: String::new(),
+            to_patient: Base::identity(),
+            atlas: GlyphAtlas::new(1),
+            revision: 0,
+        }
+    }
+}
+
+impl Annotations {
+    pub fn new() -> Annotations {
+        Annotations::default()
+    }
+
+    pub fn is_visible(&self, annotation: Annotation) -> bool {
+        match annotation {
+            Annotation::OrientationLabels => self.orientation_labels,
+            Annotation::ScaleBar => self.scale_bar,
+            Annotation::WindowLevel => self.window_level,
+            Annotation::SlicePosition => self.slice_position,
+            Annotation::Colorbar => self.colorbar,
+        }
+    }
+
+    pub fn set_visible(&mut self, annotation: Annotation, visible: bool) {
+        match annotation {
+            Annotation::OrientationLabels => self.orientation_labels = visible,
+            Annotation::ScaleBar => self.scale_bar = visible,
+            Annotation::WindowLevel => self.window_level = visible,
+            Annotation::SlicePosition => self.slice_position = visible,
+            Annotation::Colorbar => self.colorbar = visible,
+        }
+        self.revision += 1;
+    }
+
+    pub fn any_visible(&self) -> bool {
+        self.orientation_labels || self.scale_bar || self.window_level || self.slice_position || self.colorbar
+    }
+
+    pub fn set_colormap(&mut self, colormap: Colormap) {
+        self.colormap = colormap;
+        self.revision += 1;
+    }
+
+    pub fn set_units(&mut self, units: &str) {
+        self.units = String::from(units);
+        self.revision += 1;
+    }
+
+    pub fn set_color(&mut self, color: [u8; 3]) {
+        self.color = color;
+        self.revision += 1;
+    }
+
+    // The pixels of the drawing buffer a pixel of the font, e.g. 2 on the
+    // screens of 2 device pixels a CSS pixel.
+    pub fn set_text_scale(&mut self, scale: usize) {
+        if scale.max(1) != self.atlas.scale() {
+            self.atlas = GlyphAtlas::new(scale);
+            self.revision += 1;
+        }
+    }
+
+    pub fn set_patient_transform(&mut self, to_patient: Base) {
+        self.to_patient = to_patient;
+        self.revision += 1;
+    }
+
+    pub fn revision(&self) -> u32 {
+        self.revision
+    }
+
+    // The direction `d` of the view frame in the patient coordinates.
+    fn patient_direction(&self, d: &Vector3<f32>) -> Vector3<f32> {
+        self.to_patient.matrix().fixed_view::<3, 3>(0, 0) * d
+    }
+
+    // Draw the annotations on `slice` over `image`, which covers the viewport.
+    pub fn render_into(&self, image: &mut RgbaImage, slice: &SliceParams) {
+        self.render_static(image, slice);
+        let marks = self.readouts(slice, image.width, image.height);
+        self.render_readouts(image, &marks);
+    }
+
+    // The annotations but the readouts, which change only with the settings,
+    // the orientation and the secondary.
+    pub fn render_static(&self, image: &mut RgbaImage, slice: &SliceParams) {
+        let (width, height) = (image.width as i32, image.height as i32);
+        let s = self.atlas.scale() as i32;
+        let margin = 4 * s;
+        let line = GLYPH_HEIGHT as i32 * s;
+        if width <= 2 * margin || height <= 2 * margin {
+            return;
+        }
+        let [u, v, _] = slice.orientation.axes();
+
+        if self.orientation_labels {
+            // the viewport right along u and up along v
+            let right = orientation_label(&self.patient_direction(&u));
+            let left = orientation_label(&-self.patient_direction(&u));
+            let top = orientation_label(&self.patient_direction(&v));
+            let bottom = orientation_label(&-self.patient_direction(&v));
+            let w = |text: &str| self.atlas.text_size(text).0 as i32;
+            self.atlas.draw_text(image, margin, (height - line) / 2, left, self.color);
+            self.atlas.draw_text(image, width - margin - w(right), (height - line) / 2, right, self.color);
+            self.atlas.draw_text(image, (width - w(top)) / 2, margin, top, self.color);
+            self.atlas.draw_text(image, (width - w(bottom)) / 2, height - margin - line, bottom, self.color);
+        }
+
+        if let (true, Some(window)) = (self.colorbar, slice.secondary) {
+            match slice.field_display {
+                FieldDisplay::Colormap { .. } => self.render_colorbar(image, &window),
+                FieldDisplay::Bands { bands, count } => self.render_legend(image, &bands[..count]),
+            }
+        }
+    }
+
+    // The window and level, the slice position and the scale bar on `slice`
+    // in a viewport of `width` x `height` pixels.
+    pub fn readouts(&self, slice: &SliceParams, width: usize, height: usize) -> Vec<Mark> {
+        let (width, height) = (width as i32, height as i32);
+        let s = self.atlas.scale() as i32;
+        let margin = 4 * s;
+        let line = GLYPH_HEIGHT as i32 * s;
+        let mut marks = Vec::new();
+        if width <= 2 * margin || height <= 2 * margin {
+            return marks;
+        }
+        let [_, _, n] = slice.orientation.axes();
+
+        if self.window_level {
+            let text = format!("W {:.0} L {:.0}", slice.primary.window, slice.primary.level);
+            marks.push(Mark::Text {
+                x: margin,
+                y: margin,
+                text,
+            });
+        }
+
+        if self.slice_position {
+            // along the patient axis closest to the axis across the view
+            let d = self.patient_direction(&n);
+            let axis = d.iamax();
+            let p = self.to_patient.transform_point(&(n * slice.slice * 250.0).into());
+            let text = format!("{} {:.1} mm", ["X", "Y", "Z"][axis], p[axis]);
+            marks.push(Mark::Text {
+                x: margin,
+                y: height - margin - line,
+                text,
+            });
+        }
+
+        if self.scale_bar {
+            let mm_per_pixel = 500.0 * slice.extent.0 / (slice.scale * width as f32);
+            let (length, decimals) = scale_bar_length(mm_per_pixel * width as f32 / 4.0);
+            let pixels = (length / mm_per_pixel).round() as i32;
+            let (x1, y1) = (width - margin, height - margin);
+            let x0 = x1 - pixels;
+            // the bar with the ticks at the ends
+            let bar = [(x0, y1 - s, x1, y1), (x0, y1 - 3 * s, x0 + s, y1), (x1 - s, y1 - 3 * s, x1, y1)];
+            for (x0, y0, x1, y1) in bar {
+                marks.push(Mark::Bar { x0, y0, x1, y1 });
+            }
+            let text = format!("{:.*} mm", decimals, length);
+            let w = self.atlas.text_size(&text).0 as i32;
+            marks.push(Mark::Text {
+                x: x1 - w,
+                y: y1 - 5 * s - line,
+                text,
+            });
+        }
+        marks
+    }
+
+    pub fn render_readouts(&self, image: &mut RgbaImage, marks: &[Mark]) {
+        let s = self.atlas.scale() as i32;
+        // the outlines of the bars first as for the glyphs
+        for mark in marks {
+            if let Mark::Bar { x0, y0, x1, y1 } = *mark {
+                fill_rect(image, x0 - s, y0 - s, x1 + s, y1 + s, OUTLINE_COLOR);
+            }
+        }
+        for mark in marks {
+            if let Mark::Bar { x0, y0, x1, y1 } = *mark {
+                fill_rect(image, x0, y0, x1, y1, self.color);
+            }
+        }
+        for mark in marks {
+            if let Mark::Text { x, y, text } = mark {
+                self.atlas.draw_text(image, *x, *y, text, self.color);
+            }
+        }
+    }
+
+    // The pixels `mark` may draw with its outline, from (x0, y0) to (x1, y1)
+    // exclusive.
+    pub fn mark_area(&self, mark: &Mark) -> (i32, i32, i32, i32) {
+        let s = self.atlas.scale() as i32;
+        match mark {
+            Mark::Text { x, y, text } => {
+                let (w, h) = self.atlas.text_size(text);
+                (x - s, y - s, x + w as i32 + s, y + h as i32 + s)
+            }
+            Mark::Bar { x0, y0, x1, y1 } => (x0 - s, y0 - s, x1 + s, y1 + s),
+        }
+    }
+
+    // At the top right, above the orientation label there, from the lowest
+    // value of the window at the bottom to the highest at the top with the
+    // values on the left.
+    fn render_colorbar(&self, image: &mut RgbaImage, window: &WindowLevel) {
+        let (width, height) = (image.width as i32, image.height as i32);
+        let s = self.atlas.scale() as i32;
+        let margin = 4 * s;
+        let line = GLYPH_HEIGHT as i32 * s;
+        let (bar_width, bar_height) = (3 * line / 2, (height / 2 - 2 * line - 3 * margin).min(256 * s));
+        if bar_height < 2 * line {
+            return;
+        }
+        let (x1, y0) = (width - margin, margin + line / 2);
+        let (x0, y1) = (x1 - bar_width, y0 + bar_height);
+        fill_rect(image, x0 - s, y0 - s, x1 + s, y1 + s, OUTLINE_COLOR);
+        for y in y0..y1 {
+            let t = (y1 - 1 - y) as f32 / (bar_height - 1) as f32;
+            fill_rect(image, x0, y, x1, y + 1, self.colormap.color(t));
+        }
+
+        let decimals = if window.window >= 100.0 {
+            0
+        } else if window.window >= 1.0 {
+            1
+        } else {
+            2
+        };
+        let low = window.level - window.window / 2.0;
+        for (k, y) in [(1.0, y0), (0.5, (y0 + y1) / 2), (0.0, y1)] {
+            let text = format!("{:.*}", decimals, low + window.window * k);
+            let w = self.atlas.text_size(&text).0 as i32;
+            self.atlas.draw_text(image, x0 - 3 * s - w, y - line / 2, &text, self.color);
+        }
+        if !self.units.is_empty() {
+            let w = self.atlas.text_size(&self.units).0 as i32;
+            self.atlas.draw_text(image, x1 - w, y1 + 3 * s, &self.units, self.color);
+        }
+    }
+
+    // The bands instead of the colorbar, a swatch of the color of each with
+    // its range on the left from the top.
+    fn render_legend(&self, image: &mut RgbaImage, bands: &[FieldBand]) {
+        let width = image.width as i32;
+        let s = self.atlas.scale() as i32;
+        let margin = 4 * s;
+        let line = GLYPH_HEIGHT as i32 * s;
+        let x1 = width - margin;
+        let mut y = margin;
+        for band in bands {
+            fill_rect(image, x1 - line - s, y - s, x1 + s, y + line + s, OUTLINE_COLOR);
+            fill_rect(image, x1 - line, y, x1, y + line, band.color);
+            let text = format!("{} - {}", band.lower, band.upper);
+            let w = self.atlas.text_size(&text).0 as i32;
+            self.atlas.draw_text(image, x1 - line - 3 * s - w, y, &text, self.color);
+            y += line + 3 * s;
+        }
+        if !self.units.is_empty() {
+            let w = self.atlas.text_size(&self.units).0 as i32;
+            self.atlas.draw_text(image, x1 - w, y, &self.units, self.color);
+        }
+    }
+}
+
+// The letter of the patient axis closest to `d` in the patient coordinates,
+// which are LPS as DICOM.
+fn orientation_label(d: &Vector3<f32>) -> &'static str {
+    let axis = d.iamax();
+    match (axis, d[axis] >= 0.0) {
+        (0, true) => "L",
+        (0, false) => "R",
+        (1, true) => "P",
+        (1, false) => "A",
+        (_, true) => "S",
+        (_, false) => "I",
+    }
+}
+
+// The longest of 1, 2 and 5 times a power of 10 mm up to `max`, and the
+// decimals to print it with.
+fn scale_bar_length(max: f32) -> (f32, usize) {
+    let k = max.max(1e-3).log10().floor() as i32;
+    let unit = 10f32.powi(k);
+    let m = [5.0, 2.0, 1.0].into_iter().find(|m| m * unit <= max * (1.0 + 1e-5)).unwrap_or(1.0);
+    (m * unit, (-k).max(0) as usize)
+}
+
+// From (x0, y0) to (x1, y1) exclusive, clipped to the image.
+fn fill_rect(image: &mut RgbaImage, x0: i32, y0: i32, x1: i32, y1: i32, color: [u8; 3]) {
+    for y in y0.max(0)..y1.min(image.height as i32) {
+        for x in x0.max(0)..x1.min(image.width as i32) {
+            image.set_pixel(x as usize, y as usize, [color[0], color[1], color[2], 255]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::shader_sources::Orientation;
+
+    // 100 mm across
+    fn slice(orientation: Orientation) -> SliceParams {
+        SliceParams::plane(orientation, 5.0, 0.1, (400.0, 40.0))
+    }
+
+    // Whether any pixel in [x0, x1) x [y0, y1) is drawn in `color`.
+    fn has_color(image: &RgbaImage, (x0, y0, x1, y1): (usize, usize, usize, usize), color: [u8; 3]) -> bool {
+        (y0..y1).any(|y| (x0..x1).any(|x| image.pixel(x, y) == [color[0], color[1], color[2], 255]))
+    }
+
+    #[test]
+    fn test_labels() {
+        let labels = |orientation: Orientation| {
+            let [u, v, _] = orientation.axes();
+            [
+                orientation_label(&-u),
+                orientation_label(&u),
+                orientation_label(&v),
+                orientation_label(&-v),
+            ]
+        };
+        // left, right, top and bottom
+        assert_eq!(labels(Orientation::Transverse), ["R", "L", "A", "P"]);
+        assert_eq!(labels(Orientation::Sagittal), ["P", "A", "S", "I"]);
+        assert_eq!(labels(Orientation::Coronal), ["R", "L", "S", "I"]);
+
+        assert_eq!(scale_bar_length(30.0), (20.0, 0));
+        assert_eq!(scale_bar_length(125.0), (100.0, 0));
+        assert_eq!(scale_bar_length(50.0), (50.0, 0));
+        let (length, decimals) = scale_bar_length(0.3);
+        assert!((length - 0.2).abs() < 1e-6);
+        assert_eq!(decimals, 1);
+    }
+
+    #[test]
+    fn test_render_into() {
+        let mut annotations = Annotations::new();
+        let white = [255, 255, 255];
+        let mut image = RgbaImage::new(200, 200);
+        annotations.render_into(&mut image, &slice(Orientation::Transverse));
+        // the labels in the middle of the edges, the readouts in the corners
+        // but the top right, and nothing at the center
+        assert!(has_color(&image, (0, 90, 20, 110), white));
+        assert!(has_color(&image, (180, 90, 200, 110), white));
+        assert!(has_color(&image, (90, 0, 110, 20), white));
+        assert!(has_color(&image, (0, 0, 20, 20), white));
+        assert!(has_color(&image, (0, 180, 20, 200), white));
+        assert!(!has_color(&image, (150, 0, 200, 20), white));
+        assert!(!has_color(&image, (20, 20, 180, 180), white));
+        assert_eq!(image.pixel(1, 1)[3], 0);
+
+        // 0.5 mm a pixel, a bar of 20 mm at the bottom right, 4 pixels from
+        // the edges
+        let (x, y) = (200 - 4, 200 - 4 - 1);
+        assert_eq!(image.pixel(x - 1, y), [255, 255, 255, 255]);
+        assert_eq!(image.pixel(x - 40, y), [255, 255, 255, 255]);
+        assert_eq!(image.pixel(x - 41, y), [0, 0, 0, 255]);
+
+        // each can be hidden
+        for annotation in [
+            Annotation::OrientationLabels,
+            Annotation::ScaleBar,
+            Annotation::WindowLevel,
+            Annotation::SlicePosition,
+            Annotation::Colorbar,
+        ] {
+            annotations.set_visible(annotation, false);
+        }
+        assert!(!annotations.any_visible());
+        let mut image = RgbaImage::new(200, 200);
+        annotations.render_into(&mut image, &slice(Orientation::Transverse));
+        assert_eq!(image, RgbaImage::new(200, 200));
+
+        // twice as large
+        annotations.set_visible(Annotation::WindowLevel, true);
+        annotations.set_text_scale(2);
+        annotations.render_into(&mut image, &slice(Orientation::Transverse));
+        assert!(has_color(&image, (8, 8, 10, 22), white));
+        assert!(!has_color(&image, (0, 23, 200, 200), white));
+    }
+
+    #[test]
+    fn test_readouts() {
+        let annotations = Annotations::new();
+        let mut slice = slice(Orientation::Sagittal);
+        slice.secondary = Some(WindowLevel {
+            window: 1000.0,
+            level: 500.0,
+        });
+        let mut image = RgbaImage::new(200, 150);
+        annotations.render_into(&mut image, &slice);
+        // the same drawn in two parts
+        let mut base = RgbaImage::new(200, 150);
+        annotations.render_static(&mut base, &slice);
+        let marks = annotations.readouts(&slice, 200, 150);
+        assert_eq!(marks.len(), 6);
+        let mut drawn = base.clone();
+        annotations.render_readouts(&mut drawn, &marks);
+        assert_eq!(drawn, image);
+
+        // the readouts change only the pixels in their areas
+        slice.primary.level = 60.0;
+        slice.slice = -0.3;
+        let moved = annotations.readouts(&slice, 200, 150);
+        let mut image = RgbaImage::new(200, 150);
+        annotations.render_into(&mut image, &slice);
+        let areas: Vec<_> = marks.iter().chain(&moved).map(|mark| annotations.mark_area(mark)).collect();
+        for y in 0..150 {
+            for x in 0..200 {
+                let inside = areas.iter().any(|&(x0, y0, x1, y1)| x >= x0 && x < x1 && y >= y0 && y < y1);
+                if !inside {
+                    assert_eq!(image.pixel(x as usize, y as usize), drawn.pixel(x as usize, y as usize));
+                }
+            }
+        }
+        for area in areas {
+            let area = image.clip(area).unwrap();
+            drawn.copy_area(&base, area);
+        }
+        annotations.render_readouts(&mut drawn, &moved);
+        assert_eq!(drawn, image);
+        assert!(annotations.readouts(&slice, 8, 150).is_empty());
+    }
+
+    #[test]
+    fn test_colorbar() {
+        let mut annotations = Annotations::new();
+        for annotation in [
+            Annotation::OrientationLabels,
+            Annotation::ScaleBar,
+            Annotation::WindowLevel,
+            Annotation::SlicePosition,
+        ] {
+            annotations.set_visible(annotation, false);
+        }
+        annotations.set_colormap(Colormap::from_name("gray").unwrap());
+        annotations.set_units("V/cm");
+        let mut slice = slice(Orientation::Coronal);
+        let mut image = RgbaImage::new(200, 200);
+        // only with a secondary
+        annotations.render_into(&mut image, &slice);
+        assert_eq!(image, RgbaImage::new(200, 200));
+
+        slice.secondary = Some(WindowLevel {
+            window: 1000.0,
+            level: 500.0,
+        });
+        annotations.render_into(&mut image, &slice);
+        // the bar from 7 to 80 at the right, dark to bright upwards
+        let x = 200 - 4 - 5;
+        assert_eq!(image.pixel(x, 7), [255, 255, 255, 255]);
+        assert_eq!(image.pixel(x, 80), [0, 0, 0, 255]);
+        assert!(image.pixel(x, 30)[0] > image.pixel(x, 50)[0]);
+        // the values on the left and the units below
+        let white = [255, 255, 255];
+        assert!(has_color(&image, (150, 0, 184, 15), white));
+        assert!(has_color(&image, (150, 74, 184, 86), white));
+        assert!(has_color(&image, (184, 84, 196, 92), white));
+        assert!(!has_color(&image, (150, 92, 200, 200), white));
+        assert!(!has_color(&image, (0, 0, 140, 200), white));
+
+        // the swatches of the bands from the top instead
+        slice.field_display = FieldDisplay::bands(&[
+            FieldBand {
+                lower: 400.0,
+                upper: 700.0,
+                color: [255, 200, 0],
+            },
+            FieldBand {
+                lower: 700.0,
+                upper: 2000.0,
+                color: [255, 0, 0],
+            },
+        ])
+        .unwrap();
+        let mut image = RgbaImage::new(200, 200);
+        annotations.render_into(&mut image, &slice);
+        assert_eq!(image.pixel(193, 7), [255, 200, 0, 255]);
+        assert_eq!(image.pixel(193, 17), [255, 0, 0, 255]);
+        assert!(has_color(&image, (120, 4, 186, 11), white));
+        assert!(has_color(&image, (184, 24, 196, 31), white));
+        assert!(!has_color(&image, (0, 32, 200, 200), white));
+    }
+}
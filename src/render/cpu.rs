@@ -0,0 +1,637 @@
+// MIT License
+
+// Copyright (c) 2023 Techtron-Lab
+
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+
+// A software implementation of SliceRenderer following the shaders of
+// slice_fragment_shader pixel by pixel, for the tests without a GPU.
+
+use nalgebra::{Matrix3, Vector3};
+
+use crate::colormap::jet::LUT;
+use crate::techtron::core::base::Base;
+use crate::view::SlabMode;
+
+use super::annotations::Annotations;
+use super::isolines::IsoLineOverlay;
+use super::overlay::StructureOverlay;
+use super::{RgbaImage, SliceParams, SliceRenderer};
+
+// A volume sampled as a 3D texture with linear filtering and clamped to the
+// edge, with the centers of the voxels at (i + 0.5) / n.
+#[derive(Debug, Clone)]
+pub struct CpuVolume {
+    pub width: usize,
+    pub height: usize,
+    pub depth: usize,
+    pub data: Vec<f32>,
+}
+
+impl CpuVolume {
+    pub fn new(width: usize, height: usize, depth: usize, data: Vec<f32>) -> Result<CpuVolume, String> {
+        if width == 0 || height == 0 || depth == 0 || data.len() != width * height * depth {
+            return Err(format!(
+                "{} values do not fill a volume of {}x{}x{}.",
+                data.len(),
+                width,
+                height,
+                depth
+            ));
+        }
+        Ok(CpuVolume { width, height, depth, data })
+    }
+
+    fn voxel(&self, x: usize, y: usize, z: usize) -> f32 {
+        self.data[(z * self.height + y) * self.width + x]
+    }
+
+    pub fn sample(&self, coord: &Vector3<f32>) -> f32 {
+        // the lower voxel and the weight of the upper one along an axis
+        fn axis(c: f32, n: usize) -> (usize, usize, f32) {
+            let t = (c * n as f32 - 0.5).clamp(0.0, (n - 1) as f32);
+            let i = t.floor() as usize;
+            (i, (i + 1).min(n - 1), t - i as f32)
+        }
+        let (x0, x1, fx) = axis(coord.x, self.width);
+        let (y0, y1, fy) = axis(coord.y, self.height);
+        let (z0, z1, fz) = axis(coord.z, self.depth);
+        let lerp = |a: f32, b: f32, t: f32| a + (b - a) * t;
+        let plane = |z| {
+            lerp(
+                lerp(self.voxel(x0, y0, z), self.voxel(x1, y0, z), fx),
+                lerp(self.voxel(x0, y1, z), self.voxel(x1, y1, z), fx),
+                fy,
+            )
+        };
+        lerp(plane(z0), plane(z1), fz)
+    }
+}
+
+// A volume placed in the view frame of the primary.
+#[derive(Debug, Clone)]
+pub struct CpuLayer {
+    pub volume: CpuVolume,
+    // from the view frame of the primary to the texture coordinates
+    pub to_texture: Base,
+    // slope and intercept from the volume values to the real world values
+    pub rescale: (f32, f32),
+    // of the voxels in mm
+    pub spacing: Vector3<f32>,
+}
+
+impl CpuLayer {
+    // The primary is centered on the view frame along its axes.
+    pub fn primary(volume: CpuVolume, spacing: Vector3<f32>, rescale: (f32, f32)) -> CpuLayer {
+        let size = Vector3::new(
+            volume.width as f32 * spacing.x,
+            volume.height as f32 * spacing.y,
+            volume.depth as f32 * spacing.z,
+        );
+        let scaling = Base::from_matrix(
+            Matrix3::from_diagonal(&size.map(|s| 1.0 / s)).to_homogeneous(),
+        );
+        CpuLayer {
+            volume,
+            to_texture: Base::translation(0.5, 0.5, 0.5).compose(&scaling),
+            rescale,
+            spacing,
+        }
+    }
+
+    // The secondary with the transform of DosePane.
+    pub fn secondary(volume: CpuVolume, spacing: Vector3<f32>, to_texture: Base) -> CpuLayer {
+        CpuLayer {
+            volume,
+            to_texture,
+            rescale: (1.0, 0.0),
+            spacing,
+        }
+    }
+
+    // The real world value at `p` in the view frame, None outside.
+    pub fn value(&self, p: &Vector3<f32>) -> Option<f32> {
+        let coord = self.coord(p);
+        if is_outbound(&coord) {
+            return None;
+        }
+        Some(self.volume.sample(&coord) * self.rescale.0 + self.rescale.1)
+    }
+
+    fn coord(&self, p: &Vector3<f32>) -> Vector3<f32> {
+        Vector3::from(self.to_texture.transform_point(&[p.x, p.y, p.z]))
+    }
+
+    // `step` in mm to the texture coordinates
+    fn coord_step(&self, step: &Vector3<f32>) -> Vector3<f32> {
+        self.to_texture.matrix().fixed_view::<3, 3>(0, 0) * step
+    }
+
+    // As project_slab of the shaders, in the real world values.
+    fn project(&self, coord: &Vector3<f32>, step: &Vector3<f32>, n: usize, mode: SlabMode) -> f32 {
+        let raw = if n <= 1 {
+            self.volume.sample(coord)
+        } else {
+            let samples: Vec<f32> = (0..n)
+                .map(|i| coord + step * (i as f32 - (n - 1) as f32 / 2.0))
+                .filter(|c| !is_outbound(c))
+                .map(|c| self.volume.sample(&c))
+                .collect();
+            match (samples.is_empty(), mode) {
+                (true, _) => self.volume.sample(coord),
+                (false, SlabMode::Maximum) => samples.iter().cloned().fold(f32::MIN, f32::max),
+                (false, SlabMode::Minimum) => samples.iter().cloned().fold(f32::MAX, f32::min),
+                (false, _) => samples.iter().sum::<f32>() / samples.len() as f32,
+            }
+        };
+        raw * self.rescale.0 + self.rescale.1
+    }
+}
+
+fn is_outbound(c: &Vector3<f32>) -> bool {
+    c.iter().any(|v| *v < 0.0 || *v > 1.0)
+}
+
+pub struct CpuRenderer {
+    pub image: RgbaImage,
+    pub primary: CpuLayer,
+    pub secondary: Option<CpuLayer>,
+    // 256 RGB colors of the secondary
+    pub lut: Vec<u8>,
+    pub structures: Option<StructureOverlay>,
+    // with the needles at the center
+    pub isolines: Option<IsoLineOverlay>,
+    pub annotations: Option<Annotations>,
+}
+
+impl CpuRenderer {
+    // The image is cleared to the background of GlCanvas.
+    pub fn new(width: usize, height: usize, primary: CpuLayer) -> CpuRenderer {
+        let mut image = RgbaImage::new(width, height);
+        image.fill([128, 128, 128, 255]);
+        CpuRenderer {
+            image,
+            primary,
+            secondary: None,
+            lut: LUT.to_vec(),
+            structures: None,
+            isolines: None,
+            annotations: None,
+        }
+    }
+
+    // Linear between the colors at the centers of the texels, as sampler2.
+    fn lut_color(&self, gray: f32) -> [f32; 3] {
+        let n = self.lut.len() / 3;
+        let t = (gray * n as f32 - 0.5).clamp(0.0, (n - 1) as f32);
+        let i0 = t.floor() as usize;
+        let i1 = (i0 + 1).min(n - 1);
+        let f = t - i0 as f32;
+        let c = |i: usize, ch: usize| self.lut[i * 3 + ch] as f32 / 255.0;
+        [0, 1, 2].map(|ch| c(i0, ch) + (c(i1, ch) - c(i0, ch)) * f)
+    }
+
+    // The structures, the iso-lines and the annotations over the viewport,
+    // as OverlayPane.
+    fn overlay(&self, slice: &SliceParams, width: usize, height: usize) -> Option<RgbaImage> {
+        if self.structures.is_none() && self.isolines.is_none() && self.annotations.is_none() {
+            return None;
+        }
+        let mut image = match self.structures.as_ref() {
+            Some(structures) => structures.render(slice, width, height),
+            None => RgbaImage::new(width, height),
+        };
+        if let Some(isolines) = self.isolines.as_ref() {
+            isolines.render_into(&mut image, slice, &Vector3::zeros());
+        }
+        if let Some(annotations) = self.annotations.as_ref() {
+            annotations.render_into(&mut image, slice);
+        }
+        Some(image)
+    }
+
+    // The color at `q` of the viewport, in [-1, 1] with y pointing up.
+    fn shade(&self, slice: &SliceParams, q: (f32, f32)) -> [f32; 3] {
+        let p = slice.point(q);
+        let (n, step) = slice.slab_samples(&self.primary.spacing);
+        let mode = slice.slab.mode;
+
+        let coord0 = self.primary.coord(&p);
+        let value0 = self.primary.project(&coord0, &self.primary.coord_step(&step), n, mode);
+        let gray0 = if is_outbound(&coord0) { 0.0 } else { slice.primary.gray(value0) };
+
+        // without a secondary it is outside of it everywhere
+        let (value1, window) = match (self.secondary.as_ref(), slice.secondary) {
+            (Some(layer), Some(window)) => {
+                let coord1 = layer.coord(&p);
+                let n1 = if slice.slab.field { n } else { 1 };
+                let value1 = layer.project(&coord1, &layer.coord_step(&step), n1, mode);
+                (if is_outbound(&coord1) { None } else { Some(value1) }, window)
+            }
+            // the window is not used without a value
+            _ => (None, slice.primary),
+        };
+        let rgba1 = slice.field_display.rgba(value1, &window, |gray| self.lut_color(gray));
+        let gray1 = value1.map(|v| window.gray(v)).unwrap_or(0.0);
+        let [u, v, _] = slice.orientation.axes();
+        let weight = slice.fusion.weight(&p, &u, &v, slice.blend);
+        slice.fusion.fuse(gray0, rgba1, gray1, weight)
+    }
+}
+
+impl SliceRenderer for CpuRenderer {
+    fn render_slice(&mut self, slice: &SliceParams, viewport: (i32, i32, i32, i32)) -> Result<(), String> {
+        let (x0, y0, width, height) = viewport;
+        if width <= 0 || height <= 0 {
+            return Ok(());
+        }
+        let overlay = self.overlay(slice, width as usize, height as usize);
+        let [u, v, _] = slice.orientation.axes();
+        let pixel = (
+            500.0 * slice.extent.0 / (slice.scale * width as f32),
+            500.0 * slice.extent.1 / (slice.scale * height as f32),
+        );
+        for j in 0..height {
+            // rows of the image from the top
+            let row = self.image.height as i32 - 1 - (y0 + j);
+            if row < 0 || row >= self.image.height as i32 {
+                continue;
+            }
+            for i in 0..width {
+                let col = x0 + i;
+                if col < 0 || col >= self.image.width as i32 {
+                    continue;
+                }
+                // at the centers of the pixels as the fragments
+                let q = (
+                    (i as f32 + 0.5) / width as f32 * 2.0 - 1.0,
+                    (j as f32 + 0.5) / height as f32 * 2.0 - 1.0,
+                );
+                let mut rgb = self.shade(slice, q);
+                // over the slice as the overlay sampler
+                if let Some(image) = overlay.as_ref() {
+                    let o = image.pixel(i as usize, (height - 1 - j) as usize);
+                    let a = o[3] as f32 / 255.0;
+                    rgb = [0, 1, 2].map(|ch| rgb[ch] * (1.0 - a) + o[ch] as f32 / 255.0 * a);
+                }
+                if let Some(crosshair) = slice.crosshair {
+                    if crosshair.covers(&slice.point(q), &u, &v, pixel) {
+                        rgb = crosshair.color;
+                    }
+                }
+                let rgb = rgb.map(|c| (c.clamp(0.0, 1.0) * 255.0).round() as u8);
+                self.image.set_pixel(col as usize, row as usize, [rgb[0], rgb[1], rgb[2], 255]);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::render::{viewport_extent, Crosshair, FieldBand, FieldDisplay, Fusion, WindowLevel};
+    use crate::shader_sources::Orientation;
+    use crate::techtron::graphic::contour::{Contour2Df32, Structure, StructureSet};
+    use crate::techtron::graphic::Point;
+    use crate::view::Slab;
+
+    // A water cylinder along z with a bone sphere off the center, in HU
+    // stored with an offset of 1024, and a field peaking at the center.
+    fn phantom() -> CpuRenderer {
+        let (w, h, d) = (32, 32, 16);
+        let spacing = Vector3::new(1.0, 1.0, 2.0);
+        let mut data = Vec::with_capacity(w * h * d);
+        for k in 0..d {
+            for j in 0..h {
+                for i in 0..w {
+                    let p = Vector3::new(i as f32 + 0.5, j as f32 + 0.5, k as f32 + 0.5)
+                        .component_mul(&spacing)
+                        - Vector3::new(16.0, 16.0, 16.0);
+                    let value = if (p - Vector3::new(6.0, -6.0, 4.0)).norm() < 4.0 {
+                        700.0
+                    } else if p.x * p.x + p.y * p.y < 144.0 {
+                        40.0
+                    } else {
+                        -1000.0
+                    };
+                    data.push(value + 1024.0);
+                }
+            }
+        }
+        let volume = CpuVolume::new(w, h, d, data).unwrap();
+        let primary = CpuLayer::primary(volume, spacing, (1.0, -1024.0));
+
+        let n = 16;
+        let mut field = Vec::with_capacity(n * n * n);
+        for k in 0..n {
+            for j in 0..n {
+                for i in 0..n {
+                    let p = Vector3::new(i as f32, j as f32, k as f32) * 2.0 - Vector3::new(15.0, 15.0, 15.0);
+                    field.push(1000.0 * (-p.norm_squared() / 72.0).exp());
+                }
+            }
+        }
+        let volume = CpuVolume::new(n, n, n, field).unwrap();
+        let placed = CpuLayer::primary(volume, Vector3::new(2.0, 2.0, 2.0), (1.0, 0.0));
+        let secondary = CpuLayer::secondary(placed.volume, placed.spacing, placed.to_texture);
+
+        let mut renderer = CpuRenderer::new(48, 48, primary);
+        renderer.secondary = Some(secondary);
+        renderer
+    }
+
+    // 33 mm across
+    fn params(orientation: Orientation) -> SliceParams {
+        SliceParams {
+            secondary: Some(WindowLevel {
+                window: 1000.0,
+                level: 500.0,
+            }),
+            ..SliceParams::plane(orientation, 15.0, 0.0, (400.0, 40.0))
+        }
+    }
+
+    // Set UPDATE_GOLDEN to write the images instead after a change of the
+    // pipeline, and check them by eye.
+    fn assert_golden(name: &str, image: &RgbaImage) {
+        let path = format!("{}/tests/golden/{}.ppm", env!("CARGO_MANIFEST_DIR"), name);
+        if std::env::var("UPDATE_GOLDEN").is_ok() {
+            std::fs::write(&path, image.to_ppm()).unwrap();
+            return;
+        }
+        let buf = std::fs::read(&path).unwrap_or_else(|_| panic!("cannot read {}", path));
+        let golden = RgbaImage::from_ppm(&buf).unwrap();
+        let diff = image.max_difference(&golden);
+        assert!(
+            matches!(diff, Some(d) if d <= 1),
+            "{} differs from the golden image by {:?}",
+            name,
+            diff
+        );
+    }
+
+    #[test]
+    fn test_slice_point() {
+        let mut slice = params(Orientation::Transverse);
+        slice.scale = 1.0;
+        slice.slice = 0.1;
+        // anterior, i.e. -y, is up
+        assert_eq!(slice.point((0.0, 1.0)), Vector3::new(0.0, -250.0, 25.0));
+        slice.orientation = Orientation::Sagittal;
+        slice.pan = (0.5, 0.0);
+        assert_eq!(slice.point((1.0, 1.0)), Vector3::new(25.0, -125.0, 250.0));
+        slice.orientation = Orientation::Coronal;
+        slice.scale = 2.0;
+        assert_eq!(slice.point((0.5, 0.5)), Vector3::new(0.0, -25.0, 62.5));
+
+        // twice as wide as high, 500 mm across the height at scale 1
+        assert_eq!(viewport_extent(200, 100), (2.0, 1.0));
+        assert_eq!(viewport_extent(100, 400), (1.0, 4.0));
+        slice.scale = 1.0;
+        slice.pan = (0.0, 0.0);
+        slice.extent = viewport_extent(200, 100);
+        assert_eq!(slice.point((1.0, 1.0)), Vector3::new(500.0, -25.0, 250.0));
+        assert_eq!(slice.viewport_coord(&Vector3::new(500.0, -25.0, 250.0)), (1.0, 1.0));
+    }
+
+    #[test]
+    fn test_window_level() {
+        let mut renderer = phantom();
+        renderer.secondary = None;
+        let slice = params(Orientation::Transverse);
+        renderer.render_slice(&slice, (0, 0, 48, 48)).unwrap();
+        // water at the level, air below the window and the field colormap
+        // is blended with k = 0
+        assert_eq!(renderer.image.pixel(24, 30), [128, 128, 128, 255]);
+        assert_eq!(renderer.image.pixel(1, 1), [0, 0, 0, 255]);
+        // outside of the primary
+        let mut far = slice;
+        far.slice = 1.0;
+        renderer.render_slice(&far, (0, 0, 48, 48)).unwrap();
+        assert_eq!(renderer.image.pixel(24, 24), [0, 0, 0, 255]);
+    }
+
+    #[test]
+    fn test_field_display() {
+        let mut renderer = phantom();
+        let mut slice = params(Orientation::Transverse);
+        slice.blend = 1.0;
+        renderer.render_slice(&slice, (0, 0, 48, 48)).unwrap();
+        // a low field over the air
+        let far = renderer.image.pixel(2, 24);
+        assert!(far[2] > 128, "{:?}", far);
+        let center = renderer.image.pixel(24, 24);
+        let value = renderer.secondary.as_ref().unwrap().value(&slice.point((2.5 / 24.0 - 1.0, 0.0))).unwrap();
+
+        // only the primary below the threshold
+        slice.field_display = FieldDisplay::Colormap {
+            ramp: Some((500.0, 500.0)),
+        };
+        renderer.render_slice(&slice, (0, 0, 48, 48)).unwrap();
+        assert_eq!(renderer.image.pixel(2, 24), [0, 0, 0, 255]);
+        assert_eq!(renderer.image.pixel(24, 24), center);
+
+        // half way up the ramp
+        slice.field_display = FieldDisplay::Colormap {
+            ramp: Some((0.0, value * 2.0)),
+        };
+        renderer.render_slice(&slice, (0, 0, 48, 48)).unwrap();
+        let b = renderer.image.pixel(2, 24)[2];
+        assert!(b.abs_diff(far[2] / 2) <= 1, "{} {:?}", b, far);
+
+        slice.field_display = FieldDisplay::bands(&[
+            FieldBand {
+                lower: 900.0,
+                upper: 2000.0,
+                color: [255, 0, 0],
+            },
+            FieldBand {
+                lower: 0.0,
+                upper: 2000.0,
+                color: [0, 255, 0],
+            },
+        ])
+        .unwrap();
+        renderer.render_slice(&slice, (0, 0, 48, 48)).unwrap();
+        assert_eq!(renderer.image.pixel(24, 24), [255, 0, 0, 255]);
+        assert_eq!(renderer.image.pixel(2, 24), [0, 255, 0, 255]);
+        assert!(FieldDisplay::bands(&[]).is_err());
+    }
+
+    #[test]
+    fn test_crosshair() {
+        let mut renderer = phantom();
+        let mut slice = params(Orientation::Transverse);
+        slice.crosshair = Some(Crosshair {
+            cursor: Vector3::new(3.0, -6.0, 0.0),
+            color: [1.0, 0.0, 0.0],
+        });
+        renderer.render_slice(&slice, (0, 0, 48, 48)).unwrap();
+        // 0.69 mm a pixel, anterior up
+        assert_eq!(renderer.image.pixel(28, 5), [255, 0, 0, 255]);
+        assert_eq!(renderer.image.pixel(5, 15), [255, 0, 0, 255]);
+        assert_ne!(renderer.image.pixel(27, 5), [255, 0, 0, 255]);
+        assert_ne!(renderer.image.pixel(5, 14), [255, 0, 0, 255]);
+    }
+
+    #[test]
+    fn test_fusion() {
+        let mut renderer = phantom();
+        let mut slice = params(Orientation::Transverse);
+        renderer.render_slice(&slice, (0, 0, 48, 48)).unwrap();
+        let primary = renderer.image.clone();
+        slice.blend = 1.0;
+        renderer.render_slice(&slice, (0, 0, 48, 48)).unwrap();
+        let secondary = renderer.image.clone();
+        // at the center of the pixel from the top left
+        let frame = slice;
+        let point = |x: usize, y: usize| frame.point(((x as f32 + 0.5) / 24.0 - 1.0, 1.0 - (y as f32 + 0.5) / 24.0));
+
+        // odd tiles of 8 mm from the secondary whatever the blend
+        slice.blend = 0.0;
+        slice.fusion = Fusion::Checkerboard { tile: 8.0 };
+        renderer.render_slice(&slice, (0, 0, 48, 48)).unwrap();
+        for (x, y) in [(2, 2), (10, 2), (24, 24), (30, 40), (45, 20)] {
+            let p = point(x, y);
+            let odd = ((p.x / 8.0).floor() + (-p.y / 8.0).floor()).rem_euclid(2.0) == 1.0;
+            let expected = if odd { &secondary } else { &primary };
+            assert_eq!(renderer.image.pixel(x, y), expected.pixel(x, y), "at {} {}", x, y);
+        }
+        assert_ne!(renderer.image, primary);
+        assert_ne!(renderer.image, secondary);
+
+        // the secondary within 5 mm of the cursor, 0.69 mm a pixel
+        let cursor = Vector3::new(3.0, -6.0, 0.0);
+        slice.fusion = Fusion::Checkerboard { tile: 8.0 }.follow(cursor);
+        assert_eq!(slice.fusion, Fusion::Checkerboard { tile: 8.0 });
+        slice.fusion = Fusion::from_name("spyglass", 5.0, Vector3::zeros()).unwrap().follow(cursor);
+        renderer.render_slice(&slice, (0, 0, 48, 48)).unwrap();
+        assert_eq!(renderer.image.pixel(28, 15), secondary.pixel(28, 15));
+        assert_eq!(renderer.image.pixel(34, 15), secondary.pixel(34, 15));
+        assert_eq!(renderer.image.pixel(37, 15), primary.pixel(37, 15));
+        assert_eq!(renderer.image.pixel(24, 24), primary.pixel(24, 24));
+
+        // |gray0 - gray1| in gray
+        slice.fusion = Fusion::Difference;
+        renderer.render_slice(&slice, (0, 0, 48, 48)).unwrap();
+        let window = slice.secondary.unwrap();
+        for (x, y) in [(2, 24), (24, 24), (30, 10)] {
+            let value = renderer.secondary.as_ref().unwrap().value(&point(x, y)).unwrap();
+            let gray = (primary.pixel(x, y)[0] as f32 / 255.0 - window.gray(value)).abs();
+            let pixel = renderer.image.pixel(x, y);
+            assert_eq!(pixel[0], pixel[2]);
+            assert!(pixel[0].abs_diff((gray * 255.0).round() as u8) <= 1, "{:?} {}", pixel, gray);
+        }
+        assert!(Fusion::from_name("overlay", 1.0, cursor).is_none());
+    }
+
+    #[test]
+    fn test_ppm() {
+        let mut image = RgbaImage::new(3, 2);
+        image.fill([1, 2, 3, 255]);
+        image.set_pixel(2, 1, [200, 100, 0, 255]);
+        assert_eq!(RgbaImage::from_ppm(&image.to_ppm()).unwrap(), image);
+        assert!(RgbaImage::from_ppm(b"P5\n3 2\n255\n").is_err());
+    }
+
+    #[test]
+    fn test_golden_transverse_blend() {
+        let mut renderer = phantom();
+        let mut slice = params(Orientation::Transverse);
+        slice.slice = 4.0 / 250.0;
+        slice.blend = 0.4;
+        renderer.render_slice(&slice, (0, 0, 48, 48)).unwrap();
+        assert_golden("transverse_blend", &renderer.image);
+    }
+
+    #[test]
+    fn test_golden_sagittal_mip() {
+        let mut renderer = phantom();
+        let mut slice = params(Orientation::Sagittal);
+        slice.slice = 6.0 / 250.0;
+        slice.blend = 0.3;
+        slice.slab = Slab {
+            mode: SlabMode::Maximum,
+            thickness: 10.0,
+            field: true,
+        };
+        renderer.render_slice(&slice, (0, 0, 48, 48)).unwrap();
+        assert_golden("sagittal_mip", &renderer.image);
+    }
+
+    #[test]
+    fn test_golden_coronal_viewport() {
+        let mut renderer = phantom();
+        renderer.image = RgbaImage::new(64, 48);
+        renderer.image.fill([128, 128, 128, 255]);
+        let mut slice = params(Orientation::Coronal);
+        slice.slice = 6.0 / 250.0;
+        slice.pan = (0.2, -0.1);
+        slice.blend = 0.5;
+        renderer.render_slice(&slice, (36, 4, 24, 24)).unwrap();
+        assert_golden("coronal_viewport", &renderer.image);
+    }
+
+    // The outlines of the bone and the body of the phantom, drawn on the
+    // contours every 2 mm.
+    #[test]
+    fn test_golden_sagittal_structures() {
+        let circle = |center: (f32, f32), r: f32| {
+            let points = (0..24)
+                .map(|i| {
+                    let a = i as f32 * std::f32::consts::PI / 12.0;
+                    Point::new([center.0 + r * a.cos(), center.1 + r * a.sin()])
+                })
+                .collect();
+            Contour2Df32::from_points(points)
+        };
+        let mut set = StructureSet::new();
+        let mut body = Structure::new();
+        body.set_number(1);
+        body.set_color(0, 255, 255);
+        let mut bone = Structure::new();
+        bone.set_number(2);
+        bone.set_color(255, 255, 0);
+        for k in 0..16 {
+            let z = k as f32 * 2.0 - 15.0;
+            body.push(z, circle((0.0, 0.0), 12.0));
+            if (z - 4.0).abs() < 4.0 {
+                bone.push(z, circle((6.0, -6.0), (16.0 - (z - 4.0) * (z - 4.0)).sqrt()));
+            }
+        }
+        set.push(body);
+        set.push(bone);
+
+        let mut structures = StructureOverlay::new();
+        structures.set_structures(&set, &Base::identity(), 2.0);
+        let mut style = structures.style(2).unwrap();
+        style.line_width = 1.0;
+        style.fill_opacity = 0.4;
+        structures.set_style(2, style).unwrap();
+
+        let mut renderer = phantom();
+        renderer.structures = Some(structures);
+        let mut slice = params(Orientation::Sagittal);
+        slice.slice = 6.0 / 250.0;
+        renderer.render_slice(&slice, (0, 0, 48, 48)).unwrap();
+        assert_golden("sagittal_structures", &renderer.image);
+    }
+}
@@ -0,0 +1,227 @@
+// MIT License
+
+// Copyright (c) 2023 Techtron-Lab
+
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+
+// Iso-lines of the secondary on the slices. The field is sampled on a grid of
+// points of the slice in the view frame of the primary, every GRID_STEP
+// pixels of the viewport, and the lines are traced on the grid with
+// marching squares and drawn into the overlay image of the view.
+
+use nalgebra::Vector3;
+
+use crate::render::cpu::CpuLayer;
+use crate::render::{RgbaImage, SliceParams};
+use crate::techtron::graphic::marching_squares::iso_segments;
+
+const GRID_STEP: usize = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct IsoLevel {
+    // in the real world values of the secondary, e.g. V/cm
+    pub value: f32,
+    pub color: [u8; 3],
+}
+
+#[derive(Debug, Clone)]
+pub struct IsoLineOverlay {
+    levels: Vec<IsoLevel>,
+    // in pixels
+    line_width: f32,
+    field: Option<CpuLayer>,
+    // whether the field is moved with the needle position, as DosePane
+    follow_needles: bool,
+    // changes with the levels, the line width and the field
+    revision: u32,
+}
+
+impl Default for IsoLineOverlay {
+    fn default() -> Self {
+        IsoLineOverlay {
+            levels: Vec::new(),
+            line_width: 1.5,
+            field: None,
+            follow_needles: false,
+            revision: 0,
+        }
+    }
+}
+
+impl IsoLineOverlay {
+    pub fn new() -> IsoLineOverlay {
+        IsoLineOverlay::default()
+    }
+
+    pub fn set_field(&mut self, field: CpuLayer, follow_needles: bool) {
+        self.field = Some(field);
+        self.follow_needles = follow_needles;
+        self.revision += 1;
+    }
+
+    pub fn clear_field(&mut self) {
+        self.field = None;
+        self.revision += 1;
+    }
+
+    pub fn levels(&self) -> &[IsoLevel] {
+        &self.levels
+    }
+
+    pub fn set_levels(&mut self, levels: Vec<IsoLevel>) {
+        self.levels = levels;
+        self.revision += 1;
+    }
+
+    pub fn set_line_width(&mut self, line_width: f32) {
+        self.line_width = line_width.max(0.0);
+        self.revision += 1;
+    }
+
+    // whether there is anything to draw
+    pub fn is_active(&self) -> bool {
+        self.field.is_some() && !self.levels.is_empty() && self.line_width > 0.0
+    }
+
+    pub fn follows_needles(&self) -> bool {
+        self.follow_needles
+    }
+
+    pub fn revision(&self) -> u32 {
+        self.revision
+    }
+
+    // Draw the lines on `slice` over `image`, which covers the viewport.
+    // `needle_center` is in mm in the view frame, see TwoNeedleGeometry.
+    pub fn render_into(&self, image: &mut RgbaImage, slice: &SliceParams, needle_center: &Vector3<f32>) {
+        let field = match self.field.as_ref() {
+            Some(field) if self.is_active() => field,
+            _ => return,
+        };
+        let (width, height) = (image.width, image.height);
+        let shift = if self.follow_needles { *needle_center } else { Vector3::zeros() };
+
+        // the grid covers the viewport, 0 outside of the field
+        let (nx, ny) = (width.div_ceil(GRID_STEP) + 1, height.div_ceil(GRID_STEP) + 1);
+        let mut values = Vec::with_capacity(nx * ny);
+        for j in 0..ny {
+            for i in 0..nx {
+                let q = (
+                    (i * GRID_STEP) as f32 / width as f32 * 2.0 - 1.0,
+                    1.0 - (j * GRID_STEP) as f32 / height as f32 * 2.0,
+                );
+                values.push(field.value(&(slice.point(q) - shift)).unwrap_or(0.0));
+            }
+        }
+
+        for level in &self.levels {
+            let segments = iso_segments(level.value, &values, nx as i32, ny as i32);
+            let mut coverage = vec![0.0f32; width * height];
+            for [a, b] in segments {
+                let k = GRID_STEP as f32;
+                draw_segment(&mut coverage, width, height, [a[0] * k, a[1] * k], [b[0] * k, b[1] * k], self.line_width);
+            }
+            for (i, alpha) in coverage.iter().enumerate() {
+                if *alpha > 0.0 {
+                    image.blend_pixel(i % width, i / width, level.color, *alpha);
+                }
+            }
+        }
+    }
+}
+
+// The coverage of the pixels by a segment of `line_width` in pixels from the
+// top left, the larger where it overlaps the others.
+fn draw_segment(coverage: &mut [f32], width: usize, height: usize, a: [f32; 2], b: [f32; 2], line_width: f32) {
+    let half = line_width / 2.0;
+    let x0 = (a[0].min(b[0]) - half - 1.0).floor().max(0.0) as usize;
+    let x1 = ((a[0].max(b[0]) + half + 1.0).ceil().max(0.0) as usize).min(width);
+    let y0 = (a[1].min(b[1]) - half - 1.0).floor().max(0.0) as usize;
+    let y1 = ((a[1].max(b[1]) + half + 1.0).ceil().max(0.0) as usize).min(height);
+    let u = [b[0] - a[0], b[1] - a[1]];
+    let uu = (u[0] * u[0] + u[1] * u[1]).max(1e-12);
+    for y in y0..y1 {
+        for x in x0..x1 {
+            // from the center of the pixel
+            let p = [x as f32 + 0.5 - a[0], y as f32 + 0.5 - a[1]];
+            let s = ((p[0] * u[0] + p[1] * u[1]) / uu).clamp(0.0, 1.0);
+            let d = ((p[0] - s * u[0]).powi(2) + (p[1] - s * u[1]).powi(2)).sqrt();
+            let c = (half + 0.5 - d).clamp(0.0, 1.0);
+            let i = y * width + x;
+            coverage[i] = coverage[i].max(c);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::render::cpu::CpuVolume;
+    use crate::shader_sources::Orientation;
+
+    // 1000 at the center falling off to 500 at 20 mm
+    fn field() -> CpuLayer {
+        let n = 64;
+        let mut data = Vec::with_capacity(n * n * n);
+        for k in 0..n {
+            for j in 0..n {
+                for i in 0..n {
+                    let p = Vector3::new(i as f32, j as f32, k as f32) - Vector3::new(31.5, 31.5, 31.5);
+                    data.push(1000.0 * 0.5f32.powf(p.norm_squared() / 400.0));
+                }
+            }
+        }
+        let volume = CpuVolume::new(n, n, n, data).unwrap();
+        CpuLayer::primary(volume, Vector3::new(1.0, 1.0, 1.0), (1.0, 0.0))
+    }
+
+    // 100 mm across
+    fn slice(orientation: Orientation) -> SliceParams {
+        SliceParams::plane(orientation, 5.0, 0.0, (1.0, 0.0))
+    }
+
+    #[test]
+    fn test_render_into() {
+        let mut overlay = IsoLineOverlay::new();
+        assert!(!overlay.is_active());
+        overlay.set_field(field(), true);
+        overlay.set_levels(vec![IsoLevel {
+            value: 500.0,
+            color: [255, 0, 0],
+        }]);
+        overlay.set_line_width(2.0);
+        assert!(overlay.is_active());
+
+        // a circle of 20 mm, 1 mm a pixel, around the center of the viewport,
+        // within half a pixel on the grid of 4 mm
+        let mut image = RgbaImage::new(100, 100);
+        overlay.render_into(&mut image, &slice(Orientation::Coronal), &Vector3::zeros());
+        assert_eq!(image.pixel(50, 50)[3], 0);
+        assert_eq!(image.pixel(69, 50), [255, 0, 0, 255]);
+        assert_eq!(image.pixel(50, 30), [255, 0, 0, 255]);
+        assert_eq!(image.pixel(75, 50)[3], 0);
+
+        // and around the needles when they move
+        let mut image = RgbaImage::new(100, 100);
+        overlay.render_into(&mut image, &slice(Orientation::Coronal), &Vector3::new(10.0, 0.0, 0.0));
+        assert_eq!(image.pixel(79, 50), [255, 0, 0, 255]);
+        assert_eq!(image.pixel(69, 50)[3], 0);
+    }
+}
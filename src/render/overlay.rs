@@ -0,0 +1,412 @@
+// MIT License
+
+// Copyright (c) 2023 Techtron-Lab
+
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+
+// The structures of a structure set over the slices. Every contour stands for
+// the slab of the structure from halfway to the contours below it to halfway
+// to the ones above, so a slice across the contours cuts a rectangle out of
+// each slab. The outlines and the fill are drawn into an image of the
+// viewport, which the views blend over the slice.
+
+use nalgebra::Vector3;
+
+use crate::render::{RgbaImage, SliceParams};
+use crate::techtron::core::base::Base;
+use crate::techtron::graphic::contour::{Structure, StructureSet};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StructureStyle {
+    pub color: [u8; 3],
+    // in pixels, drawn inside of the structure
+    pub line_width: f32,
+    // 0 for the outline only
+    pub fill_opacity: f32,
+    pub visible: bool,
+}
+
+impl StructureStyle {
+    pub fn new(color: [u8; 3]) -> StructureStyle {
+        StructureStyle {
+            color,
+            line_width: 2.0,
+            fill_opacity: 0.0,
+            visible: true,
+        }
+    }
+}
+
+// The contours of a structure on the same plane.
+#[derive(Debug, Clone)]
+struct ContourPlane {
+    // the plane is normal . p = offset in the view frame of the primary
+    normal: Vector3<f32>,
+    offset: f32,
+    // the slab of the contours along the normal
+    lower: f32,
+    upper: f32,
+    polygons: Vec<Vec<Vector3<f32>>>,
+}
+
+#[derive(Debug, Clone)]
+pub struct OverlayStructure {
+    pub number: i32,
+    pub name: String,
+    pub style: StructureStyle,
+    planes: Vec<ContourPlane>,
+}
+
+impl OverlayStructure {
+    // `to_view` is from the patient coordinates to the view frame of the
+    // primary, and `thickness` is the slab of a structure on a single plane.
+    pub fn new(structure: &Structure, to_view: &Base, thickness: f32) -> OverlayStructure {
+        let o = Vector3::from(to_view.transform_point(&[0.0, 0.0, 0.0]));
+        let normal = (Vector3::from(to_view.transform_point(&[0.0, 0.0, 1.0])) - o).normalize();
+
+        let mut planes: Vec<ContourPlane> = Vec::new();
+        for (z, contour) in structure.iter() {
+            let polygon: Vec<Vector3<f32>> = contour
+                .points()
+                .iter()
+                .map(|p| Vector3::from(to_view.transform_point(&[p[0], p[1], z])))
+                .collect();
+            if polygon.len() < 3 {
+                continue;
+            }
+            let offset = normal.dot(&polygon[0]);
+            match planes.iter_mut().find(|plane| (plane.offset - offset).abs() < 1e-3) {
+                Some(plane) => plane.polygons.push(polygon),
+                None => planes.push(ContourPlane {
+                    normal,
+                    offset,
+                    lower: offset - thickness / 2.0,
+                    upper: offset + thickness / 2.0,
+                    polygons: vec![polygon],
+                }),
+            }
+        }
+
+        // halfway to the neighbors, as thick as the next one at the ends
+        planes.sort_by(|a, b| a.offset.total_cmp(&b.offset));
+        let offsets: Vec<f32> = planes.iter().map(|plane| plane.offset).collect();
+        for (i, plane) in planes.iter_mut().enumerate() {
+            let below = if i > 0 { Some(offsets[i - 1]) } else { None };
+            let above = offsets.get(i + 1).copied();
+            if let Some(b) = below.or(above.map(|a| 2.0 * plane.offset - a)) {
+                plane.lower = (b + plane.offset) / 2.0;
+            }
+            if let Some(a) = above.or(below.map(|b| 2.0 * plane.offset - b)) {
+                plane.upper = (a + plane.offset) / 2.0;
+            }
+        }
+
+        let c = structure.get_color();
+        OverlayStructure {
+            number: structure.get_number(),
+            name: structure.get_name(),
+            style: StructureStyle::new([c[0], c[1], c[2]]),
+            planes,
+        }
+    }
+
+    // The cross section on `slice` as polygons in the viewport, in [-1, 1]
+    // with y pointing up, to be filled by the even-odd rule.
+    pub fn section(&self, slice: &SliceParams) -> Vec<Vec<(f32, f32)>> {
+        let n = slice.orientation.axes()[2];
+        let d = slice.slice * 250.0;
+        let mut polygons = Vec::new();
+        for plane in &self.planes {
+            let along = plane.normal.dot(&n);
+            if plane.normal.cross(&n).norm() < 1e-3 {
+                // on the slice if the slab of the contours contains it
+                let t = along * d;
+                if t >= plane.lower && t < plane.upper {
+                    for polygon in &plane.polygons {
+                        polygons.push(polygon.iter().map(|p| slice.viewport_coord(p)).collect());
+                    }
+                }
+                continue;
+            }
+
+            // the contours cross the slice on a line, and every pair of
+            // crossings along it bounds an interval inside of the structure
+            let line = plane.normal.cross(&n);
+            let mut crossings = Vec::new();
+            for polygon in &plane.polygons {
+                for (i, a) in polygon.iter().enumerate() {
+                    let b = &polygon[(i + 1) % polygon.len()];
+                    let (sa, sb) = (n.dot(a) - d, n.dot(b) - d);
+                    if (sa < 0.0) != (sb < 0.0) {
+                        let x = a + (b - a) * (sa / (sa - sb));
+                        crossings.push((line.dot(&x), x));
+                    }
+                }
+            }
+            crossings.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+            // extruded across the slab within the slice
+            let w = plane.normal - n * along;
+            let w = w / w.norm_squared();
+            let (lower, upper) = (w * (plane.lower - plane.offset), w * (plane.upper - plane.offset));
+            for pair in crossings.chunks_exact(2) {
+                let (a, b) = (pair[0].1, pair[1].1);
+                polygons.push(
+                    [a + lower, b + lower, b + upper, a + upper]
+                        .iter()
+                        .map(|p| slice.viewport_coord(p))
+                        .collect(),
+                );
+            }
+        }
+        polygons
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct StructureOverlay {
+    structures: Vec<OverlayStructure>,
+    // changes with the structures and their styles
+    revision: u32,
+}
+
+impl StructureOverlay {
+    pub fn new() -> StructureOverlay {
+        StructureOverlay::default()
+    }
+
+    pub fn set_structures(&mut self, set: &StructureSet, to_view: &Base, thickness: f32) {
+        self.structures = set
+            .structures()
+            .iter()
+            .map(|s| OverlayStructure::new(s, to_view, thickness))
+            .collect();
+        self.revision += 1;
+    }
+
+    pub fn clear(&mut self) {
+        self.structures.clear();
+        self.revision += 1;
+    }
+
+    pub fn any_visible(&self) -> bool {
+        self.structures.iter().any(|s| s.style.visible)
+    }
+
+    pub fn revision(&self) -> u32 {
+        self.revision
+    }
+
+    pub fn structures(&self) -> &[OverlayStructure] {
+        &self.structures
+    }
+
+    pub fn style(&self, number: i32) -> Option<StructureStyle> {
+        self.structures.iter().find(|s| s.number == number).map(|s| s.style)
+    }
+
+    pub fn set_style(&mut self, number: i32, style: StructureStyle) -> Result<(), String> {
+        let structure = self
+            .structures
+            .iter_mut()
+            .find(|s| s.number == number)
+            .ok_or(format!("cannot find structure {}", number))?;
+        structure.style = style;
+        self.revision += 1;
+        Ok(())
+    }
+
+    // The visible structures on `slice` over a viewport of `width` x
+    // `height`, the later ones on top. Transparent outside of them.
+    pub fn render(&self, slice: &SliceParams, width: usize, height: usize) -> RgbaImage {
+        let mut image = RgbaImage::new(width, height);
+        for structure in self.structures.iter().filter(|s| s.style.visible) {
+            let style = &structure.style;
+            let polygons: Vec<Vec<(f32, f32)>> = structure
+                .section(slice)
+                .iter()
+                .map(|polygon| {
+                    // in pixels from the top left
+                    polygon
+                        .iter()
+                        .map(|q| ((q.0 + 1.0) / 2.0 * width as f32, (1.0 - q.1) / 2.0 * height as f32))
+                        .collect()
+                })
+                .collect();
+            if polygons.is_empty() {
+                continue;
+            }
+            let inside = fill_polygons(&polygons, width, height);
+            let line = inner_outline(&inside, width, height, style.line_width);
+            for i in 0..width * height {
+                let alpha = if line[i] {
+                    1.0
+                } else if inside[i] {
+                    style.fill_opacity.clamp(0.0, 1.0)
+                } else {
+                    continue;
+                };
+                image.blend_pixel(i % width, i / width, style.color, alpha);
+            }
+        }
+        image
+    }
+}
+
+// Whether the center of each pixel is inside of `polygons` by the even-odd
+// rule, row by row from the top.
+fn fill_polygons(polygons: &[Vec<(f32, f32)>], width: usize, height: usize) -> Vec<bool> {
+    let mut inside = vec![false; width * height];
+    let mut xs = Vec::new();
+    for row in 0..height {
+        let y = row as f32 + 0.5;
+        xs.clear();
+        for polygon in polygons {
+            for (i, a) in polygon.iter().enumerate() {
+                let b = polygon[(i + 1) % polygon.len()];
+                if (a.1 <= y) != (b.1 <= y) {
+                    xs.push(a.0 + (b.0 - a.0) * (y - a.1) / (b.1 - a.1));
+                }
+            }
+        }
+        xs.sort_by(|a, b| a.total_cmp(b));
+        for pair in xs.chunks_exact(2) {
+            // the pixels whose centers are in [x0, x1)
+            let start = (pair[0] - 0.5).ceil().clamp(0.0, width as f32) as usize;
+            let end = (pair[1] - 0.5).ceil().clamp(0.0, width as f32) as usize;
+            for x in start..end {
+                inside[row * width + x] = true;
+            }
+        }
+    }
+    inside
+}
+
+// The pixels inside within `line_width` of a pixel outside. The edges of the
+// viewport are not outlined.
+fn inner_outline(inside: &[bool], width: usize, height: usize, line_width: f32) -> Vec<bool> {
+    let mut line = vec![false; width * height];
+    let r = line_width.max(0.0).floor() as i32;
+    let offsets: Vec<(i32, i32)> = (-r..=r)
+        .flat_map(|dy| (-r..=r).map(move |dx| (dx, dy)))
+        .filter(|(dx, dy)| {
+            let d2 = (dx * dx + dy * dy) as f32;
+            d2 > 0.0 && d2 <= line_width * line_width
+        })
+        .collect();
+    for y in 0..height as i32 {
+        for x in 0..width as i32 {
+            let i = y as usize * width + x as usize;
+            if !inside[i] {
+                continue;
+            }
+            line[i] = offsets.iter().any(|(dx, dy)| {
+                let (u, v) = (x + dx, y + dy);
+                u >= 0 && v >= 0 && u < width as i32 && v < height as i32 && !inside[v as usize * width + u as usize]
+            });
+        }
+    }
+    line
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::shader_sources::Orientation;
+    use crate::techtron::graphic::contour::Contour2Df32;
+    use crate::techtron::graphic::Point;
+
+    fn slice(orientation: Orientation, slice: f32) -> SliceParams {
+        SliceParams::plane(orientation, 1.0, slice, (1.0, 0.0))
+    }
+
+    // squares of 100 mm on the planes z = -50, 0 and 50, in the patient
+    // coordinates which are the view frame here
+    fn cube() -> OverlayStructure {
+        let mut structure = Structure::new();
+        structure.set_number(3);
+        structure.set_color(0, 255, 0);
+        for z in [-50.0, 0.0, 50.0] {
+            let points = [(-50.0, -50.0), (50.0, -50.0), (50.0, 50.0), (-50.0, 50.0)]
+                .iter()
+                .map(|&(x, y)| Point::new([x, y]))
+                .collect();
+            structure.push(z, Contour2Df32::from_points(points));
+        }
+        OverlayStructure::new(&structure, &Base::identity(), 3.0)
+    }
+
+    fn bounds(polygons: &[Vec<(f32, f32)>]) -> [f32; 4] {
+        let mut b = [f32::MAX, f32::MIN, f32::MAX, f32::MIN];
+        for &(x, y) in polygons.iter().flatten() {
+            b = [b[0].min(x), b[1].max(x), b[2].min(y), b[3].max(y)];
+        }
+        b
+    }
+
+    #[test]
+    fn test_section() {
+        let cube = cube();
+        // on the transverse slices within 25 mm of a contour
+        let section = cube.section(&slice(Orientation::Transverse, 60.0 / 250.0));
+        assert_eq!(section.len(), 1);
+        assert_eq!(bounds(&section), [-0.2, 0.2, -0.2, 0.2]);
+        assert!(cube.section(&slice(Orientation::Transverse, 80.0 / 250.0)).is_empty());
+
+        // a rectangle across the slab of each contour, 150 mm high in all
+        for orientation in [Orientation::Sagittal, Orientation::Coronal] {
+            let section = cube.section(&slice(orientation, 10.0 / 250.0));
+            assert_eq!(section.len(), 3);
+            let b = bounds(&section);
+            for (v, expected) in b.iter().zip([-0.2, 0.2, -0.3, 0.3]) {
+                assert!((v - expected).abs() < 1e-6, "{:?} {:?}", orientation, b);
+            }
+            assert!(cube.section(&slice(orientation, 60.0 / 250.0)).is_empty());
+        }
+    }
+
+    #[test]
+    fn test_render() {
+        let mut overlay = StructureOverlay {
+            structures: vec![cube()],
+            revision: 0,
+        };
+        let mut style = overlay.style(3).unwrap();
+        style.fill_opacity = 0.5;
+        overlay.set_style(3, style).unwrap();
+        assert_eq!(overlay.revision(), 1);
+        assert!(overlay.set_style(4, style).is_err());
+
+        // the square spans the pixels 40 to 59 of 100
+        let image = overlay.render(&slice(Orientation::Transverse, 0.0), 100, 100);
+        assert_eq!(image.pixel(10, 50), [0, 0, 0, 0]);
+        assert_eq!(image.pixel(40, 50), [0, 255, 0, 255]);
+        assert_eq!(image.pixel(41, 50), [0, 255, 0, 255]);
+        assert_eq!(image.pixel(42, 50), [0, 255, 0, 128]);
+        assert_eq!(image.pixel(59, 50), [0, 255, 0, 255]);
+        assert_eq!(image.pixel(60, 50), [0, 0, 0, 0]);
+
+        style.visible = false;
+        overlay.set_style(3, style).unwrap();
+        assert!(!overlay.any_visible());
+        let image = overlay.render(&slice(Orientation::Sagittal, 0.0), 100, 100);
+        assert!(image.data.iter().all(|v| *v == 0));
+    }
+}
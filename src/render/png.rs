@@ -0,0 +1,335 @@
+// MIT License
+
+// Copyright (c) 2023 Techtron-Lab
+
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+
+// PNG encoding of RgbaImage with the Sub filter and deflate with the fixed
+// Huffman codes, which is enough for the views with large flat areas.
+
+use super::RgbaImage;
+
+const SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n'];
+
+pub fn encode_png(image: &RgbaImage) -> Vec<u8> {
+    let mut ihdr = Vec::with_capacity(13);
+    ihdr.extend_from_slice(&(image.width as u32).to_be_bytes());
+    ihdr.extend_from_slice(&(image.height as u32).to_be_bytes());
+    // 8 bits per channel, RGBA, deflate, adaptive filters, no interlace
+    ihdr.extend_from_slice(&[8, 6, 0, 0, 0]);
+
+    // the Sub filter, each byte minus the one of the pixel on the left
+    let stride = image.width * 4;
+    let mut filtered = Vec::with_capacity((stride + 1) * image.height);
+    for row in image.data.chunks_exact(stride.max(1)) {
+        filtered.push(1);
+        for (i, byte) in row.iter().enumerate() {
+            let left = if i >= 4 { row[i - 4] } else { 0 };
+            filtered.push(byte.wrapping_sub(left));
+        }
+    }
+
+    let mut png = SIGNATURE.to_vec();
+    write_chunk(&mut png, b"IHDR", &ihdr);
+    write_chunk(&mut png, b"IDAT", &zlib_compress(&filtered));
+    write_chunk(&mut png, b"IEND", &[]);
+    png
+}
+
+fn write_chunk(png: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    png.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    let start = png.len();
+    png.extend_from_slice(kind);
+    png.extend_from_slice(data);
+    let crc = crc32(&png[start..]);
+    png.extend_from_slice(&crc.to_be_bytes());
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xffff_ffffu32;
+    for byte in data {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0xedb8_8320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for chunk in data.chunks(5552) {
+        for byte in chunk {
+            a += *byte as u32;
+            b += a;
+        }
+        a %= 65521;
+        b %= 65521;
+    }
+    (b << 16) | a
+}
+
+// Bits are packed from the least significant bit of each byte.
+struct BitWriter {
+    bytes: Vec<u8>,
+    bit: u32,
+    bits: u32,
+}
+
+impl BitWriter {
+    fn write(&mut self, value: u32, count: u32) {
+        self.bit |= value << self.bits;
+        self.bits += count;
+        while self.bits >= 8 {
+            self.bytes.push(self.bit as u8);
+            self.bit >>= 8;
+            self.bits -= 8;
+        }
+    }
+
+    // Huffman codes are packed from their most significant bit.
+    fn write_code(&mut self, code: u32, count: u32) {
+        let reversed = code.reverse_bits() >> (32 - count);
+        self.write(reversed, count);
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.bits > 0 {
+            self.bytes.push(self.bit as u8);
+        }
+        self.bytes
+    }
+}
+
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131, 163, 195,
+    227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0];
+const DISTANCE_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537, 2049, 3073,
+    4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DISTANCE_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13, 13,
+];
+
+fn write_symbol(out: &mut BitWriter, symbol: u32) {
+    match symbol {
+        0..=143 => out.write_code(0x30 + symbol, 8),
+        144..=255 => out.write_code(0x190 + symbol - 144, 9),
+        256..=279 => out.write_code(symbol - 256, 7),
+        _ => out.write_code(0xc0 + symbol - 280, 8),
+    }
+}
+
+fn write_match(out: &mut BitWriter, length: usize, distance: usize) {
+    let i = LENGTH_BASE.iter().rposition(|b| *b as usize <= length).unwrap();
+    write_symbol(out, 257 + i as u32);
+    out.write((length - LENGTH_BASE[i] as usize) as u32, LENGTH_EXTRA[i] as u32);
+    let j = DISTANCE_BASE.iter().rposition(|b| *b as usize <= distance).unwrap();
+    out.write_code(j as u32, 5);
+    out.write((distance - DISTANCE_BASE[j] as usize) as u32, DISTANCE_EXTRA[j] as u32);
+}
+
+const WINDOW: usize = 32768;
+const MIN_MATCH: usize = 3;
+const MAX_MATCH: usize = 258;
+const MAX_CHAIN: usize = 32;
+const HASH_BITS: u32 = 15;
+
+fn hash(data: &[u8]) -> usize {
+    let v = (data[0] as u32) << 16 | (data[1] as u32) << 8 | data[2] as u32;
+    (v.wrapping_mul(2_654_435_761) >> (32 - HASH_BITS)) as usize
+}
+
+// A zlib stream of a single deflate block with the fixed Huffman codes.
+fn zlib_compress(data: &[u8]) -> Vec<u8> {
+    let mut out = BitWriter {
+        bytes: vec![0x78, 0x01],
+        bit: 0,
+        bits: 0,
+    };
+    // the final block with the fixed codes
+    out.write(1, 1);
+    out.write(1, 2);
+
+    // the last position of each hash and the previous one of the same hash
+    let mut head = vec![usize::MAX; 1 << HASH_BITS];
+    let mut prev = vec![usize::MAX; WINDOW];
+    let mut insert = |head: &mut Vec<usize>, prev: &mut Vec<usize>, pos: usize| {
+        if pos + MIN_MATCH <= data.len() {
+            let h = hash(&data[pos..]);
+            prev[pos % WINDOW] = head[h];
+            head[h] = pos;
+        }
+    };
+
+    let mut pos = 0;
+    while pos < data.len() {
+        let (mut best_length, mut best_distance) = (0, 0);
+        if pos + MIN_MATCH <= data.len() {
+            let max_length = (data.len() - pos).min(MAX_MATCH);
+            let mut candidate = head[hash(&data[pos..])];
+            for _ in 0..MAX_CHAIN {
+                if candidate == usize::MAX || pos - candidate > WINDOW - 1 {
+                    break;
+                }
+                let length = data[candidate..]
+                    .iter()
+                    .zip(&data[pos..pos + max_length])
+                    .take_while(|(a, b)| a == b)
+                    .count();
+                if length > best_length {
+                    best_length = length;
+                    best_distance = pos - candidate;
+                    if length == max_length {
+                        break;
+                    }
+                }
+                let next = prev[candidate % WINDOW];
+                if next == usize::MAX || next >= candidate {
+                    break;
+                }
+                candidate = next;
+            }
+        }
+        if best_length >= MIN_MATCH {
+            write_match(&mut out, best_length, best_distance);
+            for p in pos..pos + best_length {
+                insert(&mut head, &mut prev, p);
+            }
+            pos += best_length;
+        } else {
+            write_symbol(&mut out, data[pos] as u32);
+            insert(&mut head, &mut prev, pos);
+            pos += 1;
+        }
+    }
+    write_symbol(&mut out, 256);
+
+    let mut bytes = out.finish();
+    bytes.extend_from_slice(&adler32(data).to_be_bytes());
+    bytes
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_checksums() {
+        assert_eq!(crc32(b"IEND"), 0xae42_6082);
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+        assert_eq!(adler32(b"Wikipedia"), 0x11e6_0398);
+    }
+
+    #[test]
+    fn test_encode_png() {
+        let mut image = RgbaImage::new(300, 200);
+        image.fill([128, 128, 128, 255]);
+        image.set_pixel(10, 20, [255, 0, 0, 255]);
+        let png = encode_png(&image);
+        assert_eq!(&png[..8], &SIGNATURE);
+        assert_eq!(&png[12..16], b"IHDR");
+        assert_eq!(&png[16..24], &[0, 0, 1, 44, 0, 0, 0, 200]);
+        assert_eq!(&png[png.len() - 12..], &[0, 0, 0, 0, b'I', b'E', b'N', b'D', 0xae, 0x42, 0x60, 0x82]);
+        // the flat image is compressed to a small fraction
+        assert!(png.len() < 300 * 200 * 4 / 50);
+    }
+
+    #[test]
+    fn test_deflate_literals_and_matches() {
+        // "a" once and then a match of 9 at distance 1
+        let bytes = zlib_compress(b"aaaaaaaaaa");
+        assert_eq!(&bytes[..2], &[0x78, 0x01]);
+        assert_eq!(&bytes[2..bytes.len() - 4], &[0x4b, 0x84, 0x03, 0x00]);
+    }
+
+    // xorshift, reproducible without a dependency
+    fn noise(len: usize, mut state: u32) -> Vec<u8> {
+        (0..len)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                (state >> 24) as u8
+            })
+            .collect()
+    }
+
+    fn inflate(bytes: &[u8]) -> Vec<u8> {
+        miniz_oxide::inflate::decompress_to_vec_zlib(bytes).expect("invalid zlib stream")
+    }
+
+    #[test]
+    fn test_deflate_round_trip() {
+        let block = noise(40_000, 7);
+        let mut cases = vec![
+            Vec::new(),
+            b"ab".to_vec(),
+            noise(100_000, 1),
+            vec![0; 200_000],
+            // a pattern longer than the window, which cannot be matched
+            block.repeat(3),
+            // and one just inside of it
+            block[..WINDOW - 1].repeat(3),
+        ];
+        // runs of a few values between the noise, across the window boundaries
+        let mut mixed = Vec::new();
+        for i in 0..20 {
+            mixed.extend(noise(3_000 + i * 500, i as u32 + 1));
+            mixed.extend(vec![(i * 13) as u8; 5_000 + i * 1_000]);
+        }
+        cases.push(mixed);
+        for data in &cases {
+            assert_eq!(&inflate(&zlib_compress(data)), data);
+        }
+    }
+
+    #[test]
+    fn test_encode_png_round_trip() {
+        let mut image = RgbaImage::new(97, 61);
+        image.data = noise(97 * 61 * 4, 3);
+        for x in 0..97 {
+            image.set_pixel(x, 30, [10, 20, 30, 255]);
+        }
+        let png = encode_png(&image);
+
+        // the IDAT follows the signature and the IHDR of 13 bytes
+        let idat = 8 + 12 + 13;
+        let len = u32::from_be_bytes(png[idat..idat + 4].try_into().unwrap()) as usize;
+        assert_eq!(&png[idat + 4..idat + 8], b"IDAT");
+        let filtered = inflate(&png[idat + 8..idat + 8 + len]);
+
+        // undo the Sub filter
+        let stride = 97 * 4;
+        let mut data = Vec::new();
+        for row in filtered.chunks_exact(stride + 1) {
+            assert_eq!(row[0], 1);
+            let start = data.len();
+            for (i, byte) in row[1..].iter().enumerate() {
+                let left = if i >= 4 { data[start + i - 4] } else { 0 };
+                data.push(byte.wrapping_add(left));
+            }
+        }
+        assert_eq!(data, image.data);
+    }
+}
@@ -0,0 +1,202 @@
+// MIT License
+
+// Copyright (c) 2023 Techtron-Lab
+
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+
+// Redraws on demand. The setters of GlCanvas mark the views they change as
+// dirty, and the dirty views are redrawn together in the next animation
+// frame, each within its own viewport.
+
+use std::cell::Cell;
+use std::ops::{BitOr, BitOrAssign};
+use std::rc::Rc;
+
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+
+use log::warn;
+
+use crate::view::CanvasView;
+
+// What has changed in a view since it was drawn. A view sets all of its
+// uniforms when it is drawn, so only the layout needs to be told apart.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Dirty(u8);
+
+impl Dirty {
+    // anything drawn within the viewport
+    pub const CONTENT: Dirty = Dirty(1);
+    // the viewports, which clears the whole canvas
+    pub const LAYOUT: Dirty = Dirty(1 << 1);
+
+    pub fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    pub fn contains(self, other: Dirty) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+impl BitOr for Dirty {
+    type Output = Dirty;
+    fn bitor(self, rhs: Dirty) -> Dirty {
+        Dirty(self.0 | rhs.0)
+    }
+}
+
+impl BitOrAssign for Dirty {
+    fn bitor_assign(&mut self, rhs: Dirty) {
+        self.0 |= rhs.0;
+    }
+}
+
+pub struct FrameScheduler {
+    dirty: Vec<(CanvasView, Dirty)>,
+    // called in the animation frame, normally to GlCanvas::render_frame
+    callback: Option<js_sys::Function>,
+    // whether an animation frame has been requested and not run yet
+    requested: Rc<Cell<bool>>,
+}
+
+impl FrameScheduler {
+    // Everything is dirty until the first frame.
+    pub fn new() -> FrameScheduler {
+        let mut scheduler = FrameScheduler {
+            dirty: Vec::new(),
+            callback: None,
+            requested: Rc::new(Cell::new(false)),
+        };
+        scheduler.mark_all(Dirty::LAYOUT);
+        scheduler
+    }
+
+    pub fn set_callback(&mut self, callback: Option<js_sys::Function>) {
+        self.callback = callback;
+        if !self.dirty.is_empty() {
+            self.request_frame();
+        }
+    }
+
+    pub fn mark(&mut self, view: CanvasView, dirty: Dirty) {
+        match self.dirty.iter_mut().find(|(v, _)| *v == view) {
+            Some((_, d)) => *d |= dirty,
+            None => self.dirty.push((view, dirty)),
+        }
+        self.request_frame();
+    }
+
+    pub fn mark_all(&mut self, dirty: Dirty) {
+        for view in CanvasView::ALL {
+            self.mark(view, dirty);
+        }
+    }
+
+    pub fn is_dirty(&self) -> bool {
+        !self.dirty.is_empty()
+    }
+
+    // The dirty views in the order they were marked, which are clean after.
+    pub fn take(&mut self) -> Vec<(CanvasView, Dirty)> {
+        std::mem::take(&mut self.dirty)
+    }
+
+    // Whether the whole canvas has to be cleared and redrawn.
+    pub fn needs_full_redraw(dirty: &[(CanvasView, Dirty)]) -> bool {
+        dirty.iter().any(|(_, d)| d.contains(Dirty::LAYOUT))
+    }
+
+    // Request one animation frame for any number of marks before it.
+    fn request_frame(&self) {
+        let callback = match self.callback.as_ref() {
+            Some(callback) => callback.clone(),
+            None => return,
+        };
+        if self.requested.replace(true) {
+            return;
+        }
+        let requested = self.requested.clone();
+        let frame = Closure::once_into_js(move || {
+            requested.set(false);
+            if let Err(err) = callback.call0(&JsValue::NULL) {
+                warn!("frame callback failed: {:?}", err);
+            }
+        });
+        let done = web_sys::window().map(|w| w.request_animation_frame(frame.unchecked_ref()));
+        if !matches!(done, Some(Ok(_))) {
+            self.requested.set(false);
+        }
+    }
+
+    // Call `callback` with `args` in the next animation frame, when no method
+    // of GlCanvas is running, so that the callback may call into it.
+    pub fn defer(&self, callback: js_sys::Function, args: js_sys::Array) {
+        let call = Closure::once_into_js(move || {
+            if let Err(err) = callback.apply(&JsValue::NULL, &args) {
+                warn!("deferred callback failed: {:?}", err);
+            }
+        });
+        if let Some(Err(err)) = web_sys::window().map(|w| w.request_animation_frame(call.unchecked_ref())) {
+            warn!("cannot defer the callback: {:?}", err);
+        }
+    }
+}
+
+impl Default for FrameScheduler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_coalesce_dirty_views() {
+        let mut scheduler = FrameScheduler::new();
+        let initial = scheduler.take();
+        assert_eq!(initial.len(), CanvasView::ALL.len());
+        assert!(FrameScheduler::needs_full_redraw(&initial));
+        assert!(!scheduler.is_dirty());
+
+        scheduler.mark(CanvasView::Transverse, Dirty::CONTENT);
+        scheduler.mark(CanvasView::Sagittal, Dirty::CONTENT);
+        scheduler.mark(CanvasView::Transverse, Dirty::CONTENT);
+        let dirty = scheduler.take();
+        assert_eq!(
+            dirty,
+            vec![
+                (CanvasView::Transverse, Dirty::CONTENT),
+                (CanvasView::Sagittal, Dirty::CONTENT)
+            ]
+        );
+        assert!(!FrameScheduler::needs_full_redraw(&dirty));
+        assert!(scheduler.take().is_empty());
+
+        scheduler.mark(CanvasView::Sagittal, Dirty::CONTENT);
+        scheduler.mark(CanvasView::Sagittal, Dirty::LAYOUT);
+        let dirty = scheduler.take();
+        assert_eq!(dirty, vec![(CanvasView::Sagittal, Dirty::CONTENT | Dirty::LAYOUT)]);
+        assert!(dirty[0].1.contains(Dirty::LAYOUT));
+        assert!(FrameScheduler::needs_full_redraw(&dirty));
+    }
+}
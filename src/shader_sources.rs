@@ -21,6 +21,10 @@
 // SOFTWARE.
 
 
+use nalgebra::Vector3;
+
+use crate::techtron::webgl::shader::{ShaderBuilder, ShaderModule, ShaderSource};
+
 pub const VERTEX_SHADER_SOURCE: &str = r#"#version 300 es
     in vec4 position;
     out vec3 pos;
@@ -38,1114 +42,626 @@ pub const FRAGMENT_SHADER_SOURCE_NOIMAGE: &str = r#"#version 300 es
     }
 "#;
 
-pub const FRAGMENT_SHADER_SOURCE: &str = r#"#version 300 es
-    precision highp float;
-    precision highp sampler3D;
-    // precision mediump sampler2D;
-
-    uniform sampler3D sampler0;
-    // uniform sampler3D sampler1;
-    uniform sampler3D sampler2;
-    uniform sampler3D sampler3;
-    uniform sampler3D sampler4;
-    uniform float window;
-    uniform float level;
-    uniform float window1;
-    uniform float level1;
-    uniform float st;
-    uniform float ss;
-    uniform float sc;
-    uniform lowp int type;
-    uniform float ptx;
-    uniform float pty;
-    uniform float psx;
-    uniform float psy;
-    uniform float pcx;
-    uniform float pcy;
-    uniform float slt;
-    uniform float sls;
-    uniform float slc;
-    // uniform float slope0;
-    // uniform float intercept0;
-    // uniform float slope1;
-    // uniform float intercept1;
-    uniform float k;            // blend coefficient
-    uniform int s;            // 0: primary 1: primary/secondary
-    uniform int lut;          // 0: off  1: on
-    uniform vec3 spacing0;
-    uniform vec3 spacing1;
-    uniform vec3 dim0;
-    uniform vec3 dim1;
-    uniform vec3 shift;
-    uniform vec3 size0;
-    uniform vec3 size1;
+// Modules of the fragment shaders below. All positions are in the view frame
+// of the primary, in mm from its center along its axes.
 
-    // Ablation
-    uniform vec3 uah;     // (u0, a, h) for Ablation
-    uniform float L;      // Length of the needle
-    uniform vec3 npos;
-    uniform float theta;
-
-    in vec3 pos;
+pub static QUAD: ShaderModule = ShaderModule {
+    name: "quad",
+    requires: &[],
+    source: r#"
+    in vec3 pos;      // [-1, 1] over the viewport, see VERTEX_SHADER_SOURCE
     out vec4 color;
+    "#,
+};
 
-    float rgba2u16(vec4 rgba) {
-        return 15.0 * (rgba.r * 4096.0 + rgba.g * 256.0 + rgba.b * 16.0 + rgba.a);
-    }
-
-    float rgba2f32(vec4 rgba) {
-        return rgba2u16(rgba) / 65535.0;
-    }
-
-    vec3 project_t3(vec3 p, vec3 pan, vec3 scale) {
-        const mat4x3 M = mat4x3(0.5, 0.0, 0.0, 0.0, -0.5, 0.0, 0.0, 0.0, 0.5, 0.5, 0.5, 0.5);
-        vec3 q = (p - pan) / scale;
-        return M * vec4(q, 1.0);
-    }
-    vec3 project_s3(vec3 p, vec3 pan, vec3 scale) {
-        const mat4x3 M = mat4x3(0.5, 0.0, 0.0, 0.0, -0.5, 0.0, 0.0, 0.0, 0.5, 0.5, 0.5, 0.5);
-        vec3 q = (p - pan) / scale;
-        return M * vec4(q, 1.0);
-    }
-    vec3 project_c3(vec3 p, vec3 pan, vec3 scale) {
-        const mat4x3 M = mat4x3(0.5, 0.0, 0.0, 0.0, -0.5, 0.0, 0.0, 0.0, 0.5, 0.5, 0.5, 0.5);
-        vec3 q = (p - pan) / scale;
-        return M * vec4(q, 1.0);
-    }
-
-    float minv3(vec3 v) {
-        return min(min(v[0], v[1]), v[2]);
-    }
-    float maxv3(vec3 v) {
-        return max(max(v[0], v[1]), v[2]);
-    }
-
-    bool is_outbound(float x, float y, float z) {
-        return (x < 0.0 || x > 1.0 || y < 0.0 || y > 1.0 || z < 0.0 || z > 1.0 ); 
-    }
+pub static BOUNDS: ShaderModule = ShaderModule {
+    name: "bounds",
+    requires: &[],
+    source: r#"
     bool is_outbound(vec3 p) {
-        return (p.x < 0.0 || p.x > 1.0 || p.y < 0.0 || p.y > 1.0 || p.z < 0.0 || p.z > 1.0 ); 
-    }
-
-    // Ablation
-    float calc_dose(float u0, float a, float h, float x, float y) {
-       // return (x * x + y * y) * 2000.0;
-        float delta = 0.00000001;
-        float b2 = h*h - a*a;
-        float b = sqrt(b2);
-        float ha = h - a;
-        float ln = log((b + ha) / (b - ha));
-        float x2 = x * x;
-        float y2 = y * y;
-        float xy = ((x + b)*(x + b) + y2) * ((x - b) * (x - b) + y2);
-        float d = ln * ln * xy;
-        // d = max(delta, d);
-        float E2 = 4.0 * b2 * u0 * u0 / d;
-        float E = sqrt(E2);
-        return E;
-    }
-
-    // float asinh(float x) {
-    //     return log(x + sqrt(x * x + 1));
-    // }
-
-    float d_2(float x) {
-        return x * x;
-    }
-
-    float calc_E(float U0, float R, float L, float h, float x, float y, float z) {
-        float W = U0 / (4.0*asinh(L/(2.0*sqrt(R*R))));
-        float hh = h / 2.0;
-        float hL = L / 2.0;
-        float E = sqrt(d_2(-W*(-1.0/(sqrt(y*y + d_2(-hh + x))*sqrt(d_2(hL - z)/(y*y + d_2(-hh + x)) + 1.0)) + \
-                  1.0/(sqrt(y*y + d_2(-hh + x))*sqrt(d_2(-hL - z)/(y*y + d_2(-hh + x)) + 1.0))) + \
-              W*(-1.0/(sqrt(y*y + d_2(hh + x))*sqrt(d_2(hL - z)/(y*y + d_2(hh + x)) + 1.0)) + \
-                 1.0/(sqrt(y*y + d_2(hh + x))*sqrt(d_2(-hL - z)/(y*y + d_2(hh + x)) + 1.0)))) + \
-             d_2(-W*(y*(-hL - z)/(pow(y*y + d_2(-hh + x), 1.5)*sqrt(d_2(-hL - z)/(y*y + d_2(-hh + x)) + 1.0)) - \
-                  y*(hL - z)/(pow(y*y + d_2(-hh + x), 3.0/2.0)*sqrt(d_2(hL - z)/(y*y + d_2(-hh + x)) + 1.0))) + \
-              W*(y*(-hL - z)/(pow(y*y + d_2(hh + x), 3.0/2.0)*sqrt(d_2(-hL - z)/(y*y + d_2(hh + x)) + 1.0)) - \
-                 y*(hL - z)/(pow(y*y + d_2(hh + x), 3.0/2.0)*sqrt(d_2(hL - z)/(y*y + d_2(hh + x)) + 1.0)))) + \
-                 d_2(W*(-(-hL - z)*(-hh - x)/(pow(y*y + d_2(hh + x), 3.0/2.0)*sqrt(d_2(-hL - z)/(y*y + d_2(hh + x)) + 1.0)) + \
-                     (hL - z)*(-hh - x)/(pow(y*y + d_2(hh + x), 1.5)*sqrt(d_2(hL - z)/(y*y + \
-            d_2(hh + x)) + 1.0))) - W*(-(-hL - z)*(hh - x)/(pow(y*y + d_2(-hh + x), 1.5)*sqrt(d_2(-hL - z)/(y*y + \
-            d_2(-hh + x)) + 1.0)) + (hL - z)*(hh - x)/(pow(y*y + d_2(-hh + x), 3.0/2.0)*sqrt(d_2(hL - z)/(y*y + d_2(-hh + x)) + 1.0)))));
-        return E;
-    }
-
-    void main() {
-        vec3 coord0, coord1;
-        float u, v, w;
-        float u1, v1, w1;
-        // vec3 n0 = dim0 * spacing0;
-        // vec3 n1 = dim1 * spacing1;
-        vec3 n0 = size0;
-        vec3 n1 = size1;
-        
-        float d = 500.0;
-        if (type == 0) {
-            float z0 = rgba2f32(texture(sampler3, vec3((slt + 1.0) / 2.0, 0.5, 0.0))) * 2.0 - 1.0;
-            vec3 m = vec3(pos.xy, z0);
-            vec3 np0 = m / n0 * d;
-            vec3 scale = vec3(st, st, 1.0);
-
-            coord0 = project_t3(np0, vec3(ptx, pty, 0.0)/n0*d, scale);
-        } 
-        else if (type == 1) {
-            vec3 m = vec3(sls, pos.xy);
-            vec3 np0 = m / n0 * d;
-            vec3 scale = vec3(1.0, ss, ss);
-            coord0 = project_s3(np0, vec3(0.0, psx, psy)/n0*d, scale);
-        }
-        else if (type == 2) {
-            float z = rgba2f32(texture(sampler3, vec3((pos.y + 1.0) / 2.0, 0.5, 0.0))) * 2.0 - 1.0;
-            vec3 m = vec3(pos.x, slc, z);
-            vec3 np0 = m / n0 * d;
-            vec3 scale = vec3(sc, 1.0, sc);
-            coord0 = project_c3(np0, vec3(pcx, 0.0, pcy)/n0*d, scale);
-        }
-        float lower = level - window / 2.0;
-        float upper = level + window / 2.0;
-
-        vec4 pixel0 = texture(sampler0, coord0);
-        float value0 = rgba2u16(pixel0);
-        float gray0 = clamp((value0 - lower) / window, 0.0, 1.0);
-        if (is_outbound(coord0)) {
-            gray0 = 0.0;
-        }
-
-        color = vec4(0.6, 0.1, 0.2, 1.0);
-        float upper1 = level1 + window1 / 2.0;
-        float lower1 = level1 - window1 / 2.0;
-        mat2x2 R = mat2x2(cos(theta), -sin(theta), sin(theta), cos(theta));
-        if (type == 0) {
-            // float value1 = calc_dose(uah.x, uah.y, uah.z, pos.x * d / st, pos.y * d / st);
-            vec3 p = vec3((pos.x - ptx) * d / st - npos.x, (pos.y - pty) * d / st - npos.y, slt * d - npos.z);
-            p = vec3(R * vec2(p.xy), p.z);
-            float value1 = calc_E(uah.x, uah.y, L, uah.z*2.0, p.x, p.y, p.z);
-            float gray1 = clamp((value1 - lower1) / window1, 0.0, 1.0);
-            if (is_outbound(coord1)) {
-                gray1 = 0.0;
-            }
-            vec4 scolor = texture(sampler2, vec3(gray1, 0.5, 0.0));
-            color = vec4((gray0 * (1.0 - k) + scolor.xyz * k), 1.0);
-        } else if (type == 1) {
-            vec3 p = vec3(sls * d - npos.x, (pos.x - psx) * d / ss - npos.y, (pos.y - psy) * d / ss - npos.z);
-            p = vec3(R * vec2(p.xy), p.z);
-            float value1 = calc_E(uah.x, uah.y, L, uah.z*2.0, p.x, p.y, p.z);
-            float gray1 = clamp((value1 - lower1) / window1, 0.0, 1.0);
-            if (is_outbound(coord1)) {
-                gray1 = 0.0;
-            }
-            vec4 scolor = texture(sampler2, vec3(gray1, 0.5, 0.0));
-            color = vec4((gray0 * (1.0 - k) + scolor.xyz * k), 1.0);
-        } else if (type == 2) {
-            vec3 p = vec3((pos.x - pcx) * d / sc - npos.x, slc * d - npos.y, (pos.y - pcy) * d / sc - npos.z);
-            p = vec3(R * vec2(p.xy), p.z);
-            float value1 = calc_E(uah.x, uah.y, L, uah.z*2.0, p.x, p.y, p.z);
-            float gray1 = clamp((value1 - lower1) / window1, 0.0, 1.0);
-            if (is_outbound(coord1)) {
-                gray1 = 0.0;
-            }
-            vec4 scolor = texture(sampler2, vec3(gray1, 0.5, 0.0));
-            color = vec4((gray0 * (1.0 - k) + scolor.xyz * k), 1.0);
-        } else {
-            color = vec4(vec3(gray0), 1.0);
-        }
-        // if (type == 0) {
-        //     float x0 = (pos.x - ptx) * d / st - npos.x;
-        //     float y0 = (pos.y - pty) * d / st - npos.y;
-        //     float z0 = slt * d - npos.z;
-        //     float e0 = calc_E(uah.x, uah.y, L, uah.z * 2.0, x0 - 1.0, y0 + 1.0, z0);
-        //     float e1 = calc_E(uah.x, uah.y, L, uah.z * 2.0, x0 + 1.0, y0 + 1.0, z0);
-        //     float e2 = calc_E(uah.x, uah.y, L, uah.z * 2.0, x0 - 1.0, y0 - 1.0, z0);
-        //     float e3 = calc_E(uah.x, uah.y, L, uah.z * 2.0, x0 + 1.0, y0 - 1.0, z0);
-        //     if (e0 > 80.0 && e1 > 80.0 && e2 > 80.0 && e3 > 80.0) {
-        //         color = vec4(vec3(gray0), 1.0);
-        //     } else if (e0 < 80.0 && e1 < 80.0 && e2 < 80.0 && e3 < 80.0) {
-        //         color = vec4(vec3(gray0), 1.0);
-        //     } else {
-        //         color = vec4(1.0, 0.0, 0.0, 1.0);
-        //     }
-        // } else {
-        //     color = vec4(vec3(gray0), 1.0);
-        // }
-
-    }"#;
-
-pub const FRAGMENT_SHADER_SOURCE_TRANS: &str = r#"#version 300 es
-    precision highp float;
-    precision highp sampler3D;
-
-    uniform sampler3D sampler0;
-    uniform sampler3D sampler1;
-    uniform sampler3D sampler2;
-    uniform sampler3D sampler3;
-    uniform sampler3D sampler4;
-
-    uniform float st;
-    uniform float ptx;
-    uniform float pty;
-    uniform float slt;
-
-    uniform float k;            // blend coefficient
-    uniform int s;            // 0: primary 1: primary/secondary
-
+        return (p.x < 0.0 || p.x > 1.0 || p.y < 0.0 || p.y > 1.0 || p.z < 0.0 || p.z > 1.0 );
+    }
+    "#,
+};
+
+pub static PRIMARY: ShaderModule = ShaderModule {
+    name: "primary",
+    requires: &[&BOUNDS],
+    source: r#"
+    // the stored values, isampler3D of a Texture3DR16I or usampler3D of a
+    // Texture3DR16UI by PRIMARY_SAMPLER
+    #ifndef PRIMARY_SAMPLER
+    #define PRIMARY_SAMPLER isampler3D
+    #endif
+    uniform PRIMARY_SAMPLER sampler0;
     uniform vec3 spacing0;
-    uniform vec3 spacing1;
     uniform vec3 size0;
-    uniform vec3 size1;
-
-    // uniform vec3 shift;
-
+    uniform vec2 rescale0;    // from the texture values to the real world values
     uniform float window;
     uniform float level;
-    uniform float window1;
-    uniform float level1;
-
-    uniform float dl;
-    uniform float dh;
-
-    // // Ablation
-    uniform vec3 uah;     // (u0, a, h) for Ablation
-    uniform float L;      // Length of the needle
-    uniform vec3 npos;
-    uniform float theta;
-
-    in vec3 pos;
-    out vec4 color;
 
-    float rgba2u16(vec4 rgba) {
-        return 15.0 * (rgba.r * 4096.0 + rgba.g * 256.0 + rgba.b * 16.0 + rgba.a);
+    vec3 primary_coord(vec3 p) {
+        return p / size0 + 0.5;
     }
 
-    float rgba2f32(vec4 rgba) {
-        return rgba2u16(rgba) / 65535.0;
+    // The stored value at the texture coordinates, linear between the centers
+    // of the voxels as a filtered texture, which an integer one cannot be.
+    float primary_texel(vec3 coord) {
+        ivec3 size = textureSize(sampler0, 0);
+        vec3 t = clamp(coord * vec3(size) - 0.5, vec3(0.0), vec3(size - 1));
+        ivec3 i0 = ivec3(floor(t));
+        ivec3 i1 = min(i0 + 1, size - 1);
+        vec3 f = t - vec3(i0);
+        float c00 = mix(float(texelFetch(sampler0, i0, 0).r),
+                        float(texelFetch(sampler0, ivec3(i1.x, i0.y, i0.z), 0).r), f.x);
+        float c10 = mix(float(texelFetch(sampler0, ivec3(i0.x, i1.y, i0.z), 0).r),
+                        float(texelFetch(sampler0, ivec3(i1.x, i1.y, i0.z), 0).r), f.x);
+        float c01 = mix(float(texelFetch(sampler0, ivec3(i0.x, i0.y, i1.z), 0).r),
+                        float(texelFetch(sampler0, ivec3(i1.x, i0.y, i1.z), 0).r), f.x);
+        float c11 = mix(float(texelFetch(sampler0, ivec3(i0.x, i1.y, i1.z), 0).r),
+                        float(texelFetch(sampler0, i1, 0).r), f.x);
+        return mix(mix(c00, c10, f.y), mix(c01, c11, f.y), f.z);
     }
 
-    vec3 project_t3(vec3 p, vec3 pan, vec3 scale) {
-        const mat4x3 M = mat4x3(0.5, 0.0, 0.0, 0.0, -0.5, 0.0, 0.0, 0.0, 0.5, 0.5, 0.5, 0.5);
-        vec3 q = (p - pan) / scale;
-        return M * vec4(q, 1.0);
-    }
-    vec3 project_s3(vec3 p, vec3 pan, vec3 scale) {
-        const mat4x3 M = mat4x3(0.5, 0.0, 0.0, 0.0, -0.5, 0.0, 0.0, 0.0, 0.5, 0.5, 0.5, 0.5);
-        vec3 q = (p - pan) / scale;
-        return M * vec4(q, 1.0);
-    }
-    vec3 project_c3(vec3 p, vec3 pan, vec3 scale) {
-        const mat4x3 M = mat4x3(0.5, 0.0, 0.0, 0.0, -0.5, 0.0, 0.0, 0.0, 0.5, 0.5, 0.5, 0.5);
-        vec3 q = (p - pan) / scale;
-        return M * vec4(q, 1.0);
+    float primary_value(vec3 p) {
+        return primary_texel(primary_coord(p)) * rescale0.x + rescale0.y;
     }
 
-    float minv3(vec3 v) {
-        return min(min(v[0], v[1]), v[2]);
+    float primary_gray(float value) {
+        return clamp((value - (level - window / 2.0)) / window, 0.0, 1.0);
     }
-    float maxv3(vec3 v) {
-        return max(max(v[0], v[1]), v[2]);
-    }
-
-    bool is_outbound(float x, float y, float z) {
-        return (x < 0.0 || x > 1.0 || y < 0.0 || y > 1.0 || z < 0.0 || z > 1.0 ); 
-    }
-    bool is_outbound(vec3 p) {
-        return (p.x < 0.0 || p.x > 1.0 || p.y < 0.0 || p.y > 1.0 || p.z < 0.0 || p.z > 1.0 ); 
-    }
-
-    void main() {
-        vec3 coord0, coord1;
-        float u, v, w;
-        float u1, v1, w1;
-        // vec3 n0 = dim0 * spacing0;
-        // vec3 n1 = dim1 * spacing1;
-        vec3 n0 = size0;
-        vec3 n1 = size1;
-        
-        float d = 500.0;
-        vec3 shift = npos;
-        {
-            //float z0 = rgba2f32(texture(sampler3, vec3((slt + 1.0) / 2.0, 0.5, 0.0))) * 2.0 - 1.0;
-            //float z1 = rgba2f32(texture(sampler4, vec3((slt + 1.0) / 2.0, 0.5, 0.0))) * 2.0 - 1.0;
-            //vec3 m = vec3(pos.xy, z0);
-            vec3 m = vec3(pos.xy, slt);
-            vec3 np0 = m / n0 * d;
-            vec3 scale = vec3(st, st, 1.0);
-            //vec3 np1 = (vec3(m.xy, z1) - shift * scale) / n1 * d;
-            vec3 np1 = (vec3(m.xy, slt) - shift * scale) / n1 * d;
-
-            coord0 = project_t3(np0, vec3(ptx, pty, 0.0)/n0*d, scale);
-            coord1 = project_t3(np1, vec3(ptx, pty, 0.0)/n1*d, scale);
-        }
-        float lower = level - window / 2.0;
-        float upper = level + window / 2.0;
-
-        vec4 pixel0 = texture(sampler0, coord0);
-        float value0 = rgba2u16(pixel0);
-        float gray0 = clamp((value0 - lower) / window, 0.0, 1.0);
-        if (is_outbound(coord0)) {
-            gray0 = 0.0;
-        }
-
-        color = vec4(0.6, 0.1, 0.2, 1.0);
-        float upper1 = level1 + window1 / 2.0;
-        float lower1 = level1 - window1 / 2.0;
-        // mat2x2 R = mat2x2(cos(theta), -sin(theta), sin(theta), cos(theta));
-        {
-
-            float lower1 = level1 - window1 / 2.0;
-            float upper1 = level1 + window1 / 2.0;
-            vec4 pixel1 = texture(sampler1, coord1);
-            float value1 = rgba2u16(pixel1);
-            value1 = value1 * (dh - dl) / 65535.0 + dl;
-            float gray1 = clamp((value1 - lower1) / window1, 0.0, 1.0);
-            if (is_outbound(coord1)) {
-                gray1 = 0.0;
-            }
-            vec4 scolor = texture(sampler2, vec3(gray1, 0.5, 0.0));
-            color = vec4((gray0 * (1.0 - k) + scolor.xyz * k), 1.0);
-        }
-    }"#;
-
-    pub const FRAGMENT_SHADER_SOURCE_TRANS2: &str = r#"#version 300 es
-    precision highp float;
-    precision highp sampler3D;
-
-    uniform sampler3D sampler0;
-    uniform sampler3D sampler1;
-    uniform sampler3D sampler2;
-    uniform sampler3D sampler3;
-    // uniform sampler3D sampler4;
-
-    uniform float st;
-    uniform float ptx;
-    uniform float pty;
-    uniform float slt;
-
-    uniform float k;            // blend coefficient
-    uniform int s;            // 0: primary 1: primary/secondary
-
-    uniform vec3 spacing0;
-    uniform vec3 spacing1;
-    uniform vec3 size0;
-    uniform vec3 size1;
-
-    uniform float window;
-    uniform float level;
-    uniform float window1;
-    uniform float level1;
+    "#,
+};
 
-    // Ablation
+pub static NEEDLES: ShaderModule = ShaderModule {
+    name: "needles",
+    requires: &[],
+    source: r#"
     uniform vec3 uah;     // (u0, a, h) for Ablation
     uniform float L;      // Length of the needle
     uniform vec3 npos;
     uniform float theta;
 
-    in vec3 pos;
-    out vec4 color;
-
-    float rgba2u16(vec4 rgba) {
-        return 15.0 * (rgba.r * 4096.0 + rgba.g * 256.0 + rgba.b * 16.0 + rgba.a);
-    }
-
-    float rgba2f32(vec4 rgba) {
-        return rgba2u16(rgba) / 65535.0;
-    }
-
-    vec3 project_t3(vec3 p, vec3 pan, vec3 scale) {
-        const mat4x3 M = mat4x3(0.5, 0.0, 0.0, 0.0, -0.5, 0.0, 0.0, 0.0, 0.5, 0.5, 0.5, 0.5);
-        vec3 q = (p - pan) / scale;
-        return M * vec4(q, 1.0);
-    }
-    vec3 project_s3(vec3 p, vec3 pan, vec3 scale) {
-        const mat4x3 M = mat4x3(0.5, 0.0, 0.0, 0.0, -0.5, 0.0, 0.0, 0.0, 0.5, 0.5, 0.5, 0.5);
-        vec3 q = (p - pan) / scale;
-        return M * vec4(q, 1.0);
-    }
-    vec3 project_c3(vec3 p, vec3 pan, vec3 scale) {
-        const mat4x3 M = mat4x3(0.5, 0.0, 0.0, 0.0, -0.5, 0.0, 0.0, 0.0, 0.5, 0.5, 0.5, 0.5);
-        vec3 q = (p - pan) / scale;
-        return M * vec4(q, 1.0);
-    }
-
-    float minv3(vec3 v) {
-        return min(min(v[0], v[1]), v[2]);
-    }
-    float maxv3(vec3 v) {
-        return max(max(v[0], v[1]), v[2]);
-    }
-
-    bool is_outbound(float x, float y, float z) {
-        return (x < 0.0 || x > 1.0 || y < 0.0 || y > 1.0 || z < 0.0 || z > 1.0 ); 
-    }
-    bool is_outbound(vec3 p) {
-        return (p.x < 0.0 || p.x > 1.0 || p.y < 0.0 || p.y > 1.0 || p.z < 0.0 || p.z > 1.0 ); 
+    // the needles are parallel to the z axis, h apart from the center in the
+    // direction of theta; npos is in 250 mm with y pointing up
+    vec3 needle_center() {
+        return vec3(npos.x, -npos.y, npos.z) * 250.0;
     }
 
-    // Ablation
-    float calc_dose(float u0, float a, float h, float x, float y) {
-       // return (x * x + y * y) * 2000.0;
-        float delta = 0.00000001;
-        float b2 = h*h - a*a;
-        float b = sqrt(b2);
-        float ha = h - a;
-        float ln = log((b + ha) / (b - ha));
-        float x2 = x * x;
-        float y2 = y * y;
-        float xy = ((x + b)*(x + b) + y2) * ((x - b) * (x - b) + y2);
-        float d = ln * ln * xy;
-        // d = max(delta, d);
-        float E2 = 4.0 * b2 * u0 * u0 / d;
-        float E = sqrt(E2);
-        return E;
+    vec3 needle_spread() {
+        return vec3(cos(theta), -sin(theta), 0.0);
     }
+    "#,
+};
 
-    float d_2(float x) {
-        return x * x;
-    }
-
-    float calc_E(float U0, float R, float L, float h, float x, float y, float z) {
-        float W = U0 / (4.0*asinh(L/(2.0*sqrt(R*R))));
-        float hh = h / 2.0;
-        float hL = L / 2.0;
-        float E = sqrt(d_2(-W*(-1.0/(sqrt(y*y + d_2(-hh + x))*sqrt(d_2(hL - z)/(y*y + d_2(-hh + x)) + 1.0)) + \
-                  1.0/(sqrt(y*y + d_2(-hh + x))*sqrt(d_2(-hL - z)/(y*y + d_2(-hh + x)) + 1.0))) + \
-              W*(-1.0/(sqrt(y*y + d_2(hh + x))*sqrt(d_2(hL - z)/(y*y + d_2(hh + x)) + 1.0)) + \
-                 1.0/(sqrt(y*y + d_2(hh + x))*sqrt(d_2(-hL - z)/(y*y + d_2(hh + x)) + 1.0)))) + \
-             d_2(-W*(y*(-hL - z)/(pow(y*y + d_2(-hh + x), 1.5)*sqrt(d_2(-hL - z)/(y*y + d_2(-hh + x)) + 1.0)) - \
-                  y*(hL - z)/(pow(y*y + d_2(-hh + x), 3.0/2.0)*sqrt(d_2(hL - z)/(y*y + d_2(-hh + x)) + 1.0))) + \
-              W*(y*(-hL - z)/(pow(y*y + d_2(hh + x), 3.0/2.0)*sqrt(d_2(-hL - z)/(y*y + d_2(hh + x)) + 1.0)) - \
-                 y*(hL - z)/(pow(y*y + d_2(hh + x), 3.0/2.0)*sqrt(d_2(hL - z)/(y*y + d_2(hh + x)) + 1.0)))) + \
-                 d_2(W*(-(-hL - z)*(-hh - x)/(pow(y*y + d_2(hh + x), 3.0/2.0)*sqrt(d_2(-hL - z)/(y*y + d_2(hh + x)) + 1.0)) + \
-                     (hL - z)*(-hh - x)/(pow(y*y + d_2(hh + x), 1.5)*sqrt(d_2(hL - z)/(y*y + \
-            d_2(hh + x)) + 1.0))) - W*(-(-hL - z)*(hh - x)/(pow(y*y + d_2(-hh + x), 1.5)*sqrt(d_2(-hL - z)/(y*y + \
-            d_2(-hh + x)) + 1.0)) + (hL - z)*(hh - x)/(pow(y*y + d_2(-hh + x), 3.0/2.0)*sqrt(d_2(hL - z)/(y*y + d_2(-hh + x)) + 1.0)))));
-        return E;
-    }
-
-    void main() {
-        vec3 coord0, coord1;
-        float u, v, w;
-        float u1, v1, w1;
-        // vec3 n0 = dim0 * spacing0;
-        // vec3 n1 = dim1 * spacing1;
-        vec3 n0 = size0;
-        vec3 n1 = size1;
-        
-        float d = 500.0;
-        {
-            float z0 = rgba2f32(texture(sampler3, vec3((slt + 1.0) / 2.0, 0.5, 0.0))) * 2.0 - 1.0;
-            float z1 = rgba2f32(texture(sampler1, vec3((slt + 1.0) / 2.0, 0.5, 0.0))) * 2.0 - 1.0;
-            vec3 m = vec3(pos.xy, z0);
-            vec3 np0 = m / n0 * d;
-            vec3 scale = vec3(st, st, 1.0);
-
-            coord0 = project_t3(np0, vec3(ptx, pty, 0.0)/n0*d, scale);
-        }
-        float lower = level - window / 2.0;
-        float upper = level + window / 2.0;
-
-        vec4 pixel0 = texture(sampler0, coord0);
-        float value0 = rgba2u16(pixel0);
-        float gray0 = clamp((value0 - lower) / window, 0.0, 1.0);
-        if (is_outbound(coord0)) {
-            gray0 = 0.0;
-        }
-
-        color = vec4(0.6, 0.1, 0.2, 1.0);
-        float upper1 = level1 + window1 / 2.0;
-        float lower1 = level1 - window1 / 2.0;
-        mat2x2 R = mat2x2(cos(theta), -sin(theta), sin(theta), cos(theta));
-        {
-            // float value1 = calc_dose(uah.x, uah.y, uah.z, pos.x * d / st, pos.y * d / st);
-            vec3 p = vec3((pos.x - ptx) * d / st - npos.x, (pos.y - pty) * d / st - npos.y, slt * d - npos.z);
-            p = vec3(R * vec2(p.xy), p.z);
-            float value1 = calc_E(uah.x, uah.y, L, uah.z*2.0, p.x, p.y, p.z);
-            float gray1 = clamp((value1 - lower1) / window1, 0.0, 1.0);
-            if (is_outbound(coord1)) {
-                gray1 = 0.0;
-            }
-            vec4 scolor = texture(sampler2, vec3(gray1, 0.5, 0.0));
-            color = vec4((gray0 * (1.0 - k) + scolor.xyz * k), 1.0);
-            // color = vec4(1.0, 0.0, 0.0, 1.0);
-        }
-    }"#;
-
-pub const FRAGMENT_SHADER_SOURCE_SAGITTAL: &str = r#"#version 300 es
-    precision highp float;
-    precision highp sampler3D;
-
-    uniform sampler3D sampler0;
+pub static SECONDARY: ShaderModule = ShaderModule {
+    name: "secondary",
+    requires: &[&BOUNDS, &NEEDLES],
+    source: r#"
     uniform sampler3D sampler1;
-    uniform sampler3D sampler2;
-    uniform sampler3D sampler3;
-    uniform sampler3D sampler4;
-
-    uniform float ss;
-    uniform float psx;
-    uniform float psy;
-    uniform float sls;
-
-    uniform float k;            // blend coefficient
-    uniform int s;            // 0: primary 1: primary/secondary
-
-    uniform vec3 spacing0;
+    uniform sampler3D sampler2;   // colormap
     uniform vec3 spacing1;
-    uniform vec3 size0;
     uniform vec3 size1;
-
-    uniform float window;
-    uniform float level;
     uniform float window1;
     uniform float level1;
+    layout(std140) uniform Registration {
+        mat4 tex1;          // view frame of the primary in mm to the texture coordinates of the secondary
+    };
+    uniform float follow1;  // 1.0 if the secondary moves with the needles
 
-    uniform float dl;
-    uniform float dh;
-
-    // Ablation
-    uniform vec3 uah;     // (u0, a, h) for Ablation
-    uniform float L;      // Length of the needle
-    uniform vec3 npos;
-    uniform float theta;
-
-    in vec3 pos;
-    out vec4 color;
-
-    float rgba2u16(vec4 rgba) {
-        return 15.0 * (rgba.r * 4096.0 + rgba.g * 256.0 + rgba.b * 16.0 + rgba.a);
-    }
+    // see FieldDisplay
+    const int MAX_BANDS = 8;
+    uniform vec3 ramp1;     // opacity from 0 at x to 1 at y of the values, if z is 1.0
+    uniform int bands1;     // the number of the bands, 0 for the colormap
+    uniform vec2 band_range1[MAX_BANDS];
+    uniform vec3 band_color1[MAX_BANDS];
 
-    float rgba2f32(vec4 rgba) {
-        return rgba2u16(rgba) / 65535.0;
+    vec3 secondary_coord(vec3 p) {
+        return (tex1 * vec4(p - follow1 * needle_center(), 1.0)).xyz;
     }
 
-    vec3 project_t3(vec3 p, vec3 pan, vec3 scale) {
-        const mat4x3 M = mat4x3(0.5, 0.0, 0.0, 0.0, -0.5, 0.0, 0.0, 0.0, 0.5, 0.5, 0.5, 0.5);
-        vec3 q = (p - pan) / scale;
-        return M * vec4(q, 1.0);
-    }
-    vec3 project_s3(vec3 p, vec3 pan, vec3 scale) {
-        const mat4x3 M = mat4x3(0.5, 0.0, 0.0, 0.0, -0.5, 0.0, 0.0, 0.0, 0.5, 0.5, 0.5, 0.5);
-        vec3 q = (p - pan) / scale;
-        return M * vec4(q, 1.0);
-    }
-    vec3 project_c3(vec3 p, vec3 pan, vec3 scale) {
-        const mat4x3 M = mat4x3(0.5, 0.0, 0.0, 0.0, -0.5, 0.0, 0.0, 0.0, 0.5, 0.5, 0.5, 0.5);
-        vec3 q = (p - pan) / scale;
-        return M * vec4(q, 1.0);
+    float secondary_gray(float value) {
+        return clamp((value - (level1 - window1 / 2.0)) / window1, 0.0, 1.0);
     }
 
-    float minv3(vec3 v) {
-        return min(min(v[0], v[1]), v[2]);
-    }
-    float maxv3(vec3 v) {
-        return max(max(v[0], v[1]), v[2]);
-    }
-
-    bool is_outbound(float x, float y, float z) {
-        return (x < 0.0 || x > 1.0 || y < 0.0 || y > 1.0 || z < 0.0 || z > 1.0 ); 
-    }
-    bool is_outbound(vec3 p) {
-        return (p.x < 0.0 || p.x > 1.0 || p.y < 0.0 || p.y > 1.0 || p.z < 0.0 || p.z > 1.0 ); 
+    vec3 secondary_color(float gray) {
+        return texture(sampler2, vec3(gray, 0.5, 0.0)).rgb;
     }
 
-    void main() {
-        vec3 coord0, coord1;
-        float u, v, w;
-        float u1, v1, w1;
-        // vec3 n0 = dim0 * spacing0;
-        // vec3 n1 = dim1 * spacing1;
-        vec3 n0 = size0;
-        vec3 n1 = size1;
-        
-        vec3 shift = npos;
-        float d = 500.0;
-        {
-            vec3 m = vec3(sls, pos.xy);
-            vec3 np0 = m / n0 * d;
-            vec3 scale = vec3(1.0, ss, ss);
-            vec3 np1 = (m - shift * scale) / n1 * d;
-            
-            coord0 = project_s3(np0, vec3(0.0, psx, psy)/n0*d, scale);
-            coord1 = project_s3(np1, vec3(0.0, psx, psy)/n1*d, scale);
+    // The color and the opacity of the value of the secondary.
+    vec4 secondary_rgba(float value, bool outbound) {
+        if (bands1 > 0) {
+            for (int i = 0; i < MAX_BANDS; i++) {
+                if (i < bands1 && !outbound && value >= band_range1[i].x && value < band_range1[i].y) {
+                    return vec4(band_color1[i], 1.0);
+                }
+            }
+            return vec4(0.0);
         }
-        float lower = level - window / 2.0;
-        float upper = level + window / 2.0;
-
-        vec4 pixel0 = texture(sampler0, coord0);
-        float value0 = rgba2u16(pixel0);
-        float gray0 = clamp((value0 - lower) / window, 0.0, 1.0);
-        if (is_outbound(coord0)) {
-            gray0 = 0.0;
+        vec3 c = secondary_color(outbound ? 0.0 : secondary_gray(value));
+        if (ramp1.z == 0.0) {
+            return vec4(c, 1.0);
         }
-
-        color = vec4(0.6, 0.1, 0.2, 1.0);
-        float upper1 = level1 + window1 / 2.0;
-        float lower1 = level1 - window1 / 2.0;
-        // mat2x2 R = mat2x2(cos(theta), -sin(theta), sin(theta), cos(theta));
-        {
-
-            float lower1 = level1 - window1 / 2.0;
-            float upper1 = level1 + window1 / 2.0;
-            vec4 pixel1 = texture(sampler1, coord1);
-            float value1 = rgba2u16(pixel1);
-            value1 = value1 * (dh - dl) / 65535.0 + dl;
-            float gray1 = clamp((value1 - lower1) / window1, 0.0, 1.0);
-            if (is_outbound(coord1)) {
-                gray1 = 0.0;
-            }
-            vec4 scolor = texture(sampler2, vec3(gray1, 0.5, 0.0));
-            color = vec4((gray0 * (1.0 - k) + scolor.xyz * k), 1.0);
+        if (outbound || value < ramp1.x) {
+            return vec4(c, 0.0);
         }
-    }"#;
-
-pub const FRAGMENT_SHADER_SOURCE_SAGITTAL2: &str = r#"#version 300 es
-    precision highp float;
-    precision highp sampler3D;
-
-    uniform sampler3D sampler0;
-    // uniform sampler3D sampler1;
-    uniform sampler3D sampler2;
-    uniform sampler3D sampler3;
-    //uniform sampler3D sampler4;
-
-    uniform float ss;
-    uniform float psx;
-    uniform float psy;
-    uniform float sls;
-
-    uniform float k;            // blend coefficient
-    uniform int s;            // 0: primary 1: primary/secondary
-
-    uniform vec3 spacing0;
-    uniform vec3 spacing1;
-    uniform vec3 size0;
-    uniform vec3 size1;
-
-    uniform float window;
-    uniform float level;
-    uniform float window1;
-    uniform float level1;
-
-    // Ablation
-    uniform vec3 uah;     // (u0, a, h) for Ablation
-    uniform float L;      // Length of the needle
-    uniform vec3 npos;
-    uniform float theta;
-
-    in vec3 pos;
-    out vec4 color;
-
-    float rgba2u16(vec4 rgba) {
-        return 15.0 * (rgba.r * 4096.0 + rgba.g * 256.0 + rgba.b * 16.0 + rgba.a);
-    }
-
-    float rgba2f32(vec4 rgba) {
-        return rgba2u16(rgba) / 65535.0;
-    }
-
-    vec3 project_t3(vec3 p, vec3 pan, vec3 scale) {
-        const mat4x3 M = mat4x3(0.5, 0.0, 0.0, 0.0, -0.5, 0.0, 0.0, 0.0, 0.5, 0.5, 0.5, 0.5);
-        vec3 q = (p - pan) / scale;
-        return M * vec4(q, 1.0);
-    }
-    vec3 project_s3(vec3 p, vec3 pan, vec3 scale) {
-        const mat4x3 M = mat4x3(0.5, 0.0, 0.0, 0.0, -0.5, 0.0, 0.0, 0.0, 0.5, 0.5, 0.5, 0.5);
-        vec3 q = (p - pan) / scale;
-        return M * vec4(q, 1.0);
-    }
-    vec3 project_c3(vec3 p, vec3 pan, vec3 scale) {
-        const mat4x3 M = mat4x3(0.5, 0.0, 0.0, 0.0, -0.5, 0.0, 0.0, 0.0, 0.5, 0.5, 0.5, 0.5);
-        vec3 q = (p - pan) / scale;
-        return M * vec4(q, 1.0);
-    }
-
-    float minv3(vec3 v) {
-        return min(min(v[0], v[1]), v[2]);
-    }
-    float maxv3(vec3 v) {
-        return max(max(v[0], v[1]), v[2]);
-    }
-
-    bool is_outbound(float x, float y, float z) {
-        return (x < 0.0 || x > 1.0 || y < 0.0 || y > 1.0 || z < 0.0 || z > 1.0 ); 
-    }
-    bool is_outbound(vec3 p) {
-        return (p.x < 0.0 || p.x > 1.0 || p.y < 0.0 || p.y > 1.0 || p.z < 0.0 || p.z > 1.0 ); 
+        return vec4(c, value >= ramp1.y ? 1.0 : (value - ramp1.x) / (ramp1.y - ramp1.x));
+    }
+    "#,
+};
+
+// The axis aligned views, specialised by AXIS_U and AXIS_V, the directions
+// of the right and the top of the view, and AXIS_N across it.
+pub static SLICE: ShaderModule = ShaderModule {
+    name: "slice",
+    requires: &[],
+    source: r#"
+    uniform float scale;
+    uniform vec2 pan;
+    uniform vec2 extent;            // of the viewport, see viewport_extent
+    uniform float slice;
+
+    // the shorter side of the quad covers 500 mm at scale 1 and the slice is
+    // in 250 mm
+    vec3 slice_point(vec2 q) {
+        vec2 s = (q * extent - pan) / scale;
+        return 250.0 * (AXIS_U * s.x + AXIS_V * s.y + AXIS_N * slice);
+    }
+    "#,
+};
+
+// Projection of a slab across the slice, see Slab.
+pub static SLAB: ShaderModule = ShaderModule {
+    name: "slab",
+    requires: &[&PRIMARY, &SLICE],
+    source: r#"
+    uniform int slab_mode;      // 0: single plane 1: maximum 2: minimum 3: mean
+    uniform float slab;         // thickness in mm
+    uniform float slab1;        // 1.0 if the secondary is projected as well
+    const int MAX_SLAB_SAMPLES = 64;
+
+    // samples every spacing of the primary across the slab
+    int slab_samples() {
+        if (slab_mode == 0 || slab <= 0.0) {
+            return 1;
+        }
+        float sp = dot(spacing0, abs(AXIS_N));
+        return clamp(int(ceil(slab / sp)) + 1, 2, MAX_SLAB_SAMPLES);
     }
 
-    // Ablation
-    float calc_dose(float u0, float a, float h, float x, float y) {
-       // return (x * x + y * y) * 2000.0;
-        float delta = 0.00000001;
-        float b2 = h*h - a*a;
-        float b = sqrt(b2);
-        float ha = h - a;
-        float ln = log((b + ha) / (b - ha));
-        float x2 = x * x;
-        float y2 = y * y;
-        float xy = ((x + b)*(x + b) + y2) * ((x - b) * (x - b) + y2);
-        float d = ln * ln * xy;
-        // d = max(delta, d);
-        float E2 = 4.0 * b2 * u0 * u0 / d;
-        float E = sqrt(E2);
-        return E;
+    float slab_start() {
+        return slab_mode == 1 ? -1e30 : (slab_mode == 2 ? 1e30 : 0.0);
     }
 
-    float d_2(float x) {
-        return x * x;
+    float slab_accumulate(float acc, float v) {
+        if (slab_mode == 1) {
+            return max(acc, v);
+        } else if (slab_mode == 2) {
+            return min(acc, v);
+        }
+        return acc + v;
     }
 
-    float calc_E(float U0, float R, float L, float h, float x, float y, float z) {
-        float W = U0 / (4.0*asinh(L/(2.0*sqrt(R*R))));
-        float hh = h / 2.0;
-        float hL = L / 2.0;
-        float E = sqrt(d_2(-W*(-1.0/(sqrt(y*y + d_2(-hh + x))*sqrt(d_2(hL - z)/(y*y + d_2(-hh + x)) + 1.0)) + \
-                  1.0/(sqrt(y*y + d_2(-hh + x))*sqrt(d_2(-hL - z)/(y*y + d_2(-hh + x)) + 1.0))) + \
-              W*(-1.0/(sqrt(y*y + d_2(hh + x))*sqrt(d_2(hL - z)/(y*y + d_2(hh + x)) + 1.0)) + \
-                 1.0/(sqrt(y*y + d_2(hh + x))*sqrt(d_2(-hL - z)/(y*y + d_2(hh + x)) + 1.0)))) + \
-             d_2(-W*(y*(-hL - z)/(pow(y*y + d_2(-hh + x), 1.5)*sqrt(d_2(-hL - z)/(y*y + d_2(-hh + x)) + 1.0)) - \
-                  y*(hL - z)/(pow(y*y + d_2(-hh + x), 3.0/2.0)*sqrt(d_2(hL - z)/(y*y + d_2(-hh + x)) + 1.0))) + \
-              W*(y*(-hL - z)/(pow(y*y + d_2(hh + x), 3.0/2.0)*sqrt(d_2(-hL - z)/(y*y + d_2(hh + x)) + 1.0)) - \
-                 y*(hL - z)/(pow(y*y + d_2(hh + x), 3.0/2.0)*sqrt(d_2(hL - z)/(y*y + d_2(hh + x)) + 1.0)))) + \
-                 d_2(W*(-(-hL - z)*(-hh - x)/(pow(y*y + d_2(hh + x), 3.0/2.0)*sqrt(d_2(-hL - z)/(y*y + d_2(hh + x)) + 1.0)) + \
-                     (hL - z)*(-hh - x)/(pow(y*y + d_2(hh + x), 1.5)*sqrt(d_2(hL - z)/(y*y + \
-            d_2(hh + x)) + 1.0))) - W*(-(-hL - z)*(hh - x)/(pow(y*y + d_2(-hh + x), 1.5)*sqrt(d_2(-hL - z)/(y*y + \
-            d_2(-hh + x)) + 1.0)) + (hL - z)*(hh - x)/(pow(y*y + d_2(-hh + x), 3.0/2.0)*sqrt(d_2(hL - z)/(y*y + d_2(-hh + x)) + 1.0)))));
-        return E;
+    // The i-th of the n samples across the slab, centered on coord.
+    vec3 slab_coord(vec3 coord, vec3 step, int i, int n) {
+        return coord + step * (float(i) - float(n - 1) / 2.0);
     }
 
-    void main() {
-        vec3 coord0, coord1;
-        float u, v, w;
-        float u1, v1, w1;
-        // vec3 n0 = dim0 * spacing0;
-        // vec3 n1 = dim1 * spacing1;
-        vec3 n0 = size0;
-        vec3 n1 = size1;
-        
-        float d = 500.0;
-        {
-            vec3 m = vec3(sls, pos.xy);
-            vec3 np0 = m / n0 * d;
-            vec3 scale = vec3(1.0, ss, ss);
-            coord0 = project_s3(np0, vec3(0.0, psx, psy)/n0*d, scale);
+    float project_slab(sampler3D volume, vec3 coord, vec3 step, int n) {
+        float acc = slab_start();
+        float count = 0.0;
+        for (int i = 0; i < MAX_SLAB_SAMPLES; i++) {
+            if (i >= n || n <= 1) {
+                break;
+            }
+            vec3 c = slab_coord(coord, step, i, n);
+            if (!is_outbound(c)) {
+                acc = slab_accumulate(acc, texture(volume, c).r);
+                count += 1.0;
+            }
         }
-        float lower = level - window / 2.0;
-        float upper = level + window / 2.0;
-
-        vec4 pixel0 = texture(sampler0, coord0);
-        float value0 = rgba2u16(pixel0);
-        float gray0 = clamp((value0 - lower) / window, 0.0, 1.0);
-        if (is_outbound(coord0)) {
-            gray0 = 0.0;
+        if (count == 0.0) {
+            return texture(volume, coord).r;
         }
+        return slab_mode == 3 ? acc / count : acc;
+    }
 
-        color = vec4(0.6, 0.1, 0.2, 1.0);
-        float upper1 = level1 + window1 / 2.0;
-        float lower1 = level1 - window1 / 2.0;
-        mat2x2 R = mat2x2(cos(theta), -sin(theta), sin(theta), cos(theta));
-        {
-            vec3 p = vec3(sls * d - npos.x, (pos.x - psx) * d / ss - npos.y, (pos.y - psy) * d / ss - npos.z);
-            p = vec3(R * vec2(p.xy), p.z);
-            float value1 = calc_E(uah.x, uah.y, L, uah.z*2.0, p.x, p.y, p.z);
-            float gray1 = clamp((value1 - lower1) / window1, 0.0, 1.0);
-            if (is_outbound(coord1)) {
-                gray1 = 0.0;
+    // project_slab of the stored values of the primary
+    float project_primary_slab(vec3 coord, vec3 step, int n) {
+        float acc = slab_start();
+        float count = 0.0;
+        for (int i = 0; i < MAX_SLAB_SAMPLES; i++) {
+            if (i >= n || n <= 1) {
+                break;
+            }
+            vec3 c = slab_coord(coord, step, i, n);
+            if (!is_outbound(c)) {
+                acc = slab_accumulate(acc, primary_texel(c));
+                count += 1.0;
             }
-            vec4 scolor = texture(sampler2, vec3(gray1, 0.5, 0.0));
-            color = vec4((gray0 * (1.0 - k) + scolor.xyz * k), 1.0);
-            // color = vec4(1.0, 0.0, 0.0, 1.0);
         }
-    }"#;
-    
-pub const FRAGMENT_SHADER_SOURCE_CORONAL: &str = r#"#version 300 es
-    precision highp float;
-    precision highp sampler3D;
-
-    uniform sampler3D sampler0;
-    uniform sampler3D sampler1;
-    uniform sampler3D sampler2;
-    uniform sampler3D sampler3;
-    uniform sampler3D sampler4;
-
-    uniform float sc;
-    uniform float pcx;
-    uniform float pcy;
-    uniform float slc;
-
-    uniform float k;            // blend coefficient
-    uniform int s;            // 0: primary 1: primary/secondary
-
-    uniform vec3 spacing0;
-    uniform vec3 spacing1;
-    uniform vec3 size0;
-    uniform vec3 size1;
-
-    uniform float window;
-    uniform float level;
-    uniform float window1;
-    uniform float level1;
-
-    uniform float dl;
-    uniform float dh;
-
-    // Ablation
-    uniform vec3 uah;     // (u0, a, h) for Ablation
-    uniform float L;      // Length of the needle
-    uniform vec3 npos;
-    uniform float theta;
-
-    in vec3 pos;
-    out vec4 color;
-
-    float rgba2u16(vec4 rgba) {
-        return 15.0 * (rgba.r * 4096.0 + rgba.g * 256.0 + rgba.b * 16.0 + rgba.a);
-    }
-
-    float rgba2f32(vec4 rgba) {
-        return rgba2u16(rgba) / 65535.0;
-    }
-
-    vec3 project_t3(vec3 p, vec3 pan, vec3 scale) {
-        const mat4x3 M = mat4x3(0.5, 0.0, 0.0, 0.0, -0.5, 0.0, 0.0, 0.0, 0.5, 0.5, 0.5, 0.5);
-        vec3 q = (p - pan) / scale;
-        return M * vec4(q, 1.0);
-    }
-    vec3 project_s3(vec3 p, vec3 pan, vec3 scale) {
-        const mat4x3 M = mat4x3(0.5, 0.0, 0.0, 0.0, -0.5, 0.0, 0.0, 0.0, 0.5, 0.5, 0.5, 0.5);
-        vec3 q = (p - pan) / scale;
-        return M * vec4(q, 1.0);
-    }
-    vec3 project_c3(vec3 p, vec3 pan, vec3 scale) {
-        const mat4x3 M = mat4x3(0.5, 0.0, 0.0, 0.0, -0.5, 0.0, 0.0, 0.0, 0.5, 0.5, 0.5, 0.5);
-        vec3 q = (p - pan) / scale;
-        return M * vec4(q, 1.0);
-    }
-
-    float minv3(vec3 v) {
-        return min(min(v[0], v[1]), v[2]);
-    }
-    float maxv3(vec3 v) {
-        return max(max(v[0], v[1]), v[2]);
+        if (count == 0.0) {
+            return primary_texel(coord);
+        }
+        return slab_mode == 3 ? acc / count : acc;
+    }
+    "#,
+};
+
+pub static SEGMENTS: ShaderModule = ShaderModule {
+    name: "segments",
+    requires: &[],
+    source: r#"
+    float segment_distance(vec3 p, vec3 a, vec3 b) {
+        vec3 u = b - a;
+        float s = clamp(dot(p - a, u) / max(dot(u, u), 1e-6), 0.0, 1.0);
+        return length(p - a - s * u);
+    }
+    "#,
+};
+
+pub static OVERLAY: ShaderModule = ShaderModule {
+    name: "overlay",
+    requires: &[&QUAD],
+    source: r#"
+    uniform sampler2D overlay;    // RGBA over the viewport from the top left, see StructureOverlay
+
+    vec3 apply_overlay(vec3 c) {
+        vec4 o = texture(overlay, vec2(0.5 + 0.5 * pos.x, 0.5 - 0.5 * pos.y));
+        return mix(c, o.rgb, o.a);
+    }
+    "#,
+};
+
+pub static CROSSHAIR: ShaderModule = ShaderModule {
+    name: "crosshair",
+    requires: &[],
+    source: r#"
+    uniform vec3 cursor;            // in the view frame of the primary in mm
+    uniform float crosshair;        // 1.0 to draw the lines through the cursor
+    uniform vec3 crosshair_color;
+
+    // 1.0 on the lines through the cursor along u and v, the axes of the
+    // view, a pixel wide
+    float crosshair_at(vec3 p, vec3 u, vec3 v) {
+        vec3 d = p - cursor;
+        bool on = abs(dot(d, u)) < 0.5 * length(dFdx(p)) || abs(dot(d, v)) < 0.5 * length(dFdy(p));
+        return crosshair > 0.0 && on ? 1.0 : 0.0;
+    }
+    "#,
+};
+
+// see Fusion
+pub static FUSION: ShaderModule = ShaderModule {
+    name: "fusion",
+    requires: &[],
+    source: r#"
+    uniform int fusion;             // 0 blend, 1 checkerboard, 2 difference, 3 spyglass
+    uniform float fusion_size;      // the tiles or the radius of the lens in mm
+    uniform vec3 lens_center;       // in the view frame of the primary in mm
+
+    // The weight of the secondary at p of the view along u and v with the
+    // blend coefficient k.
+    float fusion_weight(vec3 p, vec3 u, vec3 v, float k) {
+        if (fusion == 1) {
+            float i = floor(dot(p, u) / fusion_size) + floor(dot(p, v) / fusion_size);
+            return mod(i, 2.0) >= 1.0 ? 1.0 : 0.0;
+        }
+        if (fusion == 3) {
+            vec3 d = p - lens_center;
+            vec2 w = vec2(dot(d, u), dot(d, v));
+            return dot(w, w) < fusion_size * fusion_size ? 1.0 : 0.0;
+        }
+        return k;
     }
 
-    bool is_outbound(float x, float y, float z) {
-        return (x < 0.0 || x > 1.0 || y < 0.0 || y > 1.0 || z < 0.0 || z > 1.0 ); 
-    }
-    bool is_outbound(vec3 p) {
-        return (p.x < 0.0 || p.x > 1.0 || p.y < 0.0 || p.y > 1.0 || p.z < 0.0 || p.z > 1.0 ); 
+    // The final color from the gray level of the primary, the color and the
+    // opacity of the secondary with its gray level, and the weight.
+    vec3 fuse(float gray0, vec4 c1, float gray1, float weight) {
+        if (fusion == 2) {
+            return vec3(abs(gray0 - gray1));
+        }
+        float k1 = weight * c1.a;
+        return gray0 * (1.0 - k1) + c1.rgb * k1;
+    }
+    "#,
+};
+
+// Depth ruler from ruler_origin along ruler_dir with the ticks towards
+// ruler_side, hidden if ruler_length is 0.
+pub static RULER: ShaderModule = ShaderModule {
+    name: "ruler",
+    requires: &[],
+    source: r#"
+    uniform vec3 ruler_origin;
+    uniform vec3 ruler_dir;
+    uniform vec3 ruler_side;
+    uniform float ruler_length;
+
+    // 1.0 on the ruler: the spine, a tick every 5 mm and a longer one every 10 mm
+    float ruler(vec3 p, float px) {
+        if (ruler_length <= 0.0) {
+            return 0.0;
+        }
+        float s = dot(p - ruler_origin, ruler_dir);
+        float w = dot(p - ruler_origin, ruler_side);
+        if (s < -px || s > ruler_length + px) {
+            return 0.0;
+        }
+        if (abs(w) < px) {
+            return 1.0;
+        }
+        float tick = mod(s + 2.5, 5.0) - 2.5;
+        bool major = mod(s + 5.0, 10.0) < 5.0;
+        float tick_length = major ? 4.0 : 2.0;
+        if (abs(tick) < px && w > 0.0 && w < tick_length) {
+            return 1.0;
+        }
+        return 0.0;
+    }
+    "#,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Orientation {
+    Transverse,
+    Sagittal,
+    Coronal,
+}
+
+impl Orientation {
+    // (right, top, across) of the view in the view frame of the primary,
+    // whose y points to the posterior
+    pub fn axes(&self) -> [Vector3<f32>; 3] {
+        match self {
+            Orientation::Transverse => [Vector3::x(), -Vector3::y(), Vector3::z()],
+            Orientation::Sagittal => [-Vector3::y(), Vector3::z(), Vector3::x()],
+            Orientation::Coronal => [Vector3::x(), Vector3::z(), -Vector3::y()],
+        }
     }
-
-    void main() {
-        vec3 coord0, coord1;
-        float u, v, w;
-        float u1, v1, w1;
-        // vec3 n0 = dim0 * spacing0;
-        // vec3 n1 = dim1 * spacing1;
-        vec3 n0 = size0;
-        vec3 n1 = size1;
-        
-        vec3 shift = npos;
-        float d = 500.0;
-        {
-            // float z = rgba2f32(texture(sampler3, vec3((pos.y + 1.0) / 2.0, 0.5, 0.0))) * 2.0 - 1.0;
-            //vec3 m = vec3(pos.x, slc, z);
-            vec3 m = vec3(pos.x, slc, pos.y);
-            vec3 np0 = m / n0 * d;
-            vec3 scale = vec3(sc, 1.0, sc);
-            vec3 np1 = (m -shift * scale) / n1 * d;
-
-            coord0 = project_c3(np0, vec3(pcx, 0.0, pcy)/n0*d, scale);
-            coord1 = project_c3(np1, vec3(pcx, 0.0, pcy)/n1*d, scale);
+}
+
+fn glsl_vec3(v: &Vector3<f32>) -> String {
+    // adding 0.0 turns -0.0 into 0.0
+    format!("vec3({:.1}, {:.1}, {:.1})", v.x + 0.0, v.y + 0.0, v.z + 0.0)
+}
+
+// The transverse, sagittal and coronal views.
+pub fn slice_fragment_shader(orientation: Orientation) -> ShaderSource {
+    let [u, v, n] = orientation.axes();
+    ShaderBuilder::new()
+        .define("AXIS_U", &glsl_vec3(&u))
+        .define("AXIS_V", &glsl_vec3(&v))
+        .define("AXIS_N", &glsl_vec3(&n))
+        .module(&QUAD)
+        .module(&SLAB)
+        .module(&SECONDARY)
+        .module(&OVERLAY)
+        .module(&CROSSHAIR)
+        .module(&FUSION)
+        .declare("    uniform float k;            // blend coefficient")
+        .main(r#"
+        vec3 p = slice_point(pos.xy);
+        vec3 coord0 = primary_coord(p);
+        int n = slab_samples();
+        vec3 slab_step = AXIS_N * (n > 1 ? slab / float(n - 1) : 0.0);
+        float value0 = project_primary_slab(coord0, slab_step / size0, n) * rescale0.x + rescale0.y;
+        float gray0 = is_outbound(coord0) ? 0.0 : primary_gray(value0);
+
+        vec3 coord1 = secondary_coord(p);
+        int n1 = slab1 > 0.0 ? n : 1;
+        float value1 = project_slab(sampler1, coord1, (tex1 * vec4(slab_step, 0.0)).xyz, n1);
+        vec4 c1 = secondary_rgba(value1, is_outbound(coord1));
+        float gray1 = is_outbound(coord1) ? 0.0 : secondary_gray(value1);
+        float weight = fusion_weight(p, AXIS_U, AXIS_V, k);
+        color = vec4(apply_overlay(fuse(gray0, c1, gray1, weight)), 1.0);
+        if (crosshair_at(p, AXIS_U, AXIS_V) > 0.0) {
+            color = vec4(crosshair_color, 1.0);
+        }"#)
+        .build()
+}
+
+// Reformat on an arbitrary plane, which is given by `plane` from the quad to
+// the view frame of the primary in mm.
+pub fn oblique_fragment_shader() -> ShaderSource {
+    ShaderBuilder::new()
+        .module(&QUAD)
+        .module(&PRIMARY)
+        .module(&SECONDARY)
+        .module(&SEGMENTS)
+        .module(&RULER)
+        .module(&CROSSHAIR)
+        .module(&FUSION)
+        .declare(r#"
+    uniform mat4 plane;
+    uniform float k;            // blend coefficient"#)
+        .main(r#"
+        vec3 p = (plane * vec4(pos.xy, 0.0, 1.0)).xyz;
+        float px = length(fwidth(p));
+
+        vec3 coord0 = primary_coord(p);
+        float gray0 = is_outbound(coord0) ? 0.0 : primary_gray(primary_value(p));
+        vec3 coord1 = secondary_coord(p);
+        float value1 = texture(sampler1, coord1).r;
+        vec4 c1 = secondary_rgba(value1, is_outbound(coord1));
+        float gray1 = is_outbound(coord1) ? 0.0 : secondary_gray(value1);
+        float weight = fusion_weight(p, normalize(plane[0].xyz), normalize(plane[1].xyz), k);
+        color = vec4(fuse(gray0, c1, gray1, weight), 1.0);
+
+        vec3 center = needle_center();
+        vec3 e = needle_spread() * uah.z;
+        vec3 hl = vec3(0.0, 0.0, L / 2.0);
+        float r = max(uah.y, px);
+        if (segment_distance(p, center + e - hl, center + e + hl) < r
+            || segment_distance(p, center - e - hl, center - e + hl) < r) {
+            color = vec4(0.75, 0.75, 0.8, 1.0);
+        }
+        if (ruler(p, px) > 0.0) {
+            color = vec4(1.0, 0.85, 0.1, 1.0);
+        }
+        if (crosshair_at(p, normalize(plane[0].xyz), normalize(plane[1].xyz)) > 0.0) {
+            color = vec4(crosshair_color, 1.0);
+        }"#)
+        .build()
+}
+
+// Ray casting of the primary with the field composited into the same rays.
+// The depth is where the rays turn mostly opaque, for the meshes drawn over
+// it in the same projection.
+pub fn three_d_fragment_shader() -> ShaderSource {
+    ShaderBuilder::new()
+        .module(&QUAD)
+        .module(&PRIMARY)
+        .module(&SECONDARY)
+        .declare(r#"
+    uniform sampler3D sampler5;     // transfer function
+    uniform mat4 ray;               // from the quad to the view frame, see ThreeDView
+    uniform float step_size;        // in mm
+    uniform vec2 tf_range;          // values at the ends of the transfer function
+    uniform float opacity1;         // 0.0 to hide the field
+
+    // Distance along the ray to the needle [a, b] of radius r, -1.0 if missed.
+    float hit_needle(vec3 o, vec3 dir, vec3 a, vec3 b, float r) {
+        vec3 u = b - a;
+        vec3 w = o - a;
+        float uu = dot(u, u);
+        float ud = dot(u, dir);
+        float denom = uu - ud * ud;
+        if (denom < 1e-6) {
+            return -1.0;
+        }
+        float s = clamp((dot(u, w) - dot(dir, w) * ud) / denom, 0.0, 1.0);
+        float t = s * ud - dot(dir, w);
+        if (t > 0.0 && length(w + t * dir - s * u) < r) {
+            return t;
         }
-        float lower = level - window / 2.0;
-        float upper = level + window / 2.0;
-
-        vec4 pixel0 = texture(sampler0, coord0);
-        float value0 = rgba2u16(pixel0);
-        float gray0 = clamp((value0 - lower) / window, 0.0, 1.0);
-        if (is_outbound(coord0)) {
-            gray0 = 0.0;
+        return -1.0;
+    }"#)
+        .main(r#"
+        vec3 o = (ray * vec4(pos.xy, -1.0, 1.0)).xyz;
+        vec3 dir = normalize((ray * vec4(0.0, 0.0, 1.0, 0.0)).xyz);
+
+        // the segment of the ray inside the primary
+        vec3 inv = 1.0 / mix(dir, vec3(1e-6), lessThan(abs(dir), vec3(1e-6)));
+        vec3 t0 = (-0.5 * size0 - o) * inv;
+        vec3 t1 = (0.5 * size0 - o) * inv;
+        vec3 tmin = min(t0, t1);
+        vec3 tmax = max(t0, t1);
+        float tnear = max(max(tmin.x, tmin.y), tmin.z);
+        float tfar = min(min(tmax.x, tmax.y), tmax.z);
+
+        vec3 center = needle_center();
+        vec3 e = needle_spread() * uah.z;
+        vec3 hl = vec3(0.0, 0.0, L / 2.0);
+        float r = max(uah.y, 0.5);
+        float tneedle = 1e9;
+        float th = hit_needle(o, dir, center + e - hl, center + e + hl, r);
+        if (th > 0.0) {
+            tneedle = th;
+        }
+        th = hit_needle(o, dir, center - e - hl, center - e + hl, r);
+        if (th > 0.0) {
+            tneedle = min(tneedle, th);
         }
 
-        color = vec4(0.6, 0.1, 0.2, 1.0);
-        float upper1 = level1 + window1 / 2.0;
         float lower1 = level1 - window1 / 2.0;
-        // mat2x2 R = mat2x2(cos(theta), -sin(theta), sin(theta), cos(theta));
-        {
-
-            float lower1 = level1 - window1 / 2.0;
-            float upper1 = level1 + window1 / 2.0;
-            vec4 pixel1 = texture(sampler1, coord1);
-            float value1 = rgba2u16(pixel1);
-            value1 = value1 * (dh - dl) / 65535.0 + dl;
-            float gray1 = clamp((value1 - lower1) / window1, 0.0, 1.0);
-            if (is_outbound(coord1)) {
-                gray1 = 0.0;
+        vec4 acc = vec4(0.0);
+        float thit = 1e9;
+        for (int i = 0; i < 4096; i++) {
+            float t = tnear + (float(i) + 0.5) * step_size;
+            // early ray termination
+            if (t > tfar || t > tneedle || acc.a > 0.95) {
+                break;
+            }
+            vec3 p = o + t * dir;
+
+            if (opacity1 > 0.0) {
+                vec3 coord1 = secondary_coord(p);
+                if (!is_outbound(coord1)) {
+                    float value1 = texture(sampler1, coord1).r;
+                    vec4 c1 = secondary_rgba(value1, false);
+                    if ((bands1 > 0 || value1 >= lower1) && c1.a > 0.0) {
+                        float a1 = (1.0 - pow(1.0 - opacity1, step_size)) * c1.a;
+                        acc += (1.0 - acc.a) * vec4(c1.rgb * a1, a1);
+                        if (acc.a >= 0.5) {
+                            thit = min(thit, t);
+                        }
+                    }
+                }
             }
-            vec4 scolor = texture(sampler2, vec3(gray1, 0.5, 0.0));
-            color = vec4((gray0 * (1.0 - k) + scolor.xyz * k), 1.0);
-        }
-    }"#;
-
-pub const FRAGMENT_SHADER_SOURCE_CORONAL2: &str = r#"#version 300 es
-    precision highp float;
-    precision highp sampler3D;
-
-    uniform sampler3D sampler0;
-    // uniform sampler3D sampler1;
-    uniform sampler3D sampler2;
-    uniform sampler3D sampler3;
-    uniform sampler3D sampler4;
-
-    uniform float sc;
-    uniform float pcx;
-    uniform float pcy;
-    uniform float slc;
-
-    uniform float k;            // blend coefficient
-    uniform int s;            // 0: primary 1: primary/secondary
-
-    uniform vec3 spacing0;
-    uniform vec3 spacing1;
-    uniform vec3 size0;
-    uniform vec3 size1;
-
-    uniform float window;
-    uniform float level;
-    uniform float window1;
-    uniform float level1;
 
-    // Ablation
-    uniform vec3 uah;     // (u0, a, h) for Ablation
-    uniform float L;      // Length of the needle
-    uniform vec3 npos;
-    uniform float theta;
+            float u = (primary_value(p) - tf_range.x) / (tf_range.y - tf_range.x);
+            if (u < 0.0 || u > 1.0) {
+                continue;
+            }
+            vec4 c0 = texture(sampler5, vec3(u, 0.5, 0.5));
+            if (c0.a <= 0.0) {
+                continue;
+            }
+            // gradient shading with the light at the eye
+            vec3 g = vec3(
+                primary_value(p + vec3(spacing0.x, 0.0, 0.0)) - primary_value(p - vec3(spacing0.x, 0.0, 0.0)),
+                primary_value(p + vec3(0.0, spacing0.y, 0.0)) - primary_value(p - vec3(0.0, spacing0.y, 0.0)),
+                primary_value(p + vec3(0.0, 0.0, spacing0.z)) - primary_value(p - vec3(0.0, 0.0, spacing0.z))
+            ) / (2.0 * spacing0);
+            float shade = 1.0;
+            if (length(g) > 1e-3) {
+                vec3 n = normalize(g);
+                float diffuse = abs(dot(n, dir));
+                shade = 0.3 + 0.7 * diffuse + 0.2 * pow(diffuse, 20.0);
+            }
+            // the opacities of the transfer function are for 1 mm
+            float a0 = 1.0 - pow(1.0 - c0.a, step_size);
+            acc += (1.0 - acc.a) * vec4(c0.rgb * shade * a0, a0);
+            if (acc.a >= 0.5) {
+                thit = min(thit, t);
+            }
+        }
 
-    in vec3 pos;
-    out vec4 color;
+        if (tneedle < 1e9) {
+            acc += (1.0 - acc.a) * vec4(0.8, 0.8, 0.85, 1.0);
+        }
+        color = vec4(acc.rgb, 1.0);
+        // the quad spans the depth range from z = -1 at o along dir
+        thit = min(thit, tneedle);
+        float depth_range = 2.0 * length((ray * vec4(0.0, 0.0, 1.0, 0.0)).xyz);
+        gl_FragDepth = thit < 1e9 ? clamp(thit / depth_range, 0.0, 1.0) : 1.0;"#)
+        .build()
+}
 
-    float rgba2u16(vec4 rgba) {
-        return 15.0 * (rgba.r * 4096.0 + rgba.g * 256.0 + rgba.b * 16.0 + rgba.a);
-    }
+// Surface meshes of the 3D view, in the same projection as the ray casting.
+pub const VERTEX_SHADER_SOURCE_MESH: &str = r#"#version 300 es
+    layout(location = 0) in vec3 position;
+    layout(location = 1) in vec3 normal;
 
-    float rgba2f32(vec4 rgba) {
-        return rgba2u16(rgba) / 65535.0;
-    }
+    uniform mat4 model;         // from the mesh to the view frame of the primary
+    uniform mat4 projection;    // from the view frame to the clip coordinates
 
-    vec3 project_t3(vec3 p, vec3 pan, vec3 scale) {
-        const mat4x3 M = mat4x3(0.5, 0.0, 0.0, 0.0, -0.5, 0.0, 0.0, 0.0, 0.5, 0.5, 0.5, 0.5);
-        vec3 q = (p - pan) / scale;
-        return M * vec4(q, 1.0);
-    }
-    vec3 project_s3(vec3 p, vec3 pan, vec3 scale) {
-        const mat4x3 M = mat4x3(0.5, 0.0, 0.0, 0.0, -0.5, 0.0, 0.0, 0.0, 0.5, 0.5, 0.5, 0.5);
-        vec3 q = (p - pan) / scale;
-        return M * vec4(q, 1.0);
-    }
-    vec3 project_c3(vec3 p, vec3 pan, vec3 scale) {
-        const mat4x3 M = mat4x3(0.5, 0.0, 0.0, 0.0, -0.5, 0.0, 0.0, 0.0, 0.5, 0.5, 0.5, 0.5);
-        vec3 q = (p - pan) / scale;
-        return M * vec4(q, 1.0);
-    }
+    out vec3 v_normal;
 
-    float minv3(vec3 v) {
-        return min(min(v[0], v[1]), v[2]);
-    }
-    float maxv3(vec3 v) {
-        return max(max(v[0], v[1]), v[2]);
-    }
-
-    bool is_outbound(float x, float y, float z) {
-        return (x < 0.0 || x > 1.0 || y < 0.0 || y > 1.0 || z < 0.0 || z > 1.0 ); 
-    }
-    bool is_outbound(vec3 p) {
-        return (p.x < 0.0 || p.x > 1.0 || p.y < 0.0 || p.y > 1.0 || p.z < 0.0 || p.z > 1.0 ); 
+    void main() {
+        v_normal = transpose(inverse(mat3(model))) * normal;
+        gl_Position = projection * model * vec4(position, 1.0);
     }
+    "#;
 
-    // Ablation
-    float calc_dose(float u0, float a, float h, float x, float y) {
-       // return (x * x + y * y) * 2000.0;
-        float delta = 0.00000001;
-        float b2 = h*h - a*a;
-        float b = sqrt(b2);
-        float ha = h - a;
-        float ln = log((b + ha) / (b - ha));
-        float x2 = x * x;
-        float y2 = y * y;
-        float xy = ((x + b)*(x + b) + y2) * ((x - b) * (x - b) + y2);
-        float d = ln * ln * xy;
-        // d = max(delta, d);
-        float E2 = 4.0 * b2 * u0 * u0 / d;
-        float E = sqrt(E2);
-        return E;
-    }
+pub const FRAGMENT_SHADER_SOURCE_MESH: &str = r#"#version 300 es
+    precision highp float;
 
-    float d_2(float x) {
-        return x * x;
-    }
+    uniform vec4 mesh_color;    // rgb and opacity
+    uniform vec3 eye_dir;       // viewing direction in the view frame
 
-    float calc_E(float U0, float R, float L, float h, float x, float y, float z) {
-        float W = U0 / (4.0*asinh(L/(2.0*sqrt(R*R))));
-        float hh = h / 2.0;
-        float hL = L / 2.0;
-        float E = sqrt(d_2(-W*(-1.0/(sqrt(y*y + d_2(-hh + x))*sqrt(d_2(hL - z)/(y*y + d_2(-hh + x)) + 1.0)) + \
-                  1.0/(sqrt(y*y + d_2(-hh + x))*sqrt(d_2(-hL - z)/(y*y + d_2(-hh + x)) + 1.0))) + \
-              W*(-1.0/(sqrt(y*y + d_2(hh + x))*sqrt(d_2(hL - z)/(y*y + d_2(hh + x)) + 1.0)) + \
-                 1.0/(sqrt(y*y + d_2(hh + x))*sqrt(d_2(-hL - z)/(y*y + d_2(hh + x)) + 1.0)))) + \
-             d_2(-W*(y*(-hL - z)/(pow(y*y + d_2(-hh + x), 1.5)*sqrt(d_2(-hL - z)/(y*y + d_2(-hh + x)) + 1.0)) - \
-                  y*(hL - z)/(pow(y*y + d_2(-hh + x), 3.0/2.0)*sqrt(d_2(hL - z)/(y*y + d_2(-hh + x)) + 1.0))) + \
-              W*(y*(-hL - z)/(pow(y*y + d_2(hh + x), 3.0/2.0)*sqrt(d_2(-hL - z)/(y*y + d_2(hh + x)) + 1.0)) - \
-                 y*(hL - z)/(pow(y*y + d_2(hh + x), 3.0/2.0)*sqrt(d_2(hL - z)/(y*y + d_2(hh + x)) + 1.0)))) + \
-                 d_2(W*(-(-hL - z)*(-hh - x)/(pow(y*y + d_2(hh + x), 3.0/2.0)*sqrt(d_2(-hL - z)/(y*y + d_2(hh + x)) + 1.0)) + \
-                     (hL - z)*(-hh - x)/(pow(y*y + d_2(hh + x), 1.5)*sqrt(d_2(hL - z)/(y*y + \
-            d_2(hh + x)) + 1.0))) - W*(-(-hL - z)*(hh - x)/(pow(y*y + d_2(-hh + x), 1.5)*sqrt(d_2(-hL - z)/(y*y + \
-            d_2(-hh + x)) + 1.0)) + (hL - z)*(hh - x)/(pow(y*y + d_2(-hh + x), 3.0/2.0)*sqrt(d_2(hL - z)/(y*y + d_2(-hh + x)) + 1.0)))));
-        return E;
-    }
+    in vec3 v_normal;
+    out vec4 color;
 
     void main() {
-        vec3 coord0, coord1;
-        float u, v, w;
-        float u1, v1, w1;
-        // vec3 n0 = dim0 * spacing0;
-        // vec3 n1 = dim1 * spacing1;
-        vec3 n0 = size0;
-        vec3 n1 = size1;
-        
-        float d = 500.0;
-        {
-            float z = rgba2f32(texture(sampler3, vec3((pos.y + 1.0) / 2.0, 0.5, 0.0))) * 2.0 - 1.0;
-            //vec3 m = vec3(pos.x, slc, z);
-            vec3 m = vec3(pos.x, slc, pos.y);
-            vec3 np0 = m / n0 * d;
-            vec3 scale = vec3(sc, 1.0, sc);
-            coord0 = project_c3(np0, vec3(pcx, 0.0, pcy)/n0*d, scale);
-        }
-        float lower = level - window / 2.0;
-        float upper = level + window / 2.0;
-
-        vec4 pixel0 = texture(sampler0, coord0);
-        float value0 = rgba2u16(pixel0);
-        float gray0 = clamp((value0 - lower) / window, 0.0, 1.0);
-        if (is_outbound(coord0)) {
-            gray0 = 0.0;
-        }
-
-        color = vec4(0.6, 0.1, 0.2, 1.0);
-        float upper1 = level1 + window1 / 2.0;
-        float lower1 = level1 - window1 / 2.0;
-        mat2x2 R = mat2x2(cos(theta), -sin(theta), sin(theta), cos(theta));
-        {
-            vec3 p = vec3((pos.x - pcx) * d / sc - npos.x, slc * d - npos.y, (pos.y - pcy) * d / sc - npos.z);
-            p = vec3(R * vec2(p.xy), p.z);
-            float value1 = calc_E(uah.x, uah.y, L, uah.z*2.0, p.x, p.y, p.z);
-            float gray1 = clamp((value1 - lower1) / window1, 0.0, 1.0);
-            if (is_outbound(coord1)) {
-                gray1 = 0.0;
-            }
-            vec4 scolor = texture(sampler2, vec3(gray1, 0.5, 0.0));
-            color = vec4((gray0 * (1.0 - k) + scolor.xyz * k), 1.0);
-            // color = vec4(1.0, 0.0, 0.0, 1.0);
+        // Phong shading, two sided with the light at the eye
+        vec3 l = -normalize(eye_dir);
+        vec3 n = normalize(v_normal);
+        if (dot(n, l) < 0.0) {
+            n = -n;
         }
+        float diffuse = max(dot(n, l), 0.0);
+        float specular = pow(max(dot(reflect(-l, n), l), 0.0), 32.0);
+        color = vec4(mesh_color.rgb * (0.25 + 0.75 * diffuse) + vec3(0.3) * specular, mesh_color.a);
     }"#;
\ No newline at end of file
@@ -32,6 +32,58 @@ pub struct Base {
     inv: Option<SMatrix<f32, 4, 4>>,
 }
 
+impl Base {
+    pub fn from_matrix(data: SMatrix<f32, 4, 4>) -> Base {
+        Base { data, inv: None }
+    }
+
+    pub fn identity() -> Base {
+        Base::from_matrix(SMatrix::<f32, 4, 4>::identity())
+    }
+
+    pub fn translation(x: f32, y: f32, z: f32) -> Base {
+        let mut m = SMatrix::<f32, 4, 4>::identity();
+        m[(0, 3)] = x;
+        m[(1, 3)] = y;
+        m[(2, 3)] = z;
+        Base::from_matrix(m)
+    }
+
+    pub fn matrix(&self) -> &SMatrix<f32, 4, 4> {
+        &self.data
+    }
+
+    pub fn inverse(&self) -> Option<Base> {
+        let inv = self.data.try_inverse()?;
+        Some(Base {
+            data: inv,
+            inv: Some(self.data),
+        })
+    }
+
+    // The transform applying `other` first and then `self`.
+    pub fn compose(&self, other: &Base) -> Base {
+        Base::from_matrix(self.data * other.data)
+    }
+
+    pub fn transform_point(&self, v: &[f32; 3]) -> [f32; 3] {
+        let p0 = SVector::<f32, 4>::from_row_slice(&[v[0], v[1], v[2], 1.]);
+        let p1 = self.data * p0;
+        [p1[0]/p1[3], p1[1]/p1[3], p1[2]/p1[3]]
+    }
+
+    pub fn from_column_major(data: &[f32; 16]) -> Base {
+        Base::from_matrix(SMatrix::<f32, 4, 4>::from_column_slice(data))
+    }
+
+    // In the column major order of the uniformMatrix4fv of WebGL.
+    pub fn to_column_major(&self) -> [f32; 16] {
+        let mut v = [0.0; 16];
+        v.copy_from_slice(self.data.as_slice());
+        v
+    }
+}
+
 #[cfg(not(target_family = "wasm"))]
 impl Base {
     pub fn from_row_slice(data: &[f32; 16]) -> Base {
@@ -108,4 +160,16 @@ mod test {
         let b = Base::from_row_slice(&[1.,2.,3.,4.,5.,6.,7.,8.,9.,10.,11.,12.,13.,14.,15.,16.]);
         println!("{:?}", b);
     }
+
+    #[test]
+    fn test_compose_and_inverse() {
+        let scale = Base::from_row_slice(&[2.,0.,0.,0.,0.,3.,0.,0.,0.,0.,4.,0.,0.,0.,0.,1.]);
+        let b = Base::translation(1., 2., 3.).compose(&scale);
+        assert_eq!(b.transform_point(&[1., 1., 1.]), [3., 5., 7.]);
+        let inv = b.inverse().unwrap();
+        assert_eq!(inv.transform_point(&[3., 5., 7.]), [1., 1., 1.]);
+        assert_eq!(b.to_column_major()[12..15], [1., 2., 3.]);
+        let c = Base::from_column_major(&b.to_column_major());
+        assert_eq!(c.transform_point(&[1., 1., 1.]), [3., 5., 7.]);
+    }
 }
@@ -29,8 +29,11 @@ use num::Num;
 use wasm_bindgen::prelude::*;
 
 use crate::techtron::{
-    core::grid::Grid2D,
-    graphic::{line::generate_line2d, marching_cubes::marching_cubes_impl, Point, lerp},
+    core::{base::Base, grid::Grid2D},
+    graphic::{
+        line::generate_line2d, marching_cubes::marching_cubes_impl,
+        marching_squares::iso_contours, Point, lerp,
+    },
 };
 
 use super::{
@@ -67,6 +70,14 @@ impl Contour2Df32 {
 }
 
 impl Contour2Df32 {
+    pub fn from_points(points: Vec<Point2D<f32>>) -> Contour2Df32 {
+        Contour2Df32 { data: points }
+    }
+
+    pub fn points(&self) -> &[Point2D<f32>] {
+        &self.data
+    }
+
     pub fn transform_points<F>(&self, f: F) -> Contour2Df32
     where
         F: Fn(&Point2D<f32>) -> Point2D<f32>,
@@ -182,10 +193,15 @@ impl Contour2Df32Builder {
 }
 
 #[wasm_bindgen]
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Structure {
-    contours: Vec<Contour2Df32>,
+    // the contours of each slice at z[i]
+    contours: Vec<Vec<Contour2Df32>>,
     z: Vec<f32>,
+    // ROI number, name and display color of the structure set
+    number: i32,
+    name: String,
+    color: [u8; 3],
 }
 
 // #[cfg(target_family = "wasm")]
@@ -195,16 +211,50 @@ impl Structure {
         Structure {
             contours: Vec::new(),
             z: Vec::new(),
+            number: 0,
+            name: String::new(),
+            color: [255, 0, 0],
         }
     }
 
     pub fn len(&self) -> usize {
-        self.contours.len()
+        self.contours.iter().map(|c| c.len()).sum()
+    }
+
+    pub fn get_number(&self) -> i32 {
+        self.number
+    }
+
+    pub fn set_number(&mut self, number: i32) {
+        self.number = number;
+    }
+
+    pub fn get_name(&self) -> String {
+        self.name.clone()
+    }
+
+    pub fn set_name(&mut self, name: String) {
+        self.name = name;
+    }
+
+    // [r, g, b]
+    pub fn get_color(&self) -> Vec<u8> {
+        self.color.to_vec()
+    }
+
+    pub fn set_color(&mut self, r: u8, g: u8, b: u8) {
+        self.color = [r, g, b];
     }
 
+    // Onto the slice at `z` if there is one, as a new slice otherwise.
     pub fn push(&mut self, z: f32, contour: Contour2Df32) {
-        self.z.push(z);
-        self.contours.push(contour);
+        match self.z.iter().position(|&v| approx::abs_diff_eq!(v, z)) {
+            Some(i) => self.contours[i].push(contour),
+            None => {
+                self.z.push(z);
+                self.contours.push(vec![contour]);
+            }
+        }
     }
 
     #[cfg(target_family = "wasm")]
@@ -221,6 +271,10 @@ impl Structure {
     }
 
     pub fn to_mesh(&self) -> Surface {
+        // nothing to stitch on a single slice
+        if self.z.len() < 2 {
+            return Surface::new();
+        }
         let [xmin, xmax, ymin, ymax, zmin, zmax] = self.bounding_box();
         // const W: usize = 250;
         // const H: usize = 250;
@@ -271,25 +325,47 @@ impl Structure {
 }
 
 impl Structure {
+    // Contour the region of a scalar field above `isovalue` slice by slice.
+    // `to_patient` maps the voxel indices to the patient coordinates, the
+    // slices are expected to be transverse.
+    pub fn from_field(data: &[f32], dim: (i32, i32, i32), to_patient: &Base, isovalue: f32) -> Structure {
+        let mut structure = Structure::new();
+        let slice_len = (dim.0 * dim.1) as usize;
+        for k in 0..dim.2 as usize {
+            let slice = &data[k * slice_len..(k + 1) * slice_len];
+            for points in iso_contours(isovalue, slice, dim.0, dim.1) {
+                if points.len() < 3 {
+                    continue;
+                }
+                let points: Vec<[f32; 3]> = points
+                    .iter()
+                    .map(|p| to_patient.transform_point(&[p[0], p[1], k as f32]))
+                    .collect();
+                let z = points[0][2];
+                let points = points.iter().map(|p| Point2D::<f32>::new([p[0], p[1]])).collect();
+                structure.push(z, Contour2Df32::from_points(points));
+            }
+        }
+        structure
+    }
+
+    // (z, contour) slice by slice, in the order they are pushed.
+    pub fn iter(&self) -> impl Iterator<Item = (f32, &Contour2Df32)> {
+        self.z
+            .iter()
+            .copied()
+            .zip(self.contours.iter())
+            .flat_map(|(z, contours)| contours.iter().map(move |c| (z, c)))
+    }
+
     #[cfg(not(target_family = "wasm"))]
     pub fn get_contours_at<'a>(&'a self, z: f32) -> Option<Vec<&'a Contour2Df32>> {
         self.get_contours_at_impl(z)
     }
 
     fn get_contours_at_impl<'a>(&'a self, z: f32) -> Option<Vec<&'a Contour2Df32>> {
-        let mut ret: Vec<&Contour2Df32> = Vec::new();
-        let it = self
-            .z
-            .iter()
-            .enumerate()
-            .filter(|(i, &v)| approx::abs_diff_eq!(v, z))
-            .for_each(|(i, v)| {
-                ret.push(&self.contours[i]);
-            });
-        if ret.is_empty() {
-            return None;
-        }
-        Some(ret)
+        let i = self.z.iter().position(|&v| approx::abs_diff_eq!(v, z))?;
+        Some(self.contours[i].iter().collect())
     }
 
     // return value: [xmin, xmax, ymin, ymax, zmin, zmax]
@@ -341,10 +417,61 @@ impl Structure {
 }
 
 #[wasm_bindgen]
-struct StructureSet {
+#[derive(Debug, Clone, Default)]
+pub struct StructureSet {
+    frame_of_reference_uid: String,
     structures: Vec<Structure>,
 }
 
+#[wasm_bindgen]
+impl StructureSet {
+    pub fn new() -> StructureSet {
+        StructureSet {
+            frame_of_reference_uid: String::new(),
+            structures: Vec::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.structures.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.structures.is_empty()
+    }
+
+    pub fn get_frame_of_reference_uid(&self) -> String {
+        self.frame_of_reference_uid.clone()
+    }
+
+    pub fn set_frame_of_reference_uid(&mut self, uid: String) {
+        self.frame_of_reference_uid = uid;
+    }
+
+    pub fn push(&mut self, structure: Structure) {
+        self.structures.push(structure);
+    }
+
+    // A copy of the index-th structure, for JS.
+    pub fn get_structure(&self, index: usize) -> Option<Structure> {
+        self.structures.get(index).cloned()
+    }
+}
+
+impl StructureSet {
+    pub fn structures(&self) -> &[Structure] {
+        &self.structures
+    }
+
+    pub fn find_by_number(&self, number: i32) -> Option<&Structure> {
+        self.structures.iter().find(|s| s.number == number)
+    }
+
+    pub fn find_by_name(&self, name: &str) -> Option<&Structure> {
+        self.structures.iter().find(|s| s.name == name)
+    }
+}
+
 pub fn fill_contour(mut accumulator: Grid2D<u8>, contour: &Contour2Df32) -> Grid2D<u8> {
     let [width, height] = accumulator.dim();
     let first_point = [*contour.data.first().unwrap()];
@@ -512,17 +639,63 @@ mod tests {
         let contour = cb.close();
 
         s.push(1.24, contour);
+        // a single slice of both
+        assert_eq!(s.len(), 2);
+        assert_eq!(s.get_contours_at(1.24).unwrap().len(), 2);
+        assert!(s.iter().all(|(z, _)| z == 1.24));
+        assert!(s.to_mesh().is_empty());
+
+        let mut cb = Contour2Df32Builder::new();
+        cb.push(2., 10.);
+        cb.push(13., 0.);
+        cb.push(20., 5.);
+        cb.push(45., 20.);
+        cb.push(10., 9.);
+        let contour = cb.close();
+        s.push(0.24, contour);
 
-        // let mut cb= Contour2Df32Builder::new();
-        // cb.push(2., 10.);
-        // cb.push(13., 0.);
-        // cb.push(20., 5.);
-        // cb.push(45., 20.);
-        // cb.push(10., 9.);
-        // let contour = cb.close();
-        // s.push(0.24, contour);
+        let mesh = s.to_mesh();
+        assert!(!mesh.is_empty());
+        let z: Vec<f32> = mesh.vertex_buffer().chunks(3).map(|p| p[2]).collect();
+        assert!(z.iter().all(|z| (0.24..=1.24).contains(z)));
+    }
 
-        s.to_mesh();
+    #[test]
+    fn test_from_field() {
+        // a 3x2 block in the first slice and nothing in the second
+        let (w, h) = (6, 5);
+        let mut data = vec![0.0f32; w * h * 2];
+        for y in 1..3 {
+            for x in 2..5 {
+                data[y * w + x] = 10.0;
+            }
+        }
+        let to_patient = Base::from_row_slice(&[
+            2.0, 0.0, 0.0, -5.0,
+            0.0, 1.0, 0.0, 3.0,
+            0.0, 0.0, 2.0, -1.0,
+            0.0, 0.0, 0.0, 1.0,
+        ]);
+        let s = Structure::from_field(&data, (w as i32, h as i32, 2), &to_patient, 5.0);
+        assert_eq!(s.len(), 1);
+        assert!(s.get_contours_at(1.0).is_none());
+        let (z, contour) = s.iter().next().unwrap();
+        assert_eq!(z, -1.0);
+        assert_eq!(contour.points().len(), 10);
+        let [xmin, xmax, ymin, ymax, _, _] = s.bounding_box();
+        assert_eq!((xmin, xmax), (-5.0 + 1.5 * 2.0, -5.0 + 4.5 * 2.0));
+        assert_eq!((ymin, ymax), (3.0 + 0.5, 3.0 + 2.5));
+        // the vertices are interpolated between the voxels
+        let s = Structure::from_field(&data, (w as i32, h as i32, 2), &to_patient, 7.5);
+        let [xmin, xmax, ymin, ymax, _, _] = s.bounding_box();
+        assert_eq!((xmin, xmax), (-5.0 + 1.75 * 2.0, -5.0 + 4.25 * 2.0));
+        assert_eq!((ymin, ymax), (3.0 + 0.75, 3.0 + 2.25));
+
+        // the region touching the border is closed as well
+        let data = vec![10.0f32; 4 * 3];
+        let s = Structure::from_field(&data, (4, 3, 1), &Base::identity(), 5.0);
+        assert_eq!(s.len(), 1);
+        assert_eq!(s.iter().next().unwrap().1.points().len(), 14);
     }
 
     #[test]
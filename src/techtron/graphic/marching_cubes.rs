@@ -71,6 +71,28 @@ impl Surface {
         self.vertex = vertex;
     }
 
+    pub fn len(&self) -> usize {
+        self.index.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.index.is_empty()
+    }
+
+    // The indexed buffers for drawing: x, y, z of every vertex, the normals
+    // likewise and 3 vertex indices per triangle.
+    pub fn vertex_buffer(&self) -> Vec<f32> {
+        self.vertex.iter().flatten().copied().collect()
+    }
+
+    pub fn normal_buffer(&self) -> Vec<f32> {
+        self.normal.iter().flatten().copied().collect()
+    }
+
+    pub fn index_buffer(&self) -> Vec<u32> {
+        self.index.iter().flatten().map(|i| *i as u32).collect()
+    }
+
     // pub fn get_vertex(&mut self) -> &mut Vec<Point3D> {
     //     &mut self.vertex
     // }
@@ -1591,6 +1613,25 @@ mod test {
         ];
         assert_eq!(index, surface.index);
     }
+
+    #[test]
+    fn test_buffers() {
+        let mut data = vec![0.0f32; 27];
+        data[13] = 1.0;
+        let surface = marching_cubes_impl(&data, 0.5, 3, 3, 3);
+        assert!(!surface.is_empty());
+        let vertices = surface.vertex_buffer();
+        let normals = surface.normal_buffer();
+        let indices = surface.index_buffer();
+        assert_eq!(vertices.len(), surface.vertex.len() * 3);
+        assert_eq!(normals.len(), vertices.len());
+        assert_eq!(indices.len(), surface.len() * 3);
+        assert!(indices.iter().all(|i| (*i as usize) < surface.vertex.len()));
+        // the surface encloses the center voxel
+        for v in vertices.chunks_exact(3) {
+            assert!(v.iter().all(|x| (x - 1.0).abs() <= 0.5 + 1e-6));
+        }
+    }
 }
 
 // WASM
@@ -30,6 +30,8 @@ use js_sys::{Array, ArrayBuffer, Float64Array};
 #[cfg(target_family = "wasm")]
 use wasm_bindgen::JsValue;
 
+use std::collections::HashMap;
+
 use super::Point;
 
 use log::info;
@@ -100,7 +102,6 @@ where
                 let a = lines[i];
                 if a == -1 { break; }
                 let b = lines[i + 1];
-                info!("a: {}, b: {}", a, b);
                 let p0 = adjust_coord::<T>(a, Into::<T>::into(x as f32), Into::<T>::into(y as f32));
                 let p1 = adjust_coord::<T>(b, Into::<T>::into(x as f32), Into::<T>::into(y as f32));
                 line_segments.push([p0, p1]);
@@ -177,6 +178,108 @@ pub fn marching_squares(
     marching_squares_impl::<f64>(isovalue, data, width, height)
 }
 
+// Iso lines at `isovalue` with the end points interpolated linearly along the
+// edges of the cells, in pixel coordinates. Unlike marching_squares_impl all
+// the cells are visited.
+pub fn iso_segments(isovalue: f32, data: &[f32], width: i32, height: i32) -> Vec<[Point2D<f32>; 2]> {
+    iso_edge_segments(isovalue, data, width, height)
+        .into_iter()
+        .map(|[a, b]| [a.1, b.1])
+        .collect()
+}
+
+// An edge of the pixel grid by its two corners, the smaller one first.
+type Edge = ((i32, i32), (i32, i32));
+
+// The segments of iso_segments with the edge each end point is on.
+fn iso_edge_segments(isovalue: f32, data: &[f32], width: i32, height: i32) -> Vec<[(Edge, Point2D<f32>); 2]> {
+    let mut segments = Vec::new();
+    for y in 0..height - 1 {
+        for x in 0..width - 1 {
+            // the corners in the order of MARCHING_SQUARES_LUT
+            let corners = [(x, y + 1), (x + 1, y + 1), (x + 1, y), (x, y)];
+            let values = corners.map(|(cx, cy)| data[(cy * width + cx) as usize]);
+            let idx = (0..4).filter(|i| values[*i] > isovalue).fold(0, |acc, i| acc | 1 << i);
+            let point = |edge: i8| {
+                let (a, b) = match edge {
+                    0 => (0, 3),
+                    1 => (0, 1),
+                    2 => (1, 2),
+                    _ => (2, 3),
+                };
+                let t = ((isovalue - values[a]) / (values[b] - values[a])).clamp(0.0, 1.0);
+                let ((xa, ya), (xb, yb)) = (corners[a], corners[b]);
+                let edge = (corners[a].min(corners[b]), corners[a].max(corners[b]));
+                (edge, [xa as f32 + (xb - xa) as f32 * t, ya as f32 + (yb - ya) as f32 * t])
+            };
+            for pair in MARCHING_SQUARES_LUT[idx].chunks_exact(2) {
+                if pair[0] == -1 {
+                    break;
+                }
+                segments.push([point(pair[0]), point(pair[1])]);
+            }
+        }
+    }
+    segments
+}
+
+// Closed iso lines of the region above `isovalue`, in pixel coordinates, with
+// the vertices interpolated as in iso_segments. The line segments are chained
+// into polygons through the edges they end on. The image is padded so that
+// the region touching the border is closed as well, on the border pixels.
+pub fn iso_contours(isovalue: f32, data: &[f32], width: i32, height: i32) -> Vec<Vec<Point2D<f32>>> {
+    let (w, h) = (width as usize, height as usize);
+    let (pw, ph) = (w + 2, h + 2);
+    let mut padded = vec![f32::MIN; pw * ph];
+    for y in 0..h {
+        padded[(y + 1) * pw + 1..(y + 1) * pw + 1 + w].copy_from_slice(&data[y * w..(y + 1) * w]);
+    }
+    let segments = iso_edge_segments(isovalue, &padded, pw as i32, ph as i32);
+
+    // an edge is shared by the segments of the two cells beside it
+    let mut ends: HashMap<Edge, Vec<usize>> = HashMap::new();
+    for (i, s) in segments.iter().enumerate() {
+        ends.entry(s[0].0).or_default().push(i);
+        ends.entry(s[1].0).or_default().push(i);
+    }
+
+    let mut used = vec![false; segments.len()];
+    let mut contours = Vec::new();
+    for i in 0..segments.len() {
+        if used[i] {
+            continue;
+        }
+        used[i] = true;
+        let start = segments[i][0].0;
+        let mut contour = vec![segments[i][0].1, segments[i][1].1];
+        let mut current = segments[i][1];
+        loop {
+            let next = ends[&current.0].iter().copied().find(|j| !used[*j]);
+            let j = match next {
+                Some(j) => j,
+                None => break,
+            };
+            used[j] = true;
+            current = if segments[j][0].0 == current.0 {
+                segments[j][1]
+            } else {
+                segments[j][0]
+            };
+            if current.0 == start {
+                break;
+            }
+            contour.push(current.1);
+        }
+        contours.push(
+            contour
+                .into_iter()
+                .map(|p| [p[0] - 1.0, p[1] - 1.0])
+                .collect(),
+        );
+    }
+    contours
+}
+
 // #[cfg(target_family = "wasm")]
 #[wasm_bindgen]
 pub struct Slice {
@@ -225,3 +328,43 @@ impl Slice {
 // fn sample_grid() {
 
 // }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_iso_segments() {
+        let (w, h): (usize, usize) = (40, 30);
+        let data: Vec<f32> = (0..w * h)
+            .map(|i| {
+                let (x, y) = ((i % w) as f32 - 20.0, (i / w) as f32 - 15.0);
+                (x * x + y * y).sqrt()
+            })
+            .collect();
+        let segments = iso_segments(10.0, &data, w as i32, h as i32);
+        assert!(segments.len() > 40);
+        for p in segments.iter().flatten() {
+            let r = ((p[0] - 20.0).powi(2) + (p[1] - 15.0).powi(2)).sqrt();
+            assert!((r - 10.0).abs() < 0.1, "{:?} is {} from the center", p, r);
+        }
+    }
+
+    #[test]
+    fn test_iso_contours() {
+        let (w, h): (usize, usize) = (40, 30);
+        let data: Vec<f32> = (0..w * h)
+            .map(|i| {
+                let (x, y) = ((i % w) as f32 - 20.0, (i / w) as f32 - 15.0);
+                20.0 - (x * x + y * y).sqrt()
+            })
+            .collect();
+        let contours = iso_contours(10.0, &data, w as i32, h as i32);
+        assert_eq!(contours.len(), 1);
+        assert!(contours[0].len() > 40);
+        for p in &contours[0] {
+            let r = ((p[0] - 20.0).powi(2) + (p[1] - 15.0).powi(2)).sqrt();
+            assert!((r - 10.0).abs() < 0.1, "{:?} is {} from the center", p, r);
+        }
+    }
+}
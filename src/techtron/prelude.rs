@@ -26,6 +26,7 @@ pub use super::webgl::shader::*;
 pub use super::webgl::program::*;
 pub use super::webgl::texture::*;
 pub use super::webgl::geometry::*;
+pub use super::webgl::framebuffer::*;
 pub use super::graphic::marching_squares::*;
 pub use super::math::*;
 pub use super::graphic::*;
\ No newline at end of file
@@ -25,4 +25,5 @@ pub mod context;
 pub mod texture;
 pub mod shader;
 pub mod program;
-pub mod geometry;
\ No newline at end of file
+pub mod geometry;
+pub mod framebuffer;
\ No newline at end of file
@@ -0,0 +1,141 @@
+// MIT License
+
+// Copyright (c) 2023 Techtron-Lab
+
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+
+use std::ops::Deref;
+
+use web_sys::{WebGl2RenderingContext, WebGlFramebuffer, WebGlRenderbuffer};
+
+use crate::techtron::webgl::context::GLContext;
+
+type GL2 = WebGl2RenderingContext;
+
+// An offscreen framebuffer with RGBA8 color and a depth buffer, to draw the
+// views at another resolution than the canvas.
+#[derive(Debug)]
+pub struct RenderTarget {
+    pub context: GLContext,
+    pub handle: WebGlFramebuffer,
+    pub color: WebGlRenderbuffer,
+    pub depth: WebGlRenderbuffer,
+    pub width: i32,
+    pub height: i32,
+}
+
+impl RenderTarget {
+    pub fn new(context: &GLContext, width: i32, height: i32) -> Result<RenderTarget, String> {
+        let max = context
+            .get_parameter(GL2::MAX_RENDERBUFFER_SIZE)
+            .ok()
+            .and_then(|v| v.as_f64())
+            .unwrap_or(4096.0) as i32;
+        if width <= 0 || height <= 0 || width > max || height > max {
+            return Err(format!("the size of a render target shall be in between 1 and {}.", max));
+        }
+        let handle = context
+            .create_framebuffer()
+            .ok_or("failed to create framebuffer")?;
+        let color = context
+            .create_renderbuffer()
+            .ok_or("failed to create renderbuffer")?;
+        let depth = context
+            .create_renderbuffer()
+            .ok_or("failed to create renderbuffer")?;
+        // deleted by drop if incomplete
+        let target = RenderTarget {
+            context: context.clone(),
+            handle,
+            color,
+            depth,
+            width,
+            height,
+        };
+
+        context.bind_renderbuffer(GL2::RENDERBUFFER, Some(&target.color));
+        context.renderbuffer_storage(GL2::RENDERBUFFER, GL2::RGBA8, width, height);
+        context.bind_renderbuffer(GL2::RENDERBUFFER, Some(&target.depth));
+        context.renderbuffer_storage(GL2::RENDERBUFFER, GL2::DEPTH_COMPONENT16, width, height);
+        context.bind_renderbuffer(GL2::RENDERBUFFER, None);
+
+        context.bind_framebuffer(GL2::FRAMEBUFFER, Some(&target.handle));
+        context.framebuffer_renderbuffer(
+            GL2::FRAMEBUFFER,
+            GL2::COLOR_ATTACHMENT0,
+            GL2::RENDERBUFFER,
+            Some(&target.color),
+        );
+        context.framebuffer_renderbuffer(
+            GL2::FRAMEBUFFER,
+            GL2::DEPTH_ATTACHMENT,
+            GL2::RENDERBUFFER,
+            Some(&target.depth),
+        );
+        let status = context.check_framebuffer_status(GL2::FRAMEBUFFER);
+        context.bind_framebuffer(GL2::FRAMEBUFFER, None);
+        if status != GL2::FRAMEBUFFER_COMPLETE {
+            return Err(format!("the framebuffer is incomplete: {:#x}", status));
+        }
+        Ok(target)
+    }
+
+    // Draw into the target instead of the canvas until unbind.
+    pub fn bind(&self) {
+        self.context.bind_framebuffer(GL2::FRAMEBUFFER, Some(&self.handle));
+    }
+
+    pub fn unbind(&self) {
+        self.context.bind_framebuffer(GL2::FRAMEBUFFER, None);
+    }
+
+    // The RGBA pixels with the rows from the bottom.
+    pub fn read_pixels(&self) -> Result<Vec<u8>, String> {
+        let mut pixels = vec![0u8; (self.width * self.height * 4) as usize];
+        self.bind();
+        let result = self.context.read_pixels_with_opt_u8_array(
+            0,
+            0,
+            self.width,
+            self.height,
+            GL2::RGBA,
+            GL2::UNSIGNED_BYTE,
+            Some(&mut pixels),
+        );
+        self.unbind();
+        result.map_err(|err| format!("failed to read the pixels: {:?}", err))?;
+        Ok(pixels)
+    }
+}
+
+impl Drop for RenderTarget {
+    fn drop(&mut self) {
+        self.context.delete_framebuffer(Some(&self.handle));
+        self.context.delete_renderbuffer(Some(&self.color));
+        self.context.delete_renderbuffer(Some(&self.depth));
+    }
+}
+
+impl Deref for RenderTarget {
+    type Target = WebGlFramebuffer;
+    fn deref(&self) -> &Self::Target {
+        &self.handle
+    }
+}
@@ -23,7 +23,7 @@
 
 use std::ops::Deref;
 
-use web_sys::{WebGl2RenderingContext, WebGlBuffer};
+use web_sys::{WebGl2RenderingContext, WebGlBuffer, WebGlVertexArrayObject};
 
 use crate::techtron::webgl::context::GLContext;
 
@@ -50,7 +50,7 @@ impl LoadBuffer for VertexBuffer {
             .create_buffer()
             .ok_or("failed to create vertices buffer")?;
         context.bind_buffer(GL2::ARRAY_BUFFER, Some(&handle));
-        let vert_array = unsafe { js_sys::Float32Array::view(&self.buffer.as_slice()) };
+        let vert_array = unsafe { js_sys::Float32Array::view(self.buffer.as_slice()) };
 
         // context.vertex_attrib_pointer_with_i32(0, 3, GL2::FLOAT, false, 0, 0);
         // context.enable_vertex_attrib_array(0);
@@ -110,7 +110,7 @@ impl LoadBuffer for IndexBuffer {
             .create_buffer()
             .ok_or("failed to create vertices buffer")?;
         context.bind_buffer(GL2::ELEMENT_ARRAY_BUFFER, Some(&handle));
-        let indices_array = unsafe { js_sys::Uint16Array::view(&self.buffer.as_slice()) };
+        let indices_array = unsafe { js_sys::Uint16Array::view(self.buffer.as_slice()) };
 
         context.buffer_data_with_array_buffer_view(
             GL2::ELEMENT_ARRAY_BUFFER,
@@ -157,3 +157,78 @@ impl Geometry {
         self.context.enable_vertex_attrib_array(0);
     }
 }
+
+// Triangles with per vertex normals in a vertex array object, the positions
+// at attribute 0 and the normals at attribute 1.
+pub struct MeshGeometry {
+    pub context: GLContext,
+    pub vao: WebGlVertexArrayObject,
+    pub vbuf: LoadedVertexBuffer,
+    pub nbuf: LoadedVertexBuffer,
+    pub ibuf: WebGlBuffer,
+    pub num_of_indices: i32,
+}
+
+impl MeshGeometry {
+    pub fn new(
+        context: &GLContext,
+        vertices: Vec<f32>,
+        normals: Vec<f32>,
+        indices: Vec<u32>,
+    ) -> Result<MeshGeometry, String> {
+        if vertices.len() != normals.len() || !vertices.chunks_exact(3).remainder().is_empty() {
+            return Err(String::from("every vertex needs 3 coordinates and a normal."));
+        }
+        let num_of_vertices = (vertices.len() / 3) as u32;
+        if indices.iter().any(|i| *i >= num_of_vertices) {
+            return Err(String::from("mesh index out of range."));
+        }
+        let vao = context
+            .create_vertex_array()
+            .ok_or("failed to create vertex array")?;
+        context.bind_vertex_array(Some(&vao));
+
+        let vbuf = VertexBuffer::new(vertices).load_buffer(context)?;
+        context.vertex_attrib_pointer_with_i32(0, 3, GL2::FLOAT, false, 0, 0);
+        context.enable_vertex_attrib_array(0);
+        let nbuf = VertexBuffer::new(normals).load_buffer(context)?;
+        context.vertex_attrib_pointer_with_i32(1, 3, GL2::FLOAT, false, 0, 0);
+        context.enable_vertex_attrib_array(1);
+
+        // the indices of large meshes do not fit in u16
+        let ibuf = context
+            .create_buffer()
+            .ok_or("failed to create indices buffer")?;
+        context.bind_buffer(GL2::ELEMENT_ARRAY_BUFFER, Some(&ibuf));
+        let indices_array = unsafe { js_sys::Uint32Array::view(indices.as_slice()) };
+        context.buffer_data_with_array_buffer_view(
+            GL2::ELEMENT_ARRAY_BUFFER,
+            &indices_array,
+            GL2::STATIC_DRAW,
+        );
+        context.bind_vertex_array(None);
+
+        Ok(MeshGeometry {
+            context: context.clone(),
+            vao,
+            vbuf,
+            nbuf,
+            ibuf,
+            num_of_indices: indices.len() as i32,
+        })
+    }
+
+    pub fn draw(&self) {
+        self.context.bind_vertex_array(Some(&self.vao));
+        self.context
+            .draw_elements_with_i32(GL2::TRIANGLES, self.num_of_indices, GL2::UNSIGNED_INT, 0);
+        self.context.bind_vertex_array(None);
+    }
+}
+
+impl Drop for MeshGeometry {
+    fn drop(&mut self) {
+        self.context.delete_buffer(Some(&self.ibuf));
+        self.context.delete_vertex_array(Some(&self.vao));
+    }
+}
@@ -22,13 +22,14 @@
 
 
 use std::{
+    collections::{HashMap, HashSet},
     ops::{Deref, DerefMut},
     rc::Rc,
 };
 
 use js_sys::{Array, ArrayBuffer, Float32Array};
 use wasm_bindgen::{JsCast, JsValue};
-use web_sys::{WebGl2RenderingContext, WebGlProgram, WebGlShader};
+use web_sys::{WebGl2RenderingContext, WebGlBuffer, WebGlProgram, WebGlShader, WebGlUniformLocation};
 
 use crate::techtron::webgl::texture::LoadedTexture;
 
@@ -39,12 +40,25 @@ use crate::console;
 
 type GL2 = WebGl2RenderingContext;
 
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UniformInfo {
+    pub location: WebGlUniformLocation,
+    // GL type, e.g. FLOAT_VEC3, and the length of arrays
+    pub ty: u32,
+    pub size: i32,
+}
+
 #[derive(Debug, PartialEq, Eq)]
 pub struct Program {
     pub vertex: CompiledVertexShader,
     pub fragment: CompiledFragmentShader,
     pub handle: WebGlProgram,
     pub context: GLContext,
+    // active uniforms by name, arrays without "[0]", reflected at link time
+    pub uniforms: HashMap<String, UniformInfo>,
+    // uniforms and uniform blocks declared in the sources, including those
+    // the compiler has removed as unused
+    pub declared: HashSet<String>,
 }
 
 impl Program {
@@ -57,12 +71,19 @@ impl Program {
             todo!()
         }
         let context = context.clone();
-        let handle = link_program(&context, &*vertex, &*fragment).unwrap();
+        let handle = link_program(&context, &vertex, &fragment).unwrap();
+        let uniforms = active_uniforms(&context, &handle);
+        let declared = declared_uniforms(&vertex.source.0)
+            .into_iter()
+            .chain(declared_uniforms(&fragment.source.0))
+            .collect();
         Program {
             vertex,
             fragment,
             context,
             handle,
+            uniforms,
+            declared,
         }
     }
 
@@ -73,8 +94,8 @@ impl Program {
 
 impl Drop for Program {
     fn drop(&mut self) {
-        self.context.detach_shader(&self, &self.vertex);
-        self.context.detach_shader(&self, &self.fragment);
+        self.context.detach_shader(self, &self.vertex);
+        self.context.detach_shader(self, &self.fragment);
         self.context.delete_program(Some(&self.handle))
     }
 }
@@ -118,22 +139,158 @@ fn link_program(
     }
 }
 
+fn active_uniforms(context: &WebGl2RenderingContext, program: &WebGlProgram) -> HashMap<String, UniformInfo> {
+    let count = context
+        .get_program_parameter(program, GL2::ACTIVE_UNIFORMS)
+        .as_f64()
+        .unwrap_or(0.0) as u32;
+    let mut uniforms = HashMap::new();
+    for i in 0..count {
+        let info = match context.get_active_uniform(program, i) {
+            Some(info) => info,
+            None => continue,
+        };
+        // the members of uniform blocks have no location
+        if let Some(location) = context.get_uniform_location(program, &info.name()) {
+            let name = info.name().trim_end_matches("[0]").to_string();
+            uniforms.insert(name, UniformInfo { location, ty: info.type_(), size: info.size() });
+        }
+    }
+    uniforms
+}
+
+// Names of the uniforms and the uniform blocks declared in a GLSL source.
+fn declared_uniforms(source: &str) -> Vec<String> {
+    let mut names = Vec::new();
+    for line in source.lines() {
+        let line = line.split("//").next().unwrap_or("");
+        let rest: Vec<&str> = line
+            .split_whitespace()
+            .skip_while(|t| *t != "uniform")
+            .skip(1)
+            .collect();
+        if rest.is_empty() {
+            continue;
+        }
+        let decl = rest.join(" ");
+        // a uniform block, with its members in braces
+        if let Some((block, _)) = decl.split_once('{') {
+            names.push(block.trim().to_string());
+            continue;
+        }
+        if !decl.contains(';') {
+            names.push(decl.trim().to_string());
+            continue;
+        }
+        let decl = decl.split(';').next().unwrap_or("");
+        // skip the precision qualifier and the type
+        let decl = decl
+            .split_whitespace()
+            .skip_while(|t| matches!(*t, "lowp" | "mediump" | "highp"))
+            .skip(1)
+            .collect::<Vec<_>>()
+            .join(" ");
+        for name in decl.split(',') {
+            let name = name.split('[').next().unwrap_or("").trim();
+            if !name.is_empty() {
+                names.push(name.to_string());
+            }
+        }
+    }
+    names
+}
+
+fn type_name(ty: u32) -> &'static str {
+    match ty {
+        GL2::FLOAT => "float",
+        GL2::FLOAT_VEC2 => "vec2",
+        GL2::FLOAT_VEC3 => "vec3",
+        GL2::FLOAT_VEC4 => "vec4",
+        GL2::INT => "int",
+        GL2::INT_VEC2 => "ivec2",
+        GL2::INT_VEC3 => "ivec3",
+        GL2::INT_VEC4 => "ivec4",
+        GL2::UNSIGNED_INT => "uint",
+        GL2::BOOL => "bool",
+        GL2::FLOAT_MAT2 => "mat2",
+        GL2::FLOAT_MAT3 => "mat3",
+        GL2::FLOAT_MAT4 => "mat4",
+        GL2::SAMPLER_2D => "sampler2D",
+        GL2::SAMPLER_3D => "sampler3D",
+        GL2::INT_SAMPLER_3D => "isampler3D",
+        GL2::UNSIGNED_INT_SAMPLER_3D => "usampler3D",
+        _ => "unknown type",
+    }
+}
+
+const SAMPLER_TYPES: [u32; 4] = [
+    GL2::SAMPLER_2D,
+    GL2::SAMPLER_3D,
+    GL2::INT_SAMPLER_3D,
+    GL2::UNSIGNED_INT_SAMPLER_3D,
+];
+
 impl Program {
+    // The cached location of an active uniform of one of `types`. None if the
+    // uniform is declared but unused, as setting it has no effect.
+    pub fn uniform_location(&self, name: &str, types: &[u32]) -> Result<Option<&WebGlUniformLocation>, String> {
+        match self.uniforms.get(name) {
+            Some(u) if types.contains(&u.ty) => Ok(Some(&u.location)),
+            Some(u) => Err(format!(
+                "uniform {} is a {}, not a {}.",
+                name,
+                type_name(u.ty),
+                type_name(types[0])
+            )),
+            None if self.declared.contains(name) => Ok(None),
+            None => Err(format!("uniform {} is not declared in the shaders.", name)),
+        }
+    }
+
+    // As uniform_location for arrays of `components` values each.
+    fn array_location<T>(
+        &self,
+        name: &str,
+        types: &[u32],
+        data: &[T],
+        components: usize,
+    ) -> Result<Option<&WebGlUniformLocation>, String> {
+        let len = data.len();
+        if len == 0 || !data.chunks_exact(components).remainder().is_empty() {
+            return Err(format!(
+                "uniform {} expects a multiple of {} values, got {}.",
+                name, components, len
+            ));
+        }
+        let location = self.uniform_location(name, types)?;
+        if let Some(u) = self.uniforms.get(name) {
+            if len / components > u.size as usize {
+                return Err(format!(
+                    "uniform {} has {} elements, got {}.",
+                    name,
+                    u.size,
+                    len / components
+                ));
+            }
+        }
+        Ok(location)
+    }
+
     pub fn set_uniform1i(&self, uniform_name: &str, value: i32) -> Result<(), String> {
-        let gl = &self.context;
-        let u_var = gl.get_uniform_location(&self, uniform_name);
-        gl.uniform1i(u_var.as_ref(), value);
+        let types = [&[GL2::INT, GL2::BOOL][..], &SAMPLER_TYPES[..]].concat();
+        let u_var = self.uniform_location(uniform_name, &types)?;
+        self.context.uniform1i(u_var, value);
         Ok(())
     }
 
     pub fn get_uniform1i(&self, uniform_name: &str) -> Result<i32, String> {
         let gl = &self.context;
-        let u_var = gl.get_uniform_location(&self, uniform_name).ok_or(format!(
-            "Cannot retrieve uniform:{} location.",
-            uniform_name
-        ))?;
+        let u_var = self
+            .uniforms
+            .get(uniform_name)
+            .ok_or(format!("Cannot retrieve uniform:{} location.", uniform_name))?;
         let value: i32 = gl
-            .get_uniform(&self, u_var.as_ref())
+            .get_uniform(self, &u_var.location)
             .as_f64()
             .ok_or(format!(
                 "Cannot retrieve uniform: {} as a float.",
@@ -142,21 +299,26 @@ impl Program {
         Ok(value)
     }
 
+    pub fn set_uniform1iv(&self, u_name: &str, data: &[i32]) -> Result<(), String> {
+        let u_var = self.array_location(u_name, &[GL2::INT, GL2::BOOL], data, 1)?;
+        self.context.uniform1iv_with_i32_array(u_var, data);
+        Ok(())
+    }
+
     pub fn set_uniform1f(&self, uniform_name: &str, value: f32) -> Result<(), String> {
-        let gl = &self.context;
-        let u_var = gl.get_uniform_location(&self, uniform_name);
-        gl.uniform1f(u_var.as_ref(), value);
+        let u_var = self.uniform_location(uniform_name, &[GL2::FLOAT])?;
+        self.context.uniform1f(u_var, value);
         Ok(())
     }
 
     pub fn get_uniform1f(&self, uniform_name: &str) -> Result<f32, String> {
         let gl = &self.context;
-        let u_var = gl.get_uniform_location(&self, uniform_name).ok_or(format!(
-            "Cannot retrieve uniform:{} location.",
-            uniform_name
-        ))?;
+        let u_var = self
+            .uniforms
+            .get(uniform_name)
+            .ok_or(format!("Cannot retrieve uniform:{} location.", uniform_name))?;
         let value: f32 = gl
-            .get_uniform(&self, u_var.as_ref())
+            .get_uniform(self, &u_var.location)
             .as_f64()
             .ok_or(format!(
                 "Cannot retrieve uniform: {} as a float.",
@@ -165,54 +327,297 @@ impl Program {
         Ok(value)
     }
 
+    pub fn set_uniform1fv(&self, u_name: &str, data: &[f32]) -> Result<(), String> {
+        let u_var = self.array_location(u_name, &[GL2::FLOAT], data, 1)?;
+        self.context.uniform1fv_with_f32_array(u_var, data);
+        Ok(())
+    }
+
+    // `data` is a 3x3 matrix in the column major order.
+    pub fn set_uniform_matrix3fv(&self, u_name: &str, data: &[f32]) -> Result<(), String> {
+        if data.len() != 9 {
+            return Err(format!("uniform {} expects 9 values, got {}.", u_name, data.len()));
+        }
+        let u_var = self.uniform_location(u_name, &[GL2::FLOAT_MAT3])?;
+        self.context.uniform_matrix3fv_with_f32_array(u_var, false, data);
+        Ok(())
+    }
+
+    // `data` is a 4x4 matrix in the column major order.
+    pub fn set_uniform_matrix4fv(&self, u_name: &str, data: &[f32]) -> Result<(), String> {
+        if data.len() != 16 {
+            return Err(format!("uniform {} expects 16 values, got {}.", u_name, data.len()));
+        }
+        let u_var = self.uniform_location(u_name, &[GL2::FLOAT_MAT4])?;
+        self.context.uniform_matrix4fv_with_f32_array(u_var, false, data);
+        Ok(())
+    }
+
+    pub fn set_uniform2f(&self, u_name: &str, v0: f32, v1: f32) -> Result<(), String> {
+        let u_var = self.uniform_location(u_name, &[GL2::FLOAT_VEC2])?;
+        self.context.uniform2f(u_var, v0, v1);
+        Ok(())
+    }
+
+    pub fn set_uniform2fv(&self, u_name: &str, data: &[f32]) -> Result<(), String> {
+        let u_var = self.array_location(u_name, &[GL2::FLOAT_VEC2], data, 2)?;
+        self.context.uniform2fv_with_f32_array(u_var, data);
+        Ok(())
+    }
+
     pub fn set_uniform3f(&self, u_name: &str, v0: f32, v1: f32, v2: f32) -> Result<(), String> {
-        let gl = &self.context;
-        let u_var = gl.get_uniform_location(&self, u_name);
-        gl.uniform3f(u_var.as_ref(), v0, v1, v2);
+        let u_var = self.uniform_location(u_name, &[GL2::FLOAT_VEC3])?;
+        self.context.uniform3f(u_var, v0, v1, v2);
+        Ok(())
+    }
+
+    pub fn set_uniform3fv(&self, u_name: &str, data: &[f32]) -> Result<(), String> {
+        let u_var = self.array_location(u_name, &[GL2::FLOAT_VEC3], data, 3)?;
+        self.context.uniform3fv_with_f32_array(u_var, data);
         Ok(())
     }
 
     pub fn get_uniform3f(&self, u_name: &str) -> Result<Box<[f32]>, String> {
         let gl = &self.context;
-        let u_var = gl
-            .get_uniform_location(&self, u_name)
+        let u_var = self
+            .uniforms
+            .get(u_name)
             .ok_or(format!("Cannot retrieve uniform:{} location", u_name))?;
         let v: ArrayBuffer = gl
-            .get_uniform(&self, u_var.as_ref())
+            .get_uniform(self, &u_var.location)
             .dyn_into()
             .map_err(|err| format!("cannot retrieve uniform: {:?}", err))?;
         let ret = Float32Array::new(&v).to_vec().into_boxed_slice();
         Ok(ret)
     }
 
+    pub fn set_uniform4f(&self, u_name: &str, v0: f32, v1: f32, v2: f32, v3: f32) -> Result<(), String> {
+        let u_var = self.uniform_location(u_name, &[GL2::FLOAT_VEC4])?;
+        self.context.uniform4f(u_var, v0, v1, v2, v3);
+        Ok(())
+    }
+
+    pub fn set_uniform4fv(&self, u_name: &str, data: &[f32]) -> Result<(), String> {
+        let u_var = self.array_location(u_name, &[GL2::FLOAT_VEC4], data, 4)?;
+        self.context.uniform4fv_with_f32_array(u_var, data);
+        Ok(())
+    }
+
     pub fn bind_texture_uniform(
         &self,
         texture: &LoadedTexture,
+        target: u32,
         u_name: &str,
     ) -> Result<(), String> {
         // console!("entering bind_texture_uniform: {:?} {}", &texture, u_name);
         let gl = &self.context;
         let texture_unit = texture.id;
 
+        let sampler = self.uniform_location(u_name, &SAMPLER_TYPES).map_err(|err| {
+            console!("{} with texture {}", err, texture.id);
+            err
+        })?;
         texture.activate();
-        gl.bind_texture(GL2::TEXTURE_3D, Some(&texture));
-
-        let sampler = gl.get_uniform_location(self, u_name);
-        match sampler {
-            Some(_) => gl.uniform1i(sampler.as_ref(), texture_unit as i32),
-            None => {
-                let error = gl.get_error();
-                let err_str = format!(
-                    "cannot locate uniform: {}. Error number: {} with texture {}",
-                    u_name, error, texture.id
-                );
-                console!("{}", err_str);
-                return Err(err_str);
+        gl.bind_texture(target, Some(texture));
+        gl.uniform1i(sampler, texture_unit as i32);
+
+        // console!("bind texture {} with program uniform {}", texture.id, u_name);
+
+        Ok(())
+    }
+
+    // Bind the uniform block `block_name` to the binding point of a
+    // UniformBuffer.
+    pub fn bind_uniform_block(&self, block_name: &str, binding: u32) -> Result<(), String> {
+        let index = self.context.get_uniform_block_index(&self.handle, block_name);
+        if index == GL2::INVALID_INDEX {
+            if self.declared.contains(block_name) {
+                return Ok(());
             }
+            return Err(format!("uniform block {} is not declared in the shaders.", block_name));
         }
+        self.context.uniform_block_binding(&self.handle, index, binding);
+        Ok(())
+    }
+}
 
-        // console!("bind texture {} with program uniform {}", texture.id, u_name);
+// A uniform buffer object of `size` floats, laid out by the user as std140.
+#[derive(Debug)]
+pub struct UniformBuffer {
+    pub context: GLContext,
+    pub handle: WebGlBuffer,
+    pub size: usize,
+}
 
+impl UniformBuffer {
+    pub fn new(context: &GLContext, size: usize) -> Result<UniformBuffer, String> {
+        let handle = context
+            .create_buffer()
+            .ok_or("failed to create uniform buffer")?;
+        context.bind_buffer(GL2::UNIFORM_BUFFER, Some(&handle));
+        context.buffer_data_with_i32(GL2::UNIFORM_BUFFER, (size * 4) as i32, GL2::DYNAMIC_DRAW);
+        context.bind_buffer(GL2::UNIFORM_BUFFER, None);
+        Ok(UniformBuffer {
+            context: context.clone(),
+            handle,
+            size,
+        })
+    }
+
+    // Write `data` from the float at `offset`.
+    pub fn update(&self, offset: usize, data: &[f32]) -> Result<(), String> {
+        if offset + data.len() > self.size {
+            return Err(format!(
+                "{} floats from {} overflow the uniform buffer of {}.",
+                data.len(),
+                offset,
+                self.size
+            ));
+        }
+        let gl = &self.context;
+        gl.bind_buffer(GL2::UNIFORM_BUFFER, Some(&self.handle));
+        let array = unsafe { js_sys::Float32Array::view(data) };
+        gl.buffer_sub_data_with_i32_and_array_buffer_view(GL2::UNIFORM_BUFFER, (offset * 4) as i32, &array);
+        gl.bind_buffer(GL2::UNIFORM_BUFFER, None);
         Ok(())
     }
+
+    pub fn bind(&self, binding: u32) {
+        self.context
+            .bind_buffer_base(GL2::UNIFORM_BUFFER, binding, Some(&self.handle));
+    }
+}
+
+impl Drop for UniformBuffer {
+    fn drop(&mut self) {
+        self.context.delete_buffer(Some(&self.handle));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_declared_uniforms() {
+        let source = r#"#version 300 es
+    precision highp float;
+    uniform sampler3D sampler0;
+    uniform highp vec3 spacing0, size0;   // in mm
+    uniform float weights[4];
+    // uniform float removed;
+    layout(std140) uniform Camera {
+        mat4 view;
+    };
+    uniform Light
+    {
+        vec4 direction;
+    };
+    out vec4 color;"#;
+        assert_eq!(
+            declared_uniforms(source),
+            vec!["sampler0", "spacing0", "size0", "weights", "Camera", "Light"]
+        );
+    }
+}
+
+// Run with `wasm-pack test --headless --chrome`, as they need a WebGL2 context.
+#[cfg(all(test, target_arch = "wasm32"))]
+mod web_test {
+    use super::*;
+    use crate::techtron::webgl::shader::ShaderSource;
+    use wasm_bindgen::JsCast;
+    use wasm_bindgen_test::*;
+
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    const VERTEX: &str = r#"#version 300 es
+    in vec4 position;
+    void main() {
+        gl_Position = position;
+    }"#;
+
+    const FRAGMENT: &str = r#"#version 300 es
+    precision highp float;
+    uniform mat3 rotation;
+    uniform int flags[3];
+    uniform vec4 colors[2];
+    layout(std140) uniform Scale {
+        vec4 scale;
+    };
+    out vec4 color;
+    void main() {
+        vec3 c = rotation * colors[flags[0] + flags[1] + flags[2]].rgb;
+        color = vec4(c, colors[1].a) * scale;
+    }"#;
+
+    fn test_program() -> Program {
+        let canvas = web_sys::window()
+            .and_then(|w| w.document())
+            .and_then(|d| d.create_element("canvas").ok())
+            .and_then(|e| e.dyn_into::<web_sys::HtmlCanvasElement>().ok())
+            .unwrap();
+        let gl = canvas
+            .get_context("webgl2")
+            .unwrap()
+            .and_then(|c| c.dyn_into::<GL2>().ok())
+            .unwrap();
+        let context = GLContext::new(gl);
+        let vertex = ShaderSource::new(VERTEX).to_vertex().compile(&context).unwrap();
+        let fragment = ShaderSource::new(FRAGMENT).to_fragment().compile(&context).unwrap();
+        let program = Program::new(&context, vertex, fragment);
+        program.use_program();
+        program
+    }
+
+    fn read_floats(program: &Program, name: &str) -> Vec<f32> {
+        let location = program.uniform_location(name, &[GL2::FLOAT_MAT3, GL2::FLOAT_VEC4]).unwrap();
+        let value = program.context.get_uniform(program, location.unwrap());
+        js_sys::Float32Array::from(value).to_vec()
+    }
+
+    #[wasm_bindgen_test]
+    fn test_set_uniform_matrix3fv() {
+        let program = test_program();
+        let m = [1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0, 8.0, 9.0];
+        program.set_uniform_matrix3fv("rotation", &m).unwrap();
+        assert_eq!(read_floats(&program, "rotation"), m.to_vec());
+        assert!(program.set_uniform_matrix3fv("rotation", &m[..8]).is_err());
+        assert!(program.set_uniform_matrix3fv("colors", &m).is_err());
+    }
+
+    #[wasm_bindgen_test]
+    fn test_set_uniform1iv() {
+        let program = test_program();
+        program.set_uniform1iv("flags", &[1, 0, 0]).unwrap();
+        program.set_uniform1iv("flags", &[1]).unwrap();
+        let location = program.uniform_location("flags", &[GL2::INT]).unwrap();
+        let value = program.context.get_uniform(&program, location.unwrap());
+        assert_eq!(value.as_f64(), Some(1.0));
+        assert!(program.set_uniform1iv("flags", &[0, 0, 0, 0]).is_err());
+        assert!(program.set_uniform1iv("flags", &[]).is_err());
+        assert!(program.set_uniform1iv("rotation", &[0]).is_err());
+    }
+
+    #[wasm_bindgen_test]
+    fn test_set_uniform4fv() {
+        let program = test_program();
+        let colors = [0.1, 0.2, 0.3, 0.4, 0.5, 0.6, 0.7, 0.8];
+        program.set_uniform4fv("colors", &colors).unwrap();
+        assert_eq!(read_floats(&program, "colors"), colors[..4].to_vec());
+        assert!(program.set_uniform4fv("colors", &colors[..6]).is_err());
+        assert!(program.set_uniform4fv("colors", &[0.0; 12]).is_err());
+        assert!(program.set_uniform4fv("undeclared", &colors[..4]).is_err());
+    }
+
+    #[wasm_bindgen_test]
+    fn test_uniform_buffer() {
+        let program = test_program();
+        let buffer = UniformBuffer::new(&program.context, 4).unwrap();
+        buffer.update(0, &[1.0, 1.0, 1.0, 1.0]).unwrap();
+        assert!(buffer.update(2, &[1.0, 1.0, 1.0]).is_err());
+        buffer.bind(1);
+        program.bind_uniform_block("Scale", 1).unwrap();
+        assert!(program.bind_uniform_block("Missing", 1).is_err());
+    }
 }
@@ -42,7 +42,7 @@ use super::{context::GLContext, program::Program};
 
 type GL2 = WebGl2RenderingContext;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct ShaderSource(pub String);
 
 impl ShaderSource {
@@ -63,6 +63,82 @@ impl ShaderSource {
     }
 }
 
+// A piece of GLSL, e.g. uniforms and functions, shared by the shaders. The
+// builder puts the modules it requires before it, once each.
+#[derive(Debug)]
+pub struct ShaderModule {
+    pub name: &'static str,
+    pub requires: &'static [&'static ShaderModule],
+    pub source: &'static str,
+}
+
+// Assembles a GLSL ES 3.00 shader from defines, modules, its own
+// declarations and the body of main.
+#[derive(Debug, Clone, Default)]
+pub struct ShaderBuilder {
+    defines: Vec<(String, String)>,
+    modules: Vec<&'static ShaderModule>,
+    declarations: Vec<String>,
+    main: String,
+}
+
+impl ShaderBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // A later define of the same name replaces the former.
+    pub fn define(mut self, name: &str, value: &str) -> Self {
+        self.defines.retain(|(n, _)| n != name);
+        self.defines.push((String::from(name), String::from(value)));
+        self
+    }
+
+    // A define without value to switch on the #ifdef blocks.
+    pub fn feature(self, name: &str) -> Self {
+        self.define(name, "")
+    }
+
+    pub fn module(mut self, module: &'static ShaderModule) -> Self {
+        if self.modules.iter().any(|m| m.name == module.name) {
+            return self;
+        }
+        for m in module.requires {
+            self = self.module(m);
+        }
+        self.modules.push(module);
+        self
+    }
+
+    pub fn declare(mut self, source: &str) -> Self {
+        self.declarations.push(String::from(source));
+        self
+    }
+
+    pub fn main(mut self, body: &str) -> Self {
+        self.main = String::from(body);
+        self
+    }
+
+    pub fn build(&self) -> ShaderSource {
+        let mut s = String::from("#version 300 es\n");
+        s.push_str("precision highp float;\nprecision highp sampler3D;\nprecision highp isampler3D;\n");
+        s.push_str("precision highp usampler3D;\n");
+        for (name, value) in &self.defines {
+            s.push_str(&format!("#define {} {}\n", name, value));
+        }
+        for m in &self.modules {
+            s.push_str(&format!("// {}\n{}\n", m.name, m.source));
+        }
+        for d in &self.declarations {
+            s.push_str(d);
+            s.push('\n');
+        }
+        s.push_str(&format!("void main() {{\n{}\n}}\n", self.main));
+        ShaderSource(s)
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct VertexShader {
     pub source: ShaderSource,
@@ -71,13 +147,14 @@ pub struct VertexShader {
 impl VertexShader {
     pub fn compile(&self, context: &GLContext) -> Result<CompiledVertexShader, String> {
         let handle = compile_shader(
-            &context,
+            context,
             WebGl2RenderingContext::VERTEX_SHADER,
             &self.source.0,
         )?;
         Ok(CompiledVertexShader {
             handle,
             context: context.clone(),
+            source: self.source.clone(),
         })
     }
 }
@@ -90,13 +167,14 @@ pub struct FragmentShader {
 impl FragmentShader {
     pub fn compile(&self, context: &GLContext) -> Result<CompiledFragmentShader, String> {
         let handle = compile_shader(
-            &context,
+            context,
             WebGl2RenderingContext::FRAGMENT_SHADER,
             &self.source.0,
         )?;
         Ok(CompiledFragmentShader {
             handle,
             context: context.clone(),
+            source: self.source.clone(),
         })
     }
 }
@@ -105,6 +183,7 @@ impl FragmentShader {
 pub struct CompiledVertexShader {
     pub handle: WebGlShader,
     pub context: GLContext,
+    pub source: ShaderSource,
 }
 
 impl Drop for CompiledVertexShader {
@@ -124,6 +203,7 @@ impl Deref for CompiledVertexShader {
 pub struct CompiledFragmentShader {
     pub handle: WebGlShader,
     pub context: GLContext,
+    pub source: ShaderSource,
 }
 
 impl Drop for CompiledFragmentShader {
@@ -186,3 +266,41 @@ pub fn load_shaders(
     // context.0.use_program(Some(&program));
     Ok(program)
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    static BASE: ShaderModule = ShaderModule {
+        name: "base",
+        requires: &[],
+        source: "float base() { return 1.0; }",
+    };
+    static DERIVED: ShaderModule = ShaderModule {
+        name: "derived",
+        requires: &[&BASE],
+        source: "float derived() { return base() * 2.0; }",
+    };
+
+    #[test]
+    fn test_shader_builder() {
+        let source = ShaderBuilder::new()
+            .define("N", "1")
+            .feature("FIELD")
+            .define("N", "2")
+            .module(&DERIVED)
+            .module(&BASE)
+            .declare("out vec4 color;")
+            .main("color = vec4(derived());")
+            .build()
+            .0;
+        assert!(source.starts_with("#version 300 es\n"));
+        assert!(source.contains("#define N 2\n"));
+        assert!(!source.contains("#define N 1"));
+        assert!(source.contains("#define FIELD \n"));
+        assert_eq!(source.matches("float base()").count(), 1);
+        assert!(source.find("float base()") < source.find("float derived()"));
+        assert!(source.find("out vec4 color;") < source.find("void main()"));
+        assert!(source.ends_with("color = vec4(derived());\n}\n"));
+    }
+}
@@ -22,7 +22,7 @@
 // SOFTWARE.
 
 
-use log::info;
+use log::{info, warn};
 use once_cell::sync::Lazy;
 use std::borrow::Borrow;
 use std::ops::Deref;
@@ -64,7 +64,7 @@ pub fn release_texture_id(id: u32) -> Result<(), String> {
         TEXTURE_ID
         .lock()
         .or(Err(String::from("Cannot lock TEXTURE_ID.")))
-        .and_then(|mut v| Ok(v.push(id)))
+        .map(|mut v| v.push(id))
     } else {
         Err(String::from("Texture id is out of range."))
     }
@@ -140,6 +140,180 @@ impl LoadTexture for Texture3DRGBA16 {
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TextureFilter {
+    Nearest,
+    Linear,
+}
+
+// Upload single channel data to a new 3D texture. The integer formats cannot
+// be filtered and 32-bit float textures can be filtered only with the
+// OES_texture_float_linear extension, otherwise `filter` falls back to nearest.
+fn load_texture3d_red(
+    context: &GLContext,
+    size: (i32, i32, i32),
+    internal_format: u32,
+    source_format: u32,
+    source_type: u32,
+    array: &js_sys::Object,
+    filter: TextureFilter,
+) -> LoadedTexture {
+    let gl = context.clone();
+    let level = 0;
+    let border = 0;
+    let id = gen_texture_id().expect("Cannot generate texture id.");
+
+    let handle = gl.create_texture().expect("Failed to crate texture.");
+    gl.bind_texture(GL2::TEXTURE_3D, Some(&handle));
+    // the rows of the 16-bit formats are not aligned to 4 bytes
+    gl.pixel_storei(GL2::UNPACK_ALIGNMENT, 1);
+    gl.tex_image_3d_with_opt_array_buffer_view(
+        GL2::TEXTURE_3D,
+        level,
+        internal_format as i32,
+        size.0,
+        size.1,
+        size.2,
+        border,
+        source_format,
+        source_type,
+        Some(array),
+    )
+    .expect("Failed to copy texture data to GPU.");
+    gl.pixel_storei(GL2::UNPACK_ALIGNMENT, 4);
+
+    set_default_texture_param(&gl);
+    let filterable = match internal_format {
+        GL2::R32F => matches!(gl.get_extension("OES_texture_float_linear"), Ok(Some(_))),
+        GL2::R16I | GL2::R16UI => false,
+        _ => true,
+    };
+    if filter == TextureFilter::Nearest || !filterable {
+        if filter == TextureFilter::Linear {
+            warn!("linear filtering is not supported, fall back to nearest.");
+        }
+        set_texture_filter(&gl, GL2::NEAREST);
+    }
+
+    LoadedTexture {
+        context: context.clone(),
+        handle,
+        id,
+    }
+}
+
+fn set_texture_filter(gl: &WebGl2RenderingContext, filter: u32) {
+    gl.tex_parameteri(GL2::TEXTURE_3D, GL2::TEXTURE_MIN_FILTER, filter as i32);
+    gl.tex_parameteri(GL2::TEXTURE_3D, GL2::TEXTURE_MAG_FILTER, filter as i32);
+}
+
+// Signed 16-bit integers, sampled with an isampler3D.
+pub struct Texture3DR16I {
+    width: i32,
+    height: i32,
+    depth: i32,
+    data: Rc<Vec<i16>>,
+}
+
+impl Texture3DR16I {
+    pub fn new(width: i32, height: i32, depth: i32, data: Rc<Vec<i16>>) -> Self {
+        Texture3DR16I {
+            width,
+            height,
+            depth,
+            data,
+        }
+    }
+}
+
+impl LoadTexture for Texture3DR16I {
+    fn load_texture(&self, context: &GLContext) -> LoadedTexture {
+        let data: &[i16] = self.data.as_slice();
+        let array = unsafe { js_sys::Int16Array::view(data) };
+        load_texture3d_red(
+            context,
+            (self.width, self.height, self.depth),
+            GL2::R16I,
+            GL2::RED_INTEGER,
+            GL2::SHORT,
+            &array,
+            TextureFilter::Nearest,
+        )
+    }
+}
+
+// Unsigned 16-bit integers, sampled with an usampler3D.
+pub struct Texture3DR16UI {
+    width: i32,
+    height: i32,
+    depth: i32,
+    data: Rc<Vec<u16>>,
+}
+
+impl Texture3DR16UI {
+    pub fn new(width: i32, height: i32, depth: i32, data: Rc<Vec<u16>>) -> Self {
+        Texture3DR16UI {
+            width,
+            height,
+            depth,
+            data,
+        }
+    }
+}
+
+impl LoadTexture for Texture3DR16UI {
+    fn load_texture(&self, context: &GLContext) -> LoadedTexture {
+        let data: &[u16] = self.data.as_slice();
+        let array = unsafe { js_sys::Uint16Array::view(data) };
+        load_texture3d_red(
+            context,
+            (self.width, self.height, self.depth),
+            GL2::R16UI,
+            GL2::RED_INTEGER,
+            GL2::UNSIGNED_SHORT,
+            &array,
+            TextureFilter::Nearest,
+        )
+    }
+}
+
+// 32-bit floats, sampled with a sampler3D.
+pub struct Texture3DR32F {
+    width: i32,
+    height: i32,
+    depth: i32,
+    data: Rc<Vec<f32>>,
+    filter: TextureFilter,
+}
+
+impl Texture3DR32F {
+    pub fn new(width: i32, height: i32, depth: i32, data: Rc<Vec<f32>>, filter: TextureFilter) -> Self {
+        Texture3DR32F {
+            width,
+            height,
+            depth,
+            data,
+            filter,
+        }
+    }
+}
+
+impl LoadTexture for Texture3DR32F {
+    fn load_texture(&self, context: &GLContext) -> LoadedTexture {
+        let data: &[f32] = self.data.as_slice();
+        let array = unsafe { js_sys::Float32Array::view(data) };
+        load_texture3d_red(
+            context,
+            (self.width, self.height, self.depth),
+            GL2::R32F,
+            GL2::RED,
+            GL2::FLOAT,
+            &array,
+            self.filter,
+        )
+    }
+}
+
 #[derive(Debug)]
 pub struct LoadedTexture {
     pub context: GLContext,
@@ -167,6 +341,25 @@ impl LoadedTexture {
     pub fn activate(&self) {
         self.context.active_texture(GL2::TEXTURE0 + self.id)
     }
+
+    // Replace `width` x `height` texels of an RGBA8 2D texture from (x, y)
+    // with `data`.
+    pub fn update_rgba8(&self, x: i32, y: i32, width: i32, height: i32, data: &[u8]) -> Result<(), String> {
+        let gl = &self.context;
+        gl.bind_texture(GL2::TEXTURE_2D, Some(&self.handle));
+        gl.tex_sub_image_2d_with_i32_and_i32_and_u32_and_type_and_opt_u8_array(
+            GL2::TEXTURE_2D,
+            0,
+            x,
+            y,
+            width,
+            height,
+            GL2::RGBA,
+            GL2::UNSIGNED_BYTE,
+            Some(data),
+        )
+        .map_err(|err| format!("failed to update the texture: {:?}", err))
+    }
 }
 
 pub fn set_default_texture_param(gl: &WebGl2RenderingContext) {
@@ -233,7 +426,7 @@ impl LoadTexture for Texture3DRGB8 {
         let level = 0;
 
         let data = self.data.as_slice();
-        let lut_array_view = unsafe { js_sys::Uint8Array::view(&data) };
+        let lut_array_view = unsafe { js_sys::Uint8Array::view(data) };
         let handle = gl.create_texture().expect("Failed to create LUT texture.");
         let id = gen_texture_id().expect("Cannot generate texture id.");
         gl.bind_texture(GL2::TEXTURE_3D, Some(&handle));
@@ -259,3 +452,101 @@ impl LoadTexture for Texture3DRGB8 {
         }
     }
 }
+
+pub struct Texture3DRGBA8 {
+    width: i32,
+    height: i32,
+    depth: i32,
+    data: Rc<Vec<u8>>,
+}
+
+impl Texture3DRGBA8 {
+    pub fn new(width: i32, height: i32, depth: i32, data: Rc<Vec<u8>>) -> Self {
+        Texture3DRGBA8 {
+            width,
+            height,
+            depth,
+            data,
+        }
+    }
+}
+
+impl LoadTexture for Texture3DRGBA8 {
+    fn load_texture(&self, context: &GLContext) -> LoadedTexture {
+        let gl = context.clone();
+        let border = 0;
+        let level = 0;
+
+        let data = self.data.as_slice();
+        let array_view = unsafe { js_sys::Uint8Array::view(data) };
+        let handle = gl.create_texture().expect("Failed to create texture.");
+        let id = gen_texture_id().expect("Cannot generate texture id.");
+        gl.bind_texture(GL2::TEXTURE_3D, Some(&handle));
+        gl.tex_image_3d_with_opt_array_buffer_view(
+            GL2::TEXTURE_3D,
+            level,
+            GL2::RGBA8 as i32,
+            self.width,
+            self.height,
+            self.depth,
+            border,
+            GL2::RGBA,
+            GL2::UNSIGNED_BYTE,
+            Some(&array_view),
+        )
+        .expect("Failed to copy texture data to GPU.");
+        set_default_texture_param(&gl);
+
+        LoadedTexture {
+            context: context.clone(),
+            handle,
+            id,
+        }
+    }
+}
+
+// An RGBA8 image, for the overlays drawn over the viewport.
+pub struct Texture2DRGBA8 {
+    width: i32,
+    height: i32,
+    data: Rc<Vec<u8>>,
+}
+
+impl Texture2DRGBA8 {
+    pub fn new(width: i32, height: i32, data: Rc<Vec<u8>>) -> Self {
+        Texture2DRGBA8 { width, height, data }
+    }
+}
+
+impl LoadTexture for Texture2DRGBA8 {
+    fn load_texture(&self, context: &GLContext) -> LoadedTexture {
+        let gl = context.clone();
+        let handle = gl.create_texture().expect("Failed to create texture.");
+        let id = gen_texture_id().expect("Cannot generate texture id.");
+        gl.bind_texture(GL2::TEXTURE_2D, Some(&handle));
+        gl.tex_image_2d_with_i32_and_i32_and_i32_and_format_and_type_and_opt_u8_array(
+            GL2::TEXTURE_2D,
+            0,
+            GL2::RGBA8 as i32,
+            self.width,
+            self.height,
+            0,
+            GL2::RGBA,
+            GL2::UNSIGNED_BYTE,
+            Some(self.data.as_slice()),
+        )
+        .expect("Failed to copy texture data to GPU.");
+        for wrap in [GL2::TEXTURE_WRAP_S, GL2::TEXTURE_WRAP_T] {
+            gl.tex_parameteri(GL2::TEXTURE_2D, wrap, GL2::CLAMP_TO_EDGE as i32);
+        }
+        for filter in [GL2::TEXTURE_MIN_FILTER, GL2::TEXTURE_MAG_FILTER] {
+            gl.tex_parameteri(GL2::TEXTURE_2D, filter, GL2::LINEAR as i32);
+        }
+
+        LoadedTexture {
+            context: context.clone(),
+            handle,
+            id,
+        }
+    }
+}
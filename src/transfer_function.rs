@@ -0,0 +1,144 @@
+// MIT License
+
+// Copyright (c) 2023 Techtron-Lab
+
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+
+
+// The transfer functions of the 3D view, mapping the real world values of the
+// primary to colors and opacities.
+
+// A piecewise linear function through the control points, which are sorted
+// by their values. Below the first and above the last points it is
+// transparent.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TransferFunction {
+    points: Vec<(f32, [f32; 4])>,
+}
+
+impl TransferFunction {
+    pub fn new(mut points: Vec<(f32, [f32; 4])>) -> Result<TransferFunction, String> {
+        if points.len() < 2 {
+            return Err(String::from("a transfer function needs at least 2 points."));
+        }
+        if points.iter().any(|(v, _)| !v.is_finite()) {
+            return Err(String::from("the values of a transfer function must be finite."));
+        }
+        points.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
+        Ok(TransferFunction { points })
+    }
+
+    // `flat` is [value, r, g, b, a, value, r, g, b, a, ...] with the colors
+    // and the opacities in [0, 1].
+    pub fn from_flat(flat: &[f32]) -> Result<TransferFunction, String> {
+        let chunks = flat.chunks_exact(5);
+        if !chunks.remainder().is_empty() {
+            return Err(String::from("a transfer function point has 5 values: value, r, g, b, a."));
+        }
+        let points = chunks
+            .map(|c| (c[0], [c[1], c[2], c[3], c[4]]))
+            .collect();
+        TransferFunction::new(points)
+    }
+
+    // Presets for CT in Hounsfield units.
+    pub fn preset(name: &str) -> Option<TransferFunction> {
+        let points = match name {
+            "bone" => vec![
+                (150.0, [0.9, 0.8, 0.6, 0.0]),
+                (300.0, [0.95, 0.9, 0.8, 0.4]),
+                (1500.0, [1.0, 1.0, 1.0, 0.9]),
+            ],
+            "soft tissue" => vec![
+                (-500.0, [0.6, 0.3, 0.2, 0.0]),
+                (-100.0, [0.8, 0.5, 0.4, 0.02]),
+                (100.0, [0.9, 0.6, 0.5, 0.08]),
+                (300.0, [1.0, 1.0, 0.9, 0.6]),
+                (1500.0, [1.0, 1.0, 1.0, 0.9]),
+            ],
+            "lung" => vec![
+                (-1000.0, [0.3, 0.3, 0.6, 0.0]),
+                (-800.0, [0.6, 0.6, 0.9, 0.05]),
+                (-500.0, [0.9, 0.6, 0.5, 0.0]),
+            ],
+            _ => return None,
+        };
+        TransferFunction::new(points).ok()
+    }
+
+    pub fn range(&self) -> (f32, f32) {
+        (self.points[0].0, self.points[self.points.len() - 1].0)
+    }
+
+    pub fn eval(&self, value: f32) -> [f32; 4] {
+        let (min, max) = self.range();
+        if value < min || value > max {
+            return [0.0; 4];
+        }
+        // the segment [v0, v1] containing the value, the first point is not
+        // above the value so i >= 1
+        let i = self.points.partition_point(|(v, _)| *v <= value).min(self.points.len() - 1);
+        let (v0, c0) = self.points[i - 1];
+        let (v1, c1) = self.points[i];
+        let k = if v1 > v0 { (value - v0) / (v1 - v0) } else { 1.0 };
+        std::array::from_fn(|j| c0[j] + (c1[j] - c0[j]) * k)
+    }
+
+    // RGBA8 lookup table of `n` entries evenly covering the range, the first
+    // entry at the lowest value and the last at the highest.
+    pub fn lut(&self, n: usize) -> Vec<u8> {
+        let (min, max) = self.range();
+        let mut lut = Vec::with_capacity(n * 4);
+        for i in 0..n {
+            let value = min + (max - min) * i as f32 / (n - 1) as f32;
+            let c = self.eval(value);
+            lut.extend(c.iter().map(|x| (x.clamp(0.0, 1.0) * 255.0).round() as u8));
+        }
+        lut
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_eval() {
+        let tf = TransferFunction::from_flat(&[
+            100.0, 1.0, 1.0, 1.0, 1.0,
+            -100.0, 0.0, 0.0, 0.0, 0.0,
+            0.0, 1.0, 0.0, 0.0, 0.5,
+        ])
+        .unwrap();
+        assert_eq!(tf.range(), (-100.0, 100.0));
+        assert_eq!(tf.eval(-200.0), [0.0; 4]);
+        assert_eq!(tf.eval(-100.0), [0.0; 4]);
+        assert_eq!(tf.eval(-50.0), [0.5, 0.0, 0.0, 0.25]);
+        assert_eq!(tf.eval(50.0), [1.0, 0.5, 0.5, 0.75]);
+        assert_eq!(tf.eval(100.0), [1.0; 4]);
+
+        let lut = tf.lut(3);
+        assert_eq!(lut, vec![0, 0, 0, 0, 255, 0, 0, 128, 255, 255, 255, 255]);
+
+        assert!(TransferFunction::from_flat(&[0.0, 1.0, 1.0, 1.0]).is_err());
+        assert!(TransferFunction::from_flat(&[0.0, 1.0, 1.0, 1.0, 1.0]).is_err());
+        assert!(TransferFunction::preset("bone").is_some());
+    }
+}
@@ -24,10 +24,20 @@
 use std::cell::RefCell;
 use std::rc::Rc;
 
-use log::info;
+use log::{info, warn};
+use nalgebra::{Rotation3, Unit, Vector3};
 use web_sys::WebGl2RenderingContext;
 
 use crate::colormap;
+use crate::render::annotations::{Annotations, Mark};
+use crate::render::isolines::IsoLineOverlay;
+use crate::render::overlay::StructureOverlay;
+use crate::render::{
+    viewport_extent, Crosshair, FieldDisplay, Fusion, RgbaImage, SliceParams, SliceRenderer, WindowLevel,
+    MAX_BANDS,
+};
+use crate::shader_sources::Orientation;
+use crate::techtron::core::base::Base;
 use crate::techtron::prelude::*;
 
 type GL2 = WebGl2RenderingContext;
@@ -76,6 +86,49 @@ pub struct TwoNeedleGeometry {
     pub needle_length: f32,
 }
 
+// Planes of the views which follow the needles.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum NeedleAlignment {
+    // contains both shafts with the tips at the bottom
+    Inline,
+    // perpendicular to the shafts at the tips, looking along them
+    Perpendicular,
+}
+
+// The needles are parallel to the z axis of the primary, from center - L / 2
+// to the tips at center + L / 2, and h apart from the center in the
+// direction of needle_rot. All in the view frame of the primary in mm.
+impl TwoNeedleGeometry {
+    pub fn center(&self) -> Vector3<f32> {
+        // needle_pos is in 250 mm with y pointing up
+        let (x, y, z) = self.needle_pos;
+        Vector3::new(x, -y, z) * 250.0
+    }
+
+    pub fn axis(&self) -> Vector3<f32> {
+        Vector3::z()
+    }
+
+    // unit vector from the center to one of the needles
+    pub fn spread(&self) -> Vector3<f32> {
+        Vector3::new(self.needle_rot.cos(), -self.needle_rot.sin(), 0.0)
+    }
+
+    pub fn tip(&self) -> Vector3<f32> {
+        self.center() + self.axis() * (self.needle_length / 2.0)
+    }
+
+    // (origin, normal, up) of the plane
+    pub fn plane(&self, alignment: NeedleAlignment) -> (Vector3<f32>, Vector3<f32>, Vector3<f32>) {
+        let a = self.axis();
+        let e = self.spread();
+        match alignment {
+            NeedleAlignment::Inline => (self.center(), e.cross(&a), -a),
+            NeedleAlignment::Perpendicular => (self.tip(), a, e.cross(&a)),
+        }
+    }
+}
+
 impl UpdateUniforms for TwoNeedleGeometry {
     fn update_uniforms(&self, program: &Program) -> Result<(), String> {
         program.set_uniform3f("uah", self.uah.0, self.uah.1, self.uah.2)?;
@@ -91,20 +144,37 @@ impl UpdateUniforms for TwoNeedleGeometry {
     }
 }
 
+impl UpdateUniforms for Option<Crosshair> {
+    fn update_uniforms(&self, program: &Program) -> Result<(), String> {
+        match self {
+            Some(crosshair) => {
+                let (c, color) = (crosshair.cursor, crosshair.color);
+                program.set_uniform3f("cursor", c.x, c.y, c.z)?;
+                program.set_uniform1f("crosshair", 1.0)?;
+                program.set_uniform3f("crosshair_color", color[0], color[1], color[2])?;
+            }
+            None => program.set_uniform1f("crosshair", 0.0)?,
+        }
+        Ok(())
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct CTPane {
     pub texture: Rc<RefCell<LoadedTexture>>,
     pub z_loc: Rc<RefCell<LoadedTexture>>,
+    // in the real world values, e.g. Hounsfield units
     pub window: f32,
     pub level: f32,
+    // slope and intercept from the texture values to the real world values
+    pub rescale: (f32, f32),
     pub spacing: (f32, f32, f32),
     pub size: (f32, f32, f32),
 }
 
 impl BindTextureUniforms for CTPane {
     fn bind_texture_uniforms(&self, program: &Program) -> Result<(), String> {
-        program.bind_texture_uniform(&self.texture.borrow(), "sampler0")?;
-        program.bind_texture_uniform(&self.z_loc.borrow(), "sampler3")?;
+        program.bind_texture_uniform(&self.texture.borrow(), GL2::TEXTURE_3D, "sampler0")?;
         Ok(())
     }
 }
@@ -113,12 +183,16 @@ impl UpdateUniforms for CTPane {
     fn update_uniforms(&self, program: &Program) -> Result<(), String> {
         program.set_uniform1f("window", self.window)?;
         program.set_uniform1f("level", self.level)?;
+        program.set_uniform2f("rescale0", self.rescale.0, self.rescale.1)?;
         program.set_uniform3f("spacing0", self.spacing.0, self.spacing.1, self.spacing.2)?;
         program.set_uniform3f("size0", self.size.0, self.size.1, self.size.2)?;
         Ok(())
     }
 }
 
+// the uniform buffer binding point of the Registration block
+pub const REGISTRATION_BINDING: u32 = 0;
+
 #[derive(Debug, Clone)]
 pub struct DosePane {
     pub texture: Rc<RefCell<LoadedTexture>>,
@@ -129,14 +203,18 @@ pub struct DosePane {
     pub spacing: (f32, f32, f32),
     pub size: (f32, f32, f32),
     pub minmax: (f32, f32),
+    // the Registration block: from the view frame of the primary (mm from its
+    // center along its axes) to the texture coordinates of the secondary
+    pub registration: Rc<UniformBuffer>,
+    // whether the secondary is moved with the needle position
+    pub follow_needles: bool,
+    pub display: FieldDisplay,
 }
 
 impl BindTextureUniforms for DosePane {
     fn bind_texture_uniforms(&self, program: &Program) -> Result<(), String> {
-        program.bind_texture_uniform(&self.texture.borrow(), "sampler1");
-        program.bind_texture_uniform(&self.lut.borrow(), "sampler2");
-        program.bind_texture_uniform(&self.z_loc.borrow(), "sampler4");
-        Ok(())
+        program.bind_texture_uniform(&self.texture.borrow(), GL2::TEXTURE_3D, "sampler1")?;
+        program.bind_texture_uniform(&self.lut.borrow(), GL2::TEXTURE_3D, "sampler2")
     }
 }
 
@@ -146,10 +224,243 @@ impl UpdateUniforms for DosePane {
         program.set_uniform1f("level1", self.level)?;
         program.set_uniform3f("spacing1", self.spacing.0, self.spacing.1, self.spacing.2)?;
         program.set_uniform3f("size1", self.size.0, self.size.1, self.size.2)?;
+        self.registration.bind(REGISTRATION_BINDING);
+        program.bind_uniform_block("Registration", REGISTRATION_BINDING)?;
+        program.set_uniform1f("follow1", if self.follow_needles { 1.0 } else { 0.0 })?;
+        self.display.update_uniforms(program)?;
+        Ok(())
+    }
+}
+
+impl UpdateUniforms for FieldDisplay {
+    fn update_uniforms(&self, program: &Program) -> Result<(), String> {
+        match self {
+            FieldDisplay::Colormap { ramp } => {
+                let (lower, upper, on) = ramp.map(|(l, u)| (l, u, 1.0)).unwrap_or((0.0, 0.0, 0.0));
+                program.set_uniform3f("ramp1", lower, upper, on)?;
+                program.set_uniform1i("bands1", 0)?;
+            }
+            FieldDisplay::Bands { bands, count } => {
+                let mut ranges = [0.0; MAX_BANDS * 2];
+                let mut colors = [0.0; MAX_BANDS * 3];
+                for (i, band) in bands[..*count].iter().enumerate() {
+                    ranges[i * 2..i * 2 + 2].copy_from_slice(&[band.lower, band.upper]);
+                    colors[i * 3..i * 3 + 3].copy_from_slice(&band.color.map(|c| c as f32 / 255.0));
+                }
+                program.set_uniform1i("bands1", *count as i32)?;
+                program.set_uniform2fv("band_range1", &ranges)?;
+                program.set_uniform3fv("band_color1", &colors)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl UpdateUniforms for Fusion {
+    fn update_uniforms(&self, program: &Program) -> Result<(), String> {
+        let (mode, size, center) = match *self {
+            Fusion::Blend => (0, 0.0, Vector3::zeros()),
+            Fusion::Checkerboard { tile } => (1, tile, Vector3::zeros()),
+            Fusion::Difference => (2, 0.0, Vector3::zeros()),
+            Fusion::Spyglass { center, radius } => (3, radius, center),
+        };
+        program.set_uniform1i("fusion", mode)?;
+        program.set_uniform1f("fusion_size", size)?;
+        program.set_uniform3f("lens_center", center.x, center.y, center.z)?;
+        Ok(())
+    }
+}
+
+// What the overlay texture of a view is drawn for but the readouts.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct OverlayKey {
+    width: i32,
+    height: i32,
+    structures: u32,
+    isolines: u32,
+    annotations: u32,
+    // only with the structures or the iso-lines, which move with the slice
+    scale: f32,
+    pan: (f32, f32),
+    slice: f32,
+    // for the colorbar
+    secondary: Option<WindowLevel>,
+    field_display: FieldDisplay,
+    // only if the iso-lines follow the needles
+    needle_center: Vector3<f32>,
+}
+
+// The structures, the iso-lines and the annotations over a slice view, drawn
+// into a texture of the viewport whenever the slice, the viewport, the
+// needles or any of them change. The readouts alone are redrawn and uploaded
+// over the rest, kept in `base`, as the slice or the window change.
+pub struct OverlayPane {
+    pub context: GLContext,
+    pub structures: Rc<RefCell<StructureOverlay>>,
+    pub isolines: Rc<RefCell<IsoLineOverlay>>,
+    pub annotations: Rc<RefCell<Annotations>>,
+    texture: LoadedTexture,
+    // a transparent texel if None
+    drawn: Option<OverlayKey>,
+    base: RgbaImage,
+    image: RgbaImage,
+    readouts: Vec<Mark>,
+}
+
+impl OverlayPane {
+    pub fn new(
+        context: &GLContext,
+        structures: Rc<RefCell<StructureOverlay>>,
+        isolines: Rc<RefCell<IsoLineOverlay>>,
+        annotations: Rc<RefCell<Annotations>>,
+    ) -> OverlayPane {
+        OverlayPane {
+            context: context.clone(),
+            structures,
+            isolines,
+            annotations,
+            texture: Self::load(context, &RgbaImage::new(1, 1)),
+            drawn: None,
+            base: RgbaImage::new(1, 1),
+            image: RgbaImage::new(1, 1),
+            readouts: Vec::new(),
+        }
+    }
+
+    fn load(context: &GLContext, image: &RgbaImage) -> LoadedTexture {
+        Texture2DRGBA8::new(image.width as i32, image.height as i32, Rc::new(image.data.clone())).load_texture(context)
+    }
+
+    pub fn update(&mut self, slice: &SliceParams, size: (i32, i32, i32, i32), needle_center: &Vector3<f32>) {
+        let (_, _, width, height) = size;
+        let structures = self.structures.borrow();
+        let isolines = self.isolines.borrow();
+        let annotations = self.annotations.borrow();
+        let visible =
+            (structures.any_visible() || isolines.is_active() || annotations.any_visible()) && width > 0 && height > 0;
+        if !visible {
+            if self.drawn.is_some() {
+                self.texture = Self::load(&self.context, &RgbaImage::new(1, 1));
+                self.drawn = None;
+                self.readouts.clear();
+            }
+            return;
+        }
+        let moves = structures.any_visible() || isolines.is_active();
+        let key = OverlayKey {
+            width,
+            height,
+            structures: structures.revision(),
+            isolines: isolines.revision(),
+            annotations: annotations.revision(),
+            scale: if moves { slice.scale } else { 0.0 },
+            pan: if moves { slice.pan } else { (0.0, 0.0) },
+            slice: if moves { slice.slice } else { 0.0 },
+            secondary: slice.secondary,
+            field_display: slice.field_display,
+            needle_center: if moves && isolines.follows_needles() { *needle_center } else { Vector3::zeros() },
+        };
+        let readouts = annotations.readouts(slice, width as usize, height as usize);
+
+        if self.drawn != Some(key) {
+            let mut base = structures.render(slice, width as usize, height as usize);
+            isolines.render_into(&mut base, slice, needle_center);
+            annotations.render_static(&mut base, slice);
+            let mut image = base.clone();
+            annotations.render_readouts(&mut image, &readouts);
+            // into the texture of the same size if there is one
+            match self.drawn {
+                Some(drawn) if drawn.width == width && drawn.height == height => {
+                    if let Err(err) = self.texture.update_rgba8(0, 0, width, height, &image.data) {
+                        warn!("{}", err);
+                    }
+                }
+                _ => self.texture = Self::load(&self.context, &image),
+            }
+            self.drawn = Some(key);
+            self.base = base;
+            self.image = image;
+        } else if readouts != self.readouts {
+            // the pixels of the readouts drawn or gone, back to the base and
+            // then under all the readouts again
+            let changed = self.readouts.iter().filter(|mark| !readouts.contains(mark));
+            let added = readouts.iter().filter(|mark| !self.readouts.contains(mark));
+            let areas: Vec<_> =
+                changed.chain(added).filter_map(|mark| self.image.clip(annotations.mark_area(mark))).collect();
+            for &area in &areas {
+                self.image.copy_area(&self.base, area);
+            }
+            annotations.render_readouts(&mut self.image, &readouts);
+            for area in areas {
+                let part = self.image.crop(area);
+                let (x, y) = (area.0 as i32, area.1 as i32);
+                if let Err(err) = self.texture.update_rgba8(x, y, part.width as i32, part.height as i32, &part.data) {
+                    warn!("{}", err);
+                }
+            }
+        }
+        self.readouts = readouts;
+    }
+}
+
+impl BindTextureUniforms for OverlayPane {
+    fn bind_texture_uniforms(&self, program: &Program) -> Result<(), String> {
+        program.bind_texture_uniform(&self.texture, GL2::TEXTURE_2D, "overlay")
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SlabMode {
+    // a single plane
+    Plane,
+    Maximum,
+    Minimum,
+    Mean,
+}
+
+impl SlabMode {
+    pub fn from_name(name: &str) -> Option<SlabMode> {
+        match name {
+            "plane" => Some(SlabMode::Plane),
+            "max" | "mip" => Some(SlabMode::Maximum),
+            "min" | "minip" => Some(SlabMode::Minimum),
+            "mean" | "average" => Some(SlabMode::Mean),
+            _ => None,
+        }
+    }
+}
+
+// Projection of a slab of the volumes across the view, centered on the slice.
+#[derive(Debug, Clone, Copy)]
+pub struct Slab {
+    pub mode: SlabMode,
+    // in mm
+    pub thickness: f32,
+    // whether the secondary is projected as well
+    pub field: bool,
+}
+
+impl Default for Slab {
+    fn default() -> Self {
+        Slab {
+            mode: SlabMode::Plane,
+            thickness: 0.0,
+            field: false,
+        }
+    }
+}
 
-        program.set_uniform1f("dl", self.minmax.0)?;
-        program.set_uniform1f("dh", self.minmax.1)?;
-        // info!("dl: {}, dh: {}", self.minmax.0, self.minmax.1);
+impl UpdateUniforms for Slab {
+    fn update_uniforms(&self, program: &Program) -> Result<(), String> {
+        let mode = match self.mode {
+            SlabMode::Plane => 0,
+            SlabMode::Maximum => 1,
+            SlabMode::Minimum => 2,
+            SlabMode::Mean => 3,
+        };
+        program.set_uniform1i("slab_mode", mode)?;
+        program.set_uniform1f("slab", self.thickness)?;
+        program.set_uniform1f("slab1", if self.field { 1.0 } else { 0.0 })?;
         Ok(())
     }
 }
@@ -163,36 +474,44 @@ pub struct TransverseView {
     pub pan_y: f32,
     pub size: (i32, i32, i32, i32),
     pub blend: f32,
+    pub fusion: Fusion,
     pub needles: Rc<RefCell<TwoNeedleGeometry>>,
     pub num_of_indices: i32,
     pub minmax: (f32, f32),
 
+    pub slab: Slab,
     pub ct: CTPane,
     pub dose: Option<DosePane>,
+    pub overlay: OverlayPane,
+    pub crosshair: Option<Crosshair>,
 }
 
 impl TransverseView {
-    pub fn bind_texture_uniforms(&self) {
-        // self.program.use_program();
-        self.ct.bind_texture_uniforms(&self.program);
+    pub fn bind_texture_uniforms(&self) -> Result<(), String> {
+        self.ct.bind_texture_uniforms(&self.program)?;
         if let Some(ref dose) = self.dose {
-            dose.bind_texture_uniforms(&self.program);
+            dose.bind_texture_uniforms(&self.program)?;
         }
+        self.overlay.bind_texture_uniforms(&self.program)
     }
 
     pub fn set_window(&mut self, window: f32) -> Result<(), String> {
-        self.program.set_uniform1f("window", window as f32)
+        self.program.set_uniform1f("window", window)
     }
 
     pub fn update_uniforms(&mut self) -> Result<(), String> {
-        self.program.set_uniform1f("st", self.scale)?;
-        self.program.set_uniform1f("slt", self.z);
-        self.program.set_uniform1f("ptx", self.pan_x)?;
-        self.program.set_uniform1f("pty", self.pan_y)?;
+        self.program.set_uniform1f("scale", self.scale)?;
+        self.program.set_uniform1f("slice", self.z)?;
+        self.program.set_uniform2f("pan", self.pan_x, self.pan_y)?;
+        let (ex, ey) = viewport_extent(self.size.2, self.size.3);
+        self.program.set_uniform2f("extent", ex, ey)?;
 
-        self.program.set_uniform1f("k", self.blend);
+        self.program.set_uniform1f("k", self.blend)?;
 
         self.needles.borrow().update_uniforms(&self.program)?;
+        self.slab.update_uniforms(&self.program)?;
+        self.crosshair.update_uniforms(&self.program)?;
+        self.fusion.update_uniforms(&self.program)?;
         self.ct.update_uniforms(&self.program)?;
         if let Some(ref dose) = self.dose {
             dose.update_uniforms(&self.program)?;
@@ -212,9 +531,12 @@ impl Rendable for TransverseView {
         }
 
         self.program.use_program();
-        self.update_uniforms();
-        self.bind_texture_uniforms();
-        //gl.viewport(0, 0, self.size, self.size);
+        let center = self.needles.borrow().center();
+        self.overlay.update(&self.slice_params(), self.size, &center);
+        if let Err(err) = self.update_uniforms().and_then(|_| self.bind_texture_uniforms()) {
+            warn!("transverse view: {}", err);
+            return;
+        }
         gl.draw_elements_with_i32(GL2::TRIANGLES, self.num_of_indices, GL2::UNSIGNED_SHORT, 0);
     }
 }
@@ -228,33 +550,38 @@ pub struct SagittalView {
     pub pan_y: f32,
     pub size: (i32, i32, i32, i32),
     pub blend: f32,
+    pub fusion: Fusion,
     pub needles: Rc<RefCell<TwoNeedleGeometry>>,
     pub num_of_indices: i32,
+    pub slab: Slab,
     pub ct: CTPane,
     pub dose: Option<DosePane>,
+    pub overlay: OverlayPane,
+    pub crosshair: Option<Crosshair>,
 }
 
 impl SagittalView {
-    pub fn bind_texture_uniforms(&self) {
-        self.program.use_program();
-        self.ct.bind_texture_uniforms(&self.program);
+    pub fn bind_texture_uniforms(&self) -> Result<(), String> {
+        self.ct.bind_texture_uniforms(&self.program)?;
         if let Some(ref dose) = self.dose {
-            dose.bind_texture_uniforms(&self.program);
+            dose.bind_texture_uniforms(&self.program)?;
         }
+        self.overlay.bind_texture_uniforms(&self.program)
     }
 
     pub fn update_uniforms(&mut self) -> Result<(), String> {
-        // set window/level
-        self.program.use_program();
-
-        self.program.set_uniform1f("ss", self.scale)?;
-        self.program.set_uniform1f("sls", self.x);
-        self.program.set_uniform1f("psx", self.pan_x)?;
-        self.program.set_uniform1f("psy", self.pan_y)?;
+        self.program.set_uniform1f("scale", self.scale)?;
+        self.program.set_uniform1f("slice", self.x)?;
+        self.program.set_uniform2f("pan", self.pan_x, self.pan_y)?;
+        let (ex, ey) = viewport_extent(self.size.2, self.size.3);
+        self.program.set_uniform2f("extent", ex, ey)?;
 
-        self.program.set_uniform1f("k", self.blend);
+        self.program.set_uniform1f("k", self.blend)?;
 
-        self.needles.borrow_mut().update_uniforms(&mut self.program);
+        self.needles.borrow().update_uniforms(&self.program)?;
+        self.slab.update_uniforms(&self.program)?;
+        self.crosshair.update_uniforms(&self.program)?;
+        self.fusion.update_uniforms(&self.program)?;
         self.ct.update_uniforms(&self.program)?;
         if let Some(ref dose) = self.dose {
             dose.update_uniforms(&self.program)?;
@@ -272,9 +599,12 @@ impl Rendable for SagittalView {
         }
 
         self.program.use_program();
-        self.update_uniforms();
-        self.bind_texture_uniforms();
-        //gl.viewport(0, 0, self.size, self.size);
+        let center = self.needles.borrow().center();
+        self.overlay.update(&self.slice_params(), self.size, &center);
+        if let Err(err) = self.update_uniforms().and_then(|_| self.bind_texture_uniforms()) {
+            warn!("sagittal view: {}", err);
+            return;
+        }
         self.context.draw_elements_with_i32(
             GL2::TRIANGLES,
             self.num_of_indices,
@@ -292,28 +622,37 @@ pub struct CoronalView {
     pub pan_y: f32,
     pub size: (i32, i32, i32, i32),
     pub blend: f32,
+    pub fusion: Fusion,
     pub needles: Rc<RefCell<TwoNeedleGeometry>>,
     pub num_of_indices: i32,
+    pub slab: Slab,
     pub ct: CTPane,
     pub dose: Option<DosePane>,
+    pub overlay: OverlayPane,
+    pub crosshair: Option<Crosshair>,
 }
 
 impl CoronalView {
-    pub fn bind_texture_uniforms(&self) {
-        self.ct.bind_texture_uniforms(&self.program);
+    pub fn bind_texture_uniforms(&self) -> Result<(), String> {
+        self.ct.bind_texture_uniforms(&self.program)?;
         if let Some(ref dose) = self.dose {
-            dose.bind_texture_uniforms(&self.program);
+            dose.bind_texture_uniforms(&self.program)?;
         }
+        self.overlay.bind_texture_uniforms(&self.program)
     }
 
     pub fn update_uniforms(&mut self) -> Result<(), String> {
-        self.program.set_uniform1f("sc", self.scale)?;
-        self.program.set_uniform1f("slc", self.y);
-        self.program.set_uniform1f("pcx", self.pan_x)?;
-        self.program.set_uniform1f("pcy", self.pan_y)?;
-        self.program.set_uniform1f("k", self.blend);
+        self.program.set_uniform1f("scale", self.scale)?;
+        self.program.set_uniform1f("slice", self.y)?;
+        self.program.set_uniform2f("pan", self.pan_x, self.pan_y)?;
+        let (ex, ey) = viewport_extent(self.size.2, self.size.3);
+        self.program.set_uniform2f("extent", ex, ey)?;
+        self.program.set_uniform1f("k", self.blend)?;
 
-        self.needles.borrow_mut().update_uniforms(&mut self.program);
+        self.needles.borrow().update_uniforms(&self.program)?;
+        self.slab.update_uniforms(&self.program)?;
+        self.crosshair.update_uniforms(&self.program)?;
+        self.fusion.update_uniforms(&self.program)?;
         self.ct.update_uniforms(&self.program)?;
         if let Some(ref dose) = self.dose {
             dose.update_uniforms(&self.program)?;
@@ -331,9 +670,12 @@ impl Rendable for CoronalView {
         }
 
         self.program.use_program();
-        self.update_uniforms();
-        self.bind_texture_uniforms();
-        //gl.viewport(0, 0, self.size, self.size);
+        let center = self.needles.borrow().center();
+        self.overlay.update(&self.slice_params(), self.size, &center);
+        if let Err(err) = self.update_uniforms().and_then(|_| self.bind_texture_uniforms()) {
+            warn!("coronal view: {}", err);
+            return;
+        }
         self.context.draw_elements_with_i32(
             GL2::TRIANGLES,
             self.num_of_indices,
@@ -343,19 +685,459 @@ impl Rendable for CoronalView {
     }
 }
 
+// The state of the axis aligned views as SliceParams, and drawing any
+// SliceParams of their orientation with WebGL.
+macro_rules! impl_slice_renderer {
+    ($view: ident, $slice: ident, $orientation: expr) => {
+        impl $view {
+            pub fn slice_params(&self) -> SliceParams {
+                SliceParams {
+                    orientation: $orientation,
+                    scale: self.scale,
+                    pan: (self.pan_x, self.pan_y),
+                    extent: viewport_extent(self.size.2, self.size.3),
+                    slice: self.$slice,
+                    blend: self.blend,
+                    slab: self.slab,
+                    primary: WindowLevel {
+                        window: self.ct.window,
+                        level: self.ct.level,
+                    },
+                    secondary: self.dose.as_ref().map(|d| WindowLevel {
+                        window: d.window,
+                        level: d.level,
+                    }),
+                    field_display: self.dose.as_ref().map(|d| d.display).unwrap_or_default(),
+                    fusion: self.fusion,
+                    crosshair: self.crosshair,
+                }
+            }
+        }
+
+        impl SliceRenderer for $view {
+            fn render_slice(&mut self, slice: &SliceParams, viewport: (i32, i32, i32, i32)) -> Result<(), String> {
+                if slice.orientation != $orientation {
+                    return Err(format!(
+                        "{} cannot draw a {:?} slice.",
+                        stringify!($view),
+                        slice.orientation
+                    ));
+                }
+                self.scale = slice.scale;
+                (self.pan_x, self.pan_y) = slice.pan;
+                self.$slice = slice.slice;
+                self.blend = slice.blend;
+                self.fusion = slice.fusion;
+                self.slab = slice.slab;
+                self.crosshair = slice.crosshair;
+                self.ct.window = slice.primary.window;
+                self.ct.level = slice.primary.level;
+                if let (Some(dose), Some(secondary)) = (self.dose.as_mut(), slice.secondary) {
+                    dose.window = secondary.window;
+                    dose.level = secondary.level;
+                    dose.display = slice.field_display;
+                }
+                self.size = viewport;
+                self.render();
+                Ok(())
+            }
+        }
+    };
+}
+
+impl_slice_renderer!(TransverseView, z, Orientation::Transverse);
+impl_slice_renderer!(SagittalView, x, Orientation::Sagittal);
+impl_slice_renderer!(CoronalView, y, Orientation::Coronal);
+
+pub struct ObliqueView {
+    pub context: GLContext,
+    pub program: Program,
+    pub scale: f32,
+    pub pan_x: f32,
+    pub pan_y: f32,
+    pub size: (i32, i32, i32, i32),
+    pub blend: f32,
+    pub fusion: Fusion,
+    pub needles: Rc<RefCell<TwoNeedleGeometry>>,
+    pub num_of_indices: i32,
+    // the plane in the view frame of the primary, origin in mm; `up` points
+    // to the top of the view and is kept perpendicular to `normal`
+    pub origin: Vector3<f32>,
+    pub normal: Vector3<f32>,
+    pub up: Vector3<f32>,
+    // the plane is taken from the needles on every render if set
+    pub alignment: Option<NeedleAlignment>,
+    pub ct: CTPane,
+    pub dose: Option<DosePane>,
+    pub crosshair: Option<Crosshair>,
+}
+
+impl ObliqueView {
+    pub fn set_plane(&mut self, origin: Vector3<f32>, normal: Vector3<f32>, up: Vector3<f32>) -> Result<(), String> {
+        let normal = normal
+            .try_normalize(f32::EPSILON)
+            .ok_or("the normal of the plane is zero.")?;
+        let up = (up - normal * up.dot(&normal))
+            .try_normalize(1e-6)
+            .ok_or("the up vector is parallel to the normal.")?;
+        self.origin = origin;
+        self.normal = normal;
+        self.up = up;
+        Ok(())
+    }
+
+    pub fn right(&self) -> Vector3<f32> {
+        self.normal.cross(&self.up)
+    }
+
+    // Spin the plane about its normal, in radian.
+    pub fn rotate(&mut self, angle: f32) {
+        let rot = Rotation3::from_axis_angle(&Unit::new_normalize(self.normal), angle);
+        self.up = rot * self.up;
+    }
+
+    // Tilt the normal about the right and the up axes of the view, in radian.
+    pub fn tilt(&mut self, about_right: f32, about_up: f32) {
+        let rot = Rotation3::from_axis_angle(&Unit::new_normalize(self.right()), about_right)
+            * Rotation3::from_axis_angle(&Unit::new_normalize(self.up), about_up);
+        self.normal = rot * self.normal;
+        self.up = rot * self.up;
+    }
+
+    // From the quad to the view frame of the primary in mm. The quad covers
+    // 500 mm at scale 1 across the shorter side like the other views.
+    pub fn plane_transform(&self) -> Base {
+        let k = 250.0 / self.scale;
+        let (ex, ey) = viewport_extent(self.size.2, self.size.3);
+        let right = self.right() * k;
+        let up = self.up * k;
+        let normal = self.normal * k;
+        let t = self.origin - right * self.pan_x - up * self.pan_y;
+        let (right, up) = (right * ex, up * ey);
+        Base::from_column_major(&[
+            right.x, right.y, right.z, 0.0,
+            up.x, up.y, up.z, 0.0,
+            normal.x, normal.y, normal.z, 0.0,
+            t.x, t.y, t.z, 1.0,
+        ])
+    }
+
+    pub fn bind_texture_uniforms(&self) -> Result<(), String> {
+        self.ct.bind_texture_uniforms(&self.program)?;
+        if let Some(ref dose) = self.dose {
+            dose.bind_texture_uniforms(&self.program)?;
+        }
+        Ok(())
+    }
+
+    pub fn align_to_needles(&mut self) -> Result<(), String> {
+        if let Some(alignment) = self.alignment {
+            let (origin, normal, up) = self.needles.borrow().plane(alignment);
+            self.set_plane(origin, normal, up)?;
+        }
+        Ok(())
+    }
+
+    pub fn update_uniforms(&self) -> Result<(), String> {
+        self.program
+            .set_uniform_matrix4fv("plane", &self.plane_transform().to_column_major())?;
+        self.program.set_uniform1f("k", self.blend)?;
+
+        // the depth ruler runs from the tips back along the midline of the
+        // needles in the inline view
+        let needles = self.needles.borrow();
+        let (origin, dir, side) = (needles.tip(), -needles.axis(), needles.spread());
+        let length = match self.alignment {
+            Some(NeedleAlignment::Inline) => needles.needle_length,
+            _ => 0.0,
+        };
+        self.program.set_uniform3f("ruler_origin", origin.x, origin.y, origin.z)?;
+        self.program.set_uniform3f("ruler_dir", dir.x, dir.y, dir.z)?;
+        self.program.set_uniform3f("ruler_side", side.x, side.y, side.z)?;
+        self.program.set_uniform1f("ruler_length", length)?;
+
+        self.needles.borrow().update_uniforms(&self.program)?;
+        self.crosshair.update_uniforms(&self.program)?;
+        self.fusion.update_uniforms(&self.program)?;
+        self.ct.update_uniforms(&self.program)?;
+        if let Some(ref dose) = self.dose {
+            dose.update_uniforms(&self.program)?;
+        }
+        Ok(())
+    }
+}
+
+impl Rendable for ObliqueView {
+    fn render(&mut self) {
+        let (x0, y0, width, height) = self.size;
+        if width <= 0 || height <= 0 {
+            return;
+        }
+        self.context.viewport(x0, y0, width, height);
+
+        self.program.use_program();
+        if let Err(err) = self
+            .align_to_needles()
+            .and_then(|_| self.update_uniforms())
+            .and_then(|_| self.bind_texture_uniforms())
+        {
+            warn!("oblique view: {}", err);
+            return;
+        }
+        self.context.draw_elements_with_i32(
+            GL2::TRIANGLES,
+            self.num_of_indices,
+            GL2::UNSIGNED_SHORT,
+            0,
+        );
+    }
+}
+
+pub struct ThreeDView {
+    pub context: GLContext,
+    pub program: Program,
+    pub scale: f32,
+    // rotation of the camera about the z axis of the primary and its tilt
+    // towards z, in radian; (0, 0) looks along +y with z pointing up
+    pub azimuth: f32,
+    pub elevation: f32,
+    pub size: (i32, i32, i32, i32),
+    // sampling distance along the rays in mm
+    pub step: f32,
+    pub needles: Rc<RefCell<TwoNeedleGeometry>>,
+    pub num_of_indices: i32,
+    pub transfer: Rc<RefCell<LoadedTexture>>,
+    // values at the ends of the transfer function
+    pub transfer_range: (f32, f32),
+    pub field_opacity: f32,
+    pub ct: CTPane,
+    pub dose: Option<DosePane>,
+    pub mesh_program: Program,
+    pub meshes: Vec<MeshPane>,
+    pub next_mesh_id: u32,
+}
+
+pub struct MeshPane {
+    pub id: u32,
+    pub geometry: Rc<MeshGeometry>,
+    // from the mesh coordinates to the view frame of the primary, column major
+    pub model: [f32; 16],
+    // rgb and opacity
+    pub color: [f32; 4],
+    pub visible: bool,
+    // whether the mesh is moved with the needle position, like the secondary
+    pub follow_needles: bool,
+}
+
+impl ThreeDView {
+    // From the quad, whose z is the depth, to the view frame of the primary in
+    // mm, column major. The shorter side of the quad covers the whole primary
+    // at scale 1.
+    pub fn ray_transform(&self) -> [f32; 16] {
+        let (sa, ca) = self.azimuth.sin_cos();
+        let (se, ce) = self.elevation.sin_cos();
+        let right = [ca, sa, 0.0];
+        let up = [-sa * se, ca * se, ce];
+        let into = [-sa * ce, ca * ce, -se];
+
+        let (sx, sy, sz) = self.ct.size;
+        let r = 0.5 * (sx * sx + sy * sy + sz * sz).sqrt() / self.scale;
+        let (ex, ey) = viewport_extent(self.size.2, self.size.3);
+        [
+            right[0] * r * ex, right[1] * r * ex, right[2] * r * ex, 0.0,
+            up[0] * r * ey, up[1] * r * ey, up[2] * r * ey, 0.0,
+            into[0] * r, into[1] * r, into[2] * r, 0.0,
+            0.0, 0.0, 0.0, 1.0,
+        ]
+    }
+
+    // From the view frame to the clip coordinates, the inverse of the ray
+    // transform.
+    pub fn projection(&self) -> Option<[f32; 16]> {
+        Base::from_column_major(&self.ray_transform())
+            .inverse()
+            .map(|m| m.to_column_major())
+    }
+
+    pub fn add_mesh(&mut self, geometry: MeshGeometry, model: [f32; 16], color: [f32; 4], follow_needles: bool) -> u32 {
+        let id = self.next_mesh_id;
+        self.next_mesh_id += 1;
+        self.meshes.push(MeshPane {
+            id,
+            geometry: Rc::new(geometry),
+            model,
+            color,
+            visible: true,
+            follow_needles,
+        });
+        id
+    }
+
+    pub fn find_mesh_mut(&mut self, id: u32) -> Option<&mut MeshPane> {
+        self.meshes.iter_mut().find(|m| m.id == id)
+    }
+
+    pub fn remove_mesh(&mut self, id: u32) -> bool {
+        let len = self.meshes.len();
+        self.meshes.retain(|m| m.id != id);
+        self.meshes.len() != len
+    }
+
+    // The meshes are drawn over the ray casting with depth testing against
+    // its depth and among them, the opaque ones first and then the
+    // translucent ones without writing the depth.
+    fn render_meshes(&self) -> Result<(), String> {
+        if !self.meshes.iter().any(|m| m.visible) {
+            return Ok(());
+        }
+        let gl = &self.context;
+        let program = &self.mesh_program;
+        program.use_program();
+        let projection = self.projection().ok_or("the 3D camera is singular.")?;
+        program.set_uniform_matrix4fv("projection", &projection)?;
+        let ray = self.ray_transform();
+        program.set_uniform3f("eye_dir", ray[8], ray[9], ray[10])?;
+        // npos is in 250 mm with y pointing up
+        let (x, y, z) = self.needles.borrow().needle_pos;
+        let shift = Base::translation(x * 250.0, -y * 250.0, z * 250.0);
+
+        gl.enable(GL2::DEPTH_TEST);
+        gl.depth_func(GL2::LEQUAL);
+        for opaque in [true, false] {
+            if !opaque {
+                gl.enable(GL2::BLEND);
+                gl.blend_func(GL2::SRC_ALPHA, GL2::ONE_MINUS_SRC_ALPHA);
+                gl.depth_mask(false);
+            }
+            for mesh in self.meshes.iter().filter(|m| m.visible && (m.color[3] >= 1.0) == opaque) {
+                let model = if mesh.follow_needles {
+                    shift.compose(&Base::from_column_major(&mesh.model)).to_column_major()
+                } else {
+                    mesh.model
+                };
+                program.set_uniform_matrix4fv("model", &model)?;
+                let c = mesh.color;
+                program.set_uniform4f("mesh_color", c[0], c[1], c[2], c[3])?;
+                mesh.geometry.draw();
+            }
+        }
+        gl.depth_mask(true);
+        gl.disable(GL2::BLEND);
+        gl.disable(GL2::DEPTH_TEST);
+        Ok(())
+    }
+
+    pub fn bind_texture_uniforms(&self) -> Result<(), String> {
+        self.ct.bind_texture_uniforms(&self.program)?;
+        self.program.bind_texture_uniform(&self.transfer.borrow(), GL2::TEXTURE_3D, "sampler5")?;
+        if let Some(ref dose) = self.dose {
+            dose.bind_texture_uniforms(&self.program)?;
+        }
+        Ok(())
+    }
+
+    pub fn update_uniforms(&self) -> Result<(), String> {
+        self.program.set_uniform_matrix4fv("ray", &self.ray_transform())?;
+        self.program.set_uniform1f("step_size", self.step)?;
+        self.program.set_uniform2f("tf_range", self.transfer_range.0, self.transfer_range.1)?;
+
+        self.needles.borrow().update_uniforms(&self.program)?;
+        self.ct.update_uniforms(&self.program)?;
+        if let Some(ref dose) = self.dose {
+            dose.update_uniforms(&self.program)?;
+            self.program.set_uniform1f("opacity1", self.field_opacity)?;
+        } else {
+            self.program.set_uniform1f("opacity1", 0.0)?;
+        }
+        Ok(())
+    }
+}
+
+impl Rendable for ThreeDView {
+    fn render(&mut self) {
+        {
+            let (x0, y0, width, height) = self.size;
+            self.context.viewport(x0, y0, width, height);
+        }
+
+        self.program.use_program();
+        if let Err(err) = self.update_uniforms().and_then(|_| self.bind_texture_uniforms()) {
+            warn!("3D view: {}", err);
+        }
+        // the depth is written only with the test enabled
+        self.context.enable(GL2::DEPTH_TEST);
+        self.context.depth_func(GL2::ALWAYS);
+        self.context.draw_elements_with_i32(
+            GL2::TRIANGLES,
+            self.num_of_indices,
+            GL2::UNSIGNED_SHORT,
+            0,
+        );
+        self.context.disable(GL2::DEPTH_TEST);
+        if let Err(err) = self.render_meshes() {
+            warn!("3D view: {}", err);
+        }
+    }
+}
+
+// The viewports of the views from the bottom left of the canvas, empty for
+// the hidden ones. They fill the canvas, and the views keep the aspect of
+// their viewports, see viewport_extent.
 pub trait Layout<T> {
     fn layout(&self, ty: &T) -> (i32, i32, i32, i32);
 }
 
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum CanvasView {
     Transverse,
     Sagittal,
     Coronal,
     ThreeD,
+    Oblique,
+    NeedleInline,
+    NeedlePerpendicular,
 }
 
 use CanvasView::*;
 
+impl CanvasView {
+    // "T", "S", "C", "3D", "O", "NI" or "NP"
+    pub fn from_name(name: &str) -> Option<CanvasView> {
+        match name {
+            "T" => Some(Transverse),
+            "S" => Some(Sagittal),
+            "C" => Some(Coronal),
+            "3D" => Some(ThreeD),
+            "O" => Some(Oblique),
+            "NI" => Some(NeedleInline),
+            "NP" => Some(NeedlePerpendicular),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Transverse => "T",
+            Sagittal => "S",
+            Coronal => "C",
+            ThreeD => "3D",
+            Oblique => "O",
+            NeedleInline => "NI",
+            NeedlePerpendicular => "NP",
+        }
+    }
+
+    pub const ALL: [CanvasView; 7] = [
+        Transverse,
+        Sagittal,
+        Coronal,
+        ThreeD,
+        Oblique,
+        NeedleInline,
+        NeedlePerpendicular,
+    ];
+}
+
 pub struct LayoutOneLargeThreeSmall {
     width: i32,
     height: i32,
@@ -379,54 +1161,295 @@ impl LayoutOneLargeThreeSmall {
         self.height = height;
     }
 
-    fn large(&self) -> (i32, i32, i32, i32) {
-        (0, 0, self.height, self.height)
+    pub fn dimension(&self) -> (i32, i32) {
+        (self.width, self.height)
     }
 
-    fn small0(&self) -> (i32, i32, i32, i32) {
-        let side_length = (self.height - 2) / 3;
-        let x0 = self.height + 1;
-        let y0 = side_length * 2 + 2;
-        (x0, y0, side_length, side_length)
+    // The width of the column of the small views at the right.
+    fn small_width(&self) -> i32 {
+        ((self.width - 1) / 4).max(0)
     }
 
-    fn small1(&self) -> (i32, i32, i32, i32) {
-        let side_length = (self.height - 2) / 3;
-        let x0 = self.height + 1;
-        let y0 = side_length + 1;
-        (x0, y0, side_length, side_length)
+    // at the left, the full height
+    fn large(&self) -> (i32, i32, i32, i32) {
+        let width = (self.width - self.small_width() - 1).max(0);
+        (0, 0, width, self.height.max(0))
     }
 
-    fn small2(&self) -> (i32, i32, i32, i32) {
-        let side_length = (self.height - 2) / 3;
-        let x0 = self.height + 1;
-        let y0 = 0;
-        (x0, y0, side_length, side_length)
+    // The small slots beside the large view from the top.
+    fn small(&self, i: i32) -> (i32, i32, i32, i32) {
+        let width = self.small_width();
+        let height = ((self.height - 2) / 3).max(0);
+        let x0 = self.width - width;
+        let y0 = (height + 1) * (2 - i);
+        (x0, y0, width, height)
+    }
+}
+
+// The views in the small slots from the top when `maximized` is large, and
+// in the other cells of LayoutGrid; the rest are hidden.
+fn others(maximized: CanvasView) -> [CanvasView; 3] {
+    match maximized {
+        Transverse => [Sagittal, Coronal, ThreeD],
+        Sagittal => [Transverse, Coronal, ThreeD],
+        Coronal => [Transverse, Sagittal, ThreeD],
+        ThreeD | Oblique => [Transverse, Sagittal, Coronal],
+        NeedleInline => [Transverse, Sagittal, NeedlePerpendicular],
+        NeedlePerpendicular => [Transverse, Sagittal, NeedleInline],
     }
 }
 
 impl Layout<CanvasView> for LayoutOneLargeThreeSmall {
     fn layout(&self, ty: &CanvasView) -> (i32, i32, i32, i32) {
-        match ty {
-            Transverse => match self.maximized {
-                Transverse => self.large(),
-                _ => self.small0(),
-            },
-            Sagittal => match self.maximized {
-                Transverse => self.small0(),
-                Sagittal => self.large(),
-                _ => self.small1(),
-            },
-            Coronal => match self.maximized {
-                Coronal => self.large(),
-                ThreeD => self.small2(),
-                _ => self.small1(),
-            },
-            ThreeD => match self.maximized {
-                ThreeD => self.large(),
-                _ => self.small2(),
+        if *ty == self.maximized {
+            return self.large();
+        }
+        match others(self.maximized).iter().position(|v| v == ty) {
+            Some(i) => self.small(i as i32),
+            None => (0, 0, 0, 0),
+        }
+    }
+}
+
+// `columns` x `rows` cells of the same size over the canvas, 1 pixel apart,
+// with `views` in them row by row from the top left.
+pub struct LayoutGrid {
+    width: i32,
+    height: i32,
+    columns: i32,
+    rows: i32,
+    views: Vec<CanvasView>,
+}
+
+impl LayoutGrid {
+    pub fn new(width: i32, height: i32, columns: i32, rows: i32, views: Vec<CanvasView>) -> LayoutGrid {
+        LayoutGrid {
+            width,
+            height,
+            columns: columns.max(1),
+            rows: rows.max(1),
+            views,
+        }
+    }
+
+    // The pixels left over by the division are split around the cells.
+    fn cell(&self, i: i32) -> (i32, i32, i32, i32) {
+        let (columns, rows) = (self.columns, self.rows);
+        let width = ((self.width - columns + 1) / columns).max(0);
+        let height = ((self.height - rows + 1) / rows).max(0);
+        let x0 = (self.width - width * columns - columns + 1) / 2;
+        let y0 = (self.height - height * rows - rows + 1) / 2;
+        let (column, row) = (i % columns, i / columns);
+        (
+            x0 + column * (width + 1),
+            y0 + (rows - 1 - row) * (height + 1),
+            width,
+            height,
+        )
+    }
+}
+
+impl Layout<CanvasView> for LayoutGrid {
+    fn layout(&self, ty: &CanvasView) -> (i32, i32, i32, i32) {
+        match self.views.iter().position(|v| v == ty) {
+            Some(i) if (i as i32) < self.columns * self.rows => self.cell(i as i32),
+            _ => (0, 0, 0, 0),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum LayoutKind {
+    OneLargeThreeSmall,
+    // the maximized view first and the others as in LayoutOneLargeThreeSmall
+    Grid { columns: i32, rows: i32 },
+    // `views` in their order, the maximized one moved to the first cell if it
+    // is one of them
+    Custom {
+        columns: i32,
+        rows: i32,
+        views: Vec<CanvasView>,
+    },
+}
+
+impl LayoutKind {
+    // "1+3", or "1x1", "1x2", "2x2" and "1x3" in rows x columns
+    pub fn from_name(name: &str) -> Option<LayoutKind> {
+        let (columns, rows) = match name {
+            "1+3" => return Some(LayoutKind::OneLargeThreeSmall),
+            "1x1" => (1, 1),
+            "1x2" => (2, 1),
+            "2x2" => (2, 2),
+            "1x3" => (3, 1),
+            _ => return None,
+        };
+        Some(LayoutKind::Grid { columns, rows })
+    }
+}
+
+// The layout of GlCanvas on its drawing buffer, which is `pixel_ratio`
+// device pixels a CSS pixel of the canvas.
+pub struct LayoutManager {
+    width: i32,
+    height: i32,
+    css_size: (i32, i32),
+    pixel_ratio: f32,
+    maximized: CanvasView,
+    kind: LayoutKind,
+}
+
+impl LayoutManager {
+    pub fn new(width: i32, height: i32, maximized: CanvasView) -> LayoutManager {
+        LayoutManager {
+            width,
+            height,
+            css_size: (width, height),
+            pixel_ratio: 1.0,
+            maximized,
+            kind: LayoutKind::OneLargeThreeSmall,
+        }
+    }
+
+    pub fn set_maximized(&mut self, maximized: CanvasView) {
+        self.maximized = maximized;
+        if let LayoutKind::Custom { views, .. } = &mut self.kind {
+            if let Some(i) = views.iter().position(|v| *v == maximized) {
+                views.swap(0, i);
+            }
+        }
+    }
+
+    pub fn set_kind(&mut self, kind: LayoutKind) {
+        self.kind = kind;
+    }
+
+    pub fn kind(&self) -> &LayoutKind {
+        &self.kind
+    }
+
+    // of the drawing buffer
+    pub fn set_width(&mut self, width: i32) {
+        self.width = width;
+    }
+
+    pub fn set_height(&mut self, height: i32) {
+        self.height = height;
+    }
+
+    pub fn dimension(&self) -> (i32, i32) {
+        (self.width, self.height)
+    }
+
+    // The drawing buffer follows the size of the canvas in CSS pixels.
+    pub fn set_css_size(&mut self, width: i32, height: i32) {
+        self.css_size = (width, height);
+        self.width = (width as f32 * self.pixel_ratio).round() as i32;
+        self.height = (height as f32 * self.pixel_ratio).round() as i32;
+    }
+
+    pub fn set_pixel_ratio(&mut self, pixel_ratio: f32) {
+        self.pixel_ratio = pixel_ratio;
+        self.set_css_size(self.css_size.0, self.css_size.1);
+    }
+
+    pub fn pixel_ratio(&self) -> f32 {
+        self.pixel_ratio
+    }
+
+    fn current(&self) -> Box<dyn Layout<CanvasView>> {
+        let (width, height) = (self.width, self.height);
+        match &self.kind {
+            LayoutKind::OneLargeThreeSmall => {
+                Box::new(LayoutOneLargeThreeSmall::new(width, height, self.maximized))
+            }
+            LayoutKind::Grid { columns, rows } => {
+                let mut views = vec![self.maximized];
+                views.extend(others(self.maximized));
+                Box::new(LayoutGrid::new(width, height, *columns, *rows, views))
+            }
+            LayoutKind::Custom { columns, rows, views } => {
+                Box::new(LayoutGrid::new(width, height, *columns, *rows, views.clone()))
             }
         }
     }
 }
 
+impl Layout<CanvasView> for LayoutManager {
+    fn layout(&self, ty: &CanvasView) -> (i32, i32, i32, i32) {
+        self.current().layout(ty)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_needle_planes() {
+        let needles = TwoNeedleGeometry {
+            uah: (15000., 0.5, 10.),
+            needle_pos: (0.1, 0.2, 0.),
+            needle_rot: std::f32::consts::FRAC_PI_2,
+            needle_length: 20.,
+        };
+        assert!((needles.tip() - Vector3::new(25., -50., 10.)).norm() < 1e-4);
+
+        // the inline plane contains both shafts
+        let (origin, normal, up) = needles.plane(NeedleAlignment::Inline);
+        for s in [1.0, -1.0] {
+            let p = needles.center() + needles.spread() * (needles.uah.2 * s);
+            assert!((p - origin).dot(&normal).abs() < 1e-4);
+        }
+        assert!(up.dot(&needles.axis()) < 0.0);
+
+        // the perpendicular plane passes through the tips
+        let (origin, normal, up) = needles.plane(NeedleAlignment::Perpendicular);
+        assert!((needles.tip() - origin).dot(&normal).abs() < 1e-4);
+        assert!(up.dot(&normal).abs() < 1e-4);
+        assert!((normal.cross(&up) - needles.spread()).norm() < 1e-4);
+    }
+
+    #[test]
+    fn test_layouts() {
+        // the large view at the left and the small ones in a column beside it
+        let mut layout = LayoutManager::new(1200, 900, Transverse);
+        assert_eq!(layout.layout(&Transverse), (0, 0, 900, 900));
+        assert_eq!(layout.layout(&Sagittal), (901, 600, 299, 299));
+        assert_eq!(layout.layout(&ThreeD), (901, 0, 299, 299));
+        assert_eq!(layout.layout(&Oblique), (0, 0, 0, 0));
+        // the full height of a narrow canvas as well
+        layout.set_css_size(800, 900);
+        assert_eq!(layout.layout(&Transverse), (0, 0, 600, 900));
+        assert_eq!(layout.layout(&Sagittal), (601, 600, 199, 299));
+        assert_eq!(layout.layout(&ThreeD), (601, 0, 199, 299));
+
+        // the cells fill the canvas
+        layout.set_css_size(500, 400);
+        layout.set_pixel_ratio(2.0);
+        assert_eq!(layout.dimension(), (1000, 800));
+        layout.set_kind(LayoutKind::from_name("2x2").unwrap());
+        layout.set_maximized(Coronal);
+        assert_eq!(layout.layout(&Coronal), (0, 400, 499, 399));
+        assert_eq!(layout.layout(&Transverse), (500, 400, 499, 399));
+        assert_eq!(layout.layout(&ThreeD), (500, 0, 499, 399));
+        layout.set_kind(LayoutKind::from_name("1x1").unwrap());
+        assert_eq!(layout.layout(&Coronal), (0, 0, 1000, 800));
+        assert_eq!(layout.layout(&Transverse), (0, 0, 0, 0));
+        layout.set_kind(LayoutKind::from_name("1x3").unwrap());
+        assert_eq!(layout.layout(&Sagittal), (667, 0, 332, 800));
+
+        layout.set_kind(LayoutKind::Custom {
+            columns: 2,
+            rows: 1,
+            views: vec![Oblique, ThreeD],
+        });
+        assert_eq!(layout.layout(&Oblique), (0, 0, 499, 800));
+        assert_eq!(layout.layout(&ThreeD), (500, 0, 499, 800));
+        layout.set_maximized(ThreeD);
+        assert_eq!(layout.layout(&ThreeD), (0, 0, 499, 800));
+        layout.set_maximized(Transverse);
+        assert_eq!(layout.layout(&Transverse), (0, 0, 0, 0));
+        assert_eq!(layout.layout(&ThreeD), (0, 0, 499, 800));
+        assert_eq!(layout.layout(&Oblique), (500, 0, 499, 800));
+    }
+}